@@ -45,14 +45,20 @@ fn vector_free() {
 
 #[test_case]
 fn vector_big_alloc() {
+	use crate::memory::allocator::kheap::KHEAP_LIMIT;
 	use crate::vec::{AllocError, KGlobal};
 
 	print_fn!();
 
-    // Current heap size is 0x1000 * 100
-	// Should send an error
+	// The heap grows past its initial 0x1000 * 100 bytes
 	{
 		let x = Vec::<u32, KGlobal>::try_alloc(0x1000 * 200 / 4, &KGlobal);
+		assert!(x.is_ok());
+	}
+
+	// Should send an error, the heap can't grow that much
+	{
+		let x = Vec::<u32, KGlobal>::try_alloc(KHEAP_LIMIT / 4, &KGlobal);
 		assert_eq!(x, Err(AllocError));
 	}
 
@@ -63,6 +69,19 @@ fn vector_big_alloc() {
 	}
 }
 
+// Pages of large allocations are reused once freed
+#[test_case]
+fn vector_big_free() {
+	print_fn!();
+	let ptr: u32;
+	{
+		let x: Vec<u32> = Vec::with_capacity(0x1000 * 4 / 4);
+		ptr = x.as_ptr() as u32;
+	}
+	let y: Vec<u32> = Vec::with_capacity(0x1000 * 4 / 4);
+	assert_eq!(ptr, y.as_ptr() as u32);
+}
+
 // Simply test if conversion is working
 #[test_case]
 fn vector_slices() {
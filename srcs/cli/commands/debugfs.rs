@@ -2,23 +2,34 @@ use crate::alloc::string::String;
 use crate::alloc::vec::Vec;
 
 use crate::fs::ext2;
+use crate::proc::process::Process;
 use crate::spin::Mutex;
-use crate::utils::path::Path;
 
-pub static ROOT_INODE: usize = 2;
-pub static CURRENTDIR_INODE: Mutex<usize> = Mutex::new(ROOT_INODE);
-pub static PWD: Mutex<Option<Path>> = Mutex::new(None);
 pub static DISKNO: Mutex<Option<ext2::Ext2>> = Mutex::new(None);
 
+/// Subcommands modifying the filesystem, refused when it is mounted read-only
+const WRITE_COMMANDS: [&str; 9] = [
+	"touch", "mkdir", "rm", "rmdir", "mv", "ln", "chmod", "chown", "setfattr"
+];
+
 fn help() {
 	crate::kprintln!(
-		"Command available: ls,stat,cat,imap,cd,touch,mkdir,rm,pwd,test"
+		"Command available: ls,stat,cat,imap,cd,touch,mkdir,rm,rmdir,mv,ln,readlink,chmod,chown,getfattr,setfattr,fsck,journal,pwd,test"
 	);
 }
 
 pub fn debugfs(mut command: Vec<String>) {
 	if command.len() > 1 {
 		command.remove(0); // Delete command name before sending to subcommand
+		if WRITE_COMMANDS.contains(&command[0].as_str())
+			&& DISKNO
+				.lock()
+				.as_ref()
+				.is_some_and(|ext2| ext2.check_writable().is_err())
+		{
+			crate::kprintln!("{}: filesystem mounted read-only", command[0]);
+			return;
+		}
 		match command[0].as_str() {
 			"ls" => ls(command),
 			"stat" => stat(command),
@@ -28,6 +39,16 @@ pub fn debugfs(mut command: Vec<String>) {
 			"touch" => touch(command),
 			"mkdir" => mkdir(command),
 			"rm" => rm(command),
+			"rmdir" => rmdir(command),
+			"mv" => mv(command),
+			"ln" => ln(command),
+			"readlink" => readlink(command),
+			"chmod" => chmod(command),
+			"chown" => chown(command),
+			"getfattr" => getfattr(command),
+			"setfattr" => setfattr(command),
+			"fsck" => fsck(command),
+			"journal" => journal(command),
 			"pwd" => pwd(),
 			"test" => test(),
 			_ => {
@@ -40,27 +61,228 @@ pub fn debugfs(mut command: Vec<String>) {
 	}
 }
 
+/// Working and root directories of the running process with every permission granted
+fn context() -> ext2::perm::Context {
+	let binding = Process::get_running_process();
+	let process = binding.lock();
+	ext2::perm::Context {
+		root: process.root,
+		..ext2::perm::Context::new(process.cwd)
+	}
+}
+
 fn pwd() {
-	crate::kprintln!(
-		"[pwd]   INODE: {:>6}  PATH: {}",
-		*CURRENTDIR_INODE.lock(),
-		PWD.lock().as_ref().unwrap_or(&Path::new("/"))
-	);
-	crate::kprintln!("[root]  INODE: {:>6}  PATH: /", ROOT_INODE);
+	let ctx = context();
+	match DISKNO.lock().as_ref().unwrap().getcwd(&ctx) {
+		Ok(path) => {
+			crate::kprintln!("[pwd]   INODE: {:>6}  PATH: {}", ctx.cwd, path)
+		},
+		Err(errno) => {
+			crate::kprintln!("[pwd]   INODE: {:>6}  {:?}", ctx.cwd, errno)
+		}
+	}
+	crate::kprintln!("[root]  INODE: {:>6}", ctx.root);
 }
 
 fn rm(command: Vec<String>) {
-	if command.len() < 2 {
-		crate::kprintln!("usage: debugfs rm FILE");
+	let recursive = command.len() > 1 && command[1] == "-r";
+	let args = &command[1 + recursive as usize..];
+	if args.len() < 1 {
+		crate::kprintln!("usage: debugfs rm [-r] FILE");
 		return;
 	}
 	ext2::remove_file(
+		DISKNO.lock().as_mut().unwrap(),
+		args[0].as_str(),
+		context().cwd,
+		recursive
+	);
+}
+
+fn rmdir(command: Vec<String>) {
+	if command.len() < 2 {
+		crate::kprintln!("usage: debugfs rmdir DIR");
+		return;
+	}
+	ext2::remove_dir(
+		DISKNO.lock().as_mut().unwrap(),
+		command[1].as_str(),
+		context().cwd
+	);
+}
+
+fn mv(command: Vec<String>) {
+	if command.len() < 3 {
+		crate::kprintln!("usage: debugfs mv SOURCE DEST");
+		return;
+	}
+	ext2::rename_file(
 		DISKNO.lock().as_mut().unwrap(),
 		command[1].as_str(),
-		*CURRENTDIR_INODE.lock()
+		command[2].as_str(),
+		context().cwd
 	);
 }
 
+fn ln(command: Vec<String>) {
+	let symbolic = command.len() > 1 && command[1] == "-s";
+	let args = &command[1 + symbolic as usize..];
+	if args.len() < 2 {
+		crate::kprintln!("usage: debugfs ln [-s] TARGET LINK_NAME");
+		return;
+	}
+	ext2::link_file(
+		DISKNO.lock().as_mut().unwrap(),
+		args[0].as_str(),
+		args[1].as_str(),
+		context().cwd,
+		symbolic
+	);
+}
+
+fn readlink(command: Vec<String>) {
+	if command.len() < 2 {
+		crate::kprintln!("usage: debugfs readlink LINK");
+		return;
+	}
+	let binding = DISKNO.lock();
+	let ext2 = binding.as_ref().unwrap();
+	let ctx = context();
+	match ext2.readlink(command[1].as_str(), &ctx) {
+		Ok(target) => crate::kprintln!("{}", target),
+		Err(errno) => crate::kprintln!("'{}': {:?}", command[1], errno)
+	}
+}
+
+fn chmod(command: Vec<String>) {
+	let mode = command.get(1).and_then(|x| u16::from_str_radix(x, 8).ok());
+	let (Some(mode), Some(path)) = (mode, command.get(2)) else {
+		crate::kprintln!("usage: debugfs chmod OCTAL-MODE FILE");
+		return;
+	};
+	let ctx = context();
+	let mut binding = DISKNO.lock();
+	let ext2 = binding.as_mut().unwrap();
+	if let Err(errno) =
+		ext2.transaction(|ext2| ext2.chmod(path.as_str(), &ctx, mode))
+	{
+		crate::kprintln!("'{}': {:?}", path, errno);
+	}
+}
+
+fn chown(command: Vec<String>) {
+	let ids = command.get(1).map(|x| match x.split_once(':') {
+		Some((uid, gid)) => (uid.parse().ok(), gid.parse().ok()),
+		None => (x.parse().ok(), None)
+	});
+	let (Some((uid, gid)), Some(path)) = (ids, command.get(2)) else {
+		crate::kprintln!("usage: debugfs chown UID[:GID] FILE");
+		return;
+	};
+	let ctx = context();
+	let mut binding = DISKNO.lock();
+	let ext2 = binding.as_mut().unwrap();
+	if let Err(errno) =
+		ext2.transaction(|ext2| ext2.chown(path.as_str(), &ctx, uid, gid, true))
+	{
+		crate::kprintln!("'{}': {:?}", path, errno);
+	}
+}
+
+fn getfattr(command: Vec<String>) {
+	let Some(path) = command.get(1) else {
+		crate::kprintln!("usage: debugfs getfattr FILE");
+		return;
+	};
+	let binding = DISKNO.lock();
+	let ext2 = binding.as_ref().unwrap();
+	let ctx = context();
+	let names = match ext2.listxattr(path.as_str(), &ctx, false) {
+		Ok(names) => names,
+		Err(errno) => return crate::kprintln!("'{}': {:?}", path, errno)
+	};
+	for name in names.iter() {
+		match ext2.getxattr(path.as_str(), &ctx, name.as_str(), false) {
+			// Values are shown as text when they are, hexadecimal otherwise
+			Ok(value) => match core::str::from_utf8(&value) {
+				Ok(text) if !text.contains(|c: char| c.is_control()) => {
+					crate::kprintln!("{}=\"{}\"", name, text)
+				},
+				_ => {
+					crate::kprint!("{}=0x", name);
+					value.iter().for_each(|x| crate::kprint!("{:02x}", x));
+					crate::kprintln!("");
+				}
+			},
+			Err(errno) => crate::kprintln!("{}: {:?}", name, errno)
+		}
+	}
+}
+
+fn setfattr(command: Vec<String>) {
+	let args: Vec<&str> = command[1..].iter().map(|x| x.as_str()).collect();
+	let (name, value, path) = match args.as_slice() {
+		["-x", name, path] => (*name, None, *path),
+		[name, value, path] => (*name, Some(value.as_bytes()), *path),
+		_ => {
+			crate::kprintln!("usage: debugfs setfattr NAME VALUE FILE");
+			crate::kprintln!("       debugfs setfattr -x NAME FILE");
+			return;
+		}
+	};
+	let ctx = context();
+	let mut binding = DISKNO.lock();
+	let ext2 = binding.as_mut().unwrap();
+	if let Err(errno) = ext2.transaction(|ext2| match value {
+		Some(value) => ext2.setxattr(path, &ctx, name, Some(value), 0, false),
+		None => ext2.removexattr(path, &ctx, name, false)
+	}) {
+		crate::kprintln!("'{}': {:?}", path, errno);
+	}
+}
+
+fn fsck(command: Vec<String>) {
+	let repair = command.len() > 1 && command[1] == "-y";
+	let report = DISKNO.lock().as_mut().unwrap().fsck(repair);
+	for problem in report.problems.iter() {
+		crate::kprintln!("{}", problem);
+	}
+	match report.is_clean() {
+		true => crate::kprintln!("Filesystem is clean"),
+		false => crate::kprintln!(
+			"{} problems found, {} fixed",
+			report.problems.len(),
+			report.fixed
+		)
+	}
+}
+
+fn journal(command: Vec<String>) {
+	let mut binding = DISKNO.lock();
+	let ext2 = binding.as_mut().unwrap();
+	let res = match command.get(1).map(|x| x.as_str()) {
+		Some("on") => ext2.set_journaling(true),
+		Some("off") => ext2.set_journaling(false),
+		Some(_) => {
+			crate::kprintln!("usage: debugfs journal [on|off]");
+			return;
+		},
+		None => Ok(())
+	};
+	if let Err(errno) = res {
+		crate::kprintln!("journal: {:?}", errno);
+	}
+	match ext2.journal() {
+		Some(journal) => crate::kprintln!(
+			"Journal of {} blocks, next transaction {}, logging {}",
+			journal.sb.maxlen,
+			journal.sb.sequence,
+			if journal.is_enabled() { "on" } else { "off" }
+		),
+		None => crate::kprintln!("No journal")
+	}
+}
+
 fn stat(command: Vec<String>) {
 	if command.len() < 2 {
 		crate::kprintln!("usage: debugfs stat FILE");
@@ -69,7 +291,7 @@ fn stat(command: Vec<String>) {
 	ext2::show_inode_info(
 		DISKNO.lock().as_ref().unwrap(),
 		command[1].as_str(),
-		*CURRENTDIR_INODE.lock()
+		context().cwd
 	);
 }
 
@@ -81,7 +303,7 @@ fn mkdir(command: Vec<String>) {
 	ext2::create_dir(
 		DISKNO.lock().as_mut().unwrap(),
 		command[1].as_str(),
-		*CURRENTDIR_INODE.lock()
+		context().cwd
 	);
 }
 
@@ -93,7 +315,7 @@ fn touch(command: Vec<String>) {
 	ext2::create_file(
 		DISKNO.lock().as_mut().unwrap(),
 		command[1].as_str(),
-		*CURRENTDIR_INODE.lock()
+		context().cwd
 	);
 }
 
@@ -105,7 +327,7 @@ fn cat(command: Vec<String>) {
 	let file_content = ext2::get_file_content(
 		DISKNO.lock().as_ref().unwrap(),
 		command[1].as_str(),
-		*CURRENTDIR_INODE.lock()
+		context().cwd
 	);
 	for i in file_content {
 		crate::kprint!("{}", i);
@@ -128,11 +350,8 @@ fn ls(command: Vec<String>) {
 		_ => command[1].as_str()
 	};
 	crate::dprintln!("Ls: {}", path);
-	let dentries = ext2::list_dir(
-		DISKNO.lock().as_ref().unwrap(),
-		path,
-		*CURRENTDIR_INODE.lock()
-	);
+	let dentries =
+		ext2::list_dir(DISKNO.lock().as_ref().unwrap(), path, context().cwd);
 
 	for i in dentries {
 		crate::kprint!("{} ", i.name);
@@ -145,31 +364,11 @@ fn cd(command: Vec<String>) {
 		1 => "",
 		_ => command[1].as_str()
 	};
-	let path = Path::new(path);
-	let binding = DISKNO.lock();
-	let ext2 = binding.as_ref().unwrap();
-	let lookup = ext2.recurs_find(path.as_str(), *CURRENTDIR_INODE.lock());
-	match lookup {
-		None => crate::kprintln!("Dir not found"),
-		Some((inodeno, inode)) => {
-			if inode.is_dir() {
-				*CURRENTDIR_INODE.lock() = inodeno;
-				let mut pwd;
-				if path.has_root() {
-					pwd = Path::new(path.as_str());
-				} else {
-					pwd = match (*PWD.lock()).clone() {
-						Some(x) => x.join(path.as_str()),
-						None => Path::new(&["/", path.as_str()].join(""))
-					};
-				}
-				pwd.cleanup();
-				*PWD.lock() = Some(pwd);
-			} else {
-				crate::kprintln!("Error: {} is not a directory", path.as_str());
-			}
-		},
-	};
+	let ctx = context();
+	match DISKNO.lock().as_ref().unwrap().lookup_dir(path, &ctx) {
+		Ok(inodeno) => Process::get_running_process().lock().cwd = inodeno,
+		Err(errno) => crate::kprintln!("'{}': {:?}", path, errno)
+	}
 }
 
 fn imap(command: Vec<String>) {
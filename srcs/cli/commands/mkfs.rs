@@ -0,0 +1,66 @@
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+
+use super::debugfs::DISKNO;
+use crate::fs::ext2::mkfs::{self, MkfsOptions};
+use crate::fs::ext2::namei::ROOT_INODE;
+use crate::proc::process::Process;
+
+fn usage() {
+	crate::kprintln!("usage: mkfs [-b BLOCK_SIZE] [-N INODES] [-L LABEL] [-j JOURNAL_BLOCKS] [-O dir_index] DISK");
+}
+
+/// Format the disk at index DISK of the discovered disks as ext2, or ext3 with a journal, and
+/// mount it.
+/// Everything stored on the disk is lost
+pub fn mkfs(command: Vec<String>) {
+	let mut options = MkfsOptions::default();
+	let mut args = command.iter().skip(1);
+	let mut diskno = None;
+	while let Some(arg) = args.next() {
+		let parsed = match arg.as_str() {
+			"-b" => args.next().and_then(|x| x.parse().ok()).map(|x| {
+				options.block_size = x;
+			}),
+			"-N" => args.next().and_then(|x| x.parse().ok()).map(|x| {
+				options.inode_count = Some(x);
+			}),
+			"-L" => args.next().map(|x| options.label = x.clone()),
+			"-j" => args.next().and_then(|x| x.parse().ok()).map(|x| {
+				options.journal_blocks = Some(x);
+			}),
+			"-O" => args.next().filter(|x| *x == "dir_index").map(|_| {
+				options.dir_index = true;
+			}),
+			x => x.parse().ok().map(|x: usize| diskno = Some(x))
+		};
+		if parsed.is_none() {
+			return usage();
+		}
+	}
+	let Some(diskno) = diskno else {
+		return usage();
+	};
+	let mut disks = crate::disk::discover();
+	if diskno >= disks.len() {
+		crate::kprintln!("Disk {} not found", diskno);
+		return;
+	}
+	match mkfs::mkfs(disks.swap_remove(diskno), &options) {
+		Ok(mut ext2) => {
+			crate::kprintln!(
+				"Disk {}: {} blocks of {} bytes, {} inodes",
+				diskno,
+				ext2.sblock.block_count(),
+				ext2.sblock.bsize(),
+				ext2.sblock.inode_count()
+			);
+			ext2.diskno = Some(diskno);
+			*DISKNO.lock() = Some(ext2);
+			let binding = Process::get_running_process();
+			let mut process = binding.lock();
+			(process.cwd, process.root) = (ROOT_INODE, ROOT_INODE);
+		},
+		Err(errno) => crate::kprintln!("mkfs: {:?}", errno)
+	}
+}
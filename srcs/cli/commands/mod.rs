@@ -13,17 +13,21 @@ use crate::{io, kprint, kprintln};
 // Commands modules
 pub mod debugfs;
 mod hexdump;
+mod mkfs;
 mod process;
+mod swap;
 mod time;
 mod valgrind;
 
 use debugfs::debugfs;
 use hexdump::hexdump_parser;
+use mkfs::mkfs;
 use process::{kill, pmap, ps};
+use swap::{swapoff, swapon};
 use time::{date, jiffies, uptime};
 use valgrind::valgrind;
 
-const NB_CMDS: usize = 17;
+const NB_CMDS: usize = 20;
 const MAX_CMD_LENGTH: usize = 250;
 
 pub static COMMANDS: [fn(Vec<String>); NB_CMDS] = [
@@ -43,13 +47,16 @@ pub static COMMANDS: [fn(Vec<String>); NB_CMDS] = [
 	valgrind,
 	pmap,
 	kill,
-	debugfs
+	debugfs,
+	mkfs,
+	swapon,
+	swapoff
 ];
 
 const KNOWN_CMD: [&str; NB_CMDS] = [
 	"reboot", "halt", "hexdump", "keymap", "int", "clear", "help", "shutdown",
 	"jiffies", "ps", "uptime", "date", "play", "valgrind", "pmap", "kill",
-	"debugfs"
+	"debugfs", "mkfs", "swapon", "swapoff"
 ];
 
 fn reboot(_: Vec<String>) {
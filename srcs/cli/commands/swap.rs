@@ -0,0 +1,46 @@
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+use crate::errno::ErrNo;
+use crate::memory::swap;
+
+fn usage(name: &str) {
+	crate::kprintln!("usage: {} DISK|FILE", name);
+}
+
+/// Swap pages out to the disk at index DISK of the discovered disks, or to FILE of the mounted
+/// filesystem. Everything stored on them is lost.
+/// Without argument the areas in use are listed
+pub fn swapon(command: Vec<String>) {
+	if command.len() == 1 {
+		crate::kprintln!("{:>5}   {:>10}   {:>10}", "AREA", "PAGES", "USED");
+		for (area, pages, used) in swap::areas() {
+			crate::kprintln!("{:>5}   {:>10}   {:>10}", area, pages, used);
+		}
+		return;
+	}
+	if command.len() != 2 {
+		return usage("swapon");
+	}
+	let res = match command[1].parse() {
+		Ok(diskno) => swap::swapon_disk(diskno),
+		Err(_) => swap::swapon_file(&command[1])
+	};
+	match res {
+		Ok(area) => crate::kprintln!("{}: swap area {}", command[1], area),
+		Err(errno) => crate::kprintln!("swapon: {:?}", errno)
+	}
+}
+
+/// Stop swapping to the disk at index DISK or to FILE, their pages are read back first
+pub fn swapoff(command: Vec<String>) {
+	if command.len() != 2 {
+		return usage("swapoff");
+	}
+	let res: Result<(), ErrNo> = match command[1].parse() {
+		Ok(diskno) => swap::swapoff_disk(diskno),
+		Err(_) => swap::swapoff_file(&command[1])
+	};
+	if let Err(errno) = res {
+		crate::kprintln!("swapoff: {:?}", errno);
+	}
+}
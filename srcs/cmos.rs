@@ -27,6 +27,29 @@ impl fmt::Display for Time {
 	}
 }
 
+impl Time {
+	/// Convert the date to seconds elapsed since 01/01/1970.
+	/// A null century register is considered as the 21st century
+	pub fn timestamp(&self) -> u32 {
+		const DAYS_BEFORE_MONTH: [u32; 12] =
+			[0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+		let century = match self.century {
+			0 => 20,
+			century => century as u32
+		};
+		let year = century * 100 + self.year as u32;
+		let leap = |y: u32| (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+		let mut days = (1970..year).map(|y| 365 + leap(y) as u32).sum::<u32>();
+		let month = (self.month.clamp(1, 12) - 1) as usize;
+		days += DAYS_BEFORE_MONTH[month] + self.day.max(1) as u32 - 1;
+		if month > 1 && leap(year) {
+			days += 1;
+		}
+		((days * 24 + self.hours as u32) * 60 + self.minutes as u32) * 60
+			+ self.seconds as u32
+	}
+}
+
 const CMOS_CMD: u16 = 0x70;
 const CMOS_DATA: u16 = 0x71;
 
@@ -58,3 +81,23 @@ pub fn get_time() -> Time {
 		century: from_bcd!(get_RTC_register(0x32))
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::Time;
+
+	#[sys_macros::test_case]
+	fn test_timestamp() {
+		let time = Time {
+			seconds: 40,
+			minutes: 46,
+			hours:   22,
+			weekday: 0,
+			day:     29,
+			month:   2,
+			year:    24,
+			century: 20
+		};
+		assert_eq!(time.timestamp(), 1709246800);
+	}
+}
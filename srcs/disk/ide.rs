@@ -31,4 +31,8 @@ impl DiskIO for IDEDisk {
 	fn sector_size(&self) -> usize {
 		self.device.sector_size() as usize
 	}
+
+	fn sector_count(&self) -> usize {
+		self.device.size as usize
+	}
 }
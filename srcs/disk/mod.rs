@@ -3,6 +3,7 @@ use crate::alloc::vec::Vec;
 use crate::pci::ide::IDE;
 
 pub mod ide;
+pub mod ramdisk;
 use ide::IDEDisk;
 
 pub trait DiskIO {
@@ -16,6 +17,9 @@ pub trait DiskIO {
 	) -> Result<(), u8>;
 
 	fn sector_size(&self) -> usize;
+
+	/// Size of the disk in sectors
+	fn sector_count(&self) -> usize;
 }
 
 pub fn discover() -> Vec<Box<dyn DiskIO + Send>> {
@@ -0,0 +1,94 @@
+use super::DiskIO;
+use crate::vec::Vec;
+
+const SECTOR_SIZE: usize = 512;
+
+/// Disk stored in memory, its content is lost once dropped.
+/// Mostly useful to build filesystems without touching real disks, in tests for example
+pub struct RamDisk {
+	data: Vec<u8>
+}
+
+impl RamDisk {
+	/// Create a zeroed disk of size bytes, rounded up to a whole sector
+	pub fn new(size: usize) -> Self {
+		let sectors = (size + SECTOR_SIZE - 1) / SECTOR_SIZE;
+		Self { data: crate::vec![0; sectors * SECTOR_SIZE] }
+	}
+
+	/// Byte range of numsects sectors starting at lba, same error as IDE disks when the range
+	/// goes past the end of the disk
+	fn range(
+		&self,
+		numsects: u8,
+		lba: u32
+	) -> Result<core::ops::Range<usize>, u8> {
+		let start = lba as usize * SECTOR_SIZE;
+		let end = start + numsects as usize * SECTOR_SIZE;
+		match end <= self.data.len() {
+			true => Ok(start..end),
+			false => Err(0x2)
+		}
+	}
+}
+
+impl DiskIO for RamDisk {
+	fn read_sectors(&self, numsects: u8, lba: u32, edi: u32) -> Result<(), u8> {
+		let range = self.range(numsects, lba)?;
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				self.data[range.clone()].as_ptr(),
+				edi as *mut u8,
+				range.len()
+			);
+		}
+		Ok(())
+	}
+
+	fn write_sectors(
+		&mut self,
+		numsects: u8,
+		lba: u32,
+		edi: u32
+	) -> Result<(), u8> {
+		let range = self.range(numsects, lba)?;
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				edi as *const u8,
+				self.data[range.clone()].as_mut_ptr(),
+				range.len()
+			);
+		}
+		Ok(())
+	}
+
+	fn sector_size(&self) -> usize {
+		SECTOR_SIZE
+	}
+
+	fn sector_count(&self) -> usize {
+		self.data.len() / SECTOR_SIZE
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::RamDisk;
+	use crate::disk::DiskIO;
+	use crate::vec;
+
+	#[sys_macros::test_case]
+	fn ramdisk_read_write_sectors() {
+		let mut disk = RamDisk::new(2048);
+		let to_write = vec![0x42u8; 1024];
+		let read_from = vec![0x0u8; 1024];
+		assert_eq!(disk.sector_count(), 4);
+		assert!(disk.write_sectors(2, 1, to_write.as_ptr() as u32).is_ok());
+		assert!(disk.read_sectors(2, 1, read_from.as_ptr() as u32).is_ok());
+		assert_eq!(to_write, read_from);
+		assert_eq!(
+			disk.read_sectors(2, 3, read_from.as_ptr() as u32),
+			Err(0x2)
+		);
+	}
+}
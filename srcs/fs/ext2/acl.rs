@@ -0,0 +1,357 @@
+//! POSIX access control lists, stored in the system.posix_acl_access and
+//! system.posix_acl_default extended attributes.
+//!
+//! Ext2 stores them in a compact format where entries without an id are only 4 bytes long,
+//! the xattr interface uses the format of the linux posix_acl_xattr header.
+
+use super::inode::Inode;
+use super::perm::{Context, MAY_EXEC, MAY_READ, MAY_WRITE};
+use crate::errno::ErrNo;
+use crate::vec::Vec;
+
+// Entry tags
+pub const ACL_USER_OBJ: u16 = 0x01;
+pub const ACL_USER: u16 = 0x02;
+pub const ACL_GROUP_OBJ: u16 = 0x04;
+pub const ACL_GROUP: u16 = 0x08;
+pub const ACL_MASK: u16 = 0x10;
+pub const ACL_OTHER: u16 = 0x20;
+
+const EXT2_ACL_VERSION: u32 = 0x0001;
+const XATTR_ACL_VERSION: u32 = 0x0002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+	pub tag:  u16,
+	/// Combination of MAY_READ, MAY_WRITE and MAY_EXEC
+	pub perm: u16,
+	/// User or group id of ACL_USER and ACL_GROUP entries
+	pub id:   u32
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+	pub entries: Vec<AclEntry>
+}
+
+impl Acl {
+	/// Parse the ext2 on-disk format
+	pub fn from_disk(buffer: &[u8]) -> Result<Self, ErrNo> {
+		if buffer.len() < 4 || le32(buffer, 0) != EXT2_ACL_VERSION {
+			return Err(ErrNo::EINVAL);
+		}
+		let mut entries = Vec::new();
+		let mut offset = 4;
+		while offset < buffer.len() {
+			if offset + 4 > buffer.len() {
+				return Err(ErrNo::EINVAL);
+			}
+			let tag = le16(buffer, offset);
+			let perm = le16(buffer, offset + 2);
+			let id = match tag {
+				ACL_USER | ACL_GROUP if offset + 8 <= buffer.len() => {
+					offset += 8;
+					le32(buffer, offset - 4)
+				},
+				ACL_USER | ACL_GROUP => return Err(ErrNo::EINVAL),
+				_ => {
+					offset += 4;
+					u32::MAX
+				}
+			};
+			entries.push(AclEntry { tag, perm, id });
+		}
+		let acl = Self { entries };
+		acl.check()?;
+		Ok(acl)
+	}
+
+	/// Serialize to the ext2 on-disk format
+	pub fn to_disk(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		v.extend_from_slice(&EXT2_ACL_VERSION.to_le_bytes());
+		for entry in self.entries.iter() {
+			v.extend_from_slice(&entry.tag.to_le_bytes());
+			v.extend_from_slice(&entry.perm.to_le_bytes());
+			if entry.tag == ACL_USER || entry.tag == ACL_GROUP {
+				v.extend_from_slice(&entry.id.to_le_bytes());
+			}
+		}
+		v
+	}
+
+	/// Parse the format used by getxattr and setxattr
+	pub fn from_xattr(buffer: &[u8]) -> Result<Self, ErrNo> {
+		if buffer.len() < 4
+			|| (buffer.len() - 4) % 8 != 0
+			|| le32(buffer, 0) != XATTR_ACL_VERSION
+		{
+			return Err(ErrNo::EINVAL);
+		}
+		let entries = buffer[4..]
+			.chunks(8)
+			.map(|entry| AclEntry {
+				tag:  le16(entry, 0),
+				perm: le16(entry, 2),
+				id:   match le16(entry, 0) {
+					ACL_USER | ACL_GROUP => le32(entry, 4),
+					_ => u32::MAX
+				}
+			})
+			.collect();
+		let acl = Self { entries };
+		acl.check()?;
+		Ok(acl)
+	}
+
+	/// Serialize to the format used by getxattr and setxattr
+	pub fn to_xattr(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		v.extend_from_slice(&XATTR_ACL_VERSION.to_le_bytes());
+		for entry in self.entries.iter() {
+			v.extend_from_slice(&entry.tag.to_le_bytes());
+			v.extend_from_slice(&entry.perm.to_le_bytes());
+			v.extend_from_slice(&entry.id.to_le_bytes());
+		}
+		v
+	}
+
+	/// An ACL needs exactly one owner, group and other entry, and a mask when it has named
+	/// users or groups. The mask is optional otherwise
+	fn check(&self) -> Result<(), ErrNo> {
+		let count =
+			|tag: u16| self.entries.iter().filter(|x| x.tag == tag).count();
+		let named = count(ACL_USER) + count(ACL_GROUP);
+		let valid = count(ACL_USER_OBJ) == 1
+			&& count(ACL_GROUP_OBJ) == 1
+			&& count(ACL_OTHER) == 1
+			&& (named == 0 || count(ACL_MASK) == 1)
+			&& count(ACL_MASK) <= 1
+			&& named + count(ACL_MASK) + 3 == self.entries.len()
+			&& self.entries.iter().all(|x| x.perm & !0o7 == 0);
+		match valid {
+			true => Ok(()),
+			false => Err(ErrNo::EINVAL)
+		}
+	}
+
+	fn get(&self, tag: u16) -> Option<&AclEntry> {
+		self.entries.iter().find(|x| x.tag == tag)
+	}
+
+	fn get_mut(&mut self, tag: u16) -> Option<&mut AclEntry> {
+		self.entries.iter_mut().find(|x| x.tag == tag)
+	}
+
+	/// An ACL with only the owner, group and other entries is the same as permission bits
+	pub fn is_minimal(&self) -> bool {
+		self.entries.len() == 3
+	}
+
+	/// Permission bits matching the ACL, the mask takes the place of the group class
+	pub fn mode(&self) -> u16 {
+		let perm = |tag| self.get(tag).map_or(0, |x| x.perm);
+		let group = match self.get(ACL_MASK) {
+			Some(mask) => mask.perm,
+			None => perm(ACL_GROUP_OBJ)
+		};
+		perm(ACL_USER_OBJ) << 6 | group << 3 | perm(ACL_OTHER)
+	}
+
+	/// Update the ACL after the permission bits changed to mode
+	pub fn chmod(&mut self, mode: u16) {
+		if let Some(entry) = self.get_mut(ACL_USER_OBJ) {
+			entry.perm = mode >> 6 & 0o7;
+		}
+		let group = match self.get(ACL_MASK).is_some() {
+			true => ACL_MASK,
+			false => ACL_GROUP_OBJ
+		};
+		if let Some(entry) = self.get_mut(group) {
+			entry.perm = mode >> 3 & 0o7;
+		}
+		if let Some(entry) = self.get_mut(ACL_OTHER) {
+			entry.perm = mode & 0o7;
+		}
+	}
+
+	/// Restrict an ACL inherited from a default ACL to the permission bits mode a file is
+	/// created with, like linux posix_acl_create_masq. The mask takes the place of the group
+	/// class when there is one. Return the permission bits matching the new ACL
+	pub fn create_masq(&mut self, mode: u16) -> u16 {
+		let has_mask = self.get(ACL_MASK).is_some();
+		for entry in self.entries.iter_mut() {
+			let shift = match entry.tag {
+				ACL_USER_OBJ => 6,
+				ACL_MASK => 3,
+				ACL_GROUP_OBJ if !has_mask => 3,
+				ACL_OTHER => 0,
+				_ => continue
+			};
+			entry.perm &= mode >> shift & 0o7;
+		}
+		self.mode()
+	}
+
+	/// Check that mask rights are granted to ctx on inode by the ACL, like linux
+	/// posix_acl_permission. The first entry matching the caller is used, named entries and
+	/// groups are limited by the mask
+	pub fn permission(
+		&self,
+		ctx: &Context,
+		inode: &Inode,
+		mask: u16
+	) -> Result<(), ErrNo> {
+		let mask = mask & (MAY_READ | MAY_WRITE | MAY_EXEC);
+		let acl_mask = self.get(ACL_MASK).map_or(0o7, |x| x.perm);
+		let granted = |perm: u16| match perm & mask == mask {
+			true => Ok(()),
+			false => Err(ErrNo::EACCES)
+		};
+		let mut group_found = false;
+		for entry in self.entries.iter() {
+			match entry.tag {
+				ACL_USER_OBJ if ctx.uid == inode.uid => {
+					return granted(entry.perm)
+				},
+				ACL_USER if ctx.uid as u32 == entry.id => {
+					return granted(entry.perm & acl_mask)
+				},
+				ACL_GROUP_OBJ | ACL_GROUP => {
+					let gid = match entry.tag {
+						ACL_GROUP_OBJ => inode.gid as u32,
+						_ => entry.id
+					};
+					if ctx.gid as u32 == gid {
+						group_found = true;
+						if granted(entry.perm & acl_mask).is_ok() {
+							return Ok(());
+						}
+					}
+				},
+				_ => {}
+			}
+		}
+		match group_found {
+			true => Err(ErrNo::EACCES),
+			false => granted(self.get(ACL_OTHER).map_or(0, |x| x.perm))
+		}
+	}
+}
+
+fn le16(buffer: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(buffer[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(buffer: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		Acl,
+		AclEntry,
+		ACL_GROUP_OBJ,
+		ACL_MASK,
+		ACL_OTHER,
+		ACL_USER,
+		ACL_USER_OBJ
+	};
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::inode::{Inode, ITYPE_REGU};
+	use crate::fs::ext2::perm::{Context, MAY_READ, MAY_WRITE};
+
+	fn entry(tag: u16, perm: u16, id: u32) -> AclEntry {
+		AclEntry { tag, perm, id }
+	}
+
+	#[sys_macros::test_case]
+	fn acl_formats_roundtrip() {
+		let acl = Acl {
+			entries: crate::vec![
+				entry(ACL_USER_OBJ, 0o6, u32::MAX),
+				entry(ACL_USER, 0o4, 1000),
+				entry(ACL_GROUP_OBJ, 0o4, u32::MAX),
+				entry(ACL_MASK, 0o4, u32::MAX),
+				entry(ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		let disk = acl.to_disk();
+		// Only named entries carry an id
+		assert_eq!(disk.len(), 4 + 4 * 4 + 8);
+		assert_eq!(Acl::from_disk(&disk), Ok(acl.clone()));
+		assert_eq!(Acl::from_xattr(&acl.to_xattr()), Ok(acl.clone()));
+		assert_eq!(acl.mode(), 0o640);
+		// Named users need a mask
+		let mut invalid = acl.clone();
+		invalid.entries.remove(3);
+		assert_eq!(Acl::from_disk(&invalid.to_disk()), Err(ErrNo::EINVAL));
+		// A mask is allowed without named entries
+		let masked = Acl {
+			entries: crate::vec![
+				entry(ACL_USER_OBJ, 0o6, u32::MAX),
+				entry(ACL_GROUP_OBJ, 0o6, u32::MAX),
+				entry(ACL_MASK, 0o4, u32::MAX),
+				entry(ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		assert_eq!(Acl::from_disk(&masked.to_disk()), Ok(masked.clone()));
+		assert_eq!(Acl::from_xattr(&masked.to_xattr()), Ok(masked.clone()));
+		assert!(!masked.is_minimal());
+		assert_eq!(masked.mode(), 0o640);
+	}
+
+	#[sys_macros::test_case]
+	fn acl_create_masq() {
+		let mut acl = Acl {
+			entries: crate::vec![
+				entry(ACL_USER_OBJ, 0o7, u32::MAX),
+				entry(ACL_USER, 0o7, 1000),
+				entry(ACL_GROUP_OBJ, 0o5, u32::MAX),
+				entry(ACL_MASK, 0o7, u32::MAX),
+				entry(ACL_OTHER, 0o5, u32::MAX),
+			]
+		};
+		assert_eq!(acl.create_masq(0o640), 0o640);
+		// Named entries and the owning group are only limited by the mask
+		assert_eq!(acl.get(ACL_USER).unwrap().perm, 0o7);
+		assert_eq!(acl.get(ACL_GROUP_OBJ).unwrap().perm, 0o5);
+		assert_eq!(acl.get(ACL_MASK).unwrap().perm, 0o4);
+		acl.entries
+			.retain(|x| x.tag != ACL_USER && x.tag != ACL_MASK);
+		assert_eq!(acl.create_masq(0o600), 0o600);
+		assert_eq!(acl.get(ACL_GROUP_OBJ).unwrap().perm, 0);
+	}
+
+	#[sys_macros::test_case]
+	fn acl_permission() {
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_REGU | 0o640;
+		(inode.uid, inode.gid) = (1000, 100);
+		let mut acl = Acl {
+			entries: crate::vec![
+				entry(ACL_USER_OBJ, 0o6, u32::MAX),
+				entry(ACL_USER, 0o6, 1001),
+				entry(ACL_GROUP_OBJ, 0o4, u32::MAX),
+				entry(ACL_MASK, 0o4, u32::MAX),
+				entry(ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		let named = Context { uid: 1001, gid: 1001, ..Context::new(2) };
+		let other = Context { uid: 1002, gid: 1002, ..Context::new(2) };
+		assert!(acl.permission(&named, &inode, MAY_READ).is_ok());
+		// The mask limits named users
+		assert_eq!(
+			acl.permission(&named, &inode, MAY_WRITE),
+			Err(ErrNo::EACCES)
+		);
+		assert_eq!(
+			acl.permission(&other, &inode, MAY_READ),
+			Err(ErrNo::EACCES)
+		);
+		acl.chmod(0o664);
+		assert!(acl.permission(&named, &inode, MAY_WRITE).is_ok());
+		assert!(acl.permission(&other, &inode, MAY_READ).is_ok());
+	}
+}
@@ -4,56 +4,56 @@
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct BaseSuperblock {
 	/// Total number of inodes in file system
-	inode_count:          u32,
+	pub(super) inode_count:   u32,
 	/// Total number of blocks in file system
-	blocks_count:         u32,
+	pub(super) blocks_count:  u32,
 	/// Number of blocks reserved for superuser (see offset 80)
-	rblocks_num:          u32,
+	pub(super) rblocks_num:   u32,
 	/// Total number of unallocated blocks
-	pub blocks_unalloc:   u32,
+	pub blocks_unalloc:       u32,
 	/// Total number of unallocated inodes
-	pub inode_unalloc:    u32,
+	pub inode_unalloc:        u32,
 	/// Block number of the block containing the superblock (also the starting block number, NOT always zero.)
-	pub superblock_block: u32,
+	pub superblock_block:     u32,
 	/// log2 (block size) - 10. (In other words, the number to shift 1,024 to the left by to obtain the block size)
-	block_size:           u32,
+	pub(super) block_size:    u32,
 	/// log2 (fragment size) - 10. (In other words, the number to shift 1,024 to the left by to obtain the fragment size)
-	frag_size:            u32,
+	pub(super) frag_size:     u32,
 	/// Number of blocks in each block group
-	bgroup_bno:           u32,
+	pub(super) bgroup_bno:    u32,
 	/// Number of fragments in each block group
-	bgroup_fno:           u32,
+	pub(super) bgroup_fno:    u32,
 	/// Number of inodes in each block group
-	bgroup_ino:           u32,
+	pub(super) bgroup_ino:    u32,
 	/// Last mount time (in POSIX time)
-	last_mt:              u32,
+	pub(super) last_mt:       u32,
 	/// Last written time (in POSIX time)
-	last_wt:              u32,
+	pub(super) last_wt:       u32,
 	/// Number of times the volume has been mounted since its last consistency check (fsck)
-	mount_no:             u16,
+	pub(super) mount_no:      u16,
 	/// Number of mounts allowed before a consistency check (fsck) must be done
-	mount_no_max:         u16,
+	pub(super) mount_no_max:  u16,
 	/// Ext2 signature (0xef53), used to help confirm the presence of Ext2 on a volume
-	ext2_sig:             u16,
+	pub(super) ext2_sig:      u16,
 	/// File system state (see below)
-	fs_state:             u16,
+	pub(super) fs_state:      u16,
 	/// What to do when an error is detected (see below)
-	err_handle:           u16,
+	pub(super) err_handle:    u16,
 	/// Minor portion of version (combine with Major portion below to construct full version field)
-	minor:                u16,
+	pub(super) minor:         u16,
 	/// POSIX time of last consistency check (fsck)
-	last_fsck:            u32,
+	pub(super) last_fsck:     u32,
 	/// Interval (in POSIX time) between forced consistency checks (fsck)
-	fsck_interval:        u32,
+	pub(super) fsck_interval: u32,
 	/// Operating system ID from which the filesystem on this volume was created (see below)
-	osid:                 u32,
+	pub(super) osid:          u32,
 	/// Major portion of version (combine with Minor portion above to construct full version field)
-	major:                u32,
+	pub(super) major:         u32,
 	/// User ID that can use reserved blocks
-	pub uid:              u16,
+	pub uid:                  u16,
 	/// Group ID that can use reserved blocks
-	pub gid:              u16,
-	extension:            Option<ExtendedSuperblock>
+	pub gid:                  u16,
+	pub(super) extension:     Option<ExtendedSuperblock>
 }
 
 impl BaseSuperblock {
@@ -99,6 +99,146 @@ impl BaseSuperblock {
 			+ (self.blocks_count % self.bgroup_bno != 0) as u32
 	}
 
+	/// First inode that is not reserved, fixed to 11 before version 1
+	pub fn first_inode(&self) -> u32 {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => ext.first_inode,
+			_ => 11
+		}
+	}
+
+	/// With sparse superblocks only groups 0, 1 and powers of 3, 5 and 7 keep a backup of the
+	/// superblock and group descriptors
+	pub fn sparse_super(&self) -> bool {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => ext.ro_features & ROFEAT_SPARS != 0,
+			_ => false
+		}
+	}
+
+	/// Directory entries carry the file type in the high byte of their name length
+	pub fn has_filetype(&self) -> bool {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => {
+				ext.req_features & REQFEAT_DE_TYPEFIELD != 0
+			},
+			_ => false
+		}
+	}
+
+	/// Regular files can use size_uh to grow beyond 2GiB
+	pub fn large_file(&self) -> bool {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => ext.ro_features & ROFEAT_64B != 0,
+			_ => false
+		}
+	}
+
+	/// Flag the filesystem as holding files larger than 2GiB.
+	/// Does nothing on revision 0 filesystems as they have no feature fields
+	pub fn set_large_file(&mut self) {
+		if let Some(ext) = self.extension.as_mut() {
+			ext.ro_features |= ROFEAT_64B;
+		}
+	}
+
+	/// Inode of the journal if the filesystem has one stored inside it, external journals are
+	/// not supported
+	pub fn journal_inode(&self) -> Option<u32> {
+		match &self.extension {
+			Some(ext)
+				if self.major >= 1
+					&& ext.opt_features & OPTFEAT_JOURN != 0
+					&& ext.journ_inode != 0 =>
+			{
+				Some(ext.journ_inode)
+			},
+			_ => None
+		}
+	}
+
+	/// The journal holds transactions that must be replayed before using the filesystem
+	pub fn needs_recovery(&self) -> bool {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => {
+				ext.req_features & REQFEAT_REPLAY_JOURN != 0
+			},
+			_ => false
+		}
+	}
+
+	pub fn set_needs_recovery(&mut self, needed: bool) {
+		if let Some(ext) = self.extension.as_mut() {
+			match needed {
+				true => ext.req_features |= REQFEAT_REPLAY_JOURN,
+				false => ext.req_features &= !REQFEAT_REPLAY_JOURN
+			}
+		}
+	}
+
+	/// Record the journal stored in inode
+	pub fn set_journal(&mut self, inode: u32) {
+		if let Some(ext) = self.extension.as_mut() {
+			ext.opt_features |= OPTFEAT_JOURN;
+			ext.journ_inode = inode;
+		}
+	}
+
+	/// Directories may be indexed by hashes of their entry names
+	pub fn dir_index(&self) -> bool {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => {
+				ext.opt_features & OPTFEAT_HASH_INDEX != 0
+			},
+			_ => false
+		}
+	}
+
+	pub fn set_dir_index(&mut self) {
+		if let Some(ext) = self.extension.as_mut() {
+			ext.opt_features |= OPTFEAT_HASH_INDEX;
+		}
+	}
+
+	/// Required and read-only compatible features set on the filesystem that this driver
+	/// doesn't implement. It can't be mounted with unknown required features and is only
+	/// mounted read-only with unknown read-only compatible features
+	pub fn unsupported_features(&self) -> (u32, u32) {
+		match &self.extension {
+			Some(ext) if self.major >= 1 => (
+				ext.req_features & !REQFEAT_SUPPORTED,
+				ext.ro_features & !ROFEAT_SUPPORTED
+			),
+			_ => (0, 0)
+		}
+	}
+
+	/// Check if group keeps a copy of the superblock and group descriptors
+	pub fn has_super(&self, group: usize) -> bool {
+		let is_power_of = |base: usize| {
+			let mut n = base;
+			while n < group {
+				n *= base;
+			}
+			n == group
+		};
+		group <= 1
+			|| !self.sparse_super()
+			|| is_power_of(3)
+			|| is_power_of(5)
+			|| is_power_of(7)
+	}
+
+	/// Record a consistency check done at time, the state tells if errors are left
+	pub fn mark_checked(&mut self, time: u32, clean: bool) {
+		self.last_fsck = time;
+		self.mount_no = 0;
+		self.fs_state = match clean {
+			true => FSSTATE_CLEAN,
+			false => FSSTATE_ERROR
+		};
+	}
+
 	pub fn set_extension(&mut self, extension: ExtendedSuperblock) {
 		self.extension = Some(extension);
 	}
@@ -220,6 +360,9 @@ impl BaseSuperblock {
 		vec.extend_from_slice(&self.major.to_le_bytes());
 		vec.extend_from_slice(&self.uid.to_le_bytes());
 		vec.extend_from_slice(&self.gid.to_le_bytes());
+		if let Some(extension) = &self.extension {
+			vec.extend_from_slice(&extension.into_boxed_slice());
+		}
 
 		vec.into_boxed_slice()
 	}
@@ -247,10 +390,10 @@ Last check time: {lct},
 	}
 }
 
-const FSSTATE_CLEAN: u16 = 1;
+pub const FSSTATE_CLEAN: u16 = 1;
 const FSSTATE_ERROR: u16 = 2;
 
-const FSERROR_IGN: u16 = 1;
+pub const FSERROR_IGN: u16 = 1;
 const FSERROR_MRO: u16 = 2;
 const FSERROR_KPAN: u16 = 3;
 
@@ -265,39 +408,39 @@ const FSCREAT_OTHER: u16 = 4;
 #[derive(Debug, PartialEq, Eq)]
 pub struct ExtendedSuperblock {
 	/// First non-reserved inode in file system. (In versions < 1.0, this is fixed as 11)
-	first_inode:           u32,
+	pub(super) first_inode:           u32,
 	/// Size of each inode structure in bytes. (In versions < 1.0, this is fixed as 128)
-	inode_size:            u16,
+	pub(super) inode_size:            u16,
 	/// Block group that this superblock is part of (if backup copy)
-	bgroup_superblock:     u16,
+	pub(super) bgroup_superblock:     u16,
 	/// Optional features present (features that are not required to read or write, but usually result in a performance increase. see below)
-	opt_features:          u32,
+	pub(super) opt_features:          u32,
 	/// Required features present (features that are required to be supported to read or write. see below)
-	req_features:          u32,
+	pub(super) req_features:          u32,
 	/// Features that if not supported, the volume must be mounted read-only see below)
-	ro_features:           u32,
+	pub(super) ro_features:           u32,
 	/// File system ID (what is output by blkid)
-	fsid:                  [u8; 16],
+	pub(super) fsid:                  [u8; 16],
 	/// Volume name (C-style string: characters terminated by a 0 byte)
-	vol_name:              [u8; 16],
+	pub(super) vol_name:              [u8; 16],
 	/// Path volume was last mounted to (C-style string: characters terminated by a 0 byte)
-	last_path:             [u8; 64],
+	pub(super) last_path:             [u8; 64],
 	/// Compression algorithms used (see Required features above)
-	compr:                 u32,
+	pub(super) compr:                 u32,
 	/// Number of blocks to preallocate for files
-	prealloc_blocks_files: u8,
+	pub(super) prealloc_blocks_files: u8,
 	/// Number of blocks to preallocate for directories
-	prealloc_block_dir:    u8,
+	pub(super) prealloc_block_dir:    u8,
 	/// (Unused)
-	unused:                u16,
+	pub(super) unused:                u16,
 	/// Journal ID (same style as the File system ID above)
-	journ_id:              [u8; 16],
+	pub(super) journ_id:              [u8; 16],
 	/// Journal inode
-	journ_inode:           u32,
+	pub(super) journ_inode:           u32,
 	/// Journal device
-	journ_dev:             u32,
+	pub(super) journ_dev:             u32,
 	/// Head of orphan inode list
-	orphan_inode_lst:      u32
+	pub(super) orphan_inode_lst:      u32
 }
 
 impl From<&[u8]> for ExtendedSuperblock {
@@ -332,7 +475,9 @@ impl From<&[u8]> for ExtendedSuperblock {
 			),
 			prealloc_blocks_files: buffer[120],
 			prealloc_block_dir:    buffer[121],
-			unused:                0x0,
+			unused:                u16::from_le_bytes(
+				buffer[122..124].try_into().unwrap()
+			),
 			journ_id:              [0; 16],
 			journ_inode:           u32::from_le_bytes(
 				buffer[140..144].try_into().unwrap()
@@ -353,6 +498,32 @@ impl From<&[u8]> for ExtendedSuperblock {
 	}
 }
 
+impl ExtendedSuperblock {
+	pub fn into_boxed_slice(&self) -> crate::alloc::boxed::Box<[u8]> {
+		let mut vec = crate::alloc::vec::Vec::new();
+
+		vec.extend_from_slice(&self.first_inode.to_le_bytes());
+		vec.extend_from_slice(&self.inode_size.to_le_bytes());
+		vec.extend_from_slice(&self.bgroup_superblock.to_le_bytes());
+		vec.extend_from_slice(&self.opt_features.to_le_bytes());
+		vec.extend_from_slice(&self.req_features.to_le_bytes());
+		vec.extend_from_slice(&self.ro_features.to_le_bytes());
+		vec.extend_from_slice(&self.fsid);
+		vec.extend_from_slice(&self.vol_name);
+		vec.extend_from_slice(&self.last_path);
+		vec.extend_from_slice(&self.compr.to_le_bytes());
+		vec.push(self.prealloc_blocks_files);
+		vec.push(self.prealloc_block_dir);
+		vec.extend_from_slice(&self.unused.to_le_bytes());
+		vec.extend_from_slice(&self.journ_id);
+		vec.extend_from_slice(&self.journ_inode.to_le_bytes());
+		vec.extend_from_slice(&self.journ_dev.to_le_bytes());
+		vec.extend_from_slice(&self.orphan_inode_lst.to_le_bytes());
+
+		vec.into_boxed_slice()
+	}
+}
+
 const OPTFEAT_PREALLOC: u32 = 0x0001;
 const OPTFEAT_AFSSERV: u32 = 0x0002;
 const OPTFEAT_JOURN: u32 = 0x0004;
@@ -361,10 +532,14 @@ const OPTFEAT_RESIZE: u32 = 0x0010;
 const OPTFEAT_HASH_INDEX: u32 = 0x0020;
 
 const REQFEAT_COMPR: u32 = 0x0001;
-const REQFEAT_DE_TYPEFIELD: u32 = 0x0002;
+pub const REQFEAT_DE_TYPEFIELD: u32 = 0x0002;
 const REQFEAT_REPLAY_JOURN: u32 = 0x0004;
 const REQFEAT_JOURN_DEV: u32 = 0x0008;
 
-const ROFEAT_SPARS: u32 = 0x0001;
-const ROFEAT_64B: u32 = 0x0002;
+pub const ROFEAT_SPARS: u32 = 0x0001;
+pub const ROFEAT_64B: u32 = 0x0002;
 const ROFEAT_DIR_BTRE: u32 = 0x0004;
+
+// Features handled by the driver, optional ones can always be ignored
+const REQFEAT_SUPPORTED: u32 = REQFEAT_DE_TYPEFIELD | REQFEAT_REPLAY_JOURN;
+const ROFEAT_SUPPORTED: u32 = ROFEAT_SPARS | ROFEAT_64B;
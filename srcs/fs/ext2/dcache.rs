@@ -0,0 +1,111 @@
+//! Cache of directory entries found by lookups, keyed by the parent directory inode and the
+//! entry name.
+//!
+//! Only entries that exist are cached. The filesystem drops them when the entry is removed or
+//! replaced, and forgets every entry of a directory when its inode is reused.
+
+use super::inode::Dentry;
+use crate::alloc::collections::btree_map::BTreeMap;
+use crate::string::String;
+use crate::vec::Vec;
+
+/// Number of entries kept, the least recently used one is evicted past it
+const DCACHE_SIZE: usize = 128;
+
+#[derive(Default)]
+pub struct DentryCache {
+	/// Entries with the tick of their last use
+	entries:    BTreeMap<(usize, String), (u64, Dentry)>,
+	tick:       u64,
+	pub hits:   usize,
+	pub misses: usize
+}
+
+impl DentryCache {
+	pub const fn new() -> Self {
+		Self { entries: BTreeMap::new(), tick: 0, hits: 0, misses: 0 }
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Entry name of directory parent if it is cached
+	pub fn get(&mut self, parent: usize, name: &str) -> Option<Dentry> {
+		self.tick += 1;
+		match self.entries.get_mut(&(parent, String::from(name))) {
+			Some((used, dentry)) => {
+				*used = self.tick;
+				self.hits += 1;
+				Some(dentry.clone())
+			},
+			None => {
+				self.misses += 1;
+				None
+			}
+		}
+	}
+
+	pub fn insert(&mut self, parent: usize, dentry: &Dentry) {
+		self.tick += 1;
+		let key = (parent, dentry.name.clone());
+		if self.entries.len() >= DCACHE_SIZE && !self.entries.contains_key(&key)
+		{
+			let oldest = self
+				.entries
+				.iter()
+				.min_by_key(|(_, (used, _))| *used)
+				.map(|(key, _)| key.clone());
+			if let Some(oldest) = oldest {
+				self.entries.remove(&oldest);
+			}
+		}
+		self.entries.insert(key, (self.tick, dentry.clone()));
+	}
+
+	pub fn remove(&mut self, parent: usize, name: &str) {
+		self.entries.remove(&(parent, String::from(name)));
+	}
+
+	/// Drop every entry of directory parent
+	pub fn forget_dir(&mut self, parent: usize) {
+		let keys: Vec<(usize, String)> = self
+			.entries
+			.range((parent, String::new())..)
+			.take_while(|((dir, _), _)| *dir == parent)
+			.map(|(key, _)| key.clone())
+			.collect();
+		keys.iter().for_each(|key| {
+			self.entries.remove(key);
+		});
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{DentryCache, DCACHE_SIZE};
+	use crate::fs::ext2::inode::Dentry;
+
+	#[sys_macros::test_case]
+	fn dcache_evicts_least_recently_used() {
+		let mut cache = DentryCache::new();
+		for i in 0..DCACHE_SIZE {
+			let name = crate::alloc::format!("{}", i);
+			cache.insert(2, &Dentry::new(i as u32 + 11, &name, 1));
+		}
+		assert!(cache.get(2, "0").is_some_and(|x| x.inode == 11));
+		cache.insert(3, &Dentry::new(42, "new", 1));
+		assert_eq!(cache.len(), DCACHE_SIZE);
+		// "0" was used last, "1" is the oldest
+		assert!(cache.get(2, "0").is_some());
+		assert!(cache.get(2, "1").is_none());
+		cache.forget_dir(2);
+		assert_eq!(cache.len(), 1);
+		cache.remove(3, "new");
+		assert_eq!(cache.len(), 0);
+	}
+}
@@ -0,0 +1,672 @@
+//! Consistency check of the filesystem, the in-kernel equivalent of e2fsck.
+//! Every inode and directory is walked to rebuild what the bitmaps, link counts and counters
+//! should be, then the result is compared to what is stored on disk
+
+use super::inode::{self, Dentry, Inode};
+use super::namei::ROOT_INODE;
+use super::perm::current_time;
+use super::Ext2;
+use crate::alloc::collections::btree_map::BTreeMap;
+use crate::string::{String, ToString};
+use crate::vec::Vec;
+use core::fmt;
+
+/// Inconsistency found by fsck
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+	/// An inode points to a block outside of the filesystem
+	BadBlock { inode: usize, block: u32 },
+	/// A block is claimed by several inodes
+	DuplicateBlock { inode: usize, block: u32 },
+	/// A directory entry refers to an unused inode
+	BadEntry {
+		dir:   usize,
+		name:  String,
+		inode: usize
+	},
+	/// A used inode is not referenced by any directory entry
+	Unattached { inode: usize },
+	/// The link count of an inode differs from the number of entries refering to it
+	LinkCount {
+		inode:    usize,
+		found:    u16,
+		expected: u16
+	},
+	/// Number of inodes of a group whose bitmap bit does not match their use
+	InodeBitmap {
+		group:       usize,
+		differences: usize
+	},
+	/// Number of blocks of a group whose bitmap bit does not match their use
+	BlockBitmap {
+		group:       usize,
+		differences: usize
+	},
+	/// A free inodes, free blocks or directories counter of a group is wrong
+	GroupCount {
+		group:    usize,
+		field:    &'static str,
+		found:    u32,
+		expected: u32
+	},
+	/// A free inodes or free blocks counter of the superblock is wrong
+	SuperCount {
+		field:    &'static str,
+		found:    u32,
+		expected: u32
+	}
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Problem::BadBlock { inode, block } => write!(
+				f,
+				"inode {}: block {} is outside of the filesystem",
+				inode, block
+			),
+			Problem::DuplicateBlock { inode, block } => {
+				write!(f, "inode {}: block {} is already used", inode, block)
+			},
+			Problem::BadEntry { dir, name, inode } => write!(
+				f,
+				"directory {}: entry '{}' refers to unused inode {}",
+				dir, name, inode
+			),
+			Problem::Unattached { inode } => {
+				write!(f, "inode {} is not referenced by any directory", inode)
+			},
+			Problem::LinkCount { inode, found, expected } => write!(
+				f,
+				"inode {}: link count is {}, should be {}",
+				inode, found, expected
+			),
+			Problem::InodeBitmap { group, differences } => write!(
+				f,
+				"group {}: {} inodes wrongly marked in bitmap",
+				group, differences
+			),
+			Problem::BlockBitmap { group, differences } => write!(
+				f,
+				"group {}: {} blocks wrongly marked in bitmap",
+				group, differences
+			),
+			Problem::GroupCount { group, field, found, expected } => write!(
+				f,
+				"group {}: {} count is {}, should be {}",
+				group, field, found, expected
+			),
+			Problem::SuperCount { field, found, expected } => write!(
+				f,
+				"superblock: {} count is {}, should be {}",
+				field, found, expected
+			)
+		}
+	}
+}
+
+/// Result of a check, fixed is the number of problems that were repaired
+#[derive(Debug, Default)]
+pub struct Report {
+	pub problems: Vec<Problem>,
+	pub fixed:    usize
+}
+
+impl Report {
+	pub fn is_clean(&self) -> bool {
+		self.problems.is_empty()
+	}
+
+	fn add(&mut self, problem: Problem, fixed: bool) {
+		self.fixed += fixed as usize;
+		self.problems.push(problem);
+	}
+}
+
+/// Blocks of an inode as found by walking its block pointers
+#[derive(Default)]
+struct InodeBlocks {
+	data:     Vec<u32>,
+	indirect: Vec<u32>,
+	/// Pointers outside of the filesystem, they are not followed
+	bad:      Vec<u32>
+}
+
+impl Ext2 {
+	/// Check the consistency of the filesystem.
+	/// Blocks and inodes actually referenced are compared to the bitmaps, link counts to the
+	/// directory entries and group and superblock counters to the bitmaps.
+	/// With repair set, everything but bad and duplicate blocks is fixed on disk.
+	/// Unattached inodes are moved to /lost+found if it exists.
+	pub fn fsck(&mut self, repair: bool) -> Report {
+		let mut report = Report::default();
+		// Repairs can change directories behind the dentry cache
+		if repair {
+			self.dcache.lock().clear();
+		}
+		let mut inodes = self.scan_inodes();
+		let mut refs = self.count_references(&inodes, &mut report, repair);
+		self.check_unattached(&mut inodes, &mut refs, &mut report, repair);
+		self.check_link_counts(&mut inodes, &refs, &mut report, repair);
+		let used = self.used_blocks(&inodes, &mut report);
+		self.check_groups(&inodes, &used, &mut report, repair);
+		if repair {
+			let clean = report.fixed == report.problems.len();
+			self.sblock.mark_checked(current_time(), clean);
+			self.write_superblock();
+		}
+		report
+	}
+
+	/// Read every inode table and keep the inodes in use.
+	/// Reserved inodes are kept if they have a mode, others if they have links
+	fn scan_inodes(&self) -> BTreeMap<usize, Inode> {
+		let mut inodes = BTreeMap::new();
+		let bsize = self.sblock.bsize();
+		let isize = self.inode_size() as usize;
+		let per_group = self.sblock.inode_per_grp() as usize;
+		let count = self.sblock.inode_count() as usize;
+		let first = self.sblock.first_inode() as usize;
+		let table_blocks = roundup_div(per_group * isize, bsize);
+		for group in 0..self.sblock.block_grp_count() as usize {
+			let table = self.get_gdt_entry(group).inode_table;
+			for i in 0..table_blocks {
+				let block = self.read_block(table + i as u32);
+				for (j, raw) in block.chunks_exact(isize).enumerate() {
+					let index = i * (bsize / isize) + j;
+					let inodeno = group * per_group + index + 1;
+					if index >= per_group || inodeno > count {
+						break;
+					}
+					let inode = Inode::from(raw);
+					let used = match inodeno < first {
+						true => inode.tperm != 0,
+						false => inode.count_hl != 0
+					};
+					if used {
+						inodes.insert(inodeno, inode);
+					}
+				}
+			}
+		}
+		inodes
+	}
+
+	/// Walk the block pointers of an inode.
+	/// Devices, fifos, sockets and fast symlinks don't use their pointers as blocks
+	fn inode_blocks(&self, inode: &Inode) -> InodeBlocks {
+		let mut blocks = InodeBlocks::default();
+		let no_blocks = [
+			inode::ITYPE_CHARDEV,
+			inode::ITYPE_BLOCK,
+			inode::ITYPE_FIFO,
+			inode::ITYPE_SOCK
+		];
+		if no_blocks.contains(&inode.file_type()) || self.is_fast_symlink(inode)
+		{
+			return blocks;
+		}
+		for block_no in inode.dbp {
+			self.walk_pointer(block_no, 0, &mut blocks);
+		}
+		self.walk_pointer(inode.sibp, 1, &mut blocks);
+		self.walk_pointer(inode.dibp, 2, &mut blocks);
+		self.walk_pointer(inode.tibp, 3, &mut blocks);
+		blocks
+	}
+
+	/// Add block_no to blocks, depth is the level of indirection of the block
+	fn walk_pointer(
+		&self,
+		block_no: u32,
+		depth: usize,
+		blocks: &mut InodeBlocks
+	) {
+		if block_no == 0 {
+			return;
+		}
+		if !self.is_data_block(block_no) {
+			blocks.bad.push(block_no);
+			return;
+		}
+		if depth == 0 {
+			blocks.data.push(block_no);
+			return;
+		}
+		blocks.indirect.push(block_no);
+		for pointer in self.read_block(block_no).chunks_exact(4) {
+			let pointer = u32::from_le_bytes(pointer.try_into().unwrap());
+			self.walk_pointer(pointer, depth - 1, blocks);
+		}
+	}
+
+	fn is_data_block(&self, block_no: u32) -> bool {
+		block_no >= self.sblock.superblock_block
+			&& block_no < self.sblock.block_count()
+	}
+
+	/// Entries of a directory read through its valid blocks only
+	fn fsck_read_dir(&self, inode: &Inode) -> Vec<Dentry> {
+		self.inode_blocks(inode)
+			.data
+			.iter()
+			.flat_map(|block_no| self.get_dentries(*block_no))
+			.filter(|dentry| dentry.inode != 0)
+			.collect()
+	}
+
+	/// Count the directory entries refering to each inode, '.' and '..' included.
+	/// Entries refering to unused inodes are reported and removed on repair
+	fn count_references(
+		&mut self,
+		inodes: &BTreeMap<usize, Inode>,
+		report: &mut Report,
+		repair: bool
+	) -> Vec<u16> {
+		let mut refs =
+			crate::vec![0u16; self.sblock.inode_count() as usize + 1];
+		for (dir, inode) in inodes.iter().filter(|(_, x)| x.is_dir()) {
+			for dentry in self.fsck_read_dir(inode) {
+				let inodeno = dentry.inode as usize;
+				if inodes.contains_key(&inodeno) {
+					refs[inodeno] = refs[inodeno].saturating_add(1);
+					continue;
+				}
+				let fixed =
+					repair && self.remove_dentry(*dir, &dentry.name).is_ok();
+				let problem = Problem::BadEntry {
+					dir:   *dir,
+					name:  dentry.name,
+					inode: inodeno
+				};
+				report.add(problem, fixed);
+			}
+		}
+		refs
+	}
+
+	/// Report used inodes that no entry refers to.
+	/// On repair they are linked in /lost+found as #inodeno, directories get their '..' updated
+	fn check_unattached(
+		&mut self,
+		inodes: &mut BTreeMap<usize, Inode>,
+		refs: &mut Vec<u16>,
+		report: &mut Report,
+		repair: bool
+	) {
+		let first = self.sblock.first_inode() as usize;
+		let lost_found = self
+			.dentry_find(ROOT_INODE, "lost+found")
+			.map(|dentry| dentry.inode as usize)
+			.filter(|x| inodes.get(x).is_some_and(|inode| inode.is_dir()));
+		let unattached: Vec<usize> = inodes
+			.keys()
+			.filter(|x| (**x >= first || **x == ROOT_INODE) && refs[**x] == 0)
+			.cloned()
+			.collect();
+		for inodeno in unattached {
+			let fixed = match lost_found {
+				Some(lost_found) if repair && inodeno != lost_found => self
+					.reconnect(inodeno, &inodes[&inodeno], lost_found, refs)
+					.is_ok(),
+				_ => false
+			};
+			report.add(Problem::Unattached { inode: inodeno }, fixed);
+		}
+	}
+
+	/// Link inodeno in lost_found, refs is updated with the entries added and removed
+	fn reconnect(
+		&mut self,
+		inodeno: usize,
+		inode: &Inode,
+		lost_found: usize,
+		refs: &mut Vec<u16>
+	) -> Result<(), crate::errno::ErrNo> {
+		let name = ["#", &inodeno.to_string()].concat();
+		let dentry = Dentry::new(inodeno as u32, &name, inode.dtype());
+		self.add_dentry(lost_found, dentry)?;
+		refs[inodeno] += 1;
+		if inode.is_dir() {
+			// A '..' refering to an inode outside of the table is dangling
+			let parent = self.dentry_find(inodeno, "..");
+			let count = parent.and_then(|x| refs.get_mut(x.inode as usize));
+			if let Some(count) = count {
+				*count = count.saturating_sub(1);
+			}
+			let dtype = inode::Dtype::Directory as u8;
+			match self.replace_dentry(inodeno, "..", lost_found, dtype) {
+				Err(crate::errno::ErrNo::ENOENT) => {
+					let dentry = Dentry::new(lost_found as u32, "..", dtype);
+					self.add_dentry(inodeno, dentry)?
+				},
+				res => res?
+			}
+			refs[lost_found] += 1;
+		}
+		Ok(())
+	}
+
+	/// Compare link counts to the number of entries refering to each inode.
+	/// Reserved inodes other than the root are not linked in the tree and are skipped
+	fn check_link_counts(
+		&mut self,
+		inodes: &mut BTreeMap<usize, Inode>,
+		refs: &Vec<u16>,
+		report: &mut Report,
+		repair: bool
+	) {
+		let first = self.sblock.first_inode() as usize;
+		for (inodeno, inode) in inodes.iter_mut() {
+			let expected = refs[*inodeno];
+			if (*inodeno < first && *inodeno != ROOT_INODE)
+				|| expected == 0 || inode.count_hl == expected
+			{
+				continue;
+			}
+			let problem = Problem::LinkCount {
+				inode: *inodeno,
+				found: inode.count_hl,
+				expected
+			};
+			if repair {
+				// Directory times may have changed while repairing entries
+				*inode = self.get_inode_entry(*inodeno);
+				inode.count_hl = expected;
+				self.write_inode(*inodeno, inode);
+			}
+			report.add(problem, repair);
+		}
+	}
+
+	/// Mark every block used by the filesystem metadata and the inodes.
+	/// Blocks outside of the filesystem and blocks used twice are reported
+	fn used_blocks(
+		&self,
+		inodes: &BTreeMap<usize, Inode>,
+		report: &mut Report
+	) -> Vec<bool> {
+		let mut used = crate::vec![false; self.sblock.block_count() as usize];
+		let first = self.sblock.superblock_block as usize;
+		let per_group = self.sblock.block_per_grp() as usize;
+		let bsize = self.sblock.bsize();
+		let groups = self.sblock.block_grp_count() as usize;
+		let gdt_blocks = self.gdt_blocks();
+		let table_blocks = roundup_div(
+			self.sblock.inode_per_grp() as usize * self.inode_size() as usize,
+			bsize
+		);
+		let mut mark = |start: usize, count: usize| {
+			let end = core::cmp::min(start + count, used.len());
+			used[core::cmp::min(start, end)..end].fill(true);
+		};
+		for group in 0..groups {
+			if self.sblock.has_super(group) {
+				mark(first + group * per_group, 1 + gdt_blocks);
+			}
+			let gdt = self.get_gdt_entry(group);
+			mark(gdt.bitmap_block as usize, 1);
+			mark(gdt.bitmap_inode as usize, 1);
+			mark(gdt.inode_table as usize, table_blocks);
+		}
+		for (inodeno, inode) in inodes {
+			let blocks = self.inode_blocks(inode);
+			for block in blocks.bad {
+				report.add(Problem::BadBlock { inode: *inodeno, block }, false);
+			}
+			for block in blocks.data.into_iter().chain(blocks.indirect) {
+				if used[block as usize] {
+					let problem =
+						Problem::DuplicateBlock { inode: *inodeno, block };
+					report.add(problem, false);
+				}
+				used[block as usize] = true;
+			}
+			// Extended attribute blocks can be shared between inodes
+			match inode.facl {
+				0 => {},
+				block if self.is_data_block(block) => {
+					used[block as usize] = true
+				},
+				block => report
+					.add(Problem::BadBlock { inode: *inodeno, block }, false)
+			}
+		}
+		used
+	}
+
+	/// Compare the bitmaps and counters of each group and of the superblock to the inodes and
+	/// blocks actually used
+	fn check_groups(
+		&mut self,
+		inodes: &BTreeMap<usize, Inode>,
+		used: &Vec<bool>,
+		report: &mut Report,
+		repair: bool
+	) {
+		let first_inode = self.sblock.first_inode() as usize;
+		let inode_per_grp = self.sblock.inode_per_grp() as usize;
+		let (mut free_inodes, mut free_blocks) = (0, 0);
+		for group in 0..self.sblock.block_grp_count() as usize {
+			let mut map = self.read_inode_map(group);
+			let (mut differences, mut free, mut dirs) = (0, 0, 0);
+			for bit in 0..inode_per_grp {
+				let inodeno = group * inode_per_grp + bit + 1;
+				let inode = inodes.get(&inodeno);
+				let in_use = inodeno < first_inode || inode.is_some();
+				free += !in_use as u16;
+				dirs += inode.is_some_and(|x| x.is_dir()) as u16;
+				differences += sync_bit(&mut map, bit, in_use);
+			}
+			if differences != 0 {
+				if repair {
+					self.write_inode_map(group, map);
+				}
+				report.add(Problem::InodeBitmap { group, differences }, repair);
+			}
+			let (inodes_free, dirs) = (free, dirs);
+
+			let mut map = self.read_block_map(group);
+			let (mut differences, mut free) = (0, 0);
+			for (bit, in_use) in
+				used[self.group_blocks(group)].iter().enumerate()
+			{
+				free += !in_use as u16;
+				differences += sync_bit(&mut map, bit, *in_use);
+			}
+			if differences != 0 {
+				if repair {
+					self.write_block_map(group, map);
+				}
+				report.add(Problem::BlockBitmap { group, differences }, repair);
+			}
+			let blocks_free = free;
+
+			let mut gdt = self.get_gdt_entry(group);
+			let counters = [
+				("free inodes", gdt.unalloc_inodes, inodes_free),
+				("free blocks", gdt.unalloc_block, blocks_free),
+				("directories", gdt.dir_count, dirs)
+			];
+			for (field, found, expected) in counters {
+				if found != expected {
+					let (found, expected) = (found as u32, expected as u32);
+					let problem =
+						Problem::GroupCount { group, field, found, expected };
+					report.add(problem, repair);
+				}
+			}
+			if repair {
+				gdt.unalloc_inodes = inodes_free;
+				gdt.unalloc_block = blocks_free;
+				gdt.dir_count = dirs;
+				self.write_gdt_entry(group, &gdt);
+			}
+			free_inodes += inodes_free as u32;
+			free_blocks += blocks_free as u32;
+		}
+		let counters = [
+			("free inodes", self.sblock.inode_unalloc, free_inodes),
+			("free blocks", self.sblock.blocks_unalloc, free_blocks)
+		];
+		for (field, found, expected) in counters {
+			if found != expected {
+				report.add(
+					Problem::SuperCount { field, found, expected },
+					repair
+				);
+			}
+		}
+		if repair {
+			self.sblock.inode_unalloc = free_inodes;
+			self.sblock.blocks_unalloc = free_blocks;
+		}
+	}
+
+	/// Blocks belonging to group, the last group may be shorter than the others
+	fn group_blocks(&self, group: usize) -> core::ops::Range<usize> {
+		let per_group = self.sblock.block_per_grp() as usize;
+		let start = self.sblock.superblock_block as usize + group * per_group;
+		let end = start + per_group;
+		start..core::cmp::min(end, self.sblock.block_count() as usize)
+	}
+}
+
+/// Set bit of map to in_use, return 1 if it had to be changed
+fn sync_bit(
+	map: &mut super::bitmap::Bitmap,
+	bit: usize,
+	in_use: bool
+) -> usize {
+	if map.get_node(bit) == in_use {
+		return 0;
+	}
+	match in_use {
+		true => map.set_node(bit),
+		false => map.unset_node(bit)
+	}
+	1
+}
+
+fn roundup_div(value: usize, div: usize) -> usize {
+	(value + div - 1) / div
+}
+
+#[cfg(test)]
+mod test {
+	use super::Problem;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::ext2::perm::Context;
+	use crate::fs::ext2::Ext2;
+
+	/// Path of the entry given to inodeno in /lost+found
+	fn lost_found_path(inodeno: usize) -> crate::string::String {
+		crate::alloc::format!("/lost+found/#{}", inodeno)
+	}
+
+	fn repair(ext2: &mut Ext2) -> super::Report {
+		let report = ext2.fsck(true);
+		assert_eq!(report.fixed, report.problems.len());
+		assert!(ext2.fsck(false).is_clean());
+		report
+	}
+
+	#[sys_macros::test_case]
+	fn fsck_clean_filesystem() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn fsck_reconnect_orphans() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let file = ext2.create("/file", &ctx, 0o644).unwrap();
+		let dir = ext2.mkdir("/dir", &ctx, 0o755).unwrap();
+		assert!(ext2.remove_dentry(2, "file").is_ok());
+		assert!(ext2.remove_dentry(2, "dir").is_ok());
+
+		let report = ext2.fsck(false);
+		assert!(report
+			.problems
+			.contains(&Problem::Unattached { inode: file }));
+		assert!(report
+			.problems
+			.contains(&Problem::Unattached { inode: dir }));
+		assert_eq!(report.fixed, 0);
+
+		repair(&mut ext2);
+		let lost_found = ext2.lookup("/lost+found", &ctx, false).unwrap().0;
+		let found = ext2.lookup(&lost_found_path(file), &ctx, false);
+		assert_eq!(found.map(|x| x.0), Ok(file));
+		let found = ext2.lookup(&lost_found_path(dir), &ctx, false);
+		assert_eq!(found.map(|x| x.0), Ok(dir));
+		let parent = ext2.dentry_find(dir, "..").map(|x| x.inode as usize);
+		assert_eq!(parent, Some(lost_found));
+	}
+
+	#[sys_macros::test_case]
+	fn fsck_reconnect_dangling_parent() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let dir = ext2.mkdir("/dir", &ctx, 0o755).unwrap();
+		let dtype = crate::fs::ext2::inode::Dtype::Directory as u8;
+		assert!(ext2.replace_dentry(dir, "..", 0xfffffff, dtype).is_ok());
+		assert!(ext2.remove_dentry(2, "dir").is_ok());
+
+		let report = ext2.fsck(false);
+		assert!(report
+			.problems
+			.contains(&Problem::Unattached { inode: dir }));
+		repair(&mut ext2);
+		let lost_found = ext2.lookup("/lost+found", &ctx, false).unwrap().0;
+		let parent = ext2.dentry_find(dir, "..").map(|x| x.inode as usize);
+		assert_eq!(parent, Some(lost_found));
+	}
+
+	#[sys_macros::test_case]
+	fn fsck_link_counts() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let file = ext2.create("/file", &ctx, 0o644).unwrap();
+		let mut inode = ext2.get_inode_entry(file);
+		inode.count_hl = 5;
+		ext2.write_inode(file, &inode);
+
+		let expected =
+			Problem::LinkCount { inode: file, found: 5, expected: 1 };
+		assert_eq!(ext2.fsck(false).problems, crate::vec![expected.clone()]);
+		assert_eq!(repair(&mut ext2).problems, crate::vec![expected]);
+		assert_eq!(ext2.get_inode_entry(file).count_hl, 1);
+	}
+
+	#[sys_macros::test_case]
+	fn fsck_rebuild_bitmaps() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let file = ext2.create("/file", &ctx, 0o644).unwrap();
+		let mut blocks = ext2.read_block_map(0);
+		let free = (0..ext2.sblock.block_per_grp() as usize)
+			.find(|x| !blocks.get_node(*x))
+			.unwrap();
+		blocks.set_node(free);
+		ext2.write_block_map(0, blocks);
+		let mut inodes = ext2.read_inode_map(0);
+		inodes.unset_node(file - 1);
+		ext2.write_inode_map(0, inodes);
+
+		let report = ext2.fsck(false);
+		let block_bitmap =
+			Problem::BlockBitmap { group: 0, differences: 1 };
+		let inode_bitmap =
+			Problem::InodeBitmap { group: 0, differences: 1 };
+		assert!(report.problems.contains(&block_bitmap));
+		assert!(report.problems.contains(&inode_bitmap));
+		repair(&mut ext2);
+		assert!(!ext2.read_block_map(0).get_node(free));
+		assert!(ext2.read_inode_map(0).get_node(file - 1));
+	}
+}
@@ -1,6 +1,6 @@
 // Group Descriptor Table
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct GdtEntry {
 	pub bitmap_block:   u32,
 	pub bitmap_inode:   u32,
@@ -35,7 +35,21 @@ impl From<&[u8]> for GdtEntry {
 			dir_count:      u16::from_le_bytes(
 				buffer[16..18].try_into().unwrap()
 			),
-			unused:         [0; 14]
+			unused:         buffer[18..32].try_into().unwrap()
 		}
 	}
 }
+
+impl Into<crate::alloc::vec::Vec<u8>> for &GdtEntry {
+	fn into(self) -> crate::alloc::vec::Vec<u8> {
+		let mut v = crate::alloc::vec::Vec::new();
+		v.extend_from_slice(&self.bitmap_block.to_le_bytes());
+		v.extend_from_slice(&self.bitmap_inode.to_le_bytes());
+		v.extend_from_slice(&self.inode_table.to_le_bytes());
+		v.extend_from_slice(&self.unalloc_block.to_le_bytes());
+		v.extend_from_slice(&self.unalloc_inodes.to_le_bytes());
+		v.extend_from_slice(&self.dir_count.to_le_bytes());
+		v.extend_from_slice(&self.unused);
+		v
+	}
+}
@@ -0,0 +1,780 @@
+//! Hashed directory indexes (htree) of the dir_index feature.
+//!
+//! The first block of an indexed directory holds '.' and '..', the second one spanning the
+//! block so that other drivers read it as a linear directory, followed by the index root.
+//! The index maps ranges of name hashes to leaf blocks holding the entries, through at most
+//! one level of index nodes. Nodes hide behind an unused entry spanning the whole block.
+
+use super::inode::{self, Dentry, Inode};
+use super::Ext2;
+use crate::errno::ErrNo;
+use crate::vec::Vec;
+
+// Hash versions, the unsigned variants are used when the superblock says so
+pub const DX_HASH_LEGACY: u8 = 0;
+pub const DX_HASH_HALF_MD4: u8 = 1;
+pub const DX_HASH_TEA: u8 = 2;
+const DX_HASH_UNSIGNED: u8 = 3;
+
+/// Superblock s_flags bit telling names are hashed as unsigned chars
+const FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+/// Offsets of the hash fields inside the superblock
+const SB_HASH_SEED: usize = 0xec;
+const SB_DEF_HASH_VERSION: usize = 0xfc;
+const SB_FLAGS: usize = 0x160;
+
+/// Offset of the root information, after '.' and the header of '..'
+const ROOT_INFO: usize = 24;
+/// Offset of the index entries of the root and of a node
+const ROOT_ENTRIES: usize = 32;
+const NODE_ENTRIES: usize = 8;
+/// Levels of index nodes below the root
+const MAX_LEVELS: usize = 1;
+
+/// Parameters of the hash of names, from the superblock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HashInfo {
+	pub seed:     [u32; 4],
+	/// Version used for new indexes
+	pub version:  u8,
+	pub unsigned: bool
+}
+
+/// Hash of a name as used by the index, the lowest bit is left clear
+pub fn dirhash(name: &[u8], version: u8, seed: &[u32; 4]) -> u32 {
+	let mut buf = match seed.iter().any(|x| *x != 0) {
+		true => *seed,
+		false => [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]
+	};
+	let unsigned = version >= DX_HASH_UNSIGNED;
+	let hash = match version % DX_HASH_UNSIGNED {
+		DX_HASH_LEGACY => legacy_hash(name, unsigned),
+		DX_HASH_HALF_MD4 => {
+			for chunk in chunks(name, 32) {
+				half_md4_transform(
+					&mut buf,
+					&str2hashbuf::<8>(chunk, unsigned)
+				);
+			}
+			buf[1]
+		},
+		_ => {
+			for chunk in chunks(name, 16) {
+				tea_transform(&mut buf, &str2hashbuf::<4>(chunk, unsigned));
+			}
+			buf[0]
+		}
+	};
+	match hash & !1 {
+		// Reserved for the end of directory in 32 bits readdir cookies
+		0xfffffffe => 0xfffffffc,
+		hash => hash
+	}
+}
+
+/// Name tails starting every size bytes, each chunk hashes the whole remaining length
+fn chunks(name: &[u8], size: usize) -> impl Iterator<Item = &[u8]> {
+	(0..name.len())
+		.step_by(size)
+		.map(move |start| &name[start..])
+}
+
+fn char_value(c: u8, unsigned: bool) -> u32 {
+	match unsigned {
+		true => c as u32,
+		false => c as i8 as i32 as u32
+	}
+}
+
+fn legacy_hash(name: &[u8], unsigned: bool) -> u32 {
+	let (mut hash0, mut hash1): (u32, u32) = (0x12a3fe2d, 0x37abe8f9);
+	for c in name {
+		let mut hash = hash1.wrapping_add(
+			hash0 ^ char_value(*c, unsigned).wrapping_mul(7152373)
+		);
+		if hash & 0x80000000 != 0 {
+			hash = hash.wrapping_sub(0x7fffffff);
+		}
+		(hash1, hash0) = (hash0, hash);
+	}
+	hash0 << 1
+}
+
+/// Pack the name in N words padded with its length, like linux str2hashbuf
+fn str2hashbuf<const N: usize>(msg: &[u8], unsigned: bool) -> [u32; N] {
+	let len = msg.len() as u32;
+	let mut pad = len | len << 8;
+	pad |= pad << 16;
+	let mut buf = [pad; N];
+	let mut val = pad;
+	let len = core::cmp::min(msg.len(), N * 4);
+	for i in 0..len {
+		val = char_value(msg[i], unsigned).wrapping_add(val << 8);
+		if i % 4 == 3 {
+			buf[i / 4] = val;
+			val = pad;
+		}
+	}
+	if len % 4 != 0 {
+		buf[len / 4] = val;
+	}
+	buf
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+	const K2: u32 = 0o13240474631;
+	const K3: u32 = 0o15666365641;
+	let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+	let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+	let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+	let [mut a, mut b, mut c, mut d] = *buf;
+	// Each round updates one word, the four words are rotated between rounds
+	let rounds: [(&dyn Fn(u32, u32, u32) -> u32, u32, [(usize, u32); 8]); 3] = [
+		(
+			&f,
+			0,
+			[
+				(0, 3),
+				(1, 7),
+				(2, 11),
+				(3, 19),
+				(4, 3),
+				(5, 7),
+				(6, 11),
+				(7, 19)
+			]
+		),
+		(
+			&g,
+			K2,
+			[
+				(1, 3),
+				(3, 5),
+				(5, 9),
+				(7, 13),
+				(0, 3),
+				(2, 5),
+				(4, 9),
+				(6, 13)
+			]
+		),
+		(
+			&h,
+			K3,
+			[
+				(3, 3),
+				(7, 9),
+				(2, 11),
+				(6, 15),
+				(1, 3),
+				(5, 9),
+				(0, 11),
+				(4, 15)
+			]
+		)
+	];
+	for (func, k, steps) in rounds.iter() {
+		for (index, shift) in steps.iter() {
+			let x = input[*index].wrapping_add(*k);
+			a = a
+				.wrapping_add(func(b, c, d))
+				.wrapping_add(x)
+				.rotate_left(*shift);
+			(a, b, c, d) = (d, a, b, c);
+		}
+	}
+	buf[0] = buf[0].wrapping_add(a);
+	buf[1] = buf[1].wrapping_add(b);
+	buf[2] = buf[2].wrapping_add(c);
+	buf[3] = buf[3].wrapping_add(d);
+}
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32; 4]) {
+	let (mut sum, mut b0, mut b1): (u32, u32, u32) = (0, buf[0], buf[1]);
+	let [a, b, c, d] = *input;
+	for _ in 0..16 {
+		sum = sum.wrapping_add(0x9e3779b9);
+		b0 = b0.wrapping_add(
+			(b1 << 4).wrapping_add(a)
+				^ b1.wrapping_add(sum)
+				^ (b1 >> 5).wrapping_add(b)
+		);
+		b1 = b1.wrapping_add(
+			(b0 << 4).wrapping_add(c)
+				^ b0.wrapping_add(sum)
+				^ (b0 >> 5).wrapping_add(d)
+		);
+	}
+	buf[0] = buf[0].wrapping_add(b0);
+	buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn le16(buffer: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(buffer[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(buffer: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+/// One index block on the path to a leaf
+#[derive(Debug, Clone)]
+struct DxFrame {
+	block_no: u32,
+	/// Offset of the entries in the block
+	offset:   usize,
+	limit:    usize,
+	/// Lowest hash and directory block of each range, the first hash is implicitly 0
+	entries:  Vec<(u32, u32)>,
+	/// Entry followed to reach the leaf
+	at:       usize
+}
+
+impl DxFrame {
+	/// Parse the entries at offset of block, None if they are inconsistent
+	fn parse(
+		block_no: u32,
+		block: &[u8],
+		offset: usize,
+		limit: usize
+	) -> Option<Self> {
+		let count = le16(block, offset + 2) as usize;
+		if le16(block, offset) as usize != limit || count == 0 || count > limit
+		{
+			return None;
+		}
+		let mut entries = Vec::new();
+		for i in 0..count {
+			let hash = match i {
+				0 => 0,
+				_ => le32(block, offset + 8 * i)
+			};
+			entries.push((hash, le32(block, offset + 8 * i + 4)));
+		}
+		Some(Self { block_no, offset, limit, entries, at: 0 })
+	}
+
+	/// Select the range holding hash
+	fn find(&mut self, hash: u32) {
+		self.at = self.entries.partition_point(|x| x.0 <= hash).max(1) - 1;
+	}
+
+	fn write_to(&self, block: &mut [u8]) {
+		let offset = self.offset;
+		block[offset..offset + 2]
+			.copy_from_slice(&(self.limit as u16).to_le_bytes());
+		block[offset + 2..offset + 4]
+			.copy_from_slice(&(self.entries.len() as u16).to_le_bytes());
+		for (i, (hash, block_no)) in self.entries.iter().enumerate() {
+			if i != 0 {
+				block[offset + 8 * i..offset + 8 * i + 4]
+					.copy_from_slice(&hash.to_le_bytes());
+			}
+			block[offset + 8 * i + 4..offset + 8 * i + 8]
+				.copy_from_slice(&block_no.to_le_bytes());
+		}
+	}
+
+	fn is_full(&self) -> bool {
+		self.entries.len() >= self.limit
+	}
+}
+
+impl Ext2 {
+	/// Read the hash parameters stored in the superblock
+	pub(super) fn read_hash_info(&self) -> HashInfo {
+		let (block_no, offset) = self.superblock_location();
+		let block = self.read_block(block_no);
+		let mut seed = [0; 4];
+		for (i, word) in seed.iter_mut().enumerate() {
+			*word = le32(&block, offset + SB_HASH_SEED + 4 * i);
+		}
+		HashInfo {
+			seed,
+			version: block[offset + SB_DEF_HASH_VERSION],
+			unsigned: le32(&block, offset + SB_FLAGS) & FLAGS_UNSIGNED_HASH
+				!= 0
+		}
+	}
+
+	/// Write the hash parameters to the superblock
+	pub(super) fn write_hash_info(&mut self, info: HashInfo) {
+		let (block_no, offset) = self.superblock_location();
+		let mut block = self.read_block(block_no);
+		for (i, word) in info.seed.iter().enumerate() {
+			let start = offset + SB_HASH_SEED + 4 * i;
+			block[start..start + 4].copy_from_slice(&word.to_le_bytes());
+		}
+		block[offset + SB_DEF_HASH_VERSION] = info.version;
+		let mut flags = le32(&block, offset + SB_FLAGS) & !FLAGS_UNSIGNED_HASH;
+		if info.unsigned {
+			flags |= FLAGS_UNSIGNED_HASH;
+		}
+		block[offset + SB_FLAGS..offset + SB_FLAGS + 4]
+			.copy_from_slice(&flags.to_le_bytes());
+		self.write_block(block_no, &block);
+		self.dx_hash = info;
+	}
+
+	/// Whether the entries of a directory are looked up and added through its index
+	pub fn is_indexed(&self, inode: &Inode) -> bool {
+		self.sblock.dir_index() && inode.flags & inode::IFLAG_HASHINDEX != 0
+	}
+
+	/// Hash name with the version of an index root
+	fn dx_hash(&self, name: &str, version: u8) -> u32 {
+		let version = match self.dx_hash.unsigned {
+			true => version + DX_HASH_UNSIGNED,
+			false => version
+		};
+		dirhash(name.as_bytes(), version, &self.dx_hash.seed)
+	}
+
+	/// Walk the index of a directory down to the leaf that should hold name.
+	/// Return the hash of name and the index blocks followed, None if the index is corrupted
+	fn dx_probe(
+		&self,
+		inode: &Inode,
+		name: &str
+	) -> Option<(u32, Vec<DxFrame>)> {
+		let bsize = self.sblock.bsize();
+		let root_no = self.get_block_at(inode, 0);
+		if root_no == 0 {
+			return None;
+		}
+		let block = self.read_block(root_no);
+		let (version, info_len, levels) = (
+			block[ROOT_INFO + 4],
+			block[ROOT_INFO + 5],
+			block[ROOT_INFO + 6] as usize
+		);
+		if le32(&block, ROOT_INFO) != 0
+			|| version > DX_HASH_TEA
+			|| info_len != 8
+			|| levels > MAX_LEVELS
+		{
+			return None;
+		}
+		let hash = self.dx_hash(name, version);
+		let limit = (bsize - ROOT_ENTRIES) / 8;
+		let mut frame = DxFrame::parse(root_no, &block, ROOT_ENTRIES, limit)?;
+		frame.find(hash);
+		let mut frames = crate::vec![frame];
+		for _ in 0..levels {
+			let parent = frames.last().unwrap();
+			let block_no =
+				self.get_block_at(inode, parent.entries[parent.at].1 as usize);
+			if block_no == 0 {
+				return None;
+			}
+			let block = self.read_block(block_no);
+			// Nodes start with an unused entry spanning the block
+			if le32(&block, 0) != 0 || le16(&block, 4) as usize != bsize {
+				return None;
+			}
+			let limit = (bsize - NODE_ENTRIES) / 8;
+			let mut frame =
+				DxFrame::parse(block_no, &block, NODE_ENTRIES, limit)?;
+			frame.find(hash);
+			frames.push(frame);
+		}
+		Some((hash, frames))
+	}
+
+	/// Move frames to the next leaf if it continues the hash range of the current one,
+	/// colliding hashes can be split over several leaves
+	fn dx_next_leaf(
+		&self,
+		inode: &Inode,
+		frames: &mut [DxFrame],
+		hash: u32
+	) -> bool {
+		let Some(depth) =
+			frames.iter().rposition(|x| x.at + 1 < x.entries.len())
+		else {
+			return false;
+		};
+		let next = frames[depth].entries[frames[depth].at + 1].0;
+		if next & 1 == 0 || next & !1 != hash {
+			return false;
+		}
+		frames[depth].at += 1;
+		for level in depth + 1..frames.len() {
+			let parent = &frames[level - 1];
+			let block_no =
+				self.get_block_at(inode, parent.entries[parent.at].1 as usize);
+			let block = self.read_block(block_no);
+			let limit = frames[level].limit;
+			match DxFrame::parse(block_no, &block, NODE_ENTRIES, limit) {
+				Some(frame) => frames[level] = frame,
+				None => return false
+			}
+		}
+		true
+	}
+
+	/// Leaf blocks of an indexed directory that may hold name, None if the index is
+	/// corrupted
+	pub(super) fn dx_leaf_blocks(
+		&self,
+		inode: &Inode,
+		name: &str
+	) -> Option<Vec<u32>> {
+		let (hash, mut frames) = self.dx_probe(inode, name)?;
+		let mut blocks = Vec::new();
+		loop {
+			let frame = frames.last().unwrap();
+			match self.get_block_at(inode, frame.entries[frame.at].1 as usize) {
+				0 => return None,
+				block_no => blocks.push(block_no)
+			}
+			if !self.dx_next_leaf(inode, &mut frames, hash) {
+				return Some(blocks);
+			}
+		}
+	}
+
+	fn write_dx_frame(&mut self, frame: &DxFrame) {
+		let mut block = self.read_block(frame.block_no);
+		frame.write_to(&mut block);
+		self.write_block(frame.block_no, &block);
+	}
+
+	/// Add an entry to an indexed directory, the leaf is split when it is full.
+	/// Return None if the index is corrupted, the entry is then left to the linear insertion
+	pub(super) fn dx_add_dentry(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode,
+		dentry: &Dentry
+	) -> Option<Result<(), ErrNo>> {
+		let (hash, mut frames) = self.dx_probe(inode, &dentry.name)?;
+		let frame = frames.last().unwrap();
+		let leaf = self.get_block_at(inode, frame.entries[frame.at].1 as usize);
+		if leaf == 0 {
+			return None;
+		}
+		Some(match self.insert_dentry(leaf, dentry) {
+			Ok(true) => Ok(()),
+			Ok(false) => self
+				.dx_split_leaf(inodeno, inode, &mut frames, leaf)
+				.and_then(|(split, new_leaf)| {
+					let target = match hash >= split {
+						true => new_leaf,
+						false => leaf
+					};
+					match self.insert_dentry(target, dentry)? {
+						true => Ok(()),
+						false => Err(ErrNo::ENOSPC)
+					}
+				}),
+			Err(errno) => Err(errno)
+		})
+	}
+
+	/// Move the upper half of the hashes of leaf to a new block added to the index.
+	/// Return the lowest hash moved and the new block
+	fn dx_split_leaf(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode,
+		frames: &mut Vec<DxFrame>,
+		leaf: u32
+	) -> Result<(u32, u32), ErrNo> {
+		let version = self.read_block(frames[0].block_no)[ROOT_INFO + 4];
+		let mut dentries: Vec<(u32, Dentry)> = self
+			.get_dentries(leaf)
+			.into_iter()
+			.filter(|x| x.inode != 0)
+			.map(|x| {
+				(
+					self.dx_hash(&x.name, version),
+					Dentry::new(x.inode, &x.name, x.r#type)
+				)
+			})
+			.collect();
+		if dentries.len() < 2 {
+			return Err(ErrNo::ENOSPC);
+		}
+		dentries.sort_by_key(|x| x.0);
+		// Split by size, each half keeps at least one entry
+		let total: usize =
+			dentries.iter().map(|x| x.1.dentry_size as usize).sum();
+		let mut size = 0;
+		let mut split = dentries
+			.iter()
+			.position(|x| {
+				size += x.1.dentry_size as usize;
+				size > total / 2
+			})
+			.unwrap_or(0);
+		split = split.clamp(1, dentries.len() - 1);
+		self.dx_make_room(inodeno, inode, frames)?;
+		let (index, new_leaf) = self.append_dir_block(inodeno, inode)?;
+		let upper = dentries.split_off(split);
+		let mut split_hash = upper[0].0;
+		// Colliding hashes span both leaves, lookups have to continue to the new one
+		if dentries.last().is_some_and(|x| x.0 == split_hash) {
+			split_hash |= 1;
+		}
+		let unzip =
+			|x: Vec<(u32, Dentry)>| x.into_iter().map(|x| x.1).collect();
+		self.write_dentries(leaf, unzip(dentries))
+			.map_err(|_| ErrNo::EIO)?;
+		self.write_dentries(new_leaf, unzip(upper))
+			.map_err(|_| ErrNo::EIO)?;
+		let frame = frames.last_mut().unwrap();
+		frame
+			.entries
+			.insert(frame.at + 1, (split_hash, index as u32));
+		let frame = frame.clone();
+		self.write_dx_frame(&frame);
+		Ok((split_hash & !1, new_leaf))
+	}
+
+	/// Make sure the last index block of frames has a free entry. A full root gets a level
+	/// of nodes below it, a full node is split in two
+	fn dx_make_room(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode,
+		frames: &mut Vec<DxFrame>
+	) -> Result<(), ErrNo> {
+		let bsize = self.sblock.bsize();
+		if !frames.last().unwrap().is_full() {
+			return Ok(());
+		}
+		// The index can't grow deeper
+		if frames.len() > MAX_LEVELS && frames[0].is_full() {
+			return Err(ErrNo::ENOSPC);
+		}
+		let (index, block_no) = self.append_dir_block(inodeno, inode)?;
+		let mut block = crate::vec![0; bsize];
+		block[4..6].copy_from_slice(&(bsize as u16).to_le_bytes());
+		let mut node = DxFrame {
+			block_no,
+			offset: NODE_ENTRIES,
+			limit: (bsize - NODE_ENTRIES) / 8,
+			entries: Vec::new(),
+			at: 0
+		};
+		if frames.len() == 1 {
+			// The root entries move to a node, the root then only points to it
+			let root = &mut frames[0];
+			node.entries = core::mem::replace(
+				&mut root.entries,
+				crate::vec![(0, index as u32)]
+			);
+			(node.at, root.at) = (root.at, 0);
+			let root = root.clone();
+			let mut root_block = self.read_block(root.block_no);
+			root_block[ROOT_INFO + 6] = 1;
+			root.write_to(&mut root_block);
+			self.write_block(root.block_no, &root_block);
+			node.write_to(&mut block);
+			self.write_block(block_no, &block);
+			frames.push(node);
+			return Ok(());
+		}
+		// The upper half of the full node moves to the new one
+		let full = &mut frames[1];
+		let half = full.entries.len() / 2;
+		node.entries = full.entries.split_off(half);
+		let moved = full.at >= half;
+		if moved {
+			node.at = full.at - half;
+		}
+		let root = &mut frames[0];
+		root.entries
+			.insert(root.at + 1, (node.entries[0].0, index as u32));
+		if moved {
+			root.at += 1;
+		}
+		let (root, full) = (frames[0].clone(), frames[1].clone());
+		self.write_dx_frame(&root);
+		self.write_dx_frame(&full);
+		node.write_to(&mut block);
+		self.write_block(block_no, &block);
+		if moved {
+			frames[1] = node;
+		}
+		Ok(())
+	}
+
+	/// Turn a directory of one full block into an indexed one. Its entries move to a new
+	/// leaf, the first block then holds '.', '..' and the root of the index.
+	/// Return false if the block doesn't start with '.' and '..', it can't hold the root
+	pub(super) fn dx_make_indexed(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode
+	) -> Result<bool, ErrNo> {
+		let bsize = self.sblock.bsize();
+		let root_no = self.get_block_at(inode, 0);
+		let dentries = self.get_dentries(root_no);
+		if dentries.len() < 2
+			|| dentries[0].name != "."
+			|| dentries[1].name != ".."
+		{
+			return Ok(false);
+		}
+		let moved: Vec<Dentry> = dentries[2..]
+			.iter()
+			.filter(|x| x.inode != 0)
+			.map(|x| Dentry::new(x.inode, &x.name, x.r#type))
+			.collect();
+		let (index, leaf) = self.append_dir_block(inodeno, inode)?;
+		if !moved.is_empty() {
+			self.write_dentries(leaf, moved).map_err(|_| ErrNo::EIO)?;
+		} else {
+			let mut block = crate::vec![0; bsize];
+			block[4..6].copy_from_slice(&(bsize as u16).to_le_bytes());
+			self.write_block(leaf, &block);
+		}
+
+		let mut block = crate::vec![0; bsize];
+		let mut dot = dentries[0].clone();
+		dot.dentry_size = 12;
+		let mut dotdot = dentries[1].clone();
+		dotdot.dentry_size = (bsize - 12) as u16;
+		let dot: Vec<u8> = dot.into();
+		let dotdot: Vec<u8> = dotdot.into();
+		block[..dot.len()].copy_from_slice(&dot);
+		block[12..12 + dotdot.len()].copy_from_slice(&dotdot);
+		if !self.sblock.has_filetype() {
+			(block[7], block[19]) = (0, 0);
+		}
+		block[ROOT_INFO + 4] = self.dx_hash.version % DX_HASH_UNSIGNED;
+		block[ROOT_INFO + 5] = 8;
+		let root = DxFrame {
+			block_no: root_no,
+			offset:   ROOT_ENTRIES,
+			limit:    (bsize - ROOT_ENTRIES) / 8,
+			entries:  crate::vec![(0, index as u32)],
+			at:       0
+		};
+		root.write_to(&mut block);
+		self.write_block(root_no, &block);
+		inode.flags |= inode::IFLAG_HASHINDEX;
+		self.write_inode(inodeno, inode);
+		Ok(true)
+	}
+
+	/// Add a block at the end of a directory, through its single indirect block past the
+	/// direct ones. Return its index in the directory and its number
+	pub(super) fn append_dir_block(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode
+	) -> Result<(usize, u32), ErrNo> {
+		let bsize = self.sblock.bsize();
+		let ptrs = bsize / core::mem::size_of::<u32>();
+		let index = inode.size() as usize / bsize;
+		let direct = inode.dbp.len();
+		if index >= direct + ptrs {
+			return Err(ErrNo::ENOSPC);
+		}
+		let group = self.inode_to_bgroup(inodeno as u32) as usize;
+		let block_no = self.alloc_block(group)? as u32;
+		if index < direct {
+			inode.dbp[index] = block_no;
+		} else {
+			if inode.sibp == 0 {
+				match self.alloc_block(group) {
+					Ok(sibp) => inode.sibp = sibp as u32,
+					Err(errno) => {
+						self.free_block(block_no as usize);
+						return Err(errno);
+					}
+				}
+				inode.count_ds += (bsize / 512) as u32;
+			}
+			let offset = (index - direct) * 4;
+			let mut indirect = self.read_block(inode.sibp);
+			indirect[offset..offset + 4]
+				.copy_from_slice(&block_no.to_le_bytes());
+			self.write_block(inode.sibp, &indirect);
+		}
+		inode.size_lh += bsize as u32;
+		inode.count_ds += (bsize / 512) as u32;
+		self.write_inode(inodeno, inode);
+		Ok((index, block_no))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{dirhash, DX_HASH_HALF_MD4, DX_HASH_LEGACY, DX_HASH_TEA};
+	use crate::fs::ext2::inode::IFLAG_HASHINDEX;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::ext2::perm::Context;
+
+	#[sys_macros::test_case]
+	fn dirhash_matches_e2fsprogs() {
+		let seed = [0; 4];
+		let long = b"a_rather_long_name_that_spans_more_than_thirty_two_bytes";
+		assert_eq!(dirhash(b"lost+found", DX_HASH_LEGACY, &seed), 0x5e2aba24);
+		assert_eq!(dirhash(b"lost+found", DX_HASH_HALF_MD4, &seed), 0x591de422);
+		assert_eq!(dirhash(b"lost+found", DX_HASH_TEA, &seed), 0x2dbf9e80);
+		assert_eq!(dirhash(long, DX_HASH_LEGACY, &seed), 0xbf06e2c4);
+		assert_eq!(dirhash(long, DX_HASH_HALF_MD4, &seed), 0x45ed3340);
+		assert_eq!(dirhash(long, DX_HASH_TEA, &seed), 0x47a84dd0);
+		// Bytes above 0x7f are signed chars
+		assert_eq!(
+			dirhash("é".as_bytes(), DX_HASH_HALF_MD4, &seed),
+			0x89d4704e
+		);
+		// Seed of the uuid 01234567-89ab-cdef-0123-456789abcdef
+		let seed = [0x67452301, 0xefcdab89, 0x67452301, 0xefcdab89];
+		assert_eq!(dirhash(b"file0001", DX_HASH_HALF_MD4, &seed), 0x6931c036);
+	}
+
+	#[sys_macros::test_case]
+	fn htree_grows_and_finds_entries() {
+		let options = MkfsOptions {
+			inode_count: Some(160),
+			dir_index: true,
+			..Default::default()
+		};
+		let mut ext2 = ramdisk_fs(&options);
+		let ctx = Context::new(2);
+		ext2.mkdir("/dir", &ctx, 0o755).unwrap();
+		// Enough entries to split leaves several times
+		for i in 0..120 {
+			let path = crate::alloc::format!("/dir/file_with_a_long_name{}", i);
+			assert!(ext2.create(&path, &ctx, 0o644).is_ok());
+		}
+		let (dirno, dir) = ext2.lookup("/dir", &ctx, true).unwrap();
+		assert!(dir.flags & IFLAG_HASHINDEX != 0);
+		assert!(ext2.get_data_blocks(&dir).len() > 3);
+		// Lookups only read the leaves of the name hash
+		let leaves =
+			ext2.dx_leaf_blocks(&dir, "file_with_a_long_name7").unwrap();
+		assert_eq!(leaves.len(), 1);
+		ext2.dcache.lock().clear();
+		for i in 0..120 {
+			let name = crate::alloc::format!("file_with_a_long_name{}", i);
+			assert!(ext2.dentry_find(dirno, &name).is_some(), "{}", name);
+		}
+		assert!(ext2.dentry_find(dirno, "missing").is_none());
+		assert_eq!(ext2.read_dir(dirno).len(), 122);
+		assert!(ext2.unlink("/dir/file_with_a_long_name3", &ctx).is_ok());
+		assert!(ext2.dentry_find(dirno, "file_with_a_long_name3").is_none());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn dcache_follows_changes() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		ext2.create("/file", &ctx, 0o644).unwrap();
+		let inodeno = ext2.dentry_find(2, "file").unwrap().inode;
+		let hits = ext2.dcache.lock().hits;
+		assert_eq!(ext2.dentry_find(2, "file").map(|x| x.inode), Some(inodeno));
+		assert_eq!(ext2.dcache.lock().hits, hits + 1);
+		ext2.rename("/file", "/other", &ctx).unwrap();
+		assert!(ext2.dentry_find(2, "file").is_none());
+		ext2.unlink("/other", &ctx).unwrap();
+		assert!(ext2.dentry_find(2, "other").is_none());
+	}
+}
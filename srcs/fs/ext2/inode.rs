@@ -1,4 +1,3 @@
-use crate::time;
 use crate::vec::Vec;
 use core::mem::size_of;
 
@@ -60,16 +59,15 @@ pub struct Inode {
 
 impl Inode {
 	pub fn new() -> Self {
-		// TODO: not working: only get jiffies not unix timestamp
-		let time = time::get_timestamp();
+		let time = crate::cmos::get_time().timestamp();
 		Self {
 			tperm:        0,
 			uid:          0,
 			size_lh:      0,
-			lat:          time.second as u32,
-			creatt:       time.second as u32,
-			lmt:          time.second as u32,
-			delt:         time.second as u32,
+			lat:          time,
+			creatt:       time,
+			lmt:          time,
+			delt:         0,
 			gid:          0,
 			count_hl:     1,
 			count_ds:     1,
@@ -87,8 +85,12 @@ impl Inode {
 		}
 	}
 
+	/// Only regular files use size_uh, it holds dir_acl on directories
 	pub fn size(&self) -> u64 {
-		self.size_lh as u64 | ((self.size_uh as u64) << 32)
+		match self.is_regular() {
+			true => self.size_lh as u64 | ((self.size_uh as u64) << 32),
+			false => self.size_lh as u64
+		}
 	}
 
 	pub fn get_hardlinks(&self) -> u16 {
@@ -100,8 +102,61 @@ impl Inode {
 		self.tperm & 0x7777
 	}
 
+	/// Get only the type and ignore perms
+	pub fn file_type(&self) -> u16 {
+		self.tperm & ITYPE_MASK
+	}
+
 	pub fn is_dir(&self) -> bool {
-		self.tperm & ITYPE_DIR != 0
+		self.file_type() == ITYPE_DIR
+	}
+
+	pub fn is_regular(&self) -> bool {
+		self.file_type() == ITYPE_REGU
+	}
+
+	pub fn is_symlink(&self) -> bool {
+		self.file_type() == ITYPE_SYMF
+	}
+
+	/// Directory entry type matching the inode type
+	pub fn dtype(&self) -> u8 {
+		(match self.file_type() {
+			ITYPE_FIFO => Dtype::Fifo,
+			ITYPE_CHARDEV => Dtype::Chardev,
+			ITYPE_DIR => Dtype::Directory,
+			ITYPE_BLOCK => Dtype::Blockdev,
+			ITYPE_REGU => Dtype::Regular,
+			ITYPE_SYMF => Dtype::Sym,
+			ITYPE_SOCK => Dtype::Socket,
+			_ => Dtype::Unkown
+		}) as u8
+	}
+
+	/// Raw content of the 15 block pointers.
+	/// Fast symlinks store their target there instead of using a data block
+	pub fn block_bytes(&self) -> [u8; 60] {
+		let mut bytes = [0; 60];
+		let indirect = [self.sibp, self.dibp, self.tibp];
+		for (i, pointer) in self.dbp.iter().chain(indirect.iter()).enumerate() {
+			bytes[i * 4..i * 4 + 4].copy_from_slice(&pointer.to_le_bytes());
+		}
+		bytes
+	}
+
+	/// Overwrite the 15 block pointers with bytes, see block_bytes
+	pub fn set_block_bytes(&mut self, bytes: &[u8]) {
+		let mut buffer = [0; 60];
+		buffer[..bytes.len()].copy_from_slice(bytes);
+		let pointer = |i: usize| {
+			u32::from_le_bytes(buffer[i * 4..i * 4 + 4].try_into().unwrap())
+		};
+		for i in 0..self.dbp.len() {
+			self.dbp[i] = pointer(i);
+		}
+		self.sibp = pointer(12);
+		self.dibp = pointer(13);
+		self.tibp = pointer(14);
 	}
 
 	pub fn is_valid_block(block_no: u32) -> bool {
@@ -361,6 +416,7 @@ impl fmt::Display for Inode {
 }
 
 // Inode type occupy bit [12-15]
+pub const ITYPE_MASK: u16 = 0xf << 12;
 pub const ITYPE_FIFO: u16 = 0x1 << 12;
 pub const ITYPE_CHARDEV: u16 = 0x2 << 12;
 pub const ITYPE_DIR: u16 = 0x4 << 12;
@@ -391,9 +447,9 @@ pub const IFLAG_IMMUTABLE: u32 = 0x00000010;
 pub const IFLAG_OAPPEN: u32 = 0x00000020;
 pub const IFLAG_NODUMP: u32 = 0x00000040;
 pub const IFLAG_NOUPDATE: u32 = 0x00000080;
-pub const IFLAG_HASHINDEX: u32 = 0x00010000;
+pub const IFLAG_HASHINDEX: u32 = 0x00001000;
+pub const IFLAG_JOURN: u32 = 0x00004000;
 pub const IFLAG_ASDIR: u32 = 0x00020000;
-pub const IFLAG_JOURN: u32 = 0x00040000;
 
 #[derive(Debug, Default, Clone)]
 pub struct Dentry {
@@ -404,6 +460,19 @@ pub struct Dentry {
 	pub name:        crate::string::String
 }
 
+impl Dentry {
+	/// Create an entry with the smallest record able to hold its name
+	pub fn new(inode: u32, name: &str, r#type: u8) -> Self {
+		Self {
+			inode,
+			dentry_size: crate::utils::math::roundup(8 + name.len(), 4) as u16,
+			name_length: name.len() as u8,
+			r#type,
+			name: crate::string::String::from(name)
+		}
+	}
+}
+
 #[repr(u8)]
 pub enum Dtype {
 	Unkown,
@@ -448,3 +517,39 @@ impl Into<crate::alloc::vec::Vec<u8>> for Dentry {
 		v
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::{Dentry, Dtype, Inode, ITYPE_DIR, ITYPE_SOCK, ITYPE_SYMF};
+
+	#[sys_macros::test_case]
+	fn test_inode_type() {
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_SOCK | 0o755;
+		// Socket type bits contains the directory ones
+		assert!(!inode.is_dir());
+		assert_eq!(inode.dtype(), Dtype::Socket as u8);
+		inode.tperm = ITYPE_DIR | 0o755;
+		assert!(inode.is_dir());
+		assert_eq!(inode.dtype(), Dtype::Directory as u8);
+	}
+
+	#[sys_macros::test_case]
+	fn test_fast_symlink_bytes() {
+		let target = "../some/target/path";
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_SYMF | 0o777;
+		inode.set_block_bytes(target.as_bytes());
+		assert!(inode.is_symlink());
+		assert_eq!(inode.dbp[0], u32::from_le_bytes(*b"../s"));
+		assert_eq!(&inode.block_bytes()[..target.len()], target.as_bytes());
+		assert_eq!(inode.block_bytes()[target.len()], 0);
+	}
+
+	#[sys_macros::test_case]
+	fn test_dentry_new() {
+		let dentry = Dentry::new(12, "hello", Dtype::Regular as u8);
+		assert_eq!(dentry.dentry_size, 16);
+		assert_eq!(dentry.name_length, 5);
+	}
+}
@@ -0,0 +1,723 @@
+//! Journal of ext3 filesystems, in the JBD2 format.
+//!
+//! The log is replayed when mounting a filesystem whose journal isn't empty. Writes done
+//! inside a transaction are held in memory, then written to the log and only afterwards to
+//! their place, so a crash leaves either the old or the new version of every block.
+//! Only metadata is written by this driver (bitmaps, inodes, directories and symlinks), file
+//! contents would bypass the log through write_data_block like the ordered mode of linux.
+//! The log is emptied after every transaction, so it never needs revoke records.
+
+use super::inode::{self, Inode};
+use super::{read_superblock, Ext2};
+use crate::errno::ErrNo;
+use crate::vec::Vec;
+use alloc::collections::BTreeMap;
+
+/// Inode reserved to the journal
+pub const JOURNAL_INODE: usize = 8;
+/// Smallest journal created by mkfs: superblock, descriptor, commit and a few blocks
+pub const JOURNAL_MIN_BLOCKS: usize = 8;
+/// Most blocks held by a running transaction, it is committed when full
+const MAX_TRANSACTION_BLOCKS: usize = 64;
+
+const JBD2_MAGIC: u32 = 0xc03b3998;
+
+// Journal block types
+const BLOCKTYPE_DESCRIPTOR: u32 = 1;
+const BLOCKTYPE_COMMIT: u32 = 2;
+const BLOCKTYPE_SUPERBLOCK_V1: u32 = 3;
+const BLOCKTYPE_SUPERBLOCK_V2: u32 = 4;
+const BLOCKTYPE_REVOKE: u32 = 5;
+
+// Incompatible journal features
+const JFEAT_REVOKE: u32 = 0x01;
+const JFEAT_64BIT: u32 = 0x02;
+const JFEAT_ASYNC_COMMIT: u32 = 0x04;
+const JFEAT_CSUM_V2: u32 = 0x08;
+const JFEAT_CSUM_V3: u32 = 0x10;
+const JFEAT_FAST_COMMIT: u32 = 0x20;
+/// Features understood by the replay, checksums are not verified
+const JFEAT_SUPPORTED: u32 = JFEAT_REVOKE
+	| JFEAT_64BIT
+	| JFEAT_ASYNC_COMMIT
+	| JFEAT_CSUM_V2
+	| JFEAT_CSUM_V3;
+
+// Descriptor tag flags
+const TAG_ESCAPE: u32 = 0x1;
+const TAG_SAME_UUID: u32 = 0x2;
+const TAG_LAST: u32 = 0x8;
+
+/// Header of every journal block but data ones
+const HEADER_SIZE: usize = 12;
+
+fn be32(block: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes(block[offset..offset + 4].try_into().unwrap())
+}
+
+fn be16(block: &[u8], offset: usize) -> u16 {
+	u16::from_be_bytes(block[offset..offset + 2].try_into().unwrap())
+}
+
+fn put_be32(block: &mut [u8], offset: usize, value: u32) {
+	block[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Type and transaction sequence of a journal block, None for data blocks
+fn header(block: &[u8]) -> Option<(u32, u32)> {
+	match be32(block, 0) {
+		JBD2_MAGIC => Some((be32(block, 4), be32(block, 8))),
+		_ => None
+	}
+}
+
+fn put_header(block: &mut [u8], blocktype: u32, sequence: u32) {
+	put_be32(block, 0, JBD2_MAGIC);
+	put_be32(block, 4, blocktype);
+	put_be32(block, 8, sequence);
+}
+
+/// Transaction sequences wrap around, a is after b if less than half the range ahead
+fn tid_gt(a: u32, b: u32) -> bool {
+	(a.wrapping_sub(b) as i32) > 0
+}
+
+/// Superblock of the journal, stored in its first block
+#[derive(Debug, Clone, Copy)]
+pub struct JournalSuperblock {
+	/// Size of a journal block, must be the one of the filesystem
+	pub blocksize: u32,
+	/// Number of blocks of the journal
+	pub maxlen:    u32,
+	/// First block of the log
+	pub first:     u32,
+	/// Sequence of the first transaction of the log
+	pub sequence:  u32,
+	/// Block where the log starts, 0 when the journal is empty
+	pub start:     u32,
+	/// Incompatible features, always 0 for version 1 superblocks
+	pub incompat:  u32,
+	pub uuid:      [u8; 16]
+}
+
+impl JournalSuperblock {
+	/// Parse the journal superblock, None if block isn't one
+	fn parse(block: &[u8]) -> Option<Self> {
+		let incompat = match header(block)? {
+			(BLOCKTYPE_SUPERBLOCK_V1, _) => 0,
+			(BLOCKTYPE_SUPERBLOCK_V2, _) => be32(block, 0x28),
+			_ => return None
+		};
+		Some(Self {
+			blocksize: be32(block, 0x0c),
+			maxlen: be32(block, 0x10),
+			first: be32(block, 0x14),
+			sequence: be32(block, 0x18),
+			start: be32(block, 0x1c),
+			incompat,
+			uuid: block[0x30..0x40].try_into().unwrap()
+		})
+	}
+
+	/// Write the fields changed by the driver, the others are left untouched
+	fn write_to(&self, block: &mut [u8]) {
+		put_be32(block, 0x18, self.sequence);
+		put_be32(block, 0x1c, self.start);
+	}
+
+	/// Size of a descriptor tag, depends on 64 bits block numbers and checksums
+	fn tag_size(&self) -> usize {
+		if self.incompat & JFEAT_CSUM_V3 != 0 {
+			return 16;
+		}
+		let mut size = 8;
+		if self.incompat & JFEAT_CSUM_V2 != 0 {
+			size += 2;
+		}
+		if self.incompat & JFEAT_64BIT != 0 {
+			size += 4;
+		}
+		size
+	}
+
+	/// Checksummed journals end descriptor and revoke blocks with a 4 bytes tail
+	fn tail_size(&self) -> usize {
+		match self.incompat & (JFEAT_CSUM_V2 | JFEAT_CSUM_V3) {
+			0 => 0,
+			_ => 4
+		}
+	}
+}
+
+/// Journal of a mounted filesystem
+pub struct Journal {
+	inode:   Inode,
+	pub sb:  JournalSuperblock,
+	/// Writes go through the log, otherwise the journal is only replayed at mount
+	enabled: bool,
+	/// Blocks written by the running transaction, by filesystem block number
+	running: Option<BTreeMap<u32, Vec<u8>>>
+}
+
+impl Journal {
+	/// Block of the log following index, the log wraps around to its first block
+	fn next(&self, index: u32) -> u32 {
+		match index + 1 {
+			x if x >= self.sb.maxlen => self.sb.first,
+			x => x
+		}
+	}
+
+	/// Most blocks a transaction can hold: one descriptor of tags and the commit block
+	/// must fit in the log along with them
+	fn capacity(&self) -> usize {
+		let bsize = self.sb.blocksize as usize;
+		let tags = (bsize - HEADER_SIZE - 16 - self.sb.tail_size())
+			/ self.sb.tag_size();
+		let log = (self.sb.maxlen - self.sb.first) as usize - 2;
+		MAX_TRANSACTION_BLOCKS.min(tags).min(log)
+	}
+
+	/// Content of block_no if the running transaction wrote it
+	pub(super) fn get(&self, block_no: u32) -> Option<&Vec<u8>> {
+		self.running.as_ref()?.get(&block_no)
+	}
+
+	pub(super) fn is_running(&self) -> bool {
+		self.running.is_some()
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// Filesystem block numbers and flags of the tags of a descriptor block
+	fn tags(&self, block: &[u8]) -> Vec<(u32, u32)> {
+		let size = self.sb.tag_size();
+		let end = block.len() - self.sb.tail_size();
+		let mut tags = Vec::new();
+		let mut offset = HEADER_SIZE;
+		while offset + size <= end {
+			let flags = match self.sb.incompat & JFEAT_CSUM_V3 {
+				0 => be16(block, offset + 6) as u32,
+				_ => be32(block, offset + 4)
+			};
+			tags.push((be32(block, offset), flags));
+			offset += size;
+			if flags & TAG_SAME_UUID == 0 {
+				offset += 16;
+			}
+			if flags & TAG_LAST != 0 {
+				break;
+			}
+		}
+		tags
+	}
+
+	/// Block numbers of a revoke block, only the low half of 64 bits ones is kept
+	fn revoked(&self, block: &[u8]) -> Vec<u32> {
+		let size = match self.sb.incompat & JFEAT_64BIT {
+			0 => 4,
+			_ => 8
+		};
+		let count =
+			core::cmp::min(be32(block, HEADER_SIZE) as usize, block.len());
+		(HEADER_SIZE + 4..count)
+			.step_by(size)
+			.filter(|offset| offset + size <= count)
+			.map(|offset| be32(block, offset + size - 4))
+			.collect()
+	}
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Pass {
+	/// Find the end of the committed transactions
+	Scan,
+	/// Collect the revoked blocks with the last transaction revoking them
+	Revoke,
+	/// Write the blocks that are not revoked back to the filesystem
+	Replay
+}
+
+impl Ext2 {
+	/// Load the journal of the filesystem and replay it if it holds transactions.
+	/// Fails with 0x02 when the journal uses unsupported features or is external while it
+	/// needs a recovery, and 0x03 if it is corrupted
+	pub(super) fn load_journal(&mut self) -> Result<(), u8> {
+		let inodeno = match self.sblock.journal_inode() {
+			Some(inodeno) => inodeno as usize,
+			None if self.sblock.needs_recovery() => return Err(0x02),
+			None => return Ok(())
+		};
+		let inode = self.get_inode_entry(inodeno);
+		let block = self.read_block(self.get_block_at(&inode, 0));
+		let sb = JournalSuperblock::parse(&block).ok_or(0x03_u8)?;
+		if sb.blocksize as usize != self.sblock.bsize()
+			|| sb.incompat & !JFEAT_SUPPORTED != 0
+		{
+			return Err(0x02);
+		}
+		if sb.first == 0 || sb.first + 4 > sb.maxlen {
+			return Err(0x03);
+		}
+		// Transactions are logged without checksums
+		let enabled = sb.incompat
+			& (JFEAT_CSUM_V2 | JFEAT_CSUM_V3 | JFEAT_FAST_COMMIT)
+			== 0;
+		let mut journal = Journal { inode, sb, enabled, running: None };
+		if journal.sb.start != 0 {
+			self.recover(&mut journal)?;
+		}
+		if self.sblock.needs_recovery() {
+			self.sblock.set_needs_recovery(false);
+			self.write_superblock();
+		}
+		self.journal = Some(journal);
+		Ok(())
+	}
+
+	/// Replay the committed transactions of the log in three passes like jbd2, then empty
+	/// it. Blocks revoked by a later transaction are not written back
+	fn recover(&mut self, journal: &mut Journal) -> Result<(), u8> {
+		let mut revoked = BTreeMap::new();
+		let end = self.journal_pass(journal, Pass::Scan, &mut revoked, 0);
+		self.journal_pass(journal, Pass::Revoke, &mut revoked, end);
+		self.journal_pass(journal, Pass::Replay, &mut revoked, end);
+		crate::dprintln!(
+			"ext2: journal replayed up to transaction {}",
+			end.wrapping_sub(1)
+		);
+		// The sequence is bumped so a partial transaction left in the log is never taken
+		// for a later one
+		journal.sb.sequence = end.wrapping_add(1);
+		journal.sb.start = 0;
+		self.write_journal_superblock(journal);
+		// The superblock may have been replayed
+		self.sblock = read_superblock(&mut self.diskio)?;
+		Ok(())
+	}
+
+	/// Walk the log from its start, stopping at the first block that doesn't belong to the
+	/// expected transaction or at transaction end.
+	/// Return the sequence following the last committed transaction
+	fn journal_pass(
+		&mut self,
+		journal: &Journal,
+		pass: Pass,
+		revoked: &mut BTreeMap<u32, u32>,
+		end: u32
+	) -> u32 {
+		let mut sequence = journal.sb.sequence;
+		let mut index = journal.sb.start;
+		for _ in 0..journal.sb.maxlen {
+			if pass != Pass::Scan && sequence == end {
+				break;
+			}
+			let block = self.read_log_block(journal, index);
+			index = journal.next(index);
+			let blocktype = match header(&block) {
+				Some((blocktype, seq)) if seq == sequence => blocktype,
+				_ => break
+			};
+			match blocktype {
+				BLOCKTYPE_DESCRIPTOR => {
+					for (target, flags) in journal.tags(&block) {
+						let data_index = index;
+						index = journal.next(index);
+						let is_revoked = revoked
+							.get(&target)
+							.is_some_and(|last| !tid_gt(sequence, *last));
+						if pass != Pass::Replay || is_revoked {
+							continue;
+						}
+						let mut data = self.read_log_block(journal, data_index);
+						if flags & TAG_ESCAPE != 0 {
+							put_be32(&mut data, 0, JBD2_MAGIC);
+						}
+						self.write_block(target, &data);
+					}
+				},
+				BLOCKTYPE_COMMIT => sequence = sequence.wrapping_add(1),
+				BLOCKTYPE_REVOKE if pass == Pass::Revoke => {
+					for target in journal.revoked(&block) {
+						let last = revoked.entry(target).or_insert(sequence);
+						if tid_gt(sequence, *last) {
+							*last = sequence;
+						}
+					}
+				},
+				BLOCKTYPE_REVOKE => {},
+				_ => break
+			}
+		}
+		sequence
+	}
+
+	fn read_log_block(&self, journal: &Journal, index: u32) -> Vec<u8> {
+		self.read_block(self.get_block_at(&journal.inode, index as usize))
+	}
+
+	fn write_log_block(&mut self, journal: &Journal, index: u32, block: &[u8]) {
+		let block_no = self.get_block_at(&journal.inode, index as usize);
+		self.write_block(block_no, block);
+	}
+
+	fn write_journal_superblock(&mut self, journal: &Journal) {
+		let mut block = self.read_log_block(journal, 0);
+		journal.sb.write_to(&mut block);
+		self.write_log_block(journal, 0, &block);
+	}
+
+	pub fn journal(&self) -> Option<&Journal> {
+		self.journal.as_ref()
+	}
+
+	/// Start a transaction, following writes are held until commit_transaction.
+	/// Return false when there is no journal, it is disabled or a transaction is already
+	/// running, the caller must then not commit
+	pub fn begin_transaction(&mut self) -> bool {
+		match self.journal.as_mut() {
+			Some(journal) if journal.enabled && journal.running.is_none() => {
+				journal.running = Some(BTreeMap::new());
+				true
+			},
+			_ => false
+		}
+	}
+
+	/// Write the running transaction to the log then to the filesystem
+	pub fn commit_transaction(&mut self) {
+		// While the journal is taken out, reads and writes go straight to the disk
+		let Some(mut journal) = self.journal.take() else {
+			return;
+		};
+		if let Some(mut blocks) = journal.running.take() {
+			if !blocks.is_empty() {
+				self.log_transaction(&mut journal, &mut blocks);
+				self.checkpoint(&mut journal, &blocks);
+			}
+		}
+		self.journal = Some(journal);
+	}
+
+	/// Run f in a transaction, or joins the running one
+	pub fn transaction<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+		let started = self.begin_transaction();
+		let res = f(self);
+		if started {
+			self.commit_transaction();
+		}
+		res
+	}
+
+	/// Log writes of the following operations, or write them in place
+	pub fn set_journaling(&mut self, enabled: bool) -> Result<(), ErrNo> {
+		self.commit_transaction();
+		let journal = self.journal.as_mut().ok_or(ErrNo::ENODEV)?;
+		if enabled
+			&& journal.sb.incompat
+				& (JFEAT_CSUM_V2 | JFEAT_CSUM_V3 | JFEAT_FAST_COMMIT)
+				!= 0
+		{
+			return Err(ErrNo::EOPNOTSUPP);
+		}
+		journal.enabled = enabled;
+		Ok(())
+	}
+
+	/// Hold a write in the running transaction, committing it first if it is full
+	pub(super) fn journal_write(&mut self, block_no: u32, block: &[u8]) {
+		let full = self.journal.as_ref().is_some_and(|journal| {
+			journal.running.as_ref().is_some_and(|running| {
+				running.len() >= journal.capacity()
+					&& !running.contains_key(&block_no)
+			})
+		});
+		if full {
+			self.commit_transaction();
+			self.begin_transaction();
+		}
+		if let Some(running) =
+			self.journal.as_mut().and_then(|x| x.running.as_mut())
+		{
+			running.insert(block_no, block.to_vec());
+		}
+	}
+
+	/// Write file contents in place, outside of the running transaction, before the
+	/// metadata pointing to them is committed
+	pub fn write_data_block(&mut self, block_no: u32, block: &[u8]) {
+		let journal = self.journal.take();
+		self.write_block(block_no, block);
+		self.journal = journal;
+		if let Some(running) =
+			self.journal.as_mut().and_then(|x| x.running.as_mut())
+		{
+			running.remove(&block_no);
+		}
+	}
+
+	/// Write the blocks to the log, a single descriptor followed by their copy, then the
+	/// commit block. Once it is written the transaction survives a crash.
+	/// The superblock flags the journal as needing a recovery until the checkpoint is done
+	fn log_transaction(
+		&mut self,
+		journal: &mut Journal,
+		blocks: &mut BTreeMap<u32, Vec<u8>>
+	) {
+		let (sb_block, sb_offset) = self.superblock_location();
+		if let Some(block) = blocks.get_mut(&sb_block) {
+			set_recovery_flag(block, sb_offset, true);
+		}
+		self.write_recovery_flag(true);
+
+		let bsize = self.sblock.bsize();
+		let sequence = journal.sb.sequence;
+		let tag_size = journal.sb.tag_size();
+		let mut descriptor = crate::vec![0; bsize];
+		put_header(&mut descriptor, BLOCKTYPE_DESCRIPTOR, sequence);
+		let mut offset = HEADER_SIZE;
+		let mut index = journal.sb.first;
+		for (i, (target, data)) in blocks.iter().enumerate() {
+			let mut flags = match i {
+				0 => 0,
+				_ => TAG_SAME_UUID
+			};
+			if i == blocks.len() - 1 {
+				flags |= TAG_LAST;
+			}
+			index = journal.next(index);
+			// Data blocks looking like journal blocks are escaped
+			let mut data = data.clone();
+			if be32(&data, 0) == JBD2_MAGIC {
+				flags |= TAG_ESCAPE;
+				put_be32(&mut data, 0, 0);
+			}
+			self.write_log_block(journal, index, &data);
+			put_be32(&mut descriptor, offset, *target);
+			descriptor[offset + 6..offset + 8]
+				.copy_from_slice(&(flags as u16).to_be_bytes());
+			offset += tag_size;
+			if i == 0 {
+				descriptor[offset..offset + 16]
+					.copy_from_slice(&journal.sb.uuid);
+				offset += 16;
+			}
+		}
+		self.write_log_block(journal, journal.sb.first, &descriptor);
+		journal.sb.start = journal.sb.first;
+		self.write_journal_superblock(journal);
+
+		let mut commit = crate::vec![0; bsize];
+		put_header(&mut commit, BLOCKTYPE_COMMIT, sequence);
+		self.write_log_block(journal, journal.next(index), &commit);
+	}
+
+	/// Write the blocks of a logged transaction in place and empty the log
+	fn checkpoint(
+		&mut self,
+		journal: &mut Journal,
+		blocks: &BTreeMap<u32, Vec<u8>>
+	) {
+		for (target, data) in blocks.iter() {
+			self.write_block(*target, data);
+		}
+		journal.sb.start = 0;
+		journal.sb.sequence = journal.sb.sequence.wrapping_add(1);
+		self.write_journal_superblock(journal);
+		self.write_recovery_flag(false);
+	}
+
+	/// Set or clear the recovery flag of the superblock on disk only, the superblock in
+	/// memory may hold changes of a transaction that is not logged yet
+	fn write_recovery_flag(&mut self, needed: bool) {
+		let (block_no, offset) = self.superblock_location();
+		let mut block = self.read_block(block_no);
+		set_recovery_flag(&mut block, offset, needed);
+		self.write_block(block_no, &block);
+	}
+
+	/// Create an empty journal of blocks blocks in its reserved inode.
+	/// Only direct and single indirect pointers are used, which bounds its size
+	pub(super) fn create_journal(
+		&mut self,
+		blocks: usize
+	) -> Result<(), ErrNo> {
+		let bsize = self.sblock.bsize();
+		let ptrs = bsize / core::mem::size_of::<u32>();
+		let mut journal = Inode::new();
+		if blocks < JOURNAL_MIN_BLOCKS || blocks > journal.dbp.len() + ptrs {
+			return Err(ErrNo::EINVAL);
+		}
+		journal.tperm = inode::ITYPE_REGU | 0o600;
+		journal.count_hl = 1;
+		let mut indirect = crate::vec![0; bsize];
+		let mut allocated = blocks;
+		for i in 0..blocks {
+			let block_no = self.alloc_block(0)? as u32;
+			match journal.dbp.get_mut(i) {
+				Some(pointer) => *pointer = block_no,
+				None => {
+					let offset = (i - journal.dbp.len()) * 4;
+					indirect[offset..offset + 4]
+						.copy_from_slice(&block_no.to_le_bytes());
+				}
+			}
+		}
+		if blocks > journal.dbp.len() {
+			journal.sibp = self.alloc_block(0)? as u32;
+			self.write_block(journal.sibp, &indirect);
+			allocated += 1;
+		}
+		journal.count_ds = (allocated * bsize / 512) as u32;
+		self.set_file_size(&mut journal, (blocks * bsize) as u64)?;
+		self.write_inode(JOURNAL_INODE, &journal);
+
+		// Blocks are allocated zeroed, only the superblock is left to write
+		let mut block = crate::vec![0; bsize];
+		put_header(&mut block, BLOCKTYPE_SUPERBLOCK_V2, 0);
+		put_be32(&mut block, 0x0c, bsize as u32);
+		put_be32(&mut block, 0x10, blocks as u32);
+		put_be32(&mut block, 0x14, 1);
+		put_be32(&mut block, 0x18, 1);
+		// One filesystem uses the journal
+		put_be32(&mut block, 0x40, 1);
+		if let Some(ext) = self.sblock.extension.as_ref() {
+			block[0x30..0x40].copy_from_slice(&ext.fsid);
+		}
+		self.write_block(journal.dbp[0], &block);
+		self.sblock.set_journal(JOURNAL_INODE as u32);
+		self.write_superblock();
+		Ok(())
+	}
+}
+
+/// Set the recovery flag of the superblock stored at offset of block
+fn set_recovery_flag(block: &mut [u8], offset: usize, needed: bool) {
+	let mut sblock =
+		super::block::BaseSuperblock::from(&block[offset..offset + 84]);
+	sblock.set_extension(super::block::ExtendedSuperblock::from(
+		&block[offset + 84..offset + 236]
+	));
+	sblock.set_needs_recovery(needed);
+	let sblock = sblock.into_boxed_slice();
+	block[offset..offset + sblock.len()].copy_from_slice(&sblock);
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		put_be32,
+		put_header,
+		BLOCKTYPE_COMMIT,
+		BLOCKTYPE_DESCRIPTOR,
+		BLOCKTYPE_REVOKE,
+		TAG_LAST,
+		TAG_SAME_UUID
+	};
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::ext2::perm::Context;
+	use crate::fs::ext2::Ext2;
+
+	fn journaled_fs() -> Ext2 {
+		let options =
+			MkfsOptions { journal_blocks: Some(32), ..Default::default() };
+		ramdisk_fs(&options)
+	}
+
+	#[sys_macros::test_case]
+	fn replay_committed_transactions() {
+		let mut ext2 = journaled_fs();
+		let bsize = ext2.sblock.bsize();
+		let last = ext2.sblock.block_count() - 1;
+		let (a, b, c) = (last, last - 1, last - 2);
+		let journal = ext2.journal.take().unwrap();
+		let sequence = journal.sb.sequence;
+		let mut index = journal.sb.first;
+		let mut write = |ext2: &mut Ext2, block: &[u8]| {
+			ext2.write_log_block(&journal, index, block);
+			index = journal.next(index);
+		};
+		let block = |blocktype: u32, sequence: u32| {
+			let mut block = crate::vec![0; bsize];
+			put_header(&mut block, blocktype, sequence);
+			block
+		};
+		// a and b are written, then b is revoked
+		let mut descriptor = block(BLOCKTYPE_DESCRIPTOR, sequence);
+		put_be32(&mut descriptor, 12, a);
+		put_be32(&mut descriptor, 12 + 8 + 16, b);
+		descriptor[12 + 8 + 16 + 6..12 + 8 + 16 + 8].copy_from_slice(
+			&((TAG_SAME_UUID | TAG_LAST) as u16).to_be_bytes()
+		);
+		write(&mut ext2, &descriptor);
+		write(&mut ext2, &crate::vec![0xaa; bsize]);
+		write(&mut ext2, &crate::vec![0xbb; bsize]);
+		write(&mut ext2, &block(BLOCKTYPE_COMMIT, sequence));
+		let mut revoke = block(BLOCKTYPE_REVOKE, sequence + 1);
+		put_be32(&mut revoke, 12, 20);
+		put_be32(&mut revoke, 16, b);
+		write(&mut ext2, &revoke);
+		write(&mut ext2, &block(BLOCKTYPE_COMMIT, sequence + 1));
+		// c is never committed
+		let mut descriptor = block(BLOCKTYPE_DESCRIPTOR, sequence + 2);
+		put_be32(&mut descriptor, 12, c);
+		descriptor[18..20].copy_from_slice(&(TAG_LAST as u16).to_be_bytes());
+		write(&mut ext2, &descriptor);
+		write(&mut ext2, &crate::vec![0xcc; bsize]);
+
+		let mut sb = journal.sb;
+		sb.start = sb.first;
+		ext2.write_journal_superblock(&super::Journal { sb, ..journal });
+		ext2.sblock.set_needs_recovery(true);
+		ext2.write_superblock();
+
+		let ext2 = Ext2::new(ext2.diskio).unwrap();
+		assert!(ext2.read_block(a).iter().all(|x| *x == 0xaa));
+		assert!(ext2.read_block(b).iter().all(|x| *x == 0));
+		assert!(ext2.read_block(c).iter().all(|x| *x == 0));
+		assert!(!ext2.sblock.needs_recovery());
+		let journal = ext2.journal().unwrap();
+		assert_eq!(journal.sb.start, 0);
+		assert_eq!(journal.sb.sequence, sequence + 3);
+	}
+
+	#[sys_macros::test_case]
+	fn logged_transaction_survives_crash() {
+		let mut ext2 = journaled_fs();
+		let ctx = Context::new(2);
+		assert!(ext2.begin_transaction());
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		// Nothing reached the disk yet
+		let mut journal = ext2.journal.take().unwrap();
+		let mut blocks = journal.running.take().unwrap();
+		assert!(ext2.recurs_find("/dir", 2).is_none());
+		// Crash once the commit block is written
+		ext2.log_transaction(&mut journal, &mut blocks);
+		assert!(ext2.recurs_find("/dir", 2).is_none());
+
+		let mut ext2 = Ext2::new(ext2.diskio).unwrap();
+		assert!(ext2.lookup("/dir/file", &ctx, true).is_ok());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn transaction_is_written_in_place() {
+		let mut ext2 = journaled_fs();
+		let sequence = ext2.journal().unwrap().sb.sequence;
+		let ctx = Context::new(2);
+		let res = ext2.transaction(|ext2| ext2.mkdir("/dir", &ctx, 0o755));
+		assert!(res.is_ok());
+		let journal = ext2.journal().unwrap();
+		assert!(!journal.is_running());
+		assert_eq!(journal.sb.start, 0);
+		assert_eq!(journal.sb.sequence, sequence + 1);
+		assert!(!ext2.sblock.needs_recovery());
+
+		let mut ext2 = Ext2::new(ext2.diskio).unwrap();
+		assert!(ext2.recurs_find("/dir", 2).is_some());
+		assert!(ext2.fsck(false).is_clean());
+	}
+}
@@ -0,0 +1,429 @@
+//! Creation of an empty ext2 filesystem on a disk, the in-kernel equivalent of mke2fs
+
+use super::block::{self, BaseSuperblock, ExtendedSuperblock};
+use super::dcache::DentryCache;
+use super::gdt::GdtEntry;
+use super::htree::{self, HashInfo};
+use super::inode::{self, Dentry, Inode};
+use super::namei::ROOT_INODE;
+use super::perm::{current_time, Context};
+use super::Ext2;
+use crate::disk::DiskIO;
+use crate::errno::ErrNo;
+use crate::spin::Mutex;
+use crate::string::String;
+use crate::vec::Vec;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+
+/// Bytes of data per inode when the inode count is not given
+const BYTES_PER_INODE: usize = 8192;
+/// Size of the inodes written, the one of revision 0
+const INODE_SIZE: usize = 128;
+/// First inode that is not reserved
+const FIRST_INODE: usize = 11;
+/// Percentage of blocks reserved for root
+const RESERVED_PERCENT: usize = 5;
+
+/// Options of the filesystem to create
+pub struct MkfsOptions {
+	/// Size of a block in bytes: 1024, 2048 or 4096
+	pub block_size:       usize,
+	/// Number of inodes, one for every BYTES_PER_INODE bytes if None
+	pub inode_count:      Option<usize>,
+	/// Volume name, at most 16 bytes
+	pub label:            String,
+	/// Blocks in each group, a multiple of 8 up to the bits of one bitmap block if given
+	pub blocks_per_group: Option<usize>,
+	/// Blocks of the journal, an ext3 filesystem is created if given
+	pub journal_blocks:   Option<usize>,
+	/// Index large directories by hashes of their entry names
+	pub dir_index:        bool
+}
+
+impl Default for MkfsOptions {
+	fn default() -> Self {
+		Self {
+			block_size:       1024,
+			inode_count:      None,
+			label:            String::new(),
+			blocks_per_group: None,
+			journal_blocks:   None,
+			dir_index:        false
+		}
+	}
+}
+
+/// Format diskio as a revision 1 ext2 filesystem with sparse superblocks, large files and
+/// typed directory entries, containing an empty root directory and lost+found.
+/// With a journal it is an ext3 filesystem. With dir_index, names are hashed with half MD4
+/// seeded by the filesystem id.
+/// Return the filesystem ready to be used
+pub fn mkfs(
+	diskio: Box<dyn DiskIO + Send>,
+	options: &MkfsOptions
+) -> Result<Ext2, ErrNo> {
+	let bsize = options.block_size;
+	let sector_size = diskio.sector_size();
+	if ![1024, 2048, 4096].contains(&bsize) || bsize % sector_size != 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	if options.label.len() > 16 {
+		return Err(ErrNo::ENAMETOOLONG);
+	}
+	let per_group = options.blocks_per_group.unwrap_or(8 * bsize);
+	if per_group % 8 != 0 || per_group == 0 || per_group > 8 * bsize {
+		return Err(ErrNo::EINVAL);
+	}
+	let first_block = (bsize == 1024) as usize;
+	let blocks = diskio.sector_count() / (bsize / sector_size);
+	let groups =
+		(blocks.saturating_sub(first_block) + per_group - 1) / per_group;
+	let gdt_blocks =
+		(groups * core::mem::size_of::<GdtEntry>()).div_ceil(bsize);
+
+	let inodes_per_block = bsize / INODE_SIZE;
+	let inode_count = options
+		.inode_count
+		.unwrap_or(blocks * bsize / BYTES_PER_INODE)
+		.max(FIRST_INODE + 5);
+	let inode_per_grp = ((inode_count + groups.max(1) - 1) / groups.max(1))
+		.next_multiple_of(inodes_per_block)
+		.min(8 * bsize);
+	if inode_per_grp * groups < FIRST_INODE + 1 {
+		return Err(ErrNo::ENOSPC);
+	}
+	let table_blocks = inode_per_grp / inodes_per_block;
+	// Superblock, descriptors, bitmaps, inode table and the blocks of '/' and lost+found
+	let overhead = 3 + gdt_blocks + table_blocks + 2;
+	if groups == 0 || blocks - first_block - (groups - 1) * per_group < overhead
+	{
+		return Err(ErrNo::ENOSPC);
+	}
+
+	let now = current_time();
+	let mut vol_name = [0; 16];
+	vol_name[..options.label.len()].copy_from_slice(options.label.as_bytes());
+	let mut sblock = BaseSuperblock {
+		inode_count: (inode_per_grp * groups) as u32,
+		blocks_count: blocks as u32,
+		rblocks_num: (blocks * RESERVED_PERCENT / 100) as u32,
+		superblock_block: first_block as u32,
+		block_size: bsize.trailing_zeros() - 10,
+		frag_size: bsize.trailing_zeros() - 10,
+		bgroup_bno: per_group as u32,
+		bgroup_fno: per_group as u32,
+		bgroup_ino: inode_per_grp as u32,
+		last_wt: now,
+		mount_no_max: u16::MAX,
+		ext2_sig: 0xef53,
+		fs_state: block::FSSTATE_CLEAN,
+		err_handle: block::FSERROR_IGN,
+		last_fsck: now,
+		major: 1,
+		..Default::default()
+	};
+	sblock.set_extension(ExtendedSuperblock {
+		first_inode: FIRST_INODE as u32,
+		inode_size: INODE_SIZE as u16,
+		bgroup_superblock: 0,
+		opt_features: 0,
+		req_features: block::REQFEAT_DE_TYPEFIELD,
+		ro_features: block::ROFEAT_SPARS | block::ROFEAT_64B,
+		fsid: fsid(now, blocks),
+		vol_name,
+		last_path: [0; 64],
+		compr: 0,
+		prealloc_blocks_files: 0,
+		prealloc_block_dir: 0,
+		unused: 0,
+		journ_id: [0; 16],
+		journ_inode: 0,
+		journ_dev: 0,
+		orphan_inode_lst: 0
+	});
+	if options.dir_index {
+		sblock.set_dir_index();
+	}
+	let mut ext2 = Ext2 {
+		sector_size,
+		diskio,
+		sblock,
+		journal: None,
+		dx_hash: HashInfo::default(),
+		dcache: Mutex::new(DentryCache::new()),
+		swapfiles: BTreeSet::new(),
+		diskno: None,
+		readonly: false
+	};
+	ext2.write_groups(inode_per_grp, table_blocks);
+	if options.dir_index {
+		let fsid = fsid(now, blocks);
+		let mut seed = [0; 4];
+		for (i, word) in seed.iter_mut().enumerate() {
+			*word =
+				u32::from_le_bytes(fsid[4 * i..4 * i + 4].try_into().unwrap());
+		}
+		let version = htree::DX_HASH_HALF_MD4;
+		ext2.write_hash_info(HashInfo { seed, version, unsigned: false });
+	}
+	ext2.write_root()?;
+	let ctx = Context::new(ROOT_INODE);
+	ext2.mkdir("/lost+found", &ctx, 0o700)?;
+	if let Some(blocks) = options.journal_blocks {
+		ext2.create_journal(blocks)?;
+		ext2.load_journal().map_err(|_| ErrNo::EIO)?;
+	}
+	ext2.write_backups();
+	Ok(ext2)
+}
+
+/// Filesystem id derived from the creation time and size, there is no random source
+fn fsid(time: u32, blocks: usize) -> [u8; 16] {
+	let mut fsid = [0; 16];
+	fsid[..4].copy_from_slice(&time.to_le_bytes());
+	fsid[4..8].copy_from_slice(&(blocks as u32).to_le_bytes());
+	fsid[8..]
+		.copy_from_slice(&(time as u64 * 0x9e3779b97f4a7c15).to_le_bytes());
+	fsid
+}
+
+impl Ext2 {
+	/// Write the descriptors, bitmaps and zeroed inode tables of every group, along with the
+	/// superblock
+	fn write_groups(&mut self, inode_per_grp: usize, table_blocks: usize) {
+		let bsize = self.sblock.bsize();
+		let first = self.sblock.superblock_block as usize;
+		let per_group = self.sblock.block_per_grp() as usize;
+		let groups = self.sblock.block_grp_count() as usize;
+		let blocks = self.sblock.block_count() as usize;
+		let (mut free_blocks, mut free_inodes) = (0, 0);
+		for group in 0..groups {
+			let start = first + group * per_group;
+			let size = core::cmp::min(per_group, blocks - start);
+			// Superblock and descriptors copy
+			let mut used =
+				(1 + self.gdt_blocks()) * self.sblock.has_super(group) as usize;
+			let mut gdt = GdtEntry::default();
+			gdt.bitmap_block = (start + used) as u32;
+			gdt.bitmap_inode = (start + used + 1) as u32;
+			gdt.inode_table = (start + used + 2) as u32;
+			used += 2 + table_blocks;
+
+			// Blocks past the end of the last group are marked used
+			let mut map = crate::vec![0u8; bsize];
+			(0..used)
+				.chain(size..8 * bsize)
+				.for_each(|x| set_bit(&mut map, x));
+			self.write_block(gdt.bitmap_block, &map);
+			let mut map = crate::vec![0u8; bsize];
+			let reserved = match group {
+				0 => FIRST_INODE - 1,
+				_ => 0
+			};
+			(0..reserved)
+				.chain(inode_per_grp..8 * bsize)
+				.for_each(|x| set_bit(&mut map, x));
+			self.write_block(gdt.bitmap_inode, &map);
+			for i in 0..table_blocks {
+				self.write_block(
+					gdt.inode_table + i as u32,
+					&crate::vec![0; bsize]
+				);
+			}
+
+			gdt.unalloc_block = (size - used) as u16;
+			gdt.unalloc_inodes = (inode_per_grp - reserved) as u16;
+			self.write_gdt_entry(group, &gdt);
+			free_blocks += size - used;
+			free_inodes += inode_per_grp - reserved;
+		}
+		self.sblock.blocks_unalloc = free_blocks as u32;
+		self.sblock.inode_unalloc = free_inodes as u32;
+		self.write_superblock();
+	}
+
+	/// Create the root directory, its '..' refers to itself
+	fn write_root(&mut self) -> Result<(), ErrNo> {
+		let bsize = self.sblock.bsize();
+		let block_no = self.alloc_block(0)? as u32;
+		let mut root = Inode::new();
+		root.tperm = inode::ITYPE_DIR | 0o755;
+		root.count_hl = 2;
+		root.count_ds = (bsize / 512) as u32;
+		root.size_lh = bsize as u32;
+		root.dbp[0] = block_no;
+		self.write_inode(ROOT_INODE, &root);
+		let dtype = inode::Dtype::Directory as u8;
+		self.write_dentries(
+			block_no,
+			crate::vec![
+				Dentry::new(ROOT_INODE as u32, ".", dtype),
+				Dentry::new(ROOT_INODE as u32, "..", dtype),
+			]
+		)
+		.map_err(|_| ErrNo::EIO)?;
+		self.update_used_dirs(ROOT_INODE, 1);
+		Ok(())
+	}
+
+	/// Copy the superblock and the descriptors to the groups keeping a backup
+	fn write_backups(&mut self) {
+		let bsize = self.sblock.bsize();
+		let first = self.sblock.superblock_block as usize;
+		let per_group = self.sblock.block_per_grp() as usize;
+		let gdt: Vec<Vec<u8>> = (0..self.gdt_blocks() as u32)
+			.map(|i| self.read_block(first as u32 + 1 + i))
+			.collect();
+		for group in 1..self.sblock.block_grp_count() as usize {
+			if !self.sblock.has_super(group) {
+				continue;
+			}
+			if let Some(extension) = &mut self.sblock.extension {
+				extension.bgroup_superblock = group as u16;
+			}
+			let mut block: Vec<u8> = crate::vec![0; bsize];
+			let sblock = self.sblock.into_boxed_slice();
+			block[..sblock.len()].copy_from_slice(&sblock);
+			let start = (first + group * per_group) as u32;
+			self.write_block(start, &block);
+			for (i, block) in gdt.iter().enumerate() {
+				self.write_block(start + 1 + i as u32, block);
+			}
+		}
+		if let Some(extension) = &mut self.sblock.extension {
+			extension.bgroup_superblock = 0;
+		}
+	}
+}
+
+fn set_bit(map: &mut [u8], bit: usize) {
+	map[bit / 8] |= 1 << (bit % 8);
+}
+
+/// Filesystem made on a 128 KiB ramdisk, for the tests of the ext2 modules
+#[cfg(test)]
+pub fn ramdisk_fs(options: &MkfsOptions) -> Ext2 {
+	let disk = crate::disk::ramdisk::RamDisk::new(128 * 1024);
+	mkfs(Box::new(disk), options).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+	use super::{mkfs, ramdisk_fs, MkfsOptions};
+	use crate::disk::ramdisk::RamDisk;
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::inode::{self, Inode};
+	use crate::fs::ext2::perm::Context;
+	use crate::fs::ext2::{block, Ext2};
+	use crate::string::String;
+	use alloc::boxed::Box;
+
+	#[sys_macros::test_case]
+	fn mkfs_creates_clean_filesystem() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		assert!(ext2.is_valid());
+		assert_eq!(ext2.sblock.bsize(), 1024);
+		assert!(ext2
+			.recurs_find("/lost+found", 2)
+			.is_some_and(|x| x.1.is_dir()));
+		let report = ext2.fsck(false);
+		assert!(report.is_clean(), "{:?}", report);
+	}
+
+	#[sys_macros::test_case]
+	fn mkfs_multiple_groups() {
+		// 4 groups of 32 blocks, groups 1 and 3 have a backup superblock
+		let options = MkfsOptions {
+			inode_count: Some(64),
+			label: String::from("yak"),
+			blocks_per_group: Some(32),
+			..Default::default()
+		};
+		let mut ext2 = ramdisk_fs(&options);
+		assert_eq!(ext2.sblock.block_grp_count(), 4);
+		assert!(ext2.sblock.inode_count() >= 64);
+		assert!(ext2.fsck(false).is_clean());
+		let ctx = Context::new(2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		assert!(ext2.rename("/dir/file", "/file", &ctx).is_ok());
+		assert!(ext2.remove_recursive("/dir", &ctx).is_ok());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn mkfs_multiple_gdt_blocks() {
+		// 64 groups of 16 blocks need two blocks of descriptors
+		let options =
+			MkfsOptions { blocks_per_group: Some(16), ..Default::default() };
+		let disk = RamDisk::new(1024 * 1024);
+		let mut ext2 = mkfs(Box::new(disk), &options).unwrap();
+		assert_eq!(ext2.sblock.block_grp_count(), 64);
+		assert_eq!(ext2.gdt_blocks(), 2);
+		// Backup of group 1
+		assert_eq!(ext2.read_block(17 + 2), ext2.read_block(1 + 2));
+		let ctx = Context::new(2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		let report = ext2.fsck(false);
+		assert!(report.is_clean(), "{:?}", report);
+		assert!(Ext2::new(ext2.diskio).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn mkfs_large_blocks() {
+		let options = MkfsOptions { block_size: 4096, ..Default::default() };
+		let mut ext2 = ramdisk_fs(&options);
+		assert_eq!(ext2.sblock.bsize(), 4096);
+		assert_eq!(ext2.sblock.superblock_block, 0);
+		let ctx = Context::new(2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn mount_checks_features() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		// Without filetype, entries are written with an unknown type
+		let ext = ext2.sblock.extension.as_mut().unwrap();
+		ext.req_features &= !block::REQFEAT_DE_TYPEFIELD;
+		assert!(!ext2.sblock.has_filetype());
+		let ctx = Context::new(2);
+		assert!(ext2.create("/file", &ctx, 0o644).is_ok());
+		let dentry = ext2.read_dir(2).into_iter().find(|d| d.name == "file");
+		assert_eq!(dentry.map(|d| d.r#type), Some(0));
+		// Compression is refused
+		let ext = ext2.sblock.extension.as_mut().unwrap();
+		ext.req_features |= 0x0001;
+		ext2.write_superblock();
+		assert_eq!(Ext2::new(ext2.diskio).err(), Some(0x02));
+		// Directory btrees are only mounted read-only
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ext = ext2.sblock.extension.as_mut().unwrap();
+		ext.ro_features |= 0x0004;
+		ext2.write_superblock();
+		let ext2 = Ext2::new(ext2.diskio).expect("Failed to mount read-only");
+		assert_eq!(ext2.check_writable(), Err(ErrNo::EROFS));
+	}
+
+	#[sys_macros::test_case]
+	fn large_file_size() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let mut inode = Inode::new();
+		inode.tperm = inode::ITYPE_REGU | 0o644;
+		assert!(ext2.set_file_size(&mut inode, 5 << 30).is_ok());
+		assert_eq!(inode.size(), 5 << 30);
+		assert!(ext2.sblock.large_file());
+	}
+
+	#[sys_macros::test_case]
+	fn mkfs_invalid_options() {
+		let options = MkfsOptions { block_size: 512, ..Default::default() };
+		let res = mkfs(Box::new(RamDisk::new(128 * 1024)), &options);
+		assert_eq!(res.err(), Some(ErrNo::EINVAL));
+		let res = mkfs(Box::new(RamDisk::new(4096)), &MkfsOptions::default());
+		assert_eq!(res.err(), Some(ErrNo::ENOSPC));
+	}
+}
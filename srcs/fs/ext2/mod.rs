@@ -1,15 +1,25 @@
 use crate::alloc::vec;
 use crate::disk::DiskIO;
+use crate::errno::ErrNo;
 use crate::pci::ide::IDE;
-use crate::string::ToString;
+use crate::spin::Mutex;
 use crate::utils::math::roundup;
-use crate::utils::path::Path;
 use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
 
+pub mod acl;
 mod bitmap;
 pub mod block;
+pub mod dcache;
+pub mod fsck;
 mod gdt;
+pub mod htree;
 pub mod inode;
+pub mod journal;
+pub mod mkfs;
+pub mod namei;
+pub mod perm;
+pub mod xattr;
 
 /// Current read/write use entire block to perform operations
 /// In the filesystem created to test it this means we read/write 16 sectors for each operations
@@ -17,18 +27,72 @@ pub mod inode;
 pub struct Ext2 {
 	sector_size: usize,
 	diskio:      Box<dyn DiskIO + Send>,
-	pub sblock:  block::BaseSuperblock
+	pub sblock:  block::BaseSuperblock,
+	/// Journal of ext3 filesystems
+	journal:     Option<journal::Journal>,
+	/// Hash parameters of directory indexes
+	dx_hash:     htree::HashInfo,
+	/// Entries found by lookups, see dentry_find
+	dcache:      Mutex<dcache::DentryCache>,
+	/// Inodes of the files used as swap areas, they can't be removed until swapoff
+	swapfiles:   BTreeSet<usize>,
+	/// Index in disk::discover of the disk holding the filesystem, None for ramdisks
+	pub diskno:  Option<usize>,
+	/// Set when mounted with read-only compatible features that are not implemented
+	readonly:    bool
 }
 
 impl Ext2 {
+	/// Mount the filesystem found on diskio, its journal is replayed if needed.
+	/// Fails with 0x01 if it isn't an ext2 filesystem and with 0x02 if it uses required
+	/// features that are not implemented. Read-only compatible features that are not
+	/// implemented mount it read-only since writing to it could corrupt it.
+	/// A corrupted journal fails with 0x03
 	pub fn new(mut diskio: Box<dyn DiskIO + Send>) -> Result<Self, u8> {
 		let sector_size = diskio.sector_size() as usize;
 		let sblock = read_superblock(&mut diskio)?;
-		let fs = Self { sector_size, diskio, sblock };
-		if fs.is_valid() {
-			Ok(fs)
-		} else {
-			Err(0x01)
+		let mut fs = Self {
+			sector_size,
+			diskio,
+			sblock,
+			journal: None,
+			dx_hash: htree::HashInfo::default(),
+			dcache: Mutex::new(dcache::DentryCache::new()),
+			swapfiles: BTreeSet::new(),
+			diskno: None,
+			readonly: false
+		};
+		if !fs.is_valid() {
+			return Err(0x01);
+		}
+		match fs.sblock.unsupported_features() {
+			(0, ro_compat) => {
+				if ro_compat != 0 {
+					crate::kprintln!(
+						"ext2: unsupported features (ro_compat: {:#x}), mounted read-only",
+						ro_compat
+					);
+					fs.readonly = true;
+				}
+				fs.load_journal()?;
+				fs.dx_hash = fs.read_hash_info();
+				Ok(fs)
+			},
+			(incompat, _) => {
+				crate::kprintln!(
+					"ext2: unsupported features (incompat: {:#x})",
+					incompat
+				);
+				Err(0x02)
+			}
+		}
+	}
+
+	/// EROFS if the filesystem is mounted read-only, checked before modifying it
+	pub fn check_writable(&self) -> Result<(), ErrNo> {
+		match self.readonly {
+			true => Err(ErrNo::EROFS),
+			false => Ok(())
 		}
 	}
 
@@ -66,7 +130,8 @@ impl Ext2 {
 		let inode_table_block = self
 			.get_gdt_entry(self.inode_to_bgroup(inode as u32) as usize)
 			.inode_table;
-		let offset = (inode - 1) * self.inode_size() as u32;
+		let index = (inode - 1) % self.sblock.inode_per_grp();
+		let offset = index * self.inode_size() as u32;
 		inode_table_block + offset / self.sblock.bsize() as u32
 	}
 
@@ -91,8 +156,12 @@ impl Ext2 {
 		self.sblock.inode_size()
 	}
 
-	/// Read an entire block from disk
+	/// Read an entire block from disk, or its version written by the running transaction
 	pub fn read_block(&self, block_no: u32) -> crate::vec::Vec<u8> {
+		if let Some(block) = self.journal.as_ref().and_then(|x| x.get(block_no))
+		{
+			return block.clone();
+		}
 		let mut bsize = self.sblock.bsize();
 		let mut nb_sector = bsize / self.sector_size;
 		// sector_size > bsize
@@ -102,7 +171,9 @@ impl Ext2 {
 		let buffer: Vec<u8> = vec![0; self.sector_size];
 		let sector_per_block = bsize as f64 / self.sector_size as f64;
 
-		let first_sector = (bsize * block_no as usize) / self.sector_size;
+		// Computed on 64 bits, 4KiB blocks overflow past 4GiB
+		let first_sector =
+			(bsize as u64 * block_no as u64 / self.sector_size as u64) as usize;
 		let mut block: crate::vec::Vec<u8> = crate::vec::Vec::new();
 		for i in first_sector..first_sector + nb_sector {
 			self.diskio
@@ -120,7 +191,11 @@ impl Ext2 {
 		block
 	}
 
+	/// Write an entire block, held in the running transaction if there is one
 	fn write_block(&mut self, block_no: u32, block: &[u8]) {
+		if self.journal.as_ref().is_some_and(|x| x.is_running()) {
+			return self.journal_write(block_no, block);
+		}
 		let bsize = self.sblock.bsize();
 		let sector_per_block = bsize / self.sector_size as usize;
 
@@ -162,27 +237,34 @@ impl Ext2 {
 			inodeno,
 			index
 		);
-		let mut vec = Into::<Vec<u8>>::into(*inode);
-		while vec.len() != self.inode_size() as usize {
-			vec.push(0);
-		}
-		block[index..index + self.inode_size() as usize]
-			.copy_from_slice(vec.as_slice());
+		// Inodes bigger than 128 bytes keep their extra fields untouched
+		let vec = Into::<Vec<u8>>::into(*inode);
+		block[index..index + vec.len()].copy_from_slice(vec.as_slice());
 		self.write_block(block_no, block.as_slice());
 	}
 
+	/// Block and offset of a Group Descriptor Table entry.
+	/// The table starts in the block following the superblock and may span several blocks
+	fn gdt_entry_location(&self, entry: usize) -> (u32, usize) {
+		let bsize = self.sblock.bsize();
+		let offset = entry * core::mem::size_of::<gdt::GdtEntry>();
+		let block_no =
+			self.sblock.superblock_block as usize + 1 + offset / bsize;
+		(block_no as u32, offset % bsize)
+	}
+
+	/// Blocks used by the group descriptor table, it follows the superblock and its backups
+	pub fn gdt_blocks(&self) -> usize {
+		let size = core::mem::size_of::<gdt::GdtEntry>();
+		(self.sblock.block_grp_count() as usize * size)
+			.div_ceil(self.sblock.bsize())
+	}
+
 	/// Read disk to recover Group Descriptor Table entry given an index
 	fn get_gdt_entry(&self, entry: usize) -> gdt::GdtEntry {
-		let entry_per_sector =
-			self.sector_size as usize / core::mem::size_of::<gdt::GdtEntry>();
-		let sector_no = entry / entry_per_sector;
-
-		let block = self.read_block(1 + (self.sblock.bsize() == 1024) as u32);
-		let entry_start = (sector_no * self.sector_size as usize)
-			+ (entry % entry_per_sector)
-				* core::mem::size_of::<gdt::GdtEntry>();
-		let entry = gdt::GdtEntry::from(&block[entry_start..entry_start + 32]);
-		entry
+		let (block_no, entry_start) = self.gdt_entry_location(entry);
+		let block = self.read_block(block_no);
+		gdt::GdtEntry::from(&block[entry_start..entry_start + 32])
 	}
 
 	pub fn read_inode_map(&self, group: usize) -> bitmap::Bitmap {
@@ -204,37 +286,199 @@ impl Ext2 {
 		self.write_block(inode_no.bitmap_inode, &map.map);
 	}
 
-	pub fn alloc_node(&mut self, group: usize) -> usize {
-		let mut map = self.read_inode_map(group);
-		let nodeno = map.get_free_node().unwrap();
-		self.sblock.inode_unalloc -= 1;
-		// TODO: write superblock
-		// 		self.write_slice(nodeno as u32, 0, &*self.sblock.into_boxed_slice());
-		self.write_inode_map(group, map);
-		nodeno
+	/// Write back a Group Descriptor Table entry, see get_gdt_entry
+	fn write_gdt_entry(&mut self, entry: usize, gdt: &gdt::GdtEntry) {
+		let (block_no, offset) = self.gdt_entry_location(entry);
+		let size = core::mem::size_of::<gdt::GdtEntry>();
+		let mut block = self.read_block(block_no);
+		block[offset..offset + size]
+			.copy_from_slice(&Into::<Vec<u8>>::into(gdt));
+		self.write_block(block_no, &block);
+	}
+
+	/// Set the size of a regular file. Sizes of 2GiB or more need the large_file feature,
+	/// it is turned on when missing, revision 0 filesystems can't hold them
+	pub fn set_file_size(
+		&mut self,
+		inode: &mut inode::Inode,
+		size: u64
+	) -> Result<(), ErrNo> {
+		if size >= 1 << 31 && !self.sblock.large_file() {
+			if self.sblock.version().0 < 1 {
+				return Err(ErrNo::EFBIG);
+			}
+			self.sblock.set_large_file();
+			self.write_superblock();
+		}
+		(inode.size_lh, inode.size_uh) = (size as u32, (size >> 32) as u32);
+		Ok(())
+	}
+
+	/// Block holding the superblock and its offset inside it
+	fn superblock_location(&self) -> (u32, usize) {
+		let bsize = self.sblock.bsize();
+		((1024 / bsize) as u32, 1024 % bsize)
+	}
+
+	/// Write the superblock back to disk, backup copies are left untouched
+	fn write_superblock(&mut self) {
+		let (block_no, offset) = self.superblock_location();
+		let mut block = self.read_block(block_no);
+		let sblock = self.sblock.into_boxed_slice();
+		block[offset..offset + sblock.len()].copy_from_slice(&sblock);
+		self.write_block(block_no, &block);
+	}
+
+	/// Allocate an inode, group is tried first then the following ones, its slot is zeroed.
+	/// Return the inode number or ENOSPC if every group is full
+	pub fn alloc_node(&mut self, group: usize) -> Result<usize, ErrNo> {
+		let groups = self.sblock.block_grp_count() as usize;
+		let per_group = self.sblock.inode_per_grp() as usize;
+		for i in 0..groups {
+			let group = (group + i) % groups;
+			let mut gdt = self.get_gdt_entry(group);
+			if gdt.unalloc_inodes == 0 {
+				continue;
+			}
+			let mut map = self.read_inode_map(group);
+			match map.get_free_node() {
+				Some(bit) if bit < per_group => {
+					self.write_inode_map(group, map);
+					gdt.unalloc_inodes -= 1;
+					self.write_gdt_entry(group, &gdt);
+					self.sblock.inode_unalloc -= 1;
+					self.write_superblock();
+					let inodeno = group * per_group + bit + 1;
+					self.clear_inode(inodeno);
+					return Ok(inodeno);
+				},
+				_ => continue
+			}
+		}
+		Err(ErrNo::ENOSPC)
 	}
 
-	pub fn free_node(&mut self, group: usize, inodeno: usize) {
+	/// Zero the whole on-disk slot of an inode, write_inode only covers its first 128 bytes
+	/// and a reused inode must not inherit the extra fields of the previous one
+	fn clear_inode(&mut self, inodeno: usize) {
+		// Entries cached for the directory that used the inode before are stale
+		self.dcache.lock().forget_dir(inodeno);
+		let block_no = self.inode_to_block(inodeno as u32);
+		let index = self.inode_to_offset(inodeno as u32) as usize;
+		let mut block = self.read_block(block_no);
+		block[index..index + self.inode_size() as usize].fill(0);
+		self.write_block(block_no, &block);
+	}
+
+	/// Mark an inode as unused in its group bitmap
+	pub fn free_node(&mut self, inodeno: usize) {
+		let group = self.inode_to_bgroup(inodeno as u32) as usize;
+		let bit = (inodeno - 1) % self.sblock.inode_per_grp() as usize;
 		let mut map = self.read_inode_map(group);
-		map.unset_node(inodeno);
-		self.sblock.inode_unalloc += 1;
-		// TODO: write superblock
-		// self.write_slice(nodeno as u32, 0, &*self.sblock.into_boxed_slice());
+		if !map.get_node(bit) {
+			return;
+		}
+		map.unset_node(bit);
 		self.write_inode_map(group, map);
+		let mut gdt = self.get_gdt_entry(group);
+		gdt.unalloc_inodes += 1;
+		self.write_gdt_entry(group, &gdt);
+		self.sblock.inode_unalloc += 1;
+		self.write_superblock();
 	}
 
-	pub fn alloc_block(&mut self, group: usize) -> usize {
+	/// Allocate a zeroed block, group is tried first then the following ones.
+	/// Return the block number or ENOSPC if every group is full
+	pub fn alloc_block(&mut self, group: usize) -> Result<usize, ErrNo> {
+		let groups = self.sblock.block_grp_count() as usize;
+		let per_group = self.sblock.block_per_grp() as usize;
+		let first = self.sblock.superblock_block as usize;
+		for i in 0..groups {
+			let group = (group + i) % groups;
+			let mut gdt = self.get_gdt_entry(group);
+			if gdt.unalloc_block == 0 {
+				continue;
+			}
+			let mut map = self.read_block_map(group);
+			match map.get_free_node() {
+				Some(bit) if bit < per_group => {
+					self.write_block_map(group, map);
+					gdt.unalloc_block -= 1;
+					self.write_gdt_entry(group, &gdt);
+					self.sblock.blocks_unalloc -= 1;
+					self.write_superblock();
+					let block_no = first + group * per_group + bit;
+					self.write_block(
+						block_no as u32,
+						crate::vec![0; self.sblock.bsize()].as_slice()
+					);
+					return Ok(block_no);
+				},
+				_ => continue
+			}
+		}
+		Err(ErrNo::ENOSPC)
+	}
+
+	/// Mark a block as unused in its group bitmap
+	pub fn free_block(&mut self, block_no: usize) {
+		let per_group = self.sblock.block_per_grp() as usize;
+		let index = block_no - self.sblock.superblock_block as usize;
+		let (group, bit) = (index / per_group, index % per_group);
 		let mut map = self.read_block_map(group);
-		let nodeno = map.get_free_node().unwrap();
-		self.sblock.blocks_unalloc -= 1;
-		// TODO: write superblock
-		// 		self.write_slice(nodeno as u32, 0, &*self.sblock.into_boxed_slice());
+		if !map.get_node(bit) {
+			return;
+		}
+		map.unset_node(bit);
 		self.write_block_map(group, map);
-		self.write_block(
-			nodeno as u32,
-			crate::vec![0; self.sblock.bsize()].as_slice()
-		);
-		nodeno
+		let mut gdt = self.get_gdt_entry(group);
+		gdt.unalloc_block += 1;
+		self.write_gdt_entry(group, &gdt);
+		self.sblock.blocks_unalloc += 1;
+		self.write_superblock();
+	}
+
+	/// Add delta to the directory count of the group owning inodeno
+	pub fn update_used_dirs(&mut self, inodeno: usize, delta: i16) {
+		let group = self.inode_to_bgroup(inodeno as u32) as usize;
+		let mut gdt = self.get_gdt_entry(group);
+		gdt.dir_count = gdt.dir_count.saturating_add_signed(delta);
+		self.write_gdt_entry(group, &gdt);
+	}
+
+	/// Free every data block of an inode, indirect blocks included.
+	/// The inode block pointers, size and sector count are reset
+	pub fn free_data_blocks(&mut self, inode: &mut inode::Inode) {
+		for i in 0..inode.dbp.len() {
+			if inode.dbp[i] != 0 {
+				self.free_block(inode.dbp[i] as usize);
+			}
+		}
+		self.free_indirect(inode.sibp, 1);
+		self.free_indirect(inode.dibp, 2);
+		self.free_indirect(inode.tibp, 3);
+		inode.dbp = [0; 12];
+		(inode.sibp, inode.dibp, inode.tibp) = (0, 0, 0);
+		(inode.size_lh, inode.size_uh) = (0, 0);
+		inode.count_ds = 0;
+	}
+
+	/// Free an indirect block and every block it points to.
+	/// depth is 1 for a singly indirect block, 2 for doubly and 3 for triply
+	fn free_indirect(&mut self, block_no: u32, depth: usize) {
+		if block_no == 0 {
+			return;
+		}
+		let block = self.read_block(block_no);
+		for pointer in block.chunks_exact(4) {
+			let pointer = u32::from_le_bytes(pointer.try_into().unwrap());
+			match (pointer, depth) {
+				(0, _) => {},
+				(pointer, 1) => self.free_block(pointer as usize),
+				(pointer, depth) => self.free_indirect(pointer, depth - 1)
+			}
+		}
+		self.free_block(block_no as usize);
 	}
 
 	/// Read disk to recover inode struct correcponding to the index passed as parameter
@@ -270,7 +514,8 @@ impl Ext2 {
 	}
 
 	/// Find file inside dentry given the dentry inode and file searched.
-	/// Currently ignore error cases and remain basic
+	/// Found entries are cached, indexed directories only have the blocks of the name hash
+	/// read
 	///
 	/// # Arguments
 	///
@@ -294,22 +539,38 @@ impl Ext2 {
 		inodeno: usize,
 		filename: &str
 	) -> Option<inode::Dentry> {
+		if let Some(dentry) = self.dcache.lock().get(inodeno, filename) {
+			return Some(dentry);
+		}
 		// Retrieve inode at index inodeno
 		let inode = self.get_inode_entry(inodeno);
-		// Read block pointed by inode
-		for block_no in inode.get_blocks_no() {
-			let block = self.read_block(block_no);
-			let mut entry_start = 0;
-			while entry_start != self.sblock.bsize() {
-				let dentry =
-					inode::Dentry::from(&block[entry_start..block.len()]);
-				entry_start = entry_start + dentry.dentry_size as usize;
-				if dentry.name == filename {
-					return Some(dentry);
-				}
+		if !inode.is_dir() {
+			return None;
+		}
+		let dentry = self
+			.dentry_blocks(&inode, filename)
+			.into_iter()
+			.find_map(|block_no| {
+				self.get_dentries(block_no)
+					.into_iter()
+					.find(|x| x.inode != 0 && x.name == filename)
+			})?;
+		self.dcache.lock().insert(inodeno, &dentry);
+		Some(dentry)
+	}
+
+	/// Blocks of a directory that may hold the entry name, every block unless it is indexed.
+	/// '.' and '..' of an indexed directory are in its first block, before the index root
+	fn dentry_blocks(&self, inode: &inode::Inode, name: &str) -> Vec<u32> {
+		if self.is_indexed(inode) {
+			if name == "." || name == ".." {
+				return crate::vec![self.get_block_at(inode, 0)];
+			}
+			if let Some(blocks) = self.dx_leaf_blocks(inode, name) {
+				return blocks;
 			}
 		}
-		None
+		self.get_data_blocks(inode)
 	}
 
 	/// Find file given it's path, start search from root directory
@@ -356,21 +617,137 @@ impl Ext2 {
 		path: &str,
 		inodeno: usize
 	) -> Option<(usize, inode::Inode)> {
-		if path.len() == 0 {
-			// Caller has found the entry we search
-			return Some((inodeno, self.get_inode_entry(inodeno)));
+		self.lookup(path, &perm::Context::new(inodeno), true).ok()
+	}
+
+	/// Return every data block of an inode in file order, following the indirect blocks
+	pub fn get_data_blocks(&self, inode: &inode::Inode) -> Vec<u32> {
+		let mut blocks_no = inode.get_blocks_no();
+		if inode.sibp != 0 {
+			blocks_no.append(&mut inode.get_sibp_blocks_no(self));
 		}
-		if path.starts_with('/') {
-			return self.get_inode_of(path);
+		if inode.dibp != 0 {
+			blocks_no.append(&mut inode.get_dibp_blocks_no(self));
 		}
-		let opt = path.find('/');
-		let filename = match opt {
-			Some(index) => &path[..index],
-			None => path
-		};
-		let dentry = self.dentry_find(inodeno, filename)?;
-		let newpath = path.trim_start_matches(filename).trim_start_matches('/');
-		self.recurs_find(newpath, dentry.inode as usize)
+		if inode.tibp != 0 {
+			blocks_no.append(&mut inode.get_tibp_blocks_no(self));
+		}
+		blocks_no
+	}
+
+	/// First disk sector of each block of a regular file in file order, for the swap files
+	/// that are read and written on the disk directly. Files with holes are refused since some
+	/// of their content has no sector
+	pub fn file_sectors(
+		&self,
+		inode: &inode::Inode
+	) -> Result<Vec<u32>, ErrNo> {
+		let bsize = self.sblock.bsize();
+		let nb = inode.size() as usize / bsize;
+		let blocks = self.get_data_blocks(inode);
+		if blocks.len() < nb || bsize < self.sector_size {
+			return Err(ErrNo::EINVAL);
+		}
+		Ok(blocks[..nb]
+			.iter()
+			.map(|block| {
+				(bsize as u64 * *block as u64 / self.sector_size as u64) as u32
+			})
+			.collect())
+	}
+
+	pub fn sector_size(&self) -> usize {
+		self.sector_size
+	}
+
+	/// Disk of the filesystem, see file_sectors
+	pub fn disk(&mut self) -> &mut (dyn DiskIO + Send) {
+		&mut *self.diskio
+	}
+
+	/// Copy the content of a file starting at offset into dst.
+	/// Return the number of bytes read, 0 once the end of file is reached
+	pub fn read_data(
+		&self,
+		inode: &inode::Inode,
+		offset: usize,
+		dst: &mut [u8]
+	) -> usize {
+		let bsize = self.sblock.bsize();
+		let size = inode.size() as usize;
+		if offset >= size {
+			return 0;
+		}
+		let end = core::cmp::min(size, offset + dst.len());
+		let mut pos = offset;
+		while pos < end {
+			let start = pos % bsize;
+			let len = core::cmp::min(bsize - start, end - pos);
+			let dst = &mut dst[pos - offset..pos - offset + len];
+			match self.get_block_at(inode, pos / bsize) {
+				// Hole in a sparse file, read as 0
+				0 => dst.fill(0),
+				block_no => {
+					let block = self.read_block(block_no);
+					dst.copy_from_slice(&block[start..start + len]);
+				}
+			}
+			pos += len;
+		}
+		end - offset
+	}
+
+	/// Return the block storing the index-th block of an inode, 0 if it is a hole
+	pub fn get_block_at(&self, inode: &inode::Inode, index: usize) -> u32 {
+		let ptrs = self.sblock.bsize() / core::mem::size_of::<u32>();
+		if index < inode.dbp.len() {
+			return inode.dbp[index];
+		}
+		let index = index - inode.dbp.len();
+		if index < ptrs {
+			return self.read_block_pointer(inode.sibp, index);
+		}
+		let index = index - ptrs;
+		if index < ptrs * ptrs {
+			let sibp = self.read_block_pointer(inode.dibp, index / ptrs);
+			return self.read_block_pointer(sibp, index % ptrs);
+		}
+		let index = index - ptrs * ptrs;
+		let dibp = self.read_block_pointer(inode.tibp, index / (ptrs * ptrs));
+		let sibp = self.read_block_pointer(dibp, (index / ptrs) % ptrs);
+		self.read_block_pointer(sibp, index % ptrs)
+	}
+
+	/// Read the index-th block number stored in an indirect block
+	fn read_block_pointer(&self, block_no: u32, index: usize) -> u32 {
+		if block_no == 0 {
+			return 0;
+		}
+		let block = self.read_block(block_no);
+		let start = index * core::mem::size_of::<u32>();
+		u32::from_le_bytes(block[start..start + 4].try_into().unwrap())
+	}
+
+	/// Return all used entries of a directory, including '.' and '..'
+	pub fn read_dir(&self, inodeno: usize) -> Vec<inode::Dentry> {
+		let inode = self.get_inode_entry(inodeno);
+		let mut dentries: Vec<inode::Dentry> = Vec::new();
+		for block_no in self.get_data_blocks(&inode) {
+			let block = self.read_block(block_no);
+			let mut entry_start = 0;
+			while entry_start + 8 <= block.len() {
+				let dentry = inode::Dentry::from(&block[entry_start..]);
+				// Block is empty
+				if dentry.dentry_size == 0 {
+					break;
+				}
+				entry_start += dentry.dentry_size as usize;
+				if dentry.inode != 0 {
+					dentries.push(dentry);
+				}
+			}
+		}
+		dentries
 	}
 
 	pub fn write_dentries(
@@ -381,6 +758,10 @@ impl Ext2 {
 		let mut block = self.read_block(block_no);
 		let mut entry_start: usize = 0;
 		let len = dentries.len();
+		// Without the filetype feature this byte is the high half of name_len
+		if !self.sblock.has_filetype() {
+			dentries.iter_mut().for_each(|dentry| dentry.r#type = 0);
+		}
 		for i in 0..len {
 			if dentries[i].dentry_size as usize > block.len() - entry_start {
 				return Err(());
@@ -388,13 +769,10 @@ impl Ext2 {
 			if i == len - 1 {
 				dentries[i].dentry_size = (block.len() - entry_start) as u16;
 			}
-			let mut vec = Into::<Vec<u8>>::into(dentries[i].clone());
-			let len = vec.len();
-			for _ in len..dentries[i].dentry_size as usize {
-				vec.push(0);
-			}
-			block[entry_start..entry_start + dentries[i].dentry_size as usize]
-				.copy_from_slice(&vec);
+			// Unused space of records is left as is, the index root of a directory is
+			// stored after '..'
+			let vec = Into::<Vec<u8>>::into(dentries[i].clone());
+			block[entry_start..entry_start + vec.len()].copy_from_slice(&vec);
 			entry_start += dentries[i].dentry_size as usize;
 		}
 		self.write_block(block_no, &block);
@@ -417,85 +795,155 @@ impl Ext2 {
 		dentries
 	}
 
-	// TODO: do remove_dentry recursive
+	/// Remove the entry named name from a directory and return it.
+	/// The record space is given back to the previous entry of the block, a first entry is
+	/// only marked unused. The inode itself is left untouched
 	pub fn remove_dentry(
 		&mut self,
 		parent_inodeno: usize,
-		dentry: inode::Dentry
-	) {
+		name: &str
+	) -> Result<inode::Dentry, ErrNo> {
+		self.dcache.lock().remove(parent_inodeno, name);
 		let inode = self.get_inode_entry(parent_inodeno);
-		for block_no in inode.get_blocks_no() {
+		for block_no in self.dentry_blocks(&inode, name) {
 			let mut dentries = self.get_dentries(block_no);
-			match dentries.iter().position(|x| x.inode == dentry.inode) {
-				Some(index) => {
-					// TODO: free blocks
-					self.free_node(
-						self.inode_to_bgroup(dentry.inode as u32) as usize,
-						dentry.inode as usize
-					);
+			let found =
+				dentries.iter().position(|x| x.inode != 0 && x.name == name);
+			if let Some(index) = found {
+				let dentry = dentries[index].clone();
+				if index == 0 {
+					dentries[0].inode = 0;
+				} else {
+					dentries[index - 1].dentry_size += dentry.dentry_size;
 					dentries.remove(index);
-					match self.write_dentries(block_no, dentries) {
-						Ok(()) => return,
-						Err(()) => todo!()
-					}
-				},
-				None => {}
+				}
+				self.write_dentries(block_no, dentries)
+					.map_err(|_| ErrNo::EIO)?;
+				self.touch_dir(parent_inodeno);
+				return Ok(dentry);
 			}
 		}
+		Err(ErrNo::ENOENT)
 	}
 
-	// get new inode
-	// get new blocks
-	// fill inode informations
-	// Nothing for link?
-	// fill block information (dentries for directory, file content for a file)
-	// create dentry pointing to the inode
-	// write dentry on disk
-	// write inode on disk
-	// write new imap
-	// write new bmap
-	pub fn add_dentry(&mut self, inodeno: usize, dentry: inode::Dentry) {
-		// Get block and inode
-		let group = self.inode_to_bgroup(inodeno as u32) as usize;
-		let bmap = self.read_block_map(group);
-		let imap = self.read_inode_map(group);
-		crate::dprintln!("Space {} {}", bmap.get_space().0, bmap.get_space().1);
-		crate::dprintln!("Space {} {}", imap.get_space().0, imap.get_space().1);
-
-		self.write_block_map(group, bmap);
-		self.write_inode_map(group, imap);
-
-		// Write new dentry on the parent dir
-		let inode = self.get_inode_entry(inodeno);
-		for block_no in inode.get_blocks_no() {
-			let block = self.read_block(block_no);
+	/// Make the entry named name of a directory point to another inode
+	pub fn replace_dentry(
+		&mut self,
+		parent_inodeno: usize,
+		name: &str,
+		inodeno: usize,
+		r#type: u8
+	) -> Result<(), ErrNo> {
+		self.dcache.lock().remove(parent_inodeno, name);
+		let inode = self.get_inode_entry(parent_inodeno);
+		for block_no in self.dentry_blocks(&inode, name) {
 			let mut dentries = self.get_dentries(block_no);
-			match dentries.last_mut() {
-				Some(last) => {
-					let len = roundup(8 + last.name.len(), 4) as u16;
-					if dentry.dentry_size <= last.dentry_size - len {
-						let mut new_dentry = dentry.clone();
-						new_dentry.dentry_size = last.dentry_size - len;
-						last.dentry_size = len;
-						dentries.push(new_dentry);
-						if self.write_dentries(block_no, dentries).is_err() {
-							continue;
-						}
-						return;
-					}
+			let found =
+				dentries.iter_mut().find(|x| x.inode != 0 && x.name == name);
+			if let Some(dentry) = found {
+				dentry.inode = inodeno as u32;
+				dentry.r#type = r#type;
+				self.write_dentries(block_no, dentries)
+					.map_err(|_| ErrNo::EIO)?;
+				self.touch_dir(parent_inodeno);
+				return Ok(());
+			}
+		}
+		Err(ErrNo::ENOENT)
+	}
+
+	/// Add an entry to a directory.
+	/// Indexed directories get it in the leaf of its hash. Otherwise the entry is put in the
+	/// first record with enough unused space, a new block is added to the directory if there
+	/// is none. A directory outgrowing its first block gets indexed with dir_index
+	pub fn add_dentry(
+		&mut self,
+		inodeno: usize,
+		dentry: inode::Dentry
+	) -> Result<(), ErrNo> {
+		let mut inode = self.get_inode_entry(inodeno);
+		if self.is_indexed(&inode) {
+			match self.dx_add_dentry(inodeno, &mut inode, &dentry) {
+				Some(res) => {
+					res?;
+					self.touch_dir(inodeno);
+					return Ok(());
 				},
+				// Corrupted index, the directory is only read linearly from now on
 				None => {
-					let mut new_dentry = dentry.clone();
-					new_dentry.dentry_size = block.len() as u16;
-					dentries.push(new_dentry);
-					if self.write_dentries(block_no, dentries).is_err() {
-						continue;
-					}
-					return;
+					inode.flags &= !inode::IFLAG_HASHINDEX;
+					self.write_inode(inodeno, &inode);
 				}
 			}
 		}
-		todo!("not enough space to write ?");
+		let blocks = self.get_data_blocks(&inode);
+		for block_no in blocks.iter() {
+			if self.insert_dentry(*block_no, &dentry)? {
+				self.clear_index(inodeno);
+				self.touch_dir(inodeno);
+				return Ok(());
+			}
+		}
+		if self.sblock.dir_index()
+			&& blocks.len() == 1
+			&& self.dx_make_indexed(inodeno, &mut inode)?
+		{
+			if let Some(res) = self.dx_add_dentry(inodeno, &mut inode, &dentry)
+			{
+				res?;
+				self.touch_dir(inodeno);
+				return Ok(());
+			}
+		}
+		// Directory is full, grow it by one block
+		let (_, block_no) = self.append_dir_block(inodeno, &mut inode)?;
+		self.write_dentries(block_no, crate::vec![dentry])
+			.map_err(|_| ErrNo::EIO)?;
+		self.clear_index(inodeno);
+		self.touch_dir(inodeno);
+		Ok(())
+	}
+
+	/// Entries added linearly break the index of a directory, other drivers then have to
+	/// read it linearly
+	fn clear_index(&mut self, inodeno: usize) {
+		let mut inode = self.get_inode_entry(inodeno);
+		if inode.flags & inode::IFLAG_HASHINDEX != 0 {
+			inode.flags &= !inode::IFLAG_HASHINDEX;
+			self.write_inode(inodeno, &inode);
+		}
+	}
+
+	/// Put an entry in the first record of a directory block with enough unused space.
+	/// Return false if there is none
+	fn insert_dentry(
+		&mut self,
+		block_no: u32,
+		dentry: &inode::Dentry
+	) -> Result<bool, ErrNo> {
+		let len = roundup(8 + dentry.name.len(), 4) as u16;
+		let mut dentries = self.get_dentries(block_no);
+		for i in 0..dentries.len() {
+			let used = match dentries[i].inode {
+				0 => 0,
+				_ => roundup(8 + dentries[i].name.len(), 4) as u16
+			};
+			if dentries[i].dentry_size - used < len {
+				continue;
+			}
+			let mut new_dentry = dentry.clone();
+			new_dentry.dentry_size = dentries[i].dentry_size - used;
+			if used == 0 {
+				dentries[i] = new_dentry;
+			} else {
+				dentries[i].dentry_size = used;
+				dentries.insert(i + 1, new_dentry);
+			}
+			self.write_dentries(block_no, dentries)
+				.map_err(|_| ErrNo::EIO)?;
+			return Ok(true);
+		}
+		Ok(false)
 	}
 }
 
@@ -543,7 +991,7 @@ pub fn get_file_content(ext2: &Ext2, path: &str, inode: usize) -> Vec<char> {
 	match opt {
 		None => Vec::new(),
 		Some((_, inode)) => {
-			if inode.tperm & inode::ITYPE_REGU == 0 {
+			if !inode.is_regular() {
 				crate::kprintln!("'{}': Not a regular file.", path);
 				return Vec::new();
 			}
@@ -642,171 +1090,96 @@ pub fn list_dir(
 	inode: usize
 ) -> crate::vec::Vec<inode::Dentry> {
 	// let ext2 = Ext2::new(diskno).expect("Disk is not a ext2 filesystem.");
-	let inode = ext2.recurs_find(path, inode);
-	return match inode {
-		None => crate::vec::Vec::new(),
-		Some((_, inode)) => {
-			let mut dentries: crate::vec::Vec<inode::Dentry> =
-				crate::vec::Vec::new();
-			for block_no in inode.get_blocks_no() {
-				let block = ext2.read_block(block_no);
-
-				let mut entry_start: usize = 0;
-				while entry_start != ext2.sblock.bsize() {
-					let dentry =
-						inode::Dentry::from(&block[entry_start..block.len()]);
-					entry_start = entry_start + dentry.dentry_size as usize;
-					dentries.push(dentry);
-				}
-			}
-			dentries
-		}
-	};
+	match ext2.recurs_find(path, inode) {
+		Some((inodeno, inode)) if inode.is_dir() => ext2.read_dir(inodeno),
+		_ => crate::vec::Vec::new()
+	}
 }
 
 pub fn create_file(ext2: &mut Ext2, path: &str, inode_no: usize) {
-	// let mut ext2 = Ext2::new(diskno).expect("Disk is not a ext2 filesystem.");
-	let path = Path::new(path);
-	let filename = path.file_name().unwrap();
-	let binding = path.parent().unwrap();
-	let parent = binding.as_str();
-	let inode = ext2.recurs_find(&parent, inode_no);
-	match inode {
-		None => {
-			crate::kprintln!("Path not found: {}", parent);
-		},
-		Some((inode_no, _)) => {
-			let check_exist = ext2.recurs_find(&filename, inode_no);
-			if check_exist.is_some() {
-				crate::kprintln!("'{}' already exists.", filename);
-				return;
-			}
-			let mut new_inode = inode::Inode::new();
-			// perm: Regular file and 0644
-			new_inode.tperm =
-				inode::ITYPE_REGU
-					| inode::IPERM_UREAD | inode::IPERM_UWRIT
-					| inode::IPERM_GREAD | inode::IPERM_OREAD;
-			// hardlinks: 1
-			new_inode.count_hl = 1;
-			new_inode.count_ds = 1;
-			new_inode.size_lh = 0;
-			new_inode.dbp[0] = ext2
-				.alloc_block(ext2.inode_to_bgroup(inode_no as u32) as usize)
-				as u32;
-			let new_inode_no =
-				ext2.alloc_node(ext2.inode_to_bgroup(inode_no as u32) as usize);
-			ext2.write_inode(new_inode_no, &new_inode); // copy inode to fs
-			let dentry: inode::Dentry = inode::Dentry {
-				inode:       new_inode_no as u32,
-				dentry_size: roundup(8 + filename.len(), 4) as u16,
-				name_length: filename.len() as u8,
-				r#type:      inode::Dtype::Regular as u8,
-				name:        filename.to_string()
-			};
-			ext2.add_dentry(inode_no, dentry);
-		}
+	// perm: Regular file and 0644
+	let perm = inode::IPERM_UREAD
+		| inode::IPERM_UWRIT
+		| inode::IPERM_GREAD
+		| inode::IPERM_OREAD;
+	let ctx = perm::Context::new(inode_no);
+	if let Err(errno) = ext2.transaction(|ext2| ext2.create(path, &ctx, perm)) {
+		crate::kprintln!("'{}': {:?}", path, errno);
 	}
 }
 
-pub fn remove_file(ext2: &mut Ext2, path: &str, inode_no: usize) {
-	// let mut ext2 = Ext2::new(diskno).expect("Disk is not a ext2 filesystem.");
-	let path = Path::new(path);
-	let filename = path.file_name().unwrap();
-	let binding = path.parent().unwrap();
-	let parent = binding.as_str();
-	let inode = ext2.recurs_find(&parent, inode_no);
-	match inode {
-		None => {
-			crate::kprintln!("Path not found: {}", parent);
-		},
-		Some((inode_no, _)) => match ext2.dentry_find(inode_no, &filename) {
-			Some(dentry) => {
-				let inode = ext2.get_inode(dentry.inode as usize);
-				if inode.tperm & inode::ITYPE_DIR != 0 {
-					crate::kprintln!("'{}': Is a directory.", path);
-					return;
-				}
-				ext2.remove_dentry(inode_no, dentry);
-			},
-			None => {
-				crate::kprintln!("'{}' does not exist.", filename);
-			}
-		}
+/// Helper function to remove a file, directories are only removed with their content if
+/// recursive is set
+pub fn remove_file(
+	ext2: &mut Ext2,
+	path: &str,
+	inode_no: usize,
+	recursive: bool
+) {
+	let ctx = perm::Context::new(inode_no);
+	let res = ext2.transaction(|ext2| match recursive {
+		true => ext2.remove_recursive(path, &ctx),
+		false => ext2.unlink(path, &ctx)
+	});
+	if let Err(errno) = res {
+		crate::kprintln!("'{}': {:?}", path, errno);
+	}
+}
+
+/// Helper function to remove an empty folder
+pub fn remove_dir(ext2: &mut Ext2, path: &str, inode_no: usize) {
+	let ctx = perm::Context::new(inode_no);
+	if let Err(errno) = ext2.transaction(|ext2| ext2.rmdir(path, &ctx)) {
+		crate::kprintln!("'{}': {:?}", path, errno);
 	}
 }
 
 /// Helper function to create a folder at a given path
 pub fn create_dir(ext2: &mut Ext2, path: &str, inode_no: usize) {
-	// let mut ext2 = Ext2::new(diskno).expect("Disk is not a ext2 filesystem.");
-	let path = Path::new(path);
-	let new_dir = path.file_name().unwrap();
-	let binding = path.parent().unwrap();
-	let parent = binding.as_str();
-	let inode = ext2.recurs_find(&parent, inode_no);
-	match inode {
-		None => {
-			crate::kprintln!("Path not found: {}", parent);
-		},
-		Some((inode_no, mut inode)) => {
-			let check_exist = ext2.recurs_find(&new_dir, inode_no);
-			if check_exist.is_some() {
-				crate::kprintln!("'{}' already exists.", new_dir);
-				return;
-			}
-			let mut new_inode = inode::Inode::new();
-			// perm: directory and 0755
-			new_inode.tperm =
-				inode::ITYPE_DIR
-					| inode::IPERM_UREAD | inode::IPERM_UWRIT
-					| inode::IPERM_UEXEC | inode::IPERM_GREAD
-					| inode::IPERM_GEXEC | inode::IPERM_OREAD
-					| inode::IPERM_OEXEC;
-			// hardlinks: directory and '.'
-			new_inode.count_hl = 2;
-			new_inode.count_ds = 2;
-			new_inode.size_lh = ext2.sblock.bsize() as u32;
-			new_inode.dbp[0] = ext2
-				.alloc_block(ext2.inode_to_bgroup(inode_no as u32) as usize)
-				as u32;
-			// Allocate inode
-			let new_inode_no =
-				ext2.alloc_node(ext2.inode_to_bgroup(inode_no as u32) as usize);
-			ext2.write_inode(new_inode_no, &new_inode); // copy inode to fs
-			let dentry: inode::Dentry = inode::Dentry {
-				inode:       new_inode_no as u32,
-				dentry_size: roundup(8 + new_dir.len(), 4) as u16,
-				name_length: new_dir.len() as u8,
-				r#type:      inode::Dtype::Directory as u8,
-				name:        new_dir.to_string()
-			};
-			ext2.add_dentry(inode_no, dentry);
-			// Create . and ..
-			let dot_inode_no = ext2
-				.alloc_node(ext2.inode_to_bgroup(new_inode_no as u32) as usize);
-			ext2.write_inode(dot_inode_no, &new_inode); // copy actual inode
-			let dentry: inode::Dentry = inode::Dentry {
-				inode:       dot_inode_no as u32,
-				dentry_size: roundup(8 + ".".len(), 4) as u16,
-				name_length: ".".len() as u8,
-				r#type:      inode::Dtype::Directory as u8,
-				name:        ".".to_string()
-			};
-			ext2.add_dentry(new_inode_no, dentry);
-			let dotdot_inode_no = ext2
-				.alloc_node(ext2.inode_to_bgroup(new_inode_no as u32) as usize);
-			ext2.write_inode(dotdot_inode_no, &inode); // copy previous inode
-			let dentry: inode::Dentry = inode::Dentry {
-				inode:       dotdot_inode_no as u32,
-				dentry_size: roundup(8 + "..".len(), 4) as u16,
-				name_length: "..".len() as u8,
-				r#type:      inode::Dtype::Directory as u8,
-				name:        "..".to_string()
-			};
-			ext2.add_dentry(new_inode_no, dentry);
-			inode.count_hl += 1; // add 1 hard-link (..) to parent
-			ext2.write_inode(inode_no, &inode); // copy inode
-		}
+	// perm: directory and 0755
+	let perm = inode::IPERM_UREAD
+		| inode::IPERM_UWRIT
+		| inode::IPERM_UEXEC
+		| inode::IPERM_GREAD
+		| inode::IPERM_GEXEC
+		| inode::IPERM_OREAD
+		| inode::IPERM_OEXEC;
+	let ctx = perm::Context::new(inode_no);
+	if let Err(errno) = ext2.transaction(|ext2| ext2.mkdir(path, &ctx, perm)) {
+		crate::kprintln!("'{}': {:?}", path, errno);
+	}
+}
+
+/// Helper function to move an entry to a new path
+pub fn rename_file(
+	ext2: &mut Ext2,
+	oldpath: &str,
+	newpath: &str,
+	inode_no: usize
+) {
+	let ctx = perm::Context::new(inode_no);
+	if let Err(errno) =
+		ext2.transaction(|ext2| ext2.rename(oldpath, newpath, &ctx))
+	{
+		crate::kprintln!("'{}' -> '{}': {:?}", oldpath, newpath, errno);
+	}
+}
+
+/// Helper function to create a hard link, or a symbolic link if symbolic is set
+pub fn link_file(
+	ext2: &mut Ext2,
+	target: &str,
+	path: &str,
+	inode_no: usize,
+	symbolic: bool
+) {
+	let ctx = perm::Context::new(inode_no);
+	let res = ext2.transaction(|ext2| match symbolic {
+		true => ext2.symlink(target, path, &ctx).map(|_| ()),
+		false => ext2.link(target, path, &ctx)
+	});
+	if let Err(errno) = res {
+		crate::kprintln!("'{}' -> '{}': {:?}", path, target, errno);
 	}
 }
 
@@ -821,14 +1194,14 @@ pub fn show_inode_info(ext2: &Ext2, path: &str, inode_no: usize) {
 			crate::kprint!("Inode: {:<4} ", inode_no);
 			crate::kprint!(
 				"Type: {:<12}",
-				match inode.tperm {
-					x if x & inode::ITYPE_FIFO != 0 => "fifo",
-					x if x & inode::ITYPE_CHARDEV != 0 => "chardev",
-					x if x & inode::ITYPE_DIR != 0 => "directory",
-					x if x & inode::ITYPE_BLOCK != 0 => "block",
-					x if x & inode::ITYPE_REGU != 0 => "regular",
-					x if x & inode::ITYPE_SYMF != 0 => "symbolic",
-					x if x & inode::ITYPE_SOCK != 0 => "sock",
+				match inode.file_type() {
+					inode::ITYPE_FIFO => "fifo",
+					inode::ITYPE_CHARDEV => "chardev",
+					inode::ITYPE_DIR => "directory",
+					inode::ITYPE_BLOCK => "block",
+					inode::ITYPE_REGU => "regular",
+					inode::ITYPE_SYMF => "symbolic",
+					inode::ITYPE_SOCK => "sock",
 					_ => "unknown"
 				}
 			);
@@ -0,0 +1,777 @@
+//! Path resolution and directory entries management: create, remove, link, rename and symlinks
+
+use super::inode::{self, Dentry, Inode};
+use super::perm::{current_time, Context, MAY_EXEC, MAY_READ, MAY_WRITE};
+use super::{xattr, Ext2};
+use crate::errno::ErrNo;
+use crate::string::String;
+use crate::vec::Vec;
+
+/// Inode of the root directory
+pub const ROOT_INODE: usize = 2;
+/// Maximum number of symbolic links followed while resolving a path
+pub const MAX_SYMLINKS: usize = 40;
+/// Targets shorter than this are stored inside the inode block pointers
+pub const FAST_SYMLINK_MAX: usize = 60;
+
+impl Ext2 {
+	/// Find the inode of path, relative paths start from the directory ctx.cwd and absolute ones
+	/// from ctx.root.
+	/// Every directory crossed needs the search (exec) permission.
+	/// Symbolic links met on the way are followed, the last component is only followed if follow
+	/// is set
+	pub fn lookup(
+		&self,
+		path: &str,
+		ctx: &Context,
+		follow: bool
+	) -> Result<(usize, Inode), ErrNo> {
+		let mut links = 0;
+		self.walk(path, ctx, ctx.cwd, follow, &mut links)
+	}
+
+	fn walk(
+		&self,
+		path: &str,
+		ctx: &Context,
+		start: usize,
+		follow: bool,
+		links: &mut usize
+	) -> Result<(usize, Inode), ErrNo> {
+		let mut inodeno = match path.starts_with('/') {
+			true => ctx.root,
+			false => start
+		};
+		let mut inode = self.get_inode_entry(inodeno);
+		let components: Vec<&str> =
+			path.split('/').filter(|name| !name.is_empty()).collect();
+		for (i, name) in components.iter().enumerate() {
+			if !inode.is_dir() {
+				return Err(ErrNo::ENOTDIR);
+			}
+			self.permission(ctx, inodeno, &inode, MAY_EXEC)?;
+			if *name == ".." && inodeno == ctx.root {
+				continue;
+			}
+			let parent = inodeno;
+			let dentry = self.dentry_find(parent, name).ok_or(ErrNo::ENOENT)?;
+			inodeno = dentry.inode as usize;
+			inode = self.get_inode_entry(inodeno);
+			let last = i == components.len() - 1;
+			if inode.is_symlink() && (!last || follow) {
+				*links += 1;
+				if *links > MAX_SYMLINKS {
+					return Err(ErrNo::ELOOP);
+				}
+				let target = self.read_link(&inode)?;
+				(inodeno, inode) =
+					self.walk(&target, ctx, parent, true, links)?;
+			}
+		}
+		Ok((inodeno, inode))
+	}
+
+	/// Find the directory that contains the last component of path.
+	/// Return the directory inode number and the last component name
+	pub fn lookup_parent(
+		&self,
+		path: &str,
+		ctx: &Context
+	) -> Result<(usize, String), ErrNo> {
+		let path = path.trim_end_matches('/');
+		let (parent, name) = match path.rsplit_once('/') {
+			Some(("", name)) => ("/", name),
+			Some((parent, name)) => (parent, name),
+			None => ("", path)
+		};
+		if name.is_empty() || name == "." || name == ".." {
+			return Err(ErrNo::EINVAL);
+		}
+		if name.len() > u8::MAX as usize {
+			return Err(ErrNo::ENAMETOOLONG);
+		}
+		let (inodeno, inode) = self.lookup(parent, ctx, true)?;
+		if !inode.is_dir() {
+			return Err(ErrNo::ENOTDIR);
+		}
+		Ok((inodeno, String::from(name)))
+	}
+
+	/// Allocate a new inode in the group of its parent directory and link it as path.
+	/// The inode is owned by the caller, or by the group of the parent if it has the setgid bit.
+	/// A default ACL of the parent becomes the access ACL of the inode, restricted by its
+	/// permission bits, and the default ACL of new directories.
+	/// blocks data blocks are allocated with it.
+	/// Return the parent directory and the new inode numbers
+	fn create_inode(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		mut new_inode: Inode,
+		blocks: usize
+	) -> Result<(usize, usize), ErrNo> {
+		let (parent, name) = self.lookup_parent(path, ctx)?;
+		let parent_inode = self.get_inode_entry(parent);
+		self.permission(ctx, parent, &parent_inode, MAY_WRITE | MAY_EXEC)?;
+		if self.dentry_find(parent, &name).is_some() {
+			return Err(ErrNo::EEXIST);
+		}
+		(new_inode.uid, new_inode.gid) = (ctx.uid, ctx.gid);
+		if parent_inode.tperm & inode::IPERM_SETGID != 0 {
+			new_inode.gid = parent_inode.gid;
+			if new_inode.is_dir() {
+				new_inode.tperm |= inode::IPERM_SETGID;
+			}
+		}
+		let mut acls = Vec::new();
+		if let Some(default) = self.get_default_acl(parent, &parent_inode) {
+			let mut access = default.clone();
+			let mode = access.create_masq(new_inode.tperm);
+			new_inode.tperm = (new_inode.tperm & !0o777) | mode;
+			if !access.is_minimal() {
+				acls.push((xattr::XATTR_INDEX_ACL_ACCESS, access));
+			}
+			if new_inode.is_dir() {
+				acls.push((xattr::XATTR_INDEX_ACL_DEFAULT, default));
+			}
+		}
+		let group = self.inode_to_bgroup(parent as u32) as usize;
+		let inodeno = self.alloc_node(group)?;
+		let mut allocated: Vec<usize> = Vec::new();
+		let mut res = Ok(());
+		for i in 0..blocks {
+			match self.alloc_block(group) {
+				Ok(block_no) => {
+					new_inode.dbp[i] = block_no as u32;
+					new_inode.count_ds += (self.sblock.bsize() / 512) as u32;
+					allocated.push(block_no);
+				},
+				Err(errno) => {
+					res = Err(errno);
+					break;
+				}
+			}
+		}
+		for (index, acl) in acls {
+			if res.is_ok() {
+				let value = Some(acl.to_disk());
+				res = self.store_xattr(
+					inodeno,
+					&mut new_inode,
+					index,
+					"",
+					value,
+					0
+				);
+			}
+		}
+		if res.is_ok() {
+			self.write_inode(inodeno, &new_inode);
+			let dentry = Dentry::new(inodeno as u32, &name, new_inode.dtype());
+			res = self.add_dentry(parent, dentry);
+		}
+		if let Err(errno) = res {
+			for block_no in allocated {
+				self.free_block(block_no);
+			}
+			self.release_xattr_block(&mut new_inode);
+			self.free_node(inodeno);
+			return Err(errno);
+		}
+		Ok((parent, inodeno))
+	}
+
+	/// Create an empty regular file
+	pub fn create(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		perm: u16
+	) -> Result<usize, ErrNo> {
+		let mut new_inode = Inode::new();
+		new_inode.tperm = inode::ITYPE_REGU | (perm & 0o7777);
+		new_inode.count_hl = 1;
+		new_inode.count_ds = 0;
+		let (_, inodeno) = self.create_inode(path, ctx, new_inode, 0)?;
+		Ok(inodeno)
+	}
+
+	/// Create an empty directory containing '.' and '..'
+	pub fn mkdir(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		perm: u16
+	) -> Result<usize, ErrNo> {
+		let mut new_inode = Inode::new();
+		new_inode.tperm = inode::ITYPE_DIR | (perm & 0o7777);
+		// hardlinks: entry in parent and '.'
+		new_inode.count_hl = 2;
+		new_inode.count_ds = 0;
+		new_inode.size_lh = self.sblock.bsize() as u32;
+		let (parent, inodeno) = self.create_inode(path, ctx, new_inode, 1)?;
+		let dtype = inode::Dtype::Directory as u8;
+		let block_no = self.get_inode_entry(inodeno).dbp[0];
+		self.write_dentries(
+			block_no,
+			crate::vec![
+				Dentry::new(inodeno as u32, ".", dtype),
+				Dentry::new(parent as u32, "..", dtype),
+			]
+		)
+		.map_err(|_| ErrNo::EIO)?;
+		// '..' is a new hardlink to parent
+		let mut parent_inode = self.get_inode_entry(parent);
+		parent_inode.count_hl += 1;
+		self.write_inode(parent, &parent_inode);
+		self.update_used_dirs(inodeno, 1);
+		Ok(inodeno)
+	}
+
+	/// Decrease the hard link count of an inode, it is released once no entry refer to it
+	fn drop_link(&mut self, inodeno: usize) {
+		let mut inode = self.get_inode_entry(inodeno);
+		inode.count_hl = inode.count_hl.saturating_sub(1);
+		inode.creatt = current_time();
+		match inode.count_hl {
+			0 => self.release_inode(inodeno, inode),
+			_ => self.write_inode(inodeno, &inode)
+		}
+	}
+
+	/// Release an inode no entry refer to anymore, its data and attribute blocks are freed with
+	/// it
+	fn release_inode(&mut self, inodeno: usize, mut inode: Inode) {
+		if !self.is_fast_symlink(&inode) {
+			self.free_data_blocks(&mut inode);
+		}
+		self.release_xattr_block(&mut inode);
+		if inode.is_dir() {
+			self.update_used_dirs(inodeno, -1);
+		}
+		inode.count_hl = 0;
+		inode.delt = current_time();
+		self.write_inode(inodeno, &inode);
+		self.free_node(inodeno);
+	}
+
+	/// Create a new entry newpath refering to the same inode as oldpath.
+	/// Directories can't be hard linked
+	pub fn link(
+		&mut self,
+		oldpath: &str,
+		newpath: &str,
+		ctx: &Context
+	) -> Result<(), ErrNo> {
+		let (inodeno, mut inode) = self.lookup(oldpath, ctx, false)?;
+		if inode.is_dir() {
+			return Err(ErrNo::EPERM);
+		}
+		if inode.count_hl == u16::MAX {
+			return Err(ErrNo::EMLINK);
+		}
+		let (parent, name) = self.lookup_parent(newpath, ctx)?;
+		let parent_inode = self.get_inode_entry(parent);
+		self.permission(ctx, parent, &parent_inode, MAY_WRITE | MAY_EXEC)?;
+		if self.dentry_find(parent, &name).is_some() {
+			return Err(ErrNo::EEXIST);
+		}
+		self.add_dentry(
+			parent,
+			Dentry::new(inodeno as u32, &name, inode.dtype())
+		)?;
+		inode.count_hl += 1;
+		inode.creatt = current_time();
+		self.write_inode(inodeno, &inode);
+		Ok(())
+	}
+
+	/// Remove the entry path, the inode is released with its last link.
+	/// Directories can't be unlinked
+	pub fn unlink(&mut self, path: &str, ctx: &Context) -> Result<(), ErrNo> {
+		let (parent, name) = self.lookup_parent(path, ctx)?;
+		let dentry = self.dentry_find(parent, &name).ok_or(ErrNo::ENOENT)?;
+		let inode = self.get_inode_entry(dentry.inode as usize);
+		if inode.is_dir() {
+			return Err(ErrNo::EISDIR);
+		}
+		let parent_inode = self.get_inode_entry(parent);
+		self.may_delete(
+			ctx,
+			parent,
+			&parent_inode,
+			dentry.inode as usize,
+			&inode
+		)?;
+		self.remove_dentry(parent, &name)?;
+		self.drop_link(dentry.inode as usize);
+		Ok(())
+	}
+
+	/// Remove the empty directory path
+	pub fn rmdir(&mut self, path: &str, ctx: &Context) -> Result<(), ErrNo> {
+		let (parent, name) = self.lookup_parent(path, ctx)?;
+		let dentry = self.dentry_find(parent, &name).ok_or(ErrNo::ENOENT)?;
+		let inodeno = dentry.inode as usize;
+		let inode = self.get_inode_entry(inodeno);
+		if !inode.is_dir() {
+			return Err(ErrNo::ENOTDIR);
+		}
+		let parent_inode = self.get_inode_entry(parent);
+		self.may_delete(
+			ctx,
+			parent,
+			&parent_inode,
+			dentry.inode as usize,
+			&inode
+		)?;
+		if !self.is_empty_dir(inodeno) {
+			return Err(ErrNo::ENOTEMPTY);
+		}
+		self.remove_dentry(parent, &name)?;
+		self.release_inode(inodeno, inode);
+		// Removed '..' was a hardlink to parent
+		self.drop_link(parent);
+		Ok(())
+	}
+
+	/// Remove path whatever its type, directories are emptied first.
+	/// Symbolic links are removed, never followed
+	pub fn remove_recursive(
+		&mut self,
+		path: &str,
+		ctx: &Context
+	) -> Result<(), ErrNo> {
+		let (parent, name) = self.lookup_parent(path, ctx)?;
+		self.remove_entry(parent, &name, ctx, 0)
+	}
+
+	/// Remove the entry name of directory parent, see remove_recursive.
+	/// depth bound the recursion in case of a corrupted filesystem with directory loops
+	fn remove_entry(
+		&mut self,
+		parent: usize,
+		name: &str,
+		ctx: &Context,
+		depth: usize
+	) -> Result<(), ErrNo> {
+		if depth > self.sblock.inode_count() as usize {
+			return Err(ErrNo::ELOOP);
+		}
+		let dentry = self.dentry_find(parent, name).ok_or(ErrNo::ENOENT)?;
+		let inodeno = dentry.inode as usize;
+		let inode = self.get_inode_entry(inodeno);
+		let parent_inode = self.get_inode_entry(parent);
+		self.may_delete(
+			ctx,
+			parent,
+			&parent_inode,
+			dentry.inode as usize,
+			&inode
+		)?;
+		if !inode.is_dir() {
+			self.remove_dentry(parent, name)?;
+			self.drop_link(inodeno);
+			return Ok(());
+		}
+		self.permission(ctx, inodeno, &inode, MAY_READ | MAY_WRITE | MAY_EXEC)?;
+		for child in self.read_dir(inodeno) {
+			if child.name != "." && child.name != ".." {
+				self.remove_entry(inodeno, &child.name, ctx, depth + 1)?;
+			}
+		}
+		self.remove_dentry(parent, name)?;
+		let inode = self.get_inode_entry(inodeno);
+		self.release_inode(inodeno, inode);
+		self.drop_link(parent);
+		Ok(())
+	}
+
+	/// Check that inodeno is a directory the caller can search, for chdir and chroot
+	pub fn search_dir(
+		&self,
+		inodeno: usize,
+		ctx: &Context
+	) -> Result<(), ErrNo> {
+		let inode = self.get_inode_entry(inodeno);
+		if !inode.is_dir() {
+			return Err(ErrNo::ENOTDIR);
+		}
+		self.permission(ctx, inodeno, &inode, MAY_EXEC)
+	}
+
+	/// Resolve path to a directory the caller can search, see search_dir
+	pub fn lookup_dir(
+		&self,
+		path: &str,
+		ctx: &Context
+	) -> Result<usize, ErrNo> {
+		let (inodeno, _) = self.lookup(path, ctx, true)?;
+		self.search_dir(inodeno, ctx)?;
+		Ok(inodeno)
+	}
+
+	/// Absolute path of directory ctx.cwd as seen from ctx.root.
+	/// Names are found by walking up the '..' entries, ENOENT is returned if the working directory
+	/// was removed or is outside of the root directory
+	pub fn getcwd(&self, ctx: &Context) -> Result<String, ErrNo> {
+		let mut names: Vec<String> = Vec::new();
+		let mut dir = ctx.cwd;
+		// Bound the walk in case of a corrupted filesystem
+		for _ in 0..self.sblock.inode_count() {
+			if dir == ctx.root {
+				names.reverse();
+				return Ok(String::from("/") + &names.join("/"));
+			}
+			let parent = match self.dentry_find(dir, "..") {
+				Some(dentry) if dentry.inode as usize != dir => {
+					dentry.inode as usize
+				},
+				_ => return Err(ErrNo::ENOENT)
+			};
+			let dentry = self
+				.read_dir(parent)
+				.into_iter()
+				.find(|x| {
+					x.inode as usize == dir && x.name != "." && x.name != ".."
+				})
+				.ok_or(ErrNo::ENOENT)?;
+			names.push(dentry.name);
+			dir = parent;
+		}
+		Err(ErrNo::ENOENT)
+	}
+
+	/// Check if directory inodeno only contains '.' and '..'
+	pub fn is_empty_dir(&self, inodeno: usize) -> bool {
+		self.read_dir(inodeno)
+			.iter()
+			.all(|dentry| dentry.name == "." || dentry.name == "..")
+	}
+
+	/// Check if directory inodeno is ancestor or the same as directory dir
+	fn is_ancestor(&self, inodeno: usize, mut dir: usize) -> bool {
+		// Bound the walk in case of a corrupted filesystem
+		for _ in 0..self.sblock.inode_count() {
+			if dir == inodeno {
+				return true;
+			}
+			match self.dentry_find(dir, "..") {
+				Some(dentry) if dentry.inode as usize != dir => {
+					dir = dentry.inode as usize
+				},
+				_ => return false
+			}
+		}
+		false
+	}
+
+	/// Move the entry oldpath to newpath, replacing newpath if it exists.
+	/// A directory can only replace an empty directory and can't be moved inside itself
+	pub fn rename(
+		&mut self,
+		oldpath: &str,
+		newpath: &str,
+		ctx: &Context
+	) -> Result<(), ErrNo> {
+		let (old_parent, old_name) = self.lookup_parent(oldpath, ctx)?;
+		let (new_parent, new_name) = self.lookup_parent(newpath, ctx)?;
+		let dentry = self
+			.dentry_find(old_parent, &old_name)
+			.ok_or(ErrNo::ENOENT)?;
+		let inodeno = dentry.inode as usize;
+		let inode = self.get_inode_entry(inodeno);
+		let old_parent_inode = self.get_inode_entry(old_parent);
+		self.may_delete(ctx, old_parent, &old_parent_inode, inodeno, &inode)?;
+		let new_parent_inode = self.get_inode_entry(new_parent);
+		self.permission(
+			ctx,
+			new_parent,
+			&new_parent_inode,
+			MAY_WRITE | MAY_EXEC
+		)?;
+		if inode.is_dir() && old_parent != new_parent {
+			// '..' of the directory is rewritten
+			self.permission(ctx, inodeno, &inode, MAY_WRITE)?;
+		}
+		let target = self.dentry_find(new_parent, &new_name);
+		if let Some(target) = &target {
+			if target.inode as usize == inodeno {
+				return Ok(());
+			}
+			let target_inode = self.get_inode_entry(target.inode as usize);
+			self.may_delete(
+				ctx,
+				new_parent,
+				&new_parent_inode,
+				target.inode as usize,
+				&target_inode
+			)?;
+			match (inode.is_dir(), target_inode.is_dir()) {
+				(true, false) => return Err(ErrNo::ENOTDIR),
+				(false, true) => return Err(ErrNo::EISDIR),
+				(true, true) if !self.is_empty_dir(target.inode as usize) => {
+					return Err(ErrNo::ENOTEMPTY)
+				},
+				_ => {}
+			}
+		}
+		if inode.is_dir() && self.is_ancestor(inodeno, new_parent) {
+			return Err(ErrNo::EINVAL);
+		}
+
+		match &target {
+			Some(target) => {
+				self.replace_dentry(
+					new_parent,
+					&new_name,
+					inodeno,
+					inode.dtype()
+				)?;
+				let target_inode = self.get_inode_entry(target.inode as usize);
+				if target_inode.is_dir() {
+					// Target '..' does not refer to new_parent anymore
+					self.drop_link(new_parent);
+					self.release_inode(target.inode as usize, target_inode);
+				} else {
+					self.drop_link(target.inode as usize);
+				}
+			},
+			None => self.add_dentry(
+				new_parent,
+				Dentry::new(inodeno as u32, &new_name, inode.dtype())
+			)?
+		}
+		self.remove_dentry(old_parent, &old_name)?;
+
+		if inode.is_dir() && old_parent != new_parent {
+			let dtype = inode::Dtype::Directory as u8;
+			self.replace_dentry(inodeno, "..", new_parent, dtype)?;
+			self.drop_link(old_parent);
+			let mut parent_inode = self.get_inode_entry(new_parent);
+			parent_inode.count_hl += 1;
+			self.write_inode(new_parent, &parent_inode);
+		}
+		Ok(())
+	}
+
+	/// Create a symbolic link path pointing to target.
+	/// Short targets are stored in the inode (fast symlink), longer ones in a data block
+	pub fn symlink(
+		&mut self,
+		target: &str,
+		path: &str,
+		ctx: &Context
+	) -> Result<usize, ErrNo> {
+		if target.is_empty() {
+			return Err(ErrNo::ENOENT);
+		}
+		if target.len() >= self.sblock.bsize() {
+			return Err(ErrNo::ENAMETOOLONG);
+		}
+		let mut new_inode = Inode::new();
+		new_inode.tperm = inode::ITYPE_SYMF | 0o777;
+		new_inode.count_hl = 1;
+		new_inode.count_ds = 0;
+		new_inode.size_lh = target.len() as u32;
+		if target.len() < FAST_SYMLINK_MAX {
+			new_inode.set_block_bytes(target.as_bytes());
+			let (_, inodeno) = self.create_inode(path, ctx, new_inode, 0)?;
+			return Ok(inodeno);
+		}
+		let (_, inodeno) = self.create_inode(path, ctx, new_inode, 1)?;
+		let mut block = crate::vec![0; self.sblock.bsize()];
+		block[..target.len()].copy_from_slice(target.as_bytes());
+		let block_no = self.get_inode_entry(inodeno).dbp[0];
+		self.write_block(block_no, &block);
+		Ok(inodeno)
+	}
+
+	/// Fast symlinks store their target in the block pointers and have no data block.
+	/// Blocks used by extended attributes are counted in the sectors too
+	pub fn is_fast_symlink(&self, inode: &Inode) -> bool {
+		let acl_sectors = match inode.facl {
+			0 => 0,
+			_ => self.sblock.bsize() / 512
+		};
+		inode.is_symlink() && inode.count_ds as usize == acl_sectors
+	}
+
+	/// Return the target of a symbolic link inode
+	pub fn read_link(&self, inode: &Inode) -> Result<String, ErrNo> {
+		if !inode.is_symlink() {
+			return Err(ErrNo::EINVAL);
+		}
+		// Targets fit in the block pointers or in a single block unless corrupted
+		let size = inode.size() as usize;
+		let target = if self.is_fast_symlink(inode) {
+			inode.block_bytes().get(..size).ok_or(ErrNo::EIO)?.to_vec()
+		} else if size > self.sblock.bsize() {
+			return Err(ErrNo::EIO);
+		} else {
+			let mut buffer = crate::vec![0; size];
+			let read = self.read_data(inode, 0, &mut buffer);
+			buffer.truncate(read);
+			buffer
+		};
+		String::from_utf8(target).map_err(|_| ErrNo::EIO)
+	}
+
+	/// Return the target of the symbolic link path
+	pub fn readlink(&self, path: &str, ctx: &Context) -> Result<String, ErrNo> {
+		let (_, inode) = self.lookup(path, ctx, false)?;
+		self.read_link(&inode)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::ext2::perm::Context;
+	use crate::fs::ext2::Ext2;
+
+	#[sys_macros::test_case]
+	fn working_and_root_directories() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let mut ctx = Context::new(2);
+		assert!(ext2.mkdir("/jail", &ctx, 0o755).is_ok());
+		assert!(ext2.mkdir("/jail/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/jail/file", &ctx, 0o644).is_ok());
+		assert!(ext2.symlink("/dir", "/jail/link", &ctx).is_ok());
+		assert_eq!(ext2.lookup_dir("/jail/file", &ctx), Err(ErrNo::ENOTDIR));
+
+		ctx.cwd = ext2.lookup_dir("jail/dir", &ctx).unwrap();
+		assert_eq!(ext2.getcwd(&ctx).as_deref(), Ok("/jail/dir"));
+		ctx.root = ext2.lookup_dir("..", &ctx).unwrap();
+		assert_eq!(ext2.getcwd(&ctx).as_deref(), Ok("/dir"));
+		// '..' stays in the root and absolute symlinks start from it
+		assert_eq!(ext2.lookup_dir("/../..", &ctx), Ok(ctx.root));
+		assert_eq!(ext2.lookup_dir("/link", &ctx), Ok(ctx.cwd));
+		ctx.cwd = 2;
+		assert_eq!(ext2.getcwd(&ctx), Err(ErrNo::ENOENT));
+	}
+
+	#[sys_macros::test_case]
+	fn swapfile_is_busy() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let file = ext2.create("/swap", &ctx, 0o600).unwrap();
+		assert!(ext2.create("/other", &ctx, 0o600).is_ok());
+		ext2.set_swapfile(file, true);
+		assert_eq!(ext2.unlink("/swap", &ctx), Err(ErrNo::ETXTBSY));
+		assert_eq!(ext2.rename("/swap", "/moved", &ctx), Err(ErrNo::ETXTBSY));
+		assert_eq!(ext2.rename("/other", "/swap", &ctx), Err(ErrNo::ETXTBSY));
+		assert_eq!(ext2.remove_recursive("/swap", &ctx), Err(ErrNo::ETXTBSY));
+		ext2.set_swapfile(file, false);
+		assert!(ext2.unlink("/swap", &ctx).is_ok());
+	}
+
+	/// Free blocks according to the block bitmap and to the superblock
+	fn free_blocks(ext2: &Ext2) -> (usize, u32) {
+		(ext2.read_block_map(0).get_space().0, ext2.sblock.blocks_unalloc)
+	}
+
+	/// Allocate an indirect block of depth levels, each one pointing to a single block
+	fn indirect_chain(ext2: &mut Ext2, depth: usize) -> u32 {
+		let block_no = ext2.alloc_block(0).unwrap() as u32;
+		if depth > 0 {
+			let mut block = crate::vec![0; ext2.sblock.bsize()];
+			let pointer = indirect_chain(ext2, depth - 1);
+			block[..4].copy_from_slice(&pointer.to_le_bytes());
+			ext2.write_block(block_no, &block);
+		}
+		block_no
+	}
+
+	#[sys_macros::test_case]
+	fn rmdir_errors() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/file", &ctx, 0o644).is_ok());
+		assert_eq!(ext2.rmdir("/dir", &ctx), Err(ErrNo::ENOTEMPTY));
+		assert_eq!(ext2.rmdir("/dir/file", &ctx), Err(ErrNo::ENOTDIR));
+		assert_eq!(ext2.rmdir("/none", &ctx), Err(ErrNo::ENOENT));
+		assert!(ext2.unlink("/dir/file", &ctx).is_ok());
+		let links = ext2.get_inode_entry(2).count_hl;
+		assert!(ext2.rmdir("/dir", &ctx).is_ok());
+		assert_eq!(ext2.get_inode_entry(2).count_hl, links - 1);
+		assert!(ext2.lookup("/dir", &ctx, false).is_err());
+	}
+
+	#[sys_macros::test_case]
+	fn remove_recursive_tree() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let free_inodes = ext2.sblock.inode_unalloc;
+		let free = free_blocks(&ext2);
+		assert!(ext2.mkdir("/dir", &ctx, 0o755).is_ok());
+		assert!(ext2.mkdir("/dir/sub", &ctx, 0o755).is_ok());
+		assert!(ext2.create("/dir/sub/file", &ctx, 0o644).is_ok());
+		assert!(ext2.create("/kept", &ctx, 0o644).is_ok());
+		assert!(ext2.link("/kept", "/dir/link", &ctx).is_ok());
+		assert!(ext2.symlink("/kept", "/dir/symlink", &ctx).is_ok());
+		assert!(ext2.remove_recursive("/dir", &ctx).is_ok());
+
+		assert_eq!(ext2.lookup("/dir", &ctx, false).err(), Some(ErrNo::ENOENT));
+		let (kept, inode) = ext2.lookup("/kept", &ctx, false).unwrap();
+		assert_eq!(inode.count_hl, 1);
+		assert!(ext2.unlink("/kept", &ctx).is_ok());
+		assert!(!ext2.read_inode_map(0).get_node(kept - 1));
+		assert_eq!(ext2.sblock.inode_unalloc, free_inodes);
+		assert_eq!(free_blocks(&ext2), free);
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn unlink_frees_indirect_blocks() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let file = ext2.create("/file", &ctx, 0o644).unwrap();
+		let free = free_blocks(&ext2);
+		let mut inode = ext2.get_inode_entry(file);
+		inode.dbp[0] = indirect_chain(&mut ext2, 0);
+		inode.sibp = indirect_chain(&mut ext2, 1);
+		inode.dibp = indirect_chain(&mut ext2, 2);
+		inode.tibp = indirect_chain(&mut ext2, 3);
+		ext2.write_inode(file, &inode);
+		// 1 direct block, then 1 + 2 + 3 indirect blocks with their data block
+		assert_eq!(free_blocks(&ext2), (free.0 - 10, free.1 - 10));
+		assert!(ext2.unlink("/file", &ctx).is_ok());
+		assert_eq!(free_blocks(&ext2), free);
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn read_corrupted_symlink() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let link = ext2.symlink("/target", "/link", &ctx).unwrap();
+		assert_eq!(ext2.readlink("/link", &ctx).as_deref(), Ok("/target"));
+		let mut inode = ext2.get_inode_entry(link);
+		inode.size_lh = 100;
+		ext2.write_inode(link, &inode);
+		assert_eq!(ext2.readlink("/link", &ctx), Err(ErrNo::EIO));
+		assert_eq!(ext2.lookup("/link", &ctx, true).err(), Some(ErrNo::EIO));
+	}
+
+	#[sys_macros::test_case]
+	fn released_inodes_free_blocks() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		let free = free_blocks(&ext2);
+		let target = ["/", &"a".repeat(100)].concat();
+		assert!(ext2.symlink(&target, "/slow", &ctx).is_ok());
+		assert_eq!(free_blocks(&ext2), (free.0 - 1, free.1 - 1));
+		assert!(ext2.unlink("/slow", &ctx).is_ok());
+		assert_eq!(free_blocks(&ext2), free);
+
+		// The replaced target of a rename is released with its blocks
+		assert!(ext2.symlink(&target, "/slow", &ctx).is_ok());
+		assert!(ext2.symlink("/fast", "/fast", &ctx).is_ok());
+		assert!(ext2.rename("/fast", "/slow", &ctx).is_ok());
+		assert_eq!(free_blocks(&ext2), free);
+		assert!(ext2.fsck(false).is_clean());
+	}
+}
@@ -0,0 +1,269 @@
+//! Permission checks, ownership and timestamps of inodes
+
+use super::inode::{self, Inode};
+use super::namei::ROOT_INODE;
+use super::{xattr, Ext2};
+use crate::errno::ErrNo;
+
+// Access rights asked to permission, values match linux fs.h
+pub const MAY_EXEC: u16 = 0o1;
+pub const MAY_WRITE: u16 = 0o2;
+pub const MAY_READ: u16 = 0o4;
+
+/// Identity, working and root directories of the caller of a filesystem operation
+#[derive(Clone, Copy)]
+pub struct Context {
+	pub cwd:  usize,
+	/// Directory absolute paths start from, '..' never goes above it
+	pub root: usize,
+	pub uid:  u16,
+	pub gid:  u16
+}
+
+impl Context {
+	/// Context of the kernel, every permission is granted
+	pub fn new(cwd: usize) -> Self {
+		Self { cwd, root: ROOT_INODE, uid: 0, gid: 0 }
+	}
+
+	pub fn is_root(&self) -> bool {
+		self.uid == 0
+	}
+
+	pub fn is_owner(&self, inode: &Inode) -> bool {
+		self.is_root() || self.uid == inode.uid
+	}
+
+	/// Check that mask (MAY_*) rights are granted on inode.
+	/// Only the owner, group or other class matching the caller is used.
+	/// Root is granted everything but executing a file without any exec bit
+	pub fn permission(&self, inode: &Inode, mask: u16) -> Result<(), ErrNo> {
+		if self.is_root() {
+			if mask & MAY_EXEC == 0
+				|| inode.is_dir()
+				|| inode.tperm & 0o111 != 0
+			{
+				return Ok(());
+			}
+			return Err(ErrNo::EACCES);
+		}
+		let perm = if self.uid == inode.uid {
+			inode.tperm >> 6
+		} else if self.gid == inode.gid {
+			inode.tperm >> 3
+		} else {
+			inode.tperm
+		};
+		match perm & mask == mask {
+			true => Ok(()),
+			false => Err(ErrNo::EACCES)
+		}
+	}
+
+	/// With the sticky bit on a directory, only the owners of an entry or of the directory can
+	/// remove or rename it
+	pub fn may_delete(&self, dir: &Inode, inode: &Inode) -> Result<(), ErrNo> {
+		self.permission(dir, MAY_WRITE | MAY_EXEC)?;
+		self.sticky(dir, inode)
+	}
+
+	fn sticky(&self, dir: &Inode, inode: &Inode) -> Result<(), ErrNo> {
+		if dir.tperm & inode::IPERM_STICK != 0
+			&& !self.is_owner(inode)
+			&& !self.is_owner(dir)
+		{
+			return Err(ErrNo::EPERM);
+		}
+		Ok(())
+	}
+}
+
+/// Current time as a unix timestamp
+pub fn current_time() -> u32 {
+	crate::cmos::get_time().timestamp()
+}
+
+impl Ext2 {
+	/// Check that mask (MAY_*) rights are granted on inode inodeno, using its access ACL if
+	/// it has one. Root bypasses ACLs like it bypasses the permission bits
+	pub fn permission(
+		&self,
+		ctx: &Context,
+		inodeno: usize,
+		inode: &Inode,
+		mask: u16
+	) -> Result<(), ErrNo> {
+		if ctx.is_root() {
+			return ctx.permission(inode, mask);
+		}
+		match self.get_acl(inodeno, inode) {
+			Some(acl) => acl.permission(ctx, inode, mask),
+			None => ctx.permission(inode, mask)
+		}
+	}
+
+	/// Check that inode inodeno can be removed from directory dirno, see Context::may_delete.
+	/// Active swap files are busy
+	pub fn may_delete(
+		&self,
+		ctx: &Context,
+		dirno: usize,
+		dir: &Inode,
+		inodeno: usize,
+		inode: &Inode
+	) -> Result<(), ErrNo> {
+		self.permission(ctx, dirno, dir, MAY_WRITE | MAY_EXEC)?;
+		ctx.sticky(dir, inode)?;
+		match self.is_swapfile(inodeno) {
+			true => Err(ErrNo::ETXTBSY),
+			false => Ok(())
+		}
+	}
+
+	/// Mark inodeno as used by a swap area or not, see swap::swapon_file
+	pub fn set_swapfile(&mut self, inodeno: usize, active: bool) {
+		match active {
+			true => self.swapfiles.insert(inodeno),
+			false => self.swapfiles.remove(&inodeno)
+		};
+	}
+
+	pub fn is_swapfile(&self, inodeno: usize) -> bool {
+		self.swapfiles.contains(&inodeno)
+	}
+
+	/// Change the permission bits of path, only its owner or root can do it.
+	/// The setgid bit is dropped if the caller is not in the file group
+	pub fn chmod(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		mode: u16
+	) -> Result<(), ErrNo> {
+		let (inodeno, mut inode) = self.lookup(path, ctx, true)?;
+		if !ctx.is_owner(&inode) {
+			return Err(ErrNo::EPERM);
+		}
+		let mut mode = mode & 0o7777;
+		if !ctx.is_root() && ctx.gid != inode.gid {
+			mode &= !inode::IPERM_SETGID;
+		}
+		inode.tperm = inode.file_type() | mode;
+		inode.creatt = current_time();
+		// The access ACL follows the permission bits
+		if let Some(mut acl) = self.get_acl(inodeno, &inode) {
+			acl.chmod(mode);
+			let value = Some(acl.to_disk());
+			let index = xattr::XATTR_INDEX_ACL_ACCESS;
+			self.store_xattr(inodeno, &mut inode, index, "", value, 0)?;
+		}
+		self.write_inode(inodeno, &inode);
+		Ok(())
+	}
+
+	/// Change the owner and group of path, None keep the current value.
+	/// Only root can give a file away, the owner can only set the group to its own.
+	/// setuid and setgid bits are dropped when a non root user change them
+	pub fn chown(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		uid: Option<u16>,
+		gid: Option<u16>,
+		follow: bool
+	) -> Result<(), ErrNo> {
+		let (inodeno, mut inode) = self.lookup(path, ctx, follow)?;
+		let uid = uid.unwrap_or(inode.uid);
+		let gid = gid.unwrap_or(inode.gid);
+		if !ctx.is_root()
+			&& (uid != inode.uid
+				|| ctx.uid != inode.uid
+				|| (gid != inode.gid && gid != ctx.gid))
+		{
+			return Err(ErrNo::EPERM);
+		}
+		if !ctx.is_root() && !inode.is_dir() {
+			inode.tperm &= !(inode::IPERM_SETUID | inode::IPERM_SETGID);
+		}
+		(inode.uid, inode.gid) = (uid, gid);
+		inode.creatt = current_time();
+		self.write_inode(inodeno, &inode);
+		Ok(())
+	}
+
+	/// Set access and modification times of path, None set both to the current time.
+	/// Current time can be set by anyone allowed to write the file, explicit times only by its
+	/// owner
+	pub fn utime(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		times: Option<(u32, u32)>
+	) -> Result<(), ErrNo> {
+		let (inodeno, mut inode) = self.lookup(path, ctx, true)?;
+		let now = current_time();
+		let (atime, mtime) = match times {
+			Some(times) if ctx.is_owner(&inode) => times,
+			Some(_) => return Err(ErrNo::EPERM),
+			None => {
+				if !ctx.is_owner(&inode) {
+					self.permission(ctx, inodeno, &inode, MAY_WRITE)?;
+				}
+				(now, now)
+			}
+		};
+		(inode.lat, inode.lmt, inode.creatt) = (atime, mtime, now);
+		self.write_inode(inodeno, &inode);
+		Ok(())
+	}
+
+	/// Update modification and change times of a directory whose entries changed
+	pub fn touch_dir(&mut self, inodeno: usize) {
+		let mut inode = self.get_inode_entry(inodeno);
+		let now = current_time();
+		(inode.lmt, inode.creatt) = (now, now);
+		self.write_inode(inodeno, &inode);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Context, MAY_EXEC, MAY_READ, MAY_WRITE};
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::inode::{Inode, IPERM_STICK, ITYPE_DIR, ITYPE_REGU};
+
+	#[sys_macros::test_case]
+	fn test_permission_classes() {
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_REGU | 0o640;
+		(inode.uid, inode.gid) = (1000, 100);
+		let owner = Context { uid: 1000, gid: 1000, ..Context::new(2) };
+		let group = Context { uid: 1001, gid: 100, ..Context::new(2) };
+		let other = Context { uid: 1002, gid: 1002, ..Context::new(2) };
+
+		assert!(owner.permission(&inode, MAY_READ | MAY_WRITE).is_ok());
+		assert!(group.permission(&inode, MAY_READ).is_ok());
+		assert_eq!(group.permission(&inode, MAY_WRITE), Err(ErrNo::EACCES));
+		assert_eq!(other.permission(&inode, MAY_READ), Err(ErrNo::EACCES));
+		// Root can't execute a file without exec bit
+		assert!(Context::new(2).permission(&inode, MAY_WRITE).is_ok());
+		assert_eq!(
+			Context::new(2).permission(&inode, MAY_EXEC),
+			Err(ErrNo::EACCES)
+		);
+	}
+
+	#[sys_macros::test_case]
+	fn test_sticky_directory() {
+		let mut dir = Inode::new();
+		dir.tperm = ITYPE_DIR | IPERM_STICK | 0o777;
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_REGU | 0o644;
+		inode.uid = 1000;
+		let owner = Context { uid: 1000, gid: 1000, ..Context::new(2) };
+		let other = Context { uid: 1001, gid: 1000, ..Context::new(2) };
+
+		assert!(owner.may_delete(&dir, &inode).is_ok());
+		assert_eq!(other.may_delete(&dir, &inode), Err(ErrNo::EPERM));
+	}
+}
@@ -0,0 +1,681 @@
+//! Extended attributes, stored in the block pointed by the facl field of an inode and, with
+//! inodes larger than 128 bytes, in the space left after their fields.
+//!
+//! Both places hold entries followed by the values, which are packed from the end. Attributes
+//! are only written to the block, the one of the inode can be read and updated.
+
+use super::acl::Acl;
+use super::inode::{self, Inode};
+use super::perm::{current_time, Context, MAY_READ, MAY_WRITE};
+use super::Ext2;
+use crate::errno::ErrNo;
+use crate::string::String;
+use crate::vec::Vec;
+
+const XATTR_MAGIC: u32 = 0xea020000;
+/// Size of the header of an attribute block
+const HEADER_SIZE: usize = 32;
+/// Size of an entry before its name
+const ENTRY_SIZE: usize = 16;
+/// Largest name length, stored on a byte
+const NAME_MAX: usize = 255;
+
+// setxattr flags, values match linux xattr.h
+/// Fail if the attribute already exists
+pub const XATTR_CREATE: u32 = 0x1;
+/// Fail if the attribute does not exist
+pub const XATTR_REPLACE: u32 = 0x2;
+
+// Name indexes, the prefix of a name is stored as its index
+pub const XATTR_INDEX_USER: u8 = 1;
+pub const XATTR_INDEX_ACL_ACCESS: u8 = 2;
+pub const XATTR_INDEX_ACL_DEFAULT: u8 = 3;
+pub const XATTR_INDEX_TRUSTED: u8 = 4;
+pub const XATTR_INDEX_SECURITY: u8 = 6;
+pub const XATTR_INDEX_SYSTEM: u8 = 7;
+
+const PREFIXES: [(u8, &str); 6] = [
+	(XATTR_INDEX_USER, "user."),
+	(XATTR_INDEX_ACL_ACCESS, "system.posix_acl_access"),
+	(XATTR_INDEX_ACL_DEFAULT, "system.posix_acl_default"),
+	(XATTR_INDEX_TRUSTED, "trusted."),
+	(XATTR_INDEX_SECURITY, "security."),
+	(XATTR_INDEX_SYSTEM, "system.")
+];
+
+/// Round up to the 4 bytes alignment of entries and values
+fn pad(size: usize) -> usize {
+	(size + 3) & !3
+}
+
+fn le16(buffer: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(buffer[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(buffer: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xattr {
+	pub index: u8,
+	/// Name without its prefix
+	pub name:  String,
+	pub value: Vec<u8>
+}
+
+impl Xattr {
+	/// Split a full name into its index and suffix, unknown prefixes aren't supported
+	pub fn split_name(name: &str) -> Result<(u8, &str), ErrNo> {
+		let (index, suffix) = PREFIXES
+			.iter()
+			.find_map(|(index, prefix)| {
+				name.strip_prefix(prefix).map(|suffix| (*index, suffix))
+			})
+			.ok_or(ErrNo::EOPNOTSUPP)?;
+		let acl =
+			index == XATTR_INDEX_ACL_ACCESS || index == XATTR_INDEX_ACL_DEFAULT;
+		match suffix.len() {
+			0 if !acl => Err(ErrNo::EINVAL),
+			x if x > 0 && acl => Err(ErrNo::EOPNOTSUPP),
+			x if x > NAME_MAX => Err(ErrNo::ERANGE),
+			_ => Ok((index, suffix))
+		}
+	}
+
+	/// Name with its prefix
+	pub fn full_name(&self) -> String {
+		let prefix = PREFIXES
+			.iter()
+			.find(|(index, _)| *index == self.index)
+			.map_or("", |(_, prefix)| prefix);
+		crate::alloc::format!("{}{}", prefix, self.name)
+	}
+
+	fn entry_size(&self) -> usize {
+		pad(ENTRY_SIZE + self.name.len())
+	}
+
+	/// Hash of the entry, checked by e2fsck, names are hashed as signed chars like linux
+	fn hash(&self) -> u32 {
+		let mut hash: u32 = 0;
+		for c in self.name.bytes() {
+			hash = (hash << 5) ^ (hash >> 27) ^ (c as i8 as i32 as u32);
+		}
+		let mut value = self.value.clone();
+		value.resize(pad(value.len()), 0);
+		for chunk in value.chunks(4) {
+			hash = (hash << 16) ^ (hash >> 16) ^ le32(chunk, 0);
+		}
+		hash
+	}
+
+	/// Entries are kept sorted by index, name length and name like linux does
+	fn sort_key(&self) -> (u8, usize, &str) {
+		(self.index, self.name.len(), &self.name)
+	}
+}
+
+/// Parse the entries starting at first, value offsets are relative to base.
+/// Parsing stops at the end marker or the first invalid entry
+fn parse_entries(buffer: &[u8], first: usize, base: usize) -> Vec<Xattr> {
+	let mut xattrs = Vec::new();
+	let mut offset = first;
+	while offset + 4 <= buffer.len() && le32(buffer, offset) != 0 {
+		if offset + ENTRY_SIZE > buffer.len() {
+			break;
+		}
+		let name_len = buffer[offset] as usize;
+		let value_start = base + le16(buffer, offset + 2) as usize;
+		let value_size = le32(buffer, offset + 8) as usize;
+		let name = buffer
+			.get(offset + ENTRY_SIZE..offset + ENTRY_SIZE + name_len)
+			.and_then(|x| core::str::from_utf8(x).ok());
+		let value = buffer.get(value_start..value_start + value_size);
+		// Values stored in an inode are not supported
+		let (Some(name), Some(value), 0) =
+			(name, value, le32(buffer, offset + 4))
+		else {
+			break;
+		};
+		xattrs.push(Xattr {
+			index: buffer[offset + 1],
+			name:  String::from(name),
+			value: value.to_vec()
+		});
+		offset += pad(ENTRY_SIZE + name_len);
+	}
+	xattrs
+}
+
+/// Write the entries at first, followed by the end marker, and their values packed at the
+/// end of buffer, offsets relative to base. Return false if they don't fit
+fn write_entries(
+	buffer: &mut [u8],
+	xattrs: &[Xattr],
+	first: usize,
+	base: usize
+) -> bool {
+	let entries: usize = xattrs.iter().map(|x| x.entry_size()).sum();
+	let values: usize = xattrs.iter().map(|x| pad(x.value.len())).sum();
+	if first + entries + 4 + values > buffer.len() {
+		return false;
+	}
+	buffer[first..].fill(0);
+	let mut offset = first;
+	let mut value_offset = buffer.len();
+	for xattr in xattrs.iter() {
+		value_offset -= pad(xattr.value.len());
+		buffer[value_offset..value_offset + xattr.value.len()]
+			.copy_from_slice(&xattr.value);
+		let entry = &mut buffer[offset..offset + xattr.entry_size()];
+		entry[0] = xattr.name.len() as u8;
+		entry[1] = xattr.index;
+		let value_offs = match xattr.value.len() {
+			0 => 0,
+			_ => (value_offset - base) as u16
+		};
+		entry[2..4].copy_from_slice(&value_offs.to_le_bytes());
+		entry[8..12].copy_from_slice(&(xattr.value.len() as u32).to_le_bytes());
+		entry[12..16].copy_from_slice(&xattr.hash().to_le_bytes());
+		entry[16..16 + xattr.name.len()].copy_from_slice(xattr.name.as_bytes());
+		offset += xattr.entry_size();
+	}
+	true
+}
+
+/// Hash of an attribute block, combining the hashes of its entries
+fn block_hash(xattrs: &[Xattr]) -> u32 {
+	xattrs
+		.iter()
+		.fold(0, |hash, x| (hash << 16) ^ (hash >> 16) ^ x.hash())
+}
+
+impl Ext2 {
+	/// Attributes stored in the block of inode, empty if it has none or it is corrupted
+	fn read_xattr_block(&self, inode: &Inode) -> Vec<Xattr> {
+		if inode.facl == 0 {
+			return Vec::new();
+		}
+		let block = self.read_block(inode.facl);
+		match le32(&block, 0) {
+			XATTR_MAGIC => parse_entries(&block, HEADER_SIZE, 0),
+			_ => Vec::new()
+		}
+	}
+
+	/// Block and range of the space after the fields of inode inodeno, None with 128 bytes
+	/// inodes or when the space is too small to hold attributes
+	fn inode_xattr_area(
+		&self,
+		inodeno: usize
+	) -> Option<(u32, core::ops::Range<usize>)> {
+		let size = self.inode_size() as usize;
+		if size <= 128 {
+			return None;
+		}
+		let block_no = self.inode_to_block(inodeno as u32);
+		let index = self.inode_to_offset(inodeno as u32) as usize;
+		let block = self.read_block(block_no);
+		let extra = le16(&block, index + 128) as usize;
+		let start = index + 128 + pad(extra);
+		match start + 4 < index + size {
+			true => Some((block_no, start..index + size)),
+			false => None
+		}
+	}
+
+	/// Attributes stored inside inode inodeno
+	fn read_inode_xattrs(&self, inodeno: usize) -> Vec<Xattr> {
+		let Some((block_no, area)) = self.inode_xattr_area(inodeno) else {
+			return Vec::new();
+		};
+		let block = self.read_block(block_no);
+		let area = &block[area];
+		match le32(area, 0) {
+			XATTR_MAGIC => parse_entries(area, 4, 4),
+			_ => Vec::new()
+		}
+	}
+
+	/// Every attribute of an inode, the ones stored inside it first
+	pub fn read_xattrs(&self, inodeno: usize, inode: &Inode) -> Vec<Xattr> {
+		let mut xattrs = self.read_inode_xattrs(inodeno);
+		xattrs.extend(self.read_xattr_block(inode));
+		xattrs
+	}
+
+	/// Rewrite the attributes stored inside inode inodeno
+	fn write_inode_xattrs(&mut self, inodeno: usize, xattrs: &[Xattr]) {
+		let Some((block_no, area)) = self.inode_xattr_area(inodeno) else {
+			return;
+		};
+		let mut block = self.read_block(block_no);
+		let area = &mut block[area];
+		match xattrs.is_empty() {
+			true => area.fill(0),
+			false => {
+				area[..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+				write_entries(area, xattrs, 4, 4);
+			}
+		}
+		self.write_block(block_no, &block);
+	}
+
+	/// Replace the attribute block of inode with xattrs, the inode is not written back.
+	/// A block shared with other inodes is copied before being changed
+	fn write_xattr_block(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode,
+		xattrs: &mut Vec<Xattr>
+	) -> Result<(), ErrNo> {
+		if xattrs.is_empty() {
+			self.release_xattr_block(inode);
+			return Ok(());
+		}
+		xattrs.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+		let bsize = self.sblock.bsize();
+		let mut block = crate::vec![0; bsize];
+		if !write_entries(&mut block, xattrs, HEADER_SIZE, 0) {
+			return Err(ErrNo::ENOSPC);
+		}
+		block[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+		// Reference count and number of blocks
+		block[4..8].copy_from_slice(&1_u32.to_le_bytes());
+		block[8..12].copy_from_slice(&1_u32.to_le_bytes());
+		block[12..16].copy_from_slice(&block_hash(xattrs).to_le_bytes());
+		let shared =
+			inode.facl != 0 && le32(&self.read_block(inode.facl), 4) > 1;
+		if inode.facl == 0 || shared {
+			let group = self.inode_to_bgroup(inodeno as u32) as usize;
+			let block_no = self.alloc_block(group)? as u32;
+			self.release_xattr_block(inode);
+			inode.facl = block_no;
+			inode.count_ds += (bsize / 512) as u32;
+		}
+		self.write_block(inode.facl, &block);
+		Ok(())
+	}
+
+	/// Drop the reference of inode to its attribute block, freed with its last reference
+	pub fn release_xattr_block(&mut self, inode: &mut Inode) {
+		if inode.facl == 0 {
+			return;
+		}
+		let mut block = self.read_block(inode.facl);
+		let refcount = le32(&block, 4);
+		if le32(&block, 0) == XATTR_MAGIC && refcount > 1 {
+			block[4..8].copy_from_slice(&(refcount - 1).to_le_bytes());
+			self.write_block(inode.facl, &block);
+		} else {
+			self.free_block(inode.facl as usize);
+		}
+		inode.facl = 0;
+		inode.count_ds = inode
+			.count_ds
+			.saturating_sub((self.sblock.bsize() / 512) as u32);
+	}
+
+	/// Check that ctx can read (MAY_READ) or change (MAY_WRITE) the attributes of index.
+	/// Trusted and security attributes are reserved to root, user ones to the regular files
+	/// and directories the caller can access, and only owners can change ACLs
+	fn xattr_permission(
+		&self,
+		ctx: &Context,
+		inodeno: usize,
+		inode: &Inode,
+		index: u8,
+		mask: u16
+	) -> Result<(), ErrNo> {
+		let denied = match mask & MAY_WRITE {
+			0 => ErrNo::ENODATA,
+			_ => ErrNo::EPERM
+		};
+		match index {
+			XATTR_INDEX_TRUSTED if !ctx.is_root() => Err(denied),
+			XATTR_INDEX_SECURITY if mask & MAY_WRITE != 0 && !ctx.is_root() => {
+				Err(ErrNo::EPERM)
+			},
+			XATTR_INDEX_ACL_ACCESS | XATTR_INDEX_ACL_DEFAULT
+				if mask & MAY_WRITE != 0 && !ctx.is_owner(inode) =>
+			{
+				Err(ErrNo::EPERM)
+			},
+			XATTR_INDEX_USER => {
+				if !inode.is_regular() && !inode.is_dir() {
+					return Err(denied);
+				}
+				if mask & MAY_WRITE != 0
+					&& inode.tperm & inode::IPERM_STICK != 0
+					&& inode.is_dir() && !ctx.is_owner(inode)
+				{
+					return Err(ErrNo::EPERM);
+				}
+				self.permission(ctx, inodeno, inode, mask)
+			},
+			_ => Ok(())
+		}
+	}
+
+	/// Value of the attribute name of path, ACLs are returned in the xattr format
+	pub fn getxattr(
+		&self,
+		path: &str,
+		ctx: &Context,
+		name: &str,
+		follow: bool
+	) -> Result<Vec<u8>, ErrNo> {
+		let (index, suffix) = Xattr::split_name(name)?;
+		let (inodeno, inode) = self.lookup(path, ctx, follow)?;
+		self.xattr_permission(ctx, inodeno, &inode, index, MAY_READ)?;
+		let xattr = self
+			.read_xattrs(inodeno, &inode)
+			.into_iter()
+			.find(|x| x.index == index && x.name == suffix)
+			.ok_or(ErrNo::ENODATA)?;
+		match index {
+			XATTR_INDEX_ACL_ACCESS | XATTR_INDEX_ACL_DEFAULT => {
+				Ok(Acl::from_disk(&xattr.value)
+					.map_err(|_| ErrNo::EIO)?
+					.to_xattr())
+			},
+			_ => Ok(xattr.value)
+		}
+	}
+
+	/// Full names of the attributes of path the caller can read
+	pub fn listxattr(
+		&self,
+		path: &str,
+		ctx: &Context,
+		follow: bool
+	) -> Result<Vec<String>, ErrNo> {
+		let (inodeno, inode) = self.lookup(path, ctx, follow)?;
+		Ok(self
+			.read_xattrs(inodeno, &inode)
+			.into_iter()
+			.filter(|x| x.index != XATTR_INDEX_TRUSTED || ctx.is_root())
+			.map(|x| x.full_name())
+			.collect())
+	}
+
+	/// Set the attribute name of path to value, or remove it if value is None.
+	/// flags are XATTR_CREATE or XATTR_REPLACE.
+	/// Setting an access ACL updates the permission bits, an ACL equivalent to them is not
+	/// stored
+	pub fn setxattr(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		name: &str,
+		value: Option<&[u8]>,
+		flags: u32,
+		follow: bool
+	) -> Result<(), ErrNo> {
+		let (index, suffix) = Xattr::split_name(name)?;
+		let (inodeno, mut inode) = self.lookup(path, ctx, follow)?;
+		self.xattr_permission(ctx, inodeno, &inode, index, MAY_WRITE)?;
+		let mut value = value.map(|x| x.to_vec());
+		if let (Some(acl_value), true) =
+			(&value, index == XATTR_INDEX_ACL_ACCESS)
+		{
+			let acl = Acl::from_xattr(acl_value)?;
+			inode.tperm = (inode.tperm & !0o777) | acl.mode();
+			value = match acl.is_minimal() {
+				true => None,
+				false => Some(acl.to_disk())
+			};
+		} else if let (Some(acl_value), true) =
+			(&value, index == XATTR_INDEX_ACL_DEFAULT)
+		{
+			if !inode.is_dir() {
+				return Err(ErrNo::EACCES);
+			}
+			value = Some(Acl::from_xattr(acl_value)?.to_disk());
+		}
+		self.store_xattr(inodeno, &mut inode, index, suffix, value, flags)?;
+		inode.creatt = current_time();
+		self.write_inode(inodeno, &inode);
+		Ok(())
+	}
+
+	/// Remove the attribute name of path
+	pub fn removexattr(
+		&mut self,
+		path: &str,
+		ctx: &Context,
+		name: &str,
+		follow: bool
+	) -> Result<(), ErrNo> {
+		self.setxattr(path, ctx, name, None, XATTR_REPLACE, follow)
+	}
+
+	/// Replace or remove the attribute index.name of an inode, without permission checks.
+	/// The inode is not written back
+	pub(super) fn store_xattr(
+		&mut self,
+		inodeno: usize,
+		inode: &mut Inode,
+		index: u8,
+		name: &str,
+		value: Option<Vec<u8>>,
+		flags: u32
+	) -> Result<(), ErrNo> {
+		let matches = |x: &Xattr| x.index == index && x.name == name;
+		let mut inode_xattrs = self.read_inode_xattrs(inodeno);
+		let mut block_xattrs = self.read_xattr_block(inode);
+		let exists = inode_xattrs.iter().any(matches)
+			|| block_xattrs.iter().any(matches);
+		match (exists, flags) {
+			(true, XATTR_CREATE) => return Err(ErrNo::EEXIST),
+			(false, XATTR_REPLACE) => return Err(ErrNo::ENODATA),
+			(false, _) if value.is_none() => return Ok(()),
+			_ => {}
+		}
+		if value
+			.as_ref()
+			.is_some_and(|x| x.len() > self.sblock.bsize())
+		{
+			return Err(ErrNo::E2BIG);
+		}
+		if inode_xattrs.iter().any(matches) {
+			inode_xattrs.retain(|x| !matches(x));
+			self.write_inode_xattrs(inodeno, &inode_xattrs);
+		}
+		block_xattrs.retain(|x| !matches(x));
+		if let Some(value) = value {
+			block_xattrs.push(Xattr { index, name: String::from(name), value });
+		}
+		self.write_xattr_block(inodeno, inode, &mut block_xattrs)
+	}
+
+	/// Access ACL of an inode if it has one, a corrupted one is ignored
+	pub fn get_acl(&self, inodeno: usize, inode: &Inode) -> Option<Acl> {
+		self.find_acl(inodeno, inode, XATTR_INDEX_ACL_ACCESS)
+	}
+
+	/// Default ACL of a directory, inherited by the inodes created in it
+	pub fn get_default_acl(
+		&self,
+		inodeno: usize,
+		inode: &Inode
+	) -> Option<Acl> {
+		self.find_acl(inodeno, inode, XATTR_INDEX_ACL_DEFAULT)
+	}
+
+	fn find_acl(
+		&self,
+		inodeno: usize,
+		inode: &Inode,
+		index: u8
+	) -> Option<Acl> {
+		if inode.facl == 0 && self.inode_size() <= 128 {
+			return None;
+		}
+		self.read_xattrs(inodeno, inode)
+			.into_iter()
+			.find(|x| x.index == index)
+			.and_then(|x| Acl::from_disk(&x.value).ok())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Xattr, XATTR_CREATE, XATTR_INDEX_USER, XATTR_REPLACE};
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::acl::{self, Acl, AclEntry};
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::ext2::perm::{Context, MAY_READ};
+	use crate::fs::ext2::Ext2;
+
+	#[sys_macros::test_case]
+	fn xattr_names() {
+		assert_eq!(
+			Xattr::split_name("user.mime"),
+			Ok((XATTR_INDEX_USER, "mime"))
+		);
+		assert_eq!(Xattr::split_name("user."), Err(ErrNo::EINVAL));
+		assert_eq!(Xattr::split_name("foo.bar"), Err(ErrNo::EOPNOTSUPP));
+		let (index, name) =
+			Xattr::split_name("system.posix_acl_access").unwrap();
+		let xattr = Xattr { index, name: name.into(), value: crate::vec![] };
+		assert_eq!(xattr.full_name(), "system.posix_acl_access");
+	}
+
+	#[sys_macros::test_case]
+	fn set_get_remove_xattrs() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let ctx = Context::new(2);
+		assert!(ext2.create("/file", &ctx, 0o644).is_ok());
+		let set = |ext2: &mut Ext2, name: &str, value: &[u8], flags: u32| {
+			ext2.setxattr("/file", &ctx, name, Some(value), flags, true)
+		};
+		assert!(set(&mut ext2, "user.mime", b"text/plain", 0).is_ok());
+		assert!(set(&mut ext2, "security.selinux", b"unconfined_u", 0).is_ok());
+		assert_eq!(
+			set(&mut ext2, "user.mime", b"x", XATTR_CREATE),
+			Err(ErrNo::EEXIST)
+		);
+		assert_eq!(
+			set(&mut ext2, "user.other", b"x", XATTR_REPLACE),
+			Err(ErrNo::ENODATA)
+		);
+		assert_eq!(
+			ext2.getxattr("/file", &ctx, "user.mime", true).as_deref(),
+			Ok(&b"text/plain"[..])
+		);
+		let names = ext2.listxattr("/file", &ctx, true).unwrap();
+		assert_eq!(names.len(), 2);
+		assert!(names.iter().any(|x| x == "security.selinux"));
+		assert!(ext2.fsck(false).is_clean());
+
+		assert!(ext2.removexattr("/file", &ctx, "user.mime", true).is_ok());
+		assert!(ext2
+			.removexattr("/file", &ctx, "security.selinux", true)
+			.is_ok());
+		assert!(ext2.listxattr("/file", &ctx, true).unwrap().is_empty());
+		assert_eq!(ext2.lookup("/file", &ctx, true).unwrap().1.facl, 0);
+		// The block is freed with the file
+		assert!(set(&mut ext2, "user.mime", b"text/plain", 0).is_ok());
+		assert!(ext2.unlink("/file", &ctx).is_ok());
+		assert!(ext2.fsck(false).is_clean());
+	}
+
+	#[sys_macros::test_case]
+	fn acl_applies_to_permission() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let root = Context::new(2);
+		assert!(ext2.create("/file", &root, 0o600).is_ok());
+		let reader = Context { uid: 1000, gid: 1000, ..Context::new(2) };
+		let (inodeno, inode) = ext2.lookup("/file", &root, true).unwrap();
+		assert!(ext2.permission(&reader, inodeno, &inode, MAY_READ).is_err());
+
+		let entry = |tag, perm, id| AclEntry { tag, perm, id };
+		let acl = Acl {
+			entries: crate::vec![
+				entry(acl::ACL_USER_OBJ, 0o6, u32::MAX),
+				entry(acl::ACL_USER, 0o4, 1000),
+				entry(acl::ACL_GROUP_OBJ, 0, u32::MAX),
+				entry(acl::ACL_MASK, 0o4, u32::MAX),
+				entry(acl::ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		let name = "system.posix_acl_access";
+		let value = acl.to_xattr();
+		assert_eq!(
+			ext2.setxattr("/file", &reader, name, Some(&value), 0, true),
+			Err(ErrNo::EPERM)
+		);
+		assert!(ext2
+			.setxattr("/file", &root, name, Some(&value), 0, true)
+			.is_ok());
+		let (inodeno, inode) = ext2.lookup("/file", &root, true).unwrap();
+		assert_eq!(inode.get_perms(), 0o640);
+		assert!(ext2.permission(&reader, inodeno, &inode, MAY_READ).is_ok());
+		assert_eq!(ext2.getxattr("/file", &reader, name, true), Ok(value));
+		// Removing read from the group class goes through the mask
+		assert!(ext2.chmod("/file", &root, 0o600).is_ok());
+		let (inodeno, inode) = ext2.lookup("/file", &root, true).unwrap();
+		assert!(ext2.permission(&reader, inodeno, &inode, MAY_READ).is_err());
+	}
+
+	#[sys_macros::test_case]
+	fn default_acl_inherited() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		let root = Context::new(2);
+		assert!(ext2.mkdir("/dir", &root, 0o755).is_ok());
+		let entry = |tag, perm, id| AclEntry { tag, perm, id };
+		let default = Acl {
+			entries: crate::vec![
+				entry(acl::ACL_USER_OBJ, 0o7, u32::MAX),
+				entry(acl::ACL_USER, 0o6, 1000),
+				entry(acl::ACL_GROUP_OBJ, 0o5, u32::MAX),
+				entry(acl::ACL_MASK, 0o7, u32::MAX),
+				entry(acl::ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		let name = "system.posix_acl_default";
+		let value = default.to_xattr();
+		assert!(ext2
+			.setxattr("/dir", &root, name, Some(&value), 0, true)
+			.is_ok());
+
+		assert!(ext2.create("/dir/file", &root, 0o664).is_ok());
+		let (inodeno, inode) = ext2.lookup("/dir/file", &root, true).unwrap();
+		// The mask takes the group bits of the mode
+		assert_eq!(inode.get_perms(), 0o660);
+		let reader = Context { uid: 1000, gid: 1000, ..Context::new(2) };
+		let other = Context { uid: 1001, gid: 1001, ..Context::new(2) };
+		assert!(ext2.permission(&reader, inodeno, &inode, MAY_READ).is_ok());
+		assert!(ext2.permission(&other, inodeno, &inode, MAY_READ).is_err());
+		assert_eq!(
+			ext2.getxattr("/dir/file", &root, name, true),
+			Err(ErrNo::ENODATA)
+		);
+
+		assert!(ext2.mkdir("/dir/sub", &root, 0o750).is_ok());
+		assert_eq!(ext2.getxattr("/dir/sub", &root, name, true), Ok(value));
+		let (inodeno, inode) = ext2.lookup("/dir/sub", &root, true).unwrap();
+		assert_eq!(inode.get_perms(), 0o750);
+		assert!(ext2.get_acl(inodeno, &inode).is_some());
+
+		// A default ACL equivalent to the mode only sets the permission bits
+		let minimal = Acl {
+			entries: crate::vec![
+				entry(acl::ACL_USER_OBJ, 0o6, u32::MAX),
+				entry(acl::ACL_GROUP_OBJ, 0o4, u32::MAX),
+				entry(acl::ACL_OTHER, 0, u32::MAX),
+			]
+		};
+		let value = minimal.to_xattr();
+		assert!(ext2
+			.setxattr("/dir", &root, name, Some(&value), 0, true)
+			.is_ok());
+		assert!(ext2.create("/dir/plain", &root, 0o666).is_ok());
+		let (inodeno, inode) = ext2.lookup("/dir/plain", &root, true).unwrap();
+		assert_eq!(inode.get_perms(), 0o640);
+		assert!(ext2.get_acl(inodeno, &inode).is_none());
+		assert!(ext2.fsck(false).is_clean());
+	}
+}
@@ -0,0 +1,179 @@
+use super::stat::{Dirent64, Stat64};
+use super::{
+	FileInfo,
+	FileOperation,
+	O_ACCMODE,
+	O_APPEND,
+	O_CREAT,
+	O_EXCL,
+	O_RDWR,
+	O_TRUNC,
+	O_WRONLY
+};
+use crate::cli::DISKNO;
+use crate::errno::ErrNo;
+use crate::fs::ext2::perm::{Context, MAY_READ, MAY_WRITE};
+use crate::fs::ext2::Ext2;
+use crate::proc::process::Process;
+use crate::string::String;
+use crate::utils::arcm::Arcm;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// File opened on the mounted ext2 filesystem.
+/// Offset is a position in bytes for regular files and an entry index for directories
+pub struct Ext2File {
+	inodeno: usize,
+	offset:  AtomicUsize
+}
+
+impl Ext2File {
+	pub fn new(inodeno: usize) -> Self {
+		Self { inodeno, offset: AtomicUsize::new(0) }
+	}
+}
+
+/// Credentials, working and root directories of the running process, used to resolve paths and
+/// check permissions.
+/// Collected before the filesystem is locked so the process lock is never held with it
+pub fn context() -> Context {
+	let binding = Process::get_running_process();
+	let process = binding.lock();
+	Context {
+		cwd:  process.cwd,
+		root: process.root,
+		uid:  process.owner as u16,
+		gid:  process.group as u16
+	}
+}
+
+/// Execute f on the mounted filesystem with the context of the running process.
+/// Without filesystem no path can be found so ENOENT is returned
+pub fn with_ext2<T>(
+	f: impl FnOnce(&Ext2, &Context) -> Result<T, ErrNo>
+) -> Result<T, ErrNo> {
+	let ctx = context();
+	let guard = DISKNO.lock();
+	let ext2 = guard.as_ref().ok_or(ErrNo::ENOENT)?;
+	f(ext2, &ctx)
+}
+
+/// Execute f on the mounted filesystem with write access, see with_ext2.
+/// A filesystem mounted read-only returns EROFS
+pub fn with_ext2_mut<T>(
+	f: impl FnOnce(&mut Ext2, &Context) -> Result<T, ErrNo>
+) -> Result<T, ErrNo> {
+	let ctx = context();
+	let mut guard = DISKNO.lock();
+	let ext2 = guard.as_mut().ok_or(ErrNo::ENOENT)?;
+	ext2.check_writable()?;
+	ext2.transaction(|ext2| f(ext2, &ctx))
+}
+
+/// Open a file of the mounted filesystem given its path with the access mode of flags, the
+/// matching read and write permissions are required. Directories can't be opened for writing.
+/// Files can't be created or truncated yet, O_CREAT, O_EXCL and O_TRUNC return EINVAL
+pub fn open(path: &str, flags: u32) -> Result<FileInfo, ErrNo> {
+	if flags & (O_CREAT | O_EXCL | O_TRUNC) != 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	let mask = match flags & O_ACCMODE {
+		O_WRONLY => MAY_WRITE,
+		O_RDWR => MAY_READ | MAY_WRITE,
+		_ => MAY_READ
+	};
+	let inodeno = with_ext2(|ext2, ctx| {
+		let (inodeno, inode) = ext2.lookup(path, ctx, true)?;
+		if mask & MAY_WRITE != 0 {
+			if inode.is_dir() {
+				return Err(ErrNo::EISDIR);
+			}
+			ext2.check_writable()?;
+		}
+		ext2.permission(ctx, inodeno, &inode, mask)?;
+		Ok(inodeno)
+	})?;
+	Ok(FileInfo {
+		name:  String::from(path),
+		op:    Arcm::new(Ext2File::new(inodeno)),
+		flags: AtomicU32::new(flags & (O_ACCMODE | O_APPEND))
+	})
+}
+
+/// Status of a file of the mounted filesystem given its path
+pub fn stat(path: &str, follow: bool) -> Result<Stat64, ErrNo> {
+	with_ext2(|ext2, ctx| {
+		let (inodeno, inode) = ext2.lookup(path, ctx, follow)?;
+		Ok(Stat64::from_inode(inodeno, &inode, ext2.sblock.bsize()))
+	})
+}
+
+/// Target of a symbolic link of the mounted filesystem given its path
+pub fn readlink(path: &str) -> Result<String, ErrNo> {
+	with_ext2(|ext2, ctx| ext2.readlink(path, ctx))
+}
+
+impl FileOperation for Ext2File {
+	fn read(&self, dst: &mut [u8], length: usize) -> Result<usize, ErrNo> {
+		let length = core::cmp::min(length, dst.len());
+		let offset = self.offset.load(Ordering::Relaxed);
+		let read = self.read_at(&mut dst[..length], offset)?;
+		self.offset.store(offset + read, Ordering::Relaxed);
+		Ok(read)
+	}
+
+	fn read_at(&self, dst: &mut [u8], offset: usize) -> Result<usize, ErrNo> {
+		with_ext2(|ext2, _| {
+			let inode = ext2.get_inode_entry(self.inodeno);
+			if inode.is_dir() {
+				return Err(ErrNo::EISDIR);
+			}
+			Ok(ext2.read_data(&inode, offset, dst))
+		})
+	}
+
+	/// Writing file content is not supported yet
+	fn write(&mut self, _src: &[u8], _length: usize) -> Result<usize, ErrNo> {
+		Err(ErrNo::ENOSYS)
+	}
+
+	fn seek_end(&mut self) -> Result<(), ErrNo> {
+		let size =
+			with_ext2(|ext2, _| Ok(ext2.get_inode_entry(self.inodeno).size()))?;
+		self.offset.store(size as usize, Ordering::Relaxed);
+		Ok(())
+	}
+
+	fn inodeno(&self) -> Option<usize> {
+		Some(self.inodeno)
+	}
+
+	fn stat(&self) -> Result<Stat64, ErrNo> {
+		with_ext2(|ext2, _| {
+			let inode = ext2.get_inode_entry(self.inodeno);
+			Ok(Stat64::from_inode(self.inodeno, &inode, ext2.sblock.bsize()))
+		})
+	}
+
+	/// Records are written until dst is full, EINVAL is returned if not even
+	/// the next one fits
+	fn getdents(&mut self, dst: &mut [u8]) -> Result<usize, ErrNo> {
+		let dentries = with_ext2(|ext2, _| {
+			if !ext2.get_inode_entry(self.inodeno).is_dir() {
+				return Err(ErrNo::ENOTDIR);
+			}
+			Ok(ext2.read_dir(self.inodeno))
+		})?;
+		let mut index = self.offset.load(Ordering::Relaxed);
+		let mut written = 0;
+		while let Some(dentry) = dentries.get(index) {
+			match Dirent64::write(&mut dst[written..], dentry, index + 1) {
+				Some(reclen) => written += reclen,
+				None if written == 0 => return Err(ErrNo::EINVAL),
+				None => break
+			}
+			index += 1;
+		}
+		self.offset.store(index, Ordering::Relaxed);
+		Ok(written)
+	}
+}
@@ -1,13 +1,105 @@
+use crate::alloc::sync::Arc;
 use crate::errno::ErrNo;
+use crate::memory::MemoryZone;
+use crate::proc::wait_queue::WaitQueue;
 use crate::string::String;
 use crate::utils::arcm::Arcm;
+use crate::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
 
+pub mod ext2file;
 mod raw;
+pub mod shm;
 pub mod socket;
+pub mod stat;
+pub mod tty;
+
+// Readiness events, values match linux poll.h
+pub const POLLIN: u16 = 0x001;
+pub const POLLPRI: u16 = 0x002;
+pub const POLLOUT: u16 = 0x004;
+pub const POLLERR: u16 = 0x008;
+pub const POLLHUP: u16 = 0x010;
+pub const POLLNVAL: u16 = 0x020;
+
+// File status flags, values match linux fcntl.h
+pub const O_RDONLY: u32 = 0o0;
+pub const O_WRONLY: u32 = 0o1;
+pub const O_RDWR: u32 = 0o2;
+pub const O_ACCMODE: u32 = 0o3;
+pub const O_CREAT: u32 = 0o100;
+pub const O_EXCL: u32 = 0o200;
+pub const O_TRUNC: u32 = 0o1000;
+pub const O_APPEND: u32 = 0o2000;
+pub const O_NONBLOCK: u32 = 0o4000;
+pub const O_CLOEXEC: u32 = 0o2000000;
+
+/// Wait queues a poller has to sleep on to be notified of readiness changes
+pub type PollTable = Vec<Arc<WaitQueue>>;
 
 pub trait FileOperation {
 	fn read(&self, dst: &mut [u8], length: usize) -> Result<usize, ErrNo>;
 	fn write(&mut self, src: &[u8], length: usize) -> Result<usize, ErrNo>;
+
+	/// Return the POLL* events currently ready on the file.
+	/// If table is given, the wait queues woken up when readiness change are added to it.
+	/// Files that never block are always readable and writable
+	fn poll(&self, _table: Option<&mut PollTable>) -> u16 {
+		POLLIN | POLLOUT
+	}
+
+	/// Allow socket syscalls to reach the Socket behind a file descriptor
+	fn as_socket(&mut self) -> Option<&mut socket::Socket> {
+		None
+	}
+
+	/// Read from offset without moving the file position, used to fill the pages of file
+	/// memory zones. Files that can't be mapped return ENODEV
+	fn read_at(&self, _dst: &mut [u8], _offset: usize) -> Result<usize, ErrNo> {
+		Err(ErrNo::ENODEV)
+	}
+
+	/// Zone of size bytes mapping the pages of the file from offset with paging flags, used by
+	/// MAP_SHARED mappings so writes reach the file. Files that can't share their pages return
+	/// ENODEV
+	fn map_shared(
+		&self,
+		_offset: usize,
+		_size: usize,
+		_flags: u32
+	) -> Result<MemoryZone, ErrNo> {
+		Err(ErrNo::ENODEV)
+	}
+
+	/// Set the size of the file to length bytes, used by ftruncate.
+	/// Files that can't be resized return EINVAL
+	fn truncate(&mut self, _length: usize) -> Result<(), ErrNo> {
+		Err(ErrNo::EINVAL)
+	}
+
+	/// Move the file position to the end of the file, done before every write on descriptors
+	/// with O_APPEND. Files without a position ignore it
+	fn seek_end(&mut self) -> Result<(), ErrNo> {
+		Ok(())
+	}
+
+	/// Inode of the file on the mounted filesystem, used by fchdir.
+	/// Files that are not stored on a filesystem have none
+	fn inodeno(&self) -> Option<usize> {
+		None
+	}
+
+	/// Return the status of the file for fstat.
+	/// Files that are not stored on a filesystem only report an empty status
+	fn stat(&self) -> Result<stat::Stat64, ErrNo> {
+		Ok(stat::Stat64::default())
+	}
+
+	/// Fill dst with the next linux_dirent64 records of a directory.
+	/// Return the number of bytes written, 0 once the end of directory is reached
+	fn getdents(&mut self, _dst: &mut [u8]) -> Result<usize, ErrNo> {
+		Err(ErrNo::ENOTDIR)
+	}
 }
 
 /// Contains all file information.
@@ -15,9 +107,12 @@ pub trait FileOperation {
 /// The op trait object can be store either by reference of Box. For the moment Box is choosen but
 /// this may change in the future. To make the trait object ThreadSafe Mutex is used.
 /// Arc is used to allow multiple reference on the object in a multithreaded environment
+/// Flags are the file status flags (O_*), shared by every descriptor duplicated from the same
+/// FileInfo.
 pub struct FileInfo {
-	pub name: String,
-	pub op:   Arcm<dyn FileOperation>
+	pub name:  String,
+	pub op:    Arcm<dyn FileOperation>,
+	pub flags: AtomicU32
 }
 // Sync/Send marker to indicate rust that FileInfo is thread safe
 unsafe impl Sync for FileInfo {}
@@ -25,6 +120,34 @@ unsafe impl Send for FileInfo {}
 
 impl FileInfo {
 	pub fn new(name: String, op: Arcm<dyn FileOperation>) -> Self {
-		Self { name, op }
+		Self { name, op, flags: AtomicU32::new(O_RDWR) }
+	}
+
+	pub fn get_flags(&self) -> u32 {
+		self.flags.load(Ordering::Relaxed)
+	}
+
+	/// True unless the file was opened O_WRONLY
+	pub fn readable(&self) -> bool {
+		self.get_flags() & O_ACCMODE != O_WRONLY
+	}
+
+	/// True if the file was opened O_WRONLY or O_RDWR
+	pub fn writable(&self) -> bool {
+		matches!(self.get_flags() & O_ACCMODE, O_WRONLY | O_RDWR)
+	}
+
+	/// Only O_APPEND and O_NONBLOCK can be changed, access mode is kept as is.
+	/// O_NONBLOCK is forwarded to sockets so their blocking operations honour it
+	pub fn set_flags(&self, flags: u32) {
+		let changeable = O_APPEND | O_NONBLOCK;
+		let old = self.flags.load(Ordering::Relaxed);
+		self.flags.store(
+			(old & !changeable) | (flags & changeable),
+			Ordering::Relaxed
+		);
+		if let Some(socket) = self.op.lock().as_socket() {
+			socket.set_nonblocking(flags & O_NONBLOCK != 0);
+		}
 	}
 }
@@ -1,4 +1,4 @@
-use crate::memory::paging::bitmap::PAGE_SIZE;
+use crate::memory::paging::buddy::PAGE_SIZE;
 use crate::memory::{MemoryZone, TypeZone};
 
 /// Base representation of a file in Memory
@@ -0,0 +1,179 @@
+use super::stat::{Stat64, S_IFREG};
+use super::{
+	FileInfo,
+	FileOperation,
+	O_ACCMODE,
+	O_APPEND,
+	O_CREAT,
+	O_EXCL,
+	O_RDWR,
+	O_TRUNC,
+	O_WRONLY
+};
+use crate::alloc::collections::btree_map::BTreeMap;
+use crate::errno::ErrNo;
+use crate::fs::ext2::perm::{Context, MAY_READ, MAY_WRITE};
+use crate::memory::shm::SharedMemory;
+use crate::memory::MemoryZone;
+use crate::spin::KMutex;
+use crate::string::String;
+use crate::utils::arcm::Arcm;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Longest name of a shared memory object, without its leading slash
+const NAME_MAX: usize = 255;
+
+/// Shared memory object of shm_open, its name is kept until unlinked and its pages as long as
+/// they are open or mapped
+pub struct ShmObject {
+	memory: SharedMemory,
+	uid:    u16,
+	gid:    u16,
+	mode:   u16
+}
+
+impl ShmObject {
+	/// Check that mask (MAY_*) rights are granted to the caller, see Context::permission
+	fn permission(&self, ctx: &Context, mask: u16) -> Result<(), ErrNo> {
+		let perm = match ctx.uid {
+			0 => return Ok(()),
+			uid if uid == self.uid => self.mode >> 6,
+			_ if ctx.gid == self.gid => self.mode >> 3,
+			_ => self.mode
+		};
+		match perm & mask == mask {
+			true => Ok(()),
+			false => Err(ErrNo::EACCES)
+		}
+	}
+}
+
+/// Objects of shm_open given their name
+static OBJECTS: KMutex<BTreeMap<String, Arcm<ShmObject>>> =
+	KMutex::new(BTreeMap::new());
+
+/// Shared memory object open by shm_open, offset is a position in bytes
+pub struct ShmFile {
+	object: Arcm<ShmObject>,
+	offset: AtomicUsize
+}
+
+/// Names are a slash followed by up to NAME_MAX other characters
+fn check_name(name: &str) -> Result<(), ErrNo> {
+	match name.strip_prefix('/') {
+		Some(rest) if rest.len() > NAME_MAX => Err(ErrNo::ENAMETOOLONG),
+		Some(rest) if !rest.is_empty() && !rest.contains('/') => Ok(()),
+		_ => Err(ErrNo::EINVAL)
+	}
+}
+
+/// Open the shared memory object name, see fs::shm_open
+pub fn open(name: &str, flags: u32, mode: u16) -> Result<FileInfo, ErrNo> {
+	check_name(name)?;
+	let ctx = super::ext2file::context();
+	let mask = match flags & O_ACCMODE {
+		O_WRONLY => MAY_WRITE,
+		O_RDWR => MAY_READ | MAY_WRITE,
+		_ => MAY_READ
+	};
+	let object = {
+		let mut objects = OBJECTS.lock();
+		match objects.get(name) {
+			Some(_) if flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL => {
+				return Err(ErrNo::EEXIST)
+			},
+			Some(object) => {
+				object.lock().permission(&ctx, mask)?;
+				object.clone()
+			},
+			None if flags & O_CREAT == 0 => return Err(ErrNo::ENOENT),
+			None => {
+				let object = Arcm::new(ShmObject {
+					memory: SharedMemory::new(0)?,
+					uid:    ctx.uid,
+					gid:    ctx.gid,
+					mode:   mode & 0o777
+				});
+				objects.insert(String::from(name), object.clone());
+				object
+			}
+		}
+	};
+	if flags & O_TRUNC != 0 {
+		if mask & MAY_WRITE == 0 {
+			return Err(ErrNo::EACCES);
+		}
+		object.lock().memory.resize(0)?;
+	}
+	Ok(FileInfo {
+		name:  String::from(name),
+		op:    Arcm::new(ShmFile { object, offset: AtomicUsize::new(0) }),
+		flags: AtomicU32::new(flags & (O_ACCMODE | O_APPEND))
+	})
+}
+
+/// Remove the name of a shared memory object, only its owner can. The object is destroyed
+/// once closed and unmapped by every process
+pub fn unlink(name: &str) -> Result<(), ErrNo> {
+	check_name(name)?;
+	let ctx = super::ext2file::context();
+	let mut objects = OBJECTS.lock();
+	let object = objects.get(name).ok_or(ErrNo::ENOENT)?;
+	if !ctx.is_root() && ctx.uid != object.lock().uid {
+		return Err(ErrNo::EACCES);
+	}
+	objects.remove(name);
+	Ok(())
+}
+
+impl FileOperation for ShmFile {
+	fn read(&self, dst: &mut [u8], length: usize) -> Result<usize, ErrNo> {
+		let length = core::cmp::min(length, dst.len());
+		let offset = self.offset.load(Ordering::Relaxed);
+		let read = self.read_at(&mut dst[..length], offset)?;
+		self.offset.store(offset + read, Ordering::Relaxed);
+		Ok(read)
+	}
+
+	fn write(&mut self, src: &[u8], length: usize) -> Result<usize, ErrNo> {
+		let length = core::cmp::min(length, src.len());
+		let offset = self.offset.load(Ordering::Relaxed);
+		let written =
+			self.object.lock().memory.write_at(&src[..length], offset)?;
+		self.offset.store(offset + written, Ordering::Relaxed);
+		Ok(written)
+	}
+
+	fn read_at(&self, dst: &mut [u8], offset: usize) -> Result<usize, ErrNo> {
+		Ok(self.object.lock().memory.read_at(dst, offset))
+	}
+
+	fn map_shared(
+		&self,
+		offset: usize,
+		size: usize,
+		flags: u32
+	) -> Result<MemoryZone, ErrNo> {
+		self.object.lock().memory.attach(offset, size, flags)
+	}
+
+	fn truncate(&mut self, length: usize) -> Result<(), ErrNo> {
+		self.object.lock().memory.resize(length)
+	}
+
+	fn seek_end(&mut self) -> Result<(), ErrNo> {
+		let size = self.object.lock().memory.size();
+		self.offset.store(size, Ordering::Relaxed);
+		Ok(())
+	}
+
+	fn stat(&self) -> Result<Stat64, ErrNo> {
+		let object = self.object.lock();
+		let mut stat = Stat64::from_mode(S_IFREG | object.mode as u32);
+		stat.st_uid = object.uid as u32;
+		stat.st_gid = object.gid as u32;
+		stat.st_size = object.memory.size() as i64;
+		stat.st_blksize = 4096;
+		Ok(stat)
+	}
+}
@@ -1,7 +1,15 @@
 use super::raw::RawFileMemory;
-use super::FileOperation;
+use super::stat::{Stat64, S_IFSOCK};
+use super::{FileOperation, PollTable, POLLERR, POLLHUP, POLLIN, POLLOUT};
+use crate::alloc::collections::VecDeque;
+use crate::alloc::sync::Arc;
 use crate::errno::ErrNo;
+use crate::proc::wait_queue::{wait_event, WaitQueue};
+use crate::spin::KMutex;
+use crate::string::String;
 use crate::utils::arcm::Arcm;
+use crate::utils::path::Path;
+use crate::vec::Vec;
 
 /// Represent the different domains of a socket.
 /// AF_UNIX bind the socket to the system as file.
@@ -12,10 +20,22 @@ pub enum SocketDomain {
 	AF_UNIX,
 	AF_INET
 }
+
+impl TryFrom<u32> for SocketDomain {
+	type Error = ErrNo;
+	fn try_from(value: u32) -> Result<Self, ErrNo> {
+		match value {
+			1 => Ok(SocketDomain::AF_UNIX),
+			2 => Ok(SocketDomain::AF_INET),
+			_ => Err(ErrNo::EAFNOSUPPORT)
+		}
+	}
+}
+
 /// Represent the type of the socket.
 /// SOCK_RAW: Not yet implemented
 /// SOCK_DGRAM: Partially implemented
-/// SOCK_STREAM: Not yet implemented
+/// SOCK_STREAM: Connection oriented, only for AF_UNIX domain
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SocketType {
@@ -23,6 +43,19 @@ pub enum SocketType {
 	SOCK_DGRAM,
 	SOCK_STREAM
 }
+
+impl TryFrom<u32> for SocketType {
+	type Error = ErrNo;
+	fn try_from(value: u32) -> Result<Self, ErrNo> {
+		match value {
+			1 => Ok(SocketType::SOCK_STREAM),
+			2 => Ok(SocketType::SOCK_DGRAM),
+			3 => Ok(SocketType::SOCK_RAW),
+			_ => Err(ErrNo::ESOCKTNOSUPPORT)
+		}
+	}
+}
+
 /// Represent the protocol bound to the socket.
 /// Generally only one protocol is implemented for each socket type.
 /// Currently no protocol are implemented
@@ -32,6 +65,221 @@ pub enum SocketProtocol {
 	DEFAULT
 }
 
+impl TryFrom<u32> for SocketProtocol {
+	type Error = ErrNo;
+	fn try_from(value: u32) -> Result<Self, ErrNo> {
+		match value {
+			0 => Ok(SocketProtocol::DEFAULT),
+			_ => Err(ErrNo::EPROTONOSUPPORT)
+		}
+	}
+}
+
+/// Connection state of a socket, only meaningful for SOCK_STREAM
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SocketState {
+	Unconnected,
+	Bound,
+	Listening,
+	Connected
+}
+
+/// Which half of a connection `shutdown` should close, values match linux SHUT_* constants
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shutdown {
+	Read,
+	Write,
+	Both
+}
+
+impl TryFrom<u32> for Shutdown {
+	type Error = ErrNo;
+	fn try_from(value: u32) -> Result<Self, ErrNo> {
+		match value {
+			0 => Ok(Shutdown::Read),
+			1 => Ok(Shutdown::Write),
+			2 => Ok(Shutdown::Both),
+			_ => Err(ErrNo::EINVAL)
+		}
+	}
+}
+
+/// One direction of a stream connection.
+/// Data is stored in a VecDeque, which grows as needed so writers never block.
+/// `wclosed` is set once the writing end is gone or shutdown, readers then get EOF.
+/// `rclosed` is set once the reading end is gone or shutdown, writers then get EPIPE.
+#[derive(Default)]
+pub struct StreamBuffer {
+	data:    VecDeque<u8>,
+	wclosed: bool,
+	rclosed: bool,
+	wait:    Arc<WaitQueue>
+}
+
+impl StreamBuffer {
+	fn close_read(&mut self) {
+		self.rclosed = true;
+		self.wait.wake_all();
+	}
+
+	fn close_write(&mut self) {
+		self.wclosed = true;
+		self.wait.wake_all();
+	}
+}
+
+/// Bounded queue of datagrams stored as a ring in one page of memory.
+/// Each message is stored as its length followed by its bytes, both can wrap
+/// around the end of the page. `woffset`/`roffset` of the memory always stay
+/// lower than its size.
+pub struct DgramQueue {
+	memory: RawFileMemory,
+	used:   usize,
+	count:  usize,
+	wait:   Arc<WaitQueue>
+}
+
+impl DgramQueue {
+	const HEADER: usize = core::mem::size_of::<usize>();
+
+	pub fn new() -> Self {
+		Self {
+			memory: RawFileMemory::new(),
+			used:   0,
+			count:  0,
+			wait:   Arc::new(WaitQueue::new())
+		}
+	}
+
+	/// Size of the largest message that can be queued
+	pub fn max_message(&self) -> usize {
+		self.memory.size - Self::HEADER
+	}
+
+	/// Number of messages waiting to be read
+	pub fn len(&self) -> usize {
+		self.count
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.count == 0
+	}
+
+	/// True if not even an empty message can be queued
+	pub fn is_full(&self) -> bool {
+		self.used + Self::HEADER > self.memory.size
+	}
+
+	/// Copy src at woffset, wrapping at the end of the memory
+	fn copy_in(&mut self, src: &[u8]) {
+		let size = self.memory.size;
+		let woffset = self.memory.woffset;
+		let first = core::cmp::min(src.len(), size - woffset);
+		self.memory[woffset..woffset + first].copy_from_slice(&src[0..first]);
+		self.memory[0..src.len() - first].copy_from_slice(&src[first..]);
+		self.memory.woffset = (woffset + src.len()) % size;
+	}
+
+	/// Copy the next `len` bytes at roffset to dst, wrapping at the end of the
+	/// memory. Bytes that don't fit in dst are skipped
+	fn copy_out(&mut self, dst: &mut [u8], len: usize) {
+		let size = self.memory.size;
+		let roffset = self.memory.roffset;
+		for i in 0..core::cmp::min(dst.len(), len) {
+			dst[i] = self.memory[(roffset + i) % size];
+		}
+		self.memory.roffset = (roffset + len) % size;
+	}
+
+	/// Queue one message, EAGAIN is returned if there is not enough space left
+	pub fn push(&mut self, msg: &[u8]) -> Result<(), ErrNo> {
+		if msg.len() > self.max_message() {
+			return Err(ErrNo::EMSGSIZE);
+		}
+		if self.used + Self::HEADER + msg.len() > self.memory.size {
+			return Err(ErrNo::EAGAIN);
+		}
+		self.copy_in(&msg.len().to_ne_bytes());
+		self.copy_in(msg);
+		self.used += Self::HEADER + msg.len();
+		self.count += 1;
+		self.wait.wake_all();
+		Ok(())
+	}
+
+	/// Dequeue one message into dst and return the number of bytes copied.
+	/// The part of the message that does not fit in dst is discarded
+	pub fn pop(&mut self, dst: &mut [u8]) -> Option<usize> {
+		if self.count == 0 {
+			return None;
+		}
+		let mut header: [u8; Self::HEADER] = [0; Self::HEADER];
+		self.copy_out(&mut header, Self::HEADER);
+		let len = usize::from_ne_bytes(header);
+		self.copy_out(dst, len);
+		self.used -= Self::HEADER + len;
+		self.count -= 1;
+		self.wait.wake_all();
+		Some(core::cmp::min(dst.len(), len))
+	}
+}
+
+/// Pending connections of a bound socket.
+/// Connect push the server side of the new connection, accept pop it.
+pub struct Listener {
+	listening: bool,
+	backlog:   usize,
+	pending:   VecDeque<Socket>,
+	wait:      Arc<WaitQueue>
+}
+
+impl Listener {
+	fn new() -> Self {
+		Self {
+			listening: false,
+			backlog:   0,
+			pending:   VecDeque::new(),
+			wait:      Arc::new(WaitQueue::new())
+		}
+	}
+}
+
+/// Both direction of a stream connection, index 0 is read by us and index 1 by the peer.
+/// Dropping it close the connection so the peer is woken up with EOF/EPIPE
+struct Connection([Arcm<StreamBuffer>; 2]);
+
+impl Drop for Connection {
+	fn drop(&mut self) {
+		self.0[0].lock().close_read();
+		self.0[1].lock().close_write();
+	}
+}
+
+/// Path a socket is bound to, the address is released on drop
+struct Address(String);
+
+impl Drop for Address {
+	fn drop(&mut self) {
+		unbind(&self.0);
+	}
+}
+
+/// Addresses of all AF_UNIX sockets bound to a path
+static BOUND_SOCKETS: KMutex<Vec<(String, Arcm<Listener>)>> =
+	KMutex::new(Vec::new());
+
+/// Remove an address from BOUND_SOCKETS, pending connections are dropped with it
+fn unbind(address: &str) {
+	let mut guard = BOUND_SOCKETS.lock();
+	if let Some(index) = guard.iter().position(|elem| elem.0 == address) {
+		guard.remove(index);
+	}
+	// Same as SYSFILES, avoid keeping memory allocated once empty
+	if guard.is_empty() {
+		guard.shrink_to_fit();
+	}
+}
+
 // Maybe replace Vec<u8> with reference on slice
 // The slice could be obtain with mmap and will embed it's length.
 // If we'll overflow the slice buffer, we could request a new page and etend our slice
@@ -43,11 +291,18 @@ pub enum SocketProtocol {
 /// INET domain is not implemented but could create an endpoint of the socket bound to the network
 /// interface
 /// TODO? Maybe embed the woffset inside the buffer to precisely know how much byte as been written
+/// STREAM sockets use `stream` instead of `buffer`, with the same reading/writing indexes.
+/// Closing the connection and releasing the address is done by dropping their fields.
 pub struct Socket {
 	domain:   SocketDomain,
 	stype:    SocketType,
 	protocol: SocketProtocol,
-	buffer:   Option<[Arcm<RawFileMemory>; 2]>,
+	state:    SocketState,
+	buffer:   Option<[Arcm<DgramQueue>; 2]>,
+	stream:   Option<Connection>,
+	address:  Option<Address>,
+	listener: Option<Arcm<Listener>>,
+	nonblock: bool,
 	endpoint: usize
 }
 
@@ -58,8 +313,151 @@ impl Socket {
 		stype: SocketType,
 		protocol: SocketProtocol
 	) -> Self {
-		Self { domain, stype, protocol, buffer: None, endpoint: 0 }
+		Self {
+			domain,
+			stype,
+			protocol,
+			state: SocketState::Unconnected,
+			buffer: None,
+			stream: None,
+			address: None,
+			listener: None,
+			nonblock: false,
+			endpoint: 0
+		}
+	}
+
+	pub fn state(&self) -> SocketState {
+		self.state
+	}
+
+	/// When set, operations that would block return EAGAIN instead
+	pub fn set_nonblocking(&mut self, nonblock: bool) {
+		self.nonblock = nonblock;
 	}
+
+	pub fn is_nonblocking(&self) -> bool {
+		self.nonblock
+	}
+
+	/// Bind the socket to a filesystem path.
+	/// Return EADDRINUSE if another socket is already bound to this path
+	pub fn bind(&mut self, path: &str) -> Result<(), ErrNo> {
+		if self.domain != SocketDomain::AF_UNIX {
+			return Err(ErrNo::EAFNOSUPPORT);
+		}
+		if self.state != SocketState::Unconnected || self.address.is_some() {
+			return Err(ErrNo::EINVAL);
+		}
+		let mut address = Path::new(path);
+		address.cleanup();
+		let mut guard = BOUND_SOCKETS.lock();
+		if guard.iter().any(|elem| elem.0 == address.as_str()) {
+			return Err(ErrNo::EADDRINUSE);
+		}
+		let listener = Arcm::new(Listener::new());
+		guard.push((String::from(address.as_str()), listener.clone()));
+		self.address = Some(Address(String::from(address.as_str())));
+		self.listener = Some(listener);
+		self.state = SocketState::Bound;
+		Ok(())
+	}
+
+	/// Mark a bound socket as accepting connections.
+	/// At most `backlog` connections can wait to be accepted
+	pub fn listen(&mut self, backlog: usize) -> Result<(), ErrNo> {
+		if self.stype != SocketType::SOCK_STREAM {
+			return Err(ErrNo::EOPNOTSUPP);
+		}
+		match (&self.listener, self.state) {
+			(Some(listener), SocketState::Bound | SocketState::Listening) => {
+				let mut guard = listener.lock();
+				guard.listening = true;
+				// Linux silently use a backlog of at least 1
+				guard.backlog = core::cmp::max(backlog, 1);
+				self.state = SocketState::Listening;
+				Ok(())
+			},
+			_ => Err(ErrNo::EINVAL)
+		}
+	}
+
+	/// Return the pending connections queue of a listening socket
+	/// This allow callers to wait on connections without holding the socket
+	pub fn listener(&self) -> Result<Arcm<Listener>, ErrNo> {
+		match (&self.listener, self.state) {
+			(Some(listener), SocketState::Listening) => Ok(listener.clone()),
+			_ => Err(ErrNo::EINVAL)
+		}
+	}
+
+	/// Connect the socket to the listening socket bound at path.
+	/// The server side of the connection is queued until accepted, but the
+	/// client can start writing right away.
+	pub fn connect(&mut self, path: &str) -> Result<(), ErrNo> {
+		if self.stype != SocketType::SOCK_STREAM {
+			return Err(ErrNo::EOPNOTSUPP);
+		}
+		match self.state {
+			SocketState::Connected => return Err(ErrNo::EISCONN),
+			SocketState::Listening => return Err(ErrNo::EINVAL),
+			_ => {}
+		}
+		let mut address = Path::new(path);
+		address.cleanup();
+		let listener = BOUND_SOCKETS
+			.lock()
+			.iter()
+			.find(|elem| elem.0 == address.as_str())
+			.map(|elem| elem.1.clone())
+			.ok_or(ErrNo::ECONNREFUSED)?;
+
+		let mut guard = listener.lock();
+		if !guard.listening {
+			return Err(ErrNo::ECONNREFUSED);
+		}
+		if guard.pending.len() >= guard.backlog {
+			return Err(ErrNo::EAGAIN);
+		}
+		let mut server = Socket::new(self.domain, self.stype, self.protocol);
+		let buffer1: Arcm<StreamBuffer> = Arcm::new(StreamBuffer::default());
+		let buffer2: Arcm<StreamBuffer> = Arcm::new(StreamBuffer::default());
+		server.stream = Some(Connection([buffer1.clone(), buffer2.clone()]));
+		server.state = SocketState::Connected;
+		self.stream = Some(Connection([buffer2, buffer1]));
+		self.state = SocketState::Connected;
+		guard.pending.push_back(server);
+		guard.wait.wake_all();
+		Ok(())
+	}
+
+	/// Close one or both direction of a connected socket
+	pub fn shutdown(&mut self, how: Shutdown) -> Result<(), ErrNo> {
+		let stream = &self.stream.as_ref().ok_or(ErrNo::ENOTCONN)?.0;
+		if how != Shutdown::Write {
+			stream[0].lock().close_read();
+		}
+		if how != Shutdown::Read {
+			stream[1].lock().close_write();
+		}
+		Ok(())
+	}
+}
+
+/// Wait for a connection on a listener and return the server side socket.
+/// The listener lock is only held while looking at the queue so connect can be
+/// performed while we wait
+pub fn accept(
+	listener: &Arcm<Listener>,
+	nonblock: bool
+) -> Result<Socket, ErrNo> {
+	let wait = listener.lock().wait.clone();
+	let mut socket = None;
+	wait_event(&[wait], None, || {
+		socket = listener.lock().pending.pop_front();
+		socket.is_some() || nonblock
+	});
+	socket.ok_or(ErrNo::EAGAIN)
 }
 
 impl FileOperation for Socket {
@@ -80,78 +478,154 @@ impl FileOperation for Socket {
 			SocketType::SOCK_DGRAM => self.dgram_write(src, length)
 		}
 	}
+
+	/// Readable when a message, a connection or EOF is waiting,
+	/// writable while the peer can still queue data
+	fn poll(&self, table: Option<&mut PollTable>) -> u16 {
+		let mut events: u16 = 0;
+		let mut queues: [Option<Arc<WaitQueue>>; 2] = [None, None];
+		// Buffers are shared with the peer, never lock both at once
+		if let Some(buffer) = &self.buffer {
+			let read = buffer[0].lock();
+			if !read.is_empty() {
+				events |= POLLIN;
+			}
+			queues[0] = Some(read.wait.clone());
+			drop(read);
+			let write = buffer[1].lock();
+			if !write.is_full() {
+				events |= POLLOUT;
+			}
+			queues[1] = Some(write.wait.clone());
+		} else if let Some(stream) = &self.stream {
+			let read = stream.0[0].lock();
+			if !read.data.is_empty() || read.wclosed {
+				events |= POLLIN;
+			}
+			if read.wclosed {
+				events |= POLLHUP;
+			}
+			queues[0] = Some(read.wait.clone());
+			drop(read);
+			let write = stream.0[1].lock();
+			if write.rclosed {
+				events |= POLLERR;
+			} else if !write.wclosed {
+				events |= POLLOUT;
+			}
+			queues[1] = Some(write.wait.clone());
+		} else if let (Some(listener), SocketState::Listening) =
+			(&self.listener, self.state)
+		{
+			let guard = listener.lock();
+			if !guard.pending.is_empty() {
+				events |= POLLIN;
+			}
+			queues[0] = Some(guard.wait.clone());
+		} else {
+			events |= POLLHUP;
+		}
+		if let Some(table) = table {
+			table.extend(queues.into_iter().flatten());
+		}
+		events
+	}
+
+	fn as_socket(&mut self) -> Option<&mut Socket> {
+		Some(self)
+	}
+
+	fn stat(&self) -> Result<Stat64, ErrNo> {
+		Ok(Stat64::from_mode(S_IFSOCK | 0o777))
+	}
 }
 
 /// FileOperations for DGRAM sockets
 impl Socket {
+	/// Dequeue exactly one message, truncated to the size of dst.
+	/// Block until a message is available unless the socket is non blocking
 	fn dgram_read(
 		&self,
 		dst: &mut [u8],
 		length: usize
 	) -> Result<usize, ErrNo> {
-		match &self.buffer {
-			Some(buffer) => {
-				let reading = core::cmp::min(dst.len(), length);
-				// If nobody is writing to buffer this causes a deadlock
-				// Current strategy is to lock until there's enough bytes.
-				let roffset = buffer[0].lock().roffset;
-				while buffer[0].lock().woffset < roffset + reading {
-					unsafe { hlt!() };
-				}
-				let guard = &mut buffer[0].lock();
-				dst[0..reading].copy_from_slice(
-					&guard.to_slice()[roffset..roffset + reading]
-				);
-				guard.roffset += reading;
-				// panic if overflow?
-				Ok(reading)
-			},
-			None => {
-				todo!()
-			}
-		}
+		let buffer = self.buffer.as_ref().ok_or(ErrNo::ENOTCONN)?;
+		let reading = core::cmp::min(dst.len(), length);
+		let wait = buffer[0].lock().wait.clone();
+		let mut readed = None;
+		wait_event(&[wait], None, || {
+			readed = buffer[0].lock().pop(&mut dst[0..reading]);
+			readed.is_some() || self.nonblock
+		});
+		readed.ok_or(ErrNo::EAGAIN)
 	}
 
+	/// Enqueue src as one message.
+	/// Block until the peer queue has enough room unless the socket is non blocking
 	fn dgram_write(
 		&mut self,
 		src: &[u8],
 		length: usize
 	) -> Result<usize, ErrNo> {
-		match &self.buffer {
-			Some(buffer) => {
-				let guard = &mut buffer[1].lock();
-				let writing = core::cmp::min(length, src.len());
-				let woffset = guard.woffset;
-				// Currenlty panic if overflow, ideal scenario is to extend memory zone if we risk
-				// overflow
-				guard[woffset..woffset + writing]
-					.copy_from_slice(&src[0..writing]);
-				guard.woffset += writing;
-				Ok(writing)
-			},
-			None => {
-				todo!()
-			}
-		}
+		let buffer = self.buffer.as_ref().ok_or(ErrNo::ENOTCONN)?;
+		let writing = core::cmp::min(length, src.len());
+		let wait = buffer[1].lock().wait.clone();
+		let mut res = Err(ErrNo::EAGAIN);
+		wait_event(&[wait], None, || {
+			res = buffer[1].lock().push(&src[0..writing]);
+			res != Err(ErrNo::EAGAIN) || self.nonblock
+		});
+		res.map(|_| writing)
 	}
 }
 
 /// FileOperations for STREAM sockets
 impl Socket {
+	/// Block until some bytes are available and read as much as possible.
+	/// Non blocking sockets return EAGAIN instead of waiting.
+	/// Return 0 once the peer closed its writing end and everything has been read
 	fn stream_read(
 		&self,
-		_dst: &mut [u8],
-		_length: usize
+		dst: &mut [u8],
+		length: usize
 	) -> Result<usize, ErrNo> {
-		todo!()
+		let stream = &self.stream.as_ref().ok_or(ErrNo::ENOTCONN)?.0;
+		let reading = core::cmp::min(dst.len(), length);
+		if reading == 0 {
+			return Ok(0);
+		}
+		let wait = stream[0].lock().wait.clone();
+		let mut readed = None;
+		wait_event(&[wait], None, || {
+			let mut guard = stream[0].lock();
+			if !guard.data.is_empty() || guard.wclosed || guard.rclosed {
+				let count = core::cmp::min(reading, guard.data.len());
+				for (i, byte) in guard.data.drain(..count).enumerate() {
+					dst[i] = byte;
+				}
+				readed = Some(count);
+			}
+			readed.is_some() || self.nonblock
+		});
+		readed.ok_or(ErrNo::EAGAIN)
 	}
 
+	/// Append bytes to the peer buffer, never block since the buffer grows.
+	/// Return EPIPE if the peer won't read anymore
 	fn stream_write(
 		&mut self,
-		_src: &[u8],
-		_length: usize
+		src: &[u8],
+		length: usize
 	) -> Result<usize, ErrNo> {
-		todo!()
+		let stream = &self.stream.as_ref().ok_or(ErrNo::ENOTCONN)?.0;
+		let mut guard = stream[1].lock();
+		if guard.wclosed || guard.rclosed {
+			return Err(ErrNo::EPIPE);
+		}
+		let writing = core::cmp::min(length, src.len());
+		guard.data.extend(&src[0..writing]);
+		guard.wait.wake_all();
+		Ok(writing)
 	}
 }
 
@@ -174,7 +648,8 @@ impl Socket {
 	}
 }
 
-/// Create two sockets that are bound together, and can be read and write
+/// Create two sockets that are bound together, and can be read and write.
+/// Stream sockets are connected the same way connect and accept do
 pub fn create_socket_pair(
 	domain: SocketDomain,
 	stype: SocketType,
@@ -182,8 +657,18 @@ pub fn create_socket_pair(
 ) -> Result<(Socket, Socket), ErrNo> {
 	let mut first_socket = Socket::new(domain, stype, protocol);
 	let mut second_socket = Socket::new(domain, stype, protocol);
-	let buffer1: Arcm<RawFileMemory> = Arcm::new(RawFileMemory::new());
-	let buffer2: Arcm<RawFileMemory> = Arcm::new(RawFileMemory::new());
+	if stype == SocketType::SOCK_STREAM {
+		let buffer1: Arcm<StreamBuffer> = Arcm::new(StreamBuffer::default());
+		let buffer2: Arcm<StreamBuffer> = Arcm::new(StreamBuffer::default());
+		second_socket.stream =
+			Some(Connection([buffer1.clone(), buffer2.clone()]));
+		second_socket.state = SocketState::Connected;
+		first_socket.stream = Some(Connection([buffer2, buffer1]));
+		first_socket.state = SocketState::Connected;
+		return Ok((first_socket, second_socket));
+	}
+	let buffer1: Arcm<DgramQueue> = Arcm::new(DgramQueue::new());
+	let buffer2: Arcm<DgramQueue> = Arcm::new(DgramQueue::new());
 
 	// Clone the reference to our buffers. Index 0 will be readed, index 1 will be writed to
 	second_socket.buffer = Some([buffer1.clone(), buffer2.clone()]);
@@ -195,13 +680,17 @@ pub fn create_socket_pair(
 #[cfg(test)]
 mod test {
 	use super::{
+		accept,
 		create_socket_pair,
 		FileOperation,
+		Shutdown,
 		Socket,
 		SocketDomain,
 		SocketProtocol,
+		SocketState,
 		SocketType
 	};
+	use crate::errno::ErrNo;
 	#[sys_macros::test_case]
 	fn test_write_socket_0() {
 		let mut sockets: (Socket, Socket);
@@ -219,16 +708,12 @@ mod test {
 			.expect("Failed writing to socket 0");
 		// When writing to first socket, data will go to it's buffer 1
 		// And to the buffer 0 of the other sockets
-		match sockets.1.buffer {
-			Some(buffers) => {
-				assert_eq!(input.as_bytes(), &buffers[0].lock()[0..input.len()])
-			},
+		match &sockets.1.buffer {
+			Some(buffers) => assert_eq!(buffers[0].lock().len(), 1),
 			None => panic!("Socket buffer improperly set")
 		};
-		match sockets.0.buffer {
-			Some(buffers) => {
-				assert_eq!(input.as_bytes(), &buffers[1].lock()[0..input.len()])
-			},
+		match &sockets.0.buffer {
+			Some(buffers) => assert_eq!(buffers[1].lock().len(), 1),
 			None => panic!("Socket buffer improperly set")
 		};
 	}
@@ -237,6 +722,7 @@ mod test {
 	fn test_write_socket_1() {
 		let mut sockets: (Socket, Socket);
 		let input: [u8; 4] = [15, 245, 13, 23];
+		let mut buffer: [u8; 4] = [0; 4];
 
 		sockets = create_socket_pair(
 			SocketDomain::AF_UNIX,
@@ -250,16 +736,15 @@ mod test {
 			.expect("Failed writing to socket 1");
 		// When writing to first socket, data will go to it's buffer 1
 		// And to the buffer 0 of the other sockets
-		match sockets.0.buffer {
+		match &sockets.0.buffer {
 			Some(buffers) => {
-				assert_eq!(input, &buffers[0].lock()[0..input.len()])
+				assert_eq!(buffers[0].lock().pop(&mut buffer), Some(4));
+				assert_eq!(input, buffer);
 			},
 			None => panic!("Socket buffer improperly set")
 		};
-		match sockets.1.buffer {
-			Some(buffers) => {
-				assert_eq!(input, &buffers[1].lock()[0..input.len()])
-			},
+		match &sockets.1.buffer {
+			Some(buffers) => assert!(buffers[1].lock().is_empty()),
 			None => panic!("Socket buffer improperly set")
 		};
 	}
@@ -276,17 +761,11 @@ mod test {
 			SocketProtocol::DEFAULT
 		)
 		.expect("Error creating sockets");
-		match sockets.1.buffer {
-			Some(buffers) => {
-				buffers[1].lock()[0..input.len()].copy_from_slice(&input);
-				buffers[1].lock().woffset += input.len();
-			},
+		match &sockets.1.buffer {
+			Some(buffers) => buffers[1].lock().push(&input).unwrap(),
 			None => panic!("Socket buffer improperly set")
 		};
-		sockets
-			.0
-			.read(&mut buffer, 7)
-			.expect("Couldn't read socket 1");
+		assert_eq!(sockets.0.read(&mut buffer, 10), Ok(7));
 		assert_eq!(buffer[0..7], input);
 	}
 
@@ -302,27 +781,20 @@ mod test {
 			SocketProtocol::DEFAULT
 		)
 		.expect("Error creating sockets");
-		match sockets.0.buffer {
-			Some(buffers) => {
-				buffers[1].lock()[0..input.len()]
-					.copy_from_slice(&input.as_bytes());
-				buffers[1].lock().woffset += input.len();
-			},
+		match &sockets.0.buffer {
+			Some(buffers) => buffers[1].lock().push(input.as_bytes()).unwrap(),
 			None => panic!("Socket buffer improperly set")
 		};
-		sockets
-			.1
-			.read(&mut buffer, 10)
-			.expect("Couldn't read socket 1");
+		// Message is truncated to the buffer size
+		assert_eq!(sockets.1.read(&mut buffer, 10), Ok(10));
 		assert_eq!(buffer, input.as_bytes()[0..10]);
 	}
 
 	#[sys_macros::test_case]
-	fn test_read_socket_tiny_buffer() {
-		let sockets: (Socket, Socket);
-		let input: &str = "This is not a drill!";
-		let mut buffer: [u8; 1] = [0; 1];
-		let mut readed: usize = 0;
+	fn test_read_socket_boundaries() {
+		let mut sockets: (Socket, Socket);
+		let inputs: [&str; 3] = ["first", "second message", "third"];
+		let mut buffer: [u8; 32] = [0; 32];
 
 		sockets = create_socket_pair(
 			SocketDomain::AF_UNIX,
@@ -330,20 +802,75 @@ mod test {
 			SocketProtocol::DEFAULT
 		)
 		.expect("Error creating sockets");
-		match sockets.0.buffer {
-			Some(buffers) => {
-				buffers[1].lock()[0..input.len()]
-					.copy_from_slice(&input.as_bytes());
-				buffers[1].lock().woffset += input.len();
-			},
-			None => panic!("Socket buffer improperly set")
-		};
-		while readed < input.len() {
-			readed += sockets
-				.1
-				.read(&mut buffer, 1)
-				.expect("Couldn't read socket 1");
-			assert_eq!(buffer[0], input.as_bytes()[readed - 1]);
+		for input in inputs {
+			sockets
+				.0
+				.write(input.as_bytes(), input.len())
+				.expect("Failed writing to socket 0");
+		}
+		// Each read return exactly one message, whatever the size asked
+		for input in inputs {
+			assert_eq!(sockets.1.read(&mut buffer, 32), Ok(input.len()));
+			assert_eq!(&buffer[0..input.len()], input.as_bytes());
+		}
+		// Truncated part of a message is lost
+		sockets.0.write(b"truncated", 9).unwrap();
+		sockets.0.write(b"next", 4).unwrap();
+		assert_eq!(sockets.1.read(&mut buffer, 1), Ok(1));
+		assert_eq!(buffer[0], b't');
+		assert_eq!(sockets.1.read(&mut buffer, 32), Ok(4));
+		assert_eq!(&buffer[0..4], b"next");
+	}
+
+	#[sys_macros::test_case]
+	fn test_socket_queue_full() {
+		let mut sockets: (Socket, Socket);
+		let input: [u8; 100] = [42; 100];
+		let mut buffer: [u8; 100] = [0; 100];
+
+		sockets = create_socket_pair(
+			SocketDomain::AF_UNIX,
+			SocketType::SOCK_DGRAM,
+			SocketProtocol::DEFAULT
+		)
+		.expect("Error creating sockets");
+		sockets.0.set_nonblocking(true);
+		sockets.1.set_nonblocking(true);
+		assert_eq!(sockets.1.read(&mut buffer, 100), Err(ErrNo::EAGAIN));
+		let mut sent = 0;
+		while sockets.0.write(&input, 100).is_ok() {
+			sent += 1;
+		}
+		assert_eq!(sockets.0.write(&input, 100), Err(ErrNo::EAGAIN));
+		assert_eq!(sockets.0.write(&[0; 4096], 4096), Err(ErrNo::EMSGSIZE));
+		// Room is made once a message is read
+		assert_eq!(sockets.1.read(&mut buffer, 100), Ok(100));
+		assert_eq!(sockets.0.write(&input, 100), Ok(100));
+		for _ in 0..sent {
+			assert_eq!(sockets.1.read(&mut buffer, 100), Ok(100));
+			assert_eq!(buffer, input);
+		}
+		assert_eq!(sockets.1.read(&mut buffer, 100), Err(ErrNo::EAGAIN));
+	}
+
+	#[sys_macros::test_case]
+	fn test_socket_wrap_around() {
+		let mut sockets: (Socket, Socket);
+		let mut buffer: [u8; 64] = [0; 64];
+
+		sockets = create_socket_pair(
+			SocketDomain::AF_UNIX,
+			SocketType::SOCK_DGRAM,
+			SocketProtocol::DEFAULT
+		)
+		.expect("Error creating sockets");
+		// Send several pages worth of messages with odd sizes to cross the
+		// end of the ring at different offsets
+		for i in 0..500 {
+			let input: [u8; 37] = [i as u8; 37];
+			assert_eq!(sockets.0.write(&input, 37), Ok(37));
+			assert_eq!(sockets.1.read(&mut buffer, 64), Ok(37));
+			assert_eq!(&buffer[0..37], &input);
 		}
 	}
 
@@ -381,4 +908,114 @@ mod test {
 			.expect("Failed reading socket 1");
 		assert_eq!(input2.as_bytes(), &buffer[0..input2.len()]);
 	}
+
+	fn stream_socket() -> Socket {
+		Socket::new(
+			SocketDomain::AF_UNIX,
+			SocketType::SOCK_STREAM,
+			SocketProtocol::DEFAULT
+		)
+	}
+
+	#[sys_macros::test_case]
+	fn test_stream_connect_accept() {
+		let mut server = stream_socket();
+		let mut client = stream_socket();
+		let mut buffer: [u8; 32] = [0; 32];
+
+		server.bind("/tmp/stream_test").expect("Failed to bind");
+		server.listen(1).expect("Failed to listen");
+		client
+			.connect("/tmp/stream_test")
+			.expect("Failed to connect");
+		assert_eq!(client.state(), SocketState::Connected);
+
+		let mut accepted = accept(&server.listener().unwrap(), false).unwrap();
+		client.write(b"ping", 4).expect("Failed writing to client");
+		assert_eq!(accepted.read(&mut buffer, 32), Ok(4));
+		assert_eq!(&buffer[0..4], b"ping");
+		accepted
+			.write(b"pong", 4)
+			.expect("Failed writing to server");
+		assert_eq!(client.read(&mut buffer, 2), Ok(2));
+		assert_eq!(client.read(&mut buffer[2..], 2), Ok(2));
+		assert_eq!(&buffer[0..4], b"pong");
+	}
+
+	#[sys_macros::test_case]
+	fn test_stream_errors() {
+		let mut server = stream_socket();
+		let mut client = stream_socket();
+		let mut other = stream_socket();
+
+		assert_eq!(client.connect("/tmp/nowhere"), Err(ErrNo::ECONNREFUSED));
+		assert_eq!(server.listen(1), Err(ErrNo::EINVAL));
+		server.bind("/tmp/stream_test").expect("Failed to bind");
+		assert_eq!(other.bind("/tmp//stream_test"), Err(ErrNo::EADDRINUSE));
+		assert_eq!(
+			client.connect("/tmp/stream_test"),
+			Err(ErrNo::ECONNREFUSED)
+		);
+		server.listen(1).expect("Failed to listen");
+		client
+			.connect("/tmp/stream_test")
+			.expect("Failed to connect");
+		assert_eq!(other.connect("/tmp/stream_test"), Err(ErrNo::EAGAIN));
+		assert_eq!(client.connect("/tmp/stream_test"), Err(ErrNo::EISCONN));
+		drop(server);
+		// Address is released with the socket
+		other.bind("/tmp/stream_test").expect("Failed to bind");
+	}
+
+	#[sys_macros::test_case]
+	fn test_stream_large_write() {
+		let mut server = stream_socket();
+		let mut client = stream_socket();
+		let input: [u8; 256] = core::array::from_fn(|i| i as u8);
+		let mut buffer: [u8; 256] = [0; 256];
+
+		server.bind("/tmp/stream_test").expect("Failed to bind");
+		server.listen(1).expect("Failed to listen");
+		client
+			.connect("/tmp/stream_test")
+			.expect("Failed to connect");
+		let accepted = accept(&server.listener().unwrap(), false).unwrap();
+		// Write more than a page to make sure buffer grows
+		for _ in 0..32 {
+			assert_eq!(client.write(&input, input.len()), Ok(input.len()));
+		}
+		for _ in 0..32 {
+			let mut readed = 0;
+			while readed < buffer.len() {
+				readed += accepted
+					.read(&mut buffer[readed..], 256 - readed)
+					.expect("Failed reading");
+			}
+			assert_eq!(buffer, input);
+		}
+	}
+
+	#[sys_macros::test_case]
+	fn test_stream_shutdown() {
+		let mut server = stream_socket();
+		let mut client = stream_socket();
+		let mut buffer: [u8; 8] = [0; 8];
+
+		server.bind("/tmp/stream_test").expect("Failed to bind");
+		server.listen(1).expect("Failed to listen");
+		client
+			.connect("/tmp/stream_test")
+			.expect("Failed to connect");
+		let mut accepted = accept(&server.listener().unwrap(), false).unwrap();
+		client.write(b"bye", 3).expect("Failed writing");
+		client
+			.shutdown(Shutdown::Write)
+			.expect("Failed to shutdown");
+		assert_eq!(client.write(b"bye", 3), Err(ErrNo::EPIPE));
+		assert_eq!(accepted.read(&mut buffer, 8), Ok(3));
+		// Peer writing end is closed, read return EOF
+		assert_eq!(accepted.read(&mut buffer, 8), Ok(0));
+		drop(client);
+		assert_eq!(accepted.write(b"bye", 3), Err(ErrNo::EPIPE));
+	}
 }
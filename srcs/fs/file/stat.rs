@@ -0,0 +1,186 @@
+use crate::fs::ext2::inode::{Dentry, Dtype, Inode};
+
+// File type bits of st_mode, values match linux stat.h
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFSOCK: u32 = 0o140000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFIFO: u32 = 0o010000;
+
+// Directory entry types, values match linux dirent.h
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_FIFO: u8 = 1;
+pub const DT_CHR: u8 = 2;
+pub const DT_DIR: u8 = 4;
+pub const DT_BLK: u8 = 6;
+pub const DT_REG: u8 = 8;
+pub const DT_LNK: u8 = 10;
+pub const DT_SOCK: u8 = 12;
+
+/// Layout of the i386 linux struct stat64, as filled by stat64/fstat64/lstat64.
+/// The structure is packed, fields must be written and not borrowed
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+pub struct Stat64 {
+	pub st_dev:        u64,
+	__pad0:            [u8; 4],
+	pub __st_ino:      u32,
+	pub st_mode:       u32,
+	pub st_nlink:      u32,
+	pub st_uid:        u32,
+	pub st_gid:        u32,
+	pub st_rdev:       u64,
+	__pad3:            [u8; 4],
+	pub st_size:       i64,
+	pub st_blksize:    u32,
+	pub st_blocks:     u64,
+	pub st_atime:      u32,
+	pub st_atime_nsec: u32,
+	pub st_mtime:      u32,
+	pub st_mtime_nsec: u32,
+	pub st_ctime:      u32,
+	pub st_ctime_nsec: u32,
+	pub st_ino:        u64
+}
+
+impl Stat64 {
+	/// Stat of a file that does not live on a filesystem, only its type is known
+	pub fn from_mode(mode: u32) -> Self {
+		Self { st_mode: mode, st_nlink: 1, ..Default::default() }
+	}
+
+	/// Fill a stat from an ext2 inode.
+	/// Ext2 already count blocks in 512 bytes sectors like st_blocks does
+	pub fn from_inode(inodeno: usize, inode: &Inode, bsize: usize) -> Self {
+		Self {
+			__st_ino: inodeno as u32,
+			st_mode: inode.tperm as u32,
+			st_nlink: inode.count_hl as u32,
+			st_uid: inode.uid as u32,
+			st_gid: inode.gid as u32,
+			st_size: inode.size() as i64,
+			st_blksize: bsize as u32,
+			st_blocks: inode.count_ds as u64,
+			st_atime: inode.lat,
+			st_mtime: inode.lmt,
+			st_ctime: inode.creatt,
+			st_ino: inodeno as u64,
+			..Default::default()
+		}
+	}
+}
+
+/// Header of the i386 linux struct linux_dirent64 filled by getdents64.
+/// Each record is followed by its null terminated name and padded to 8 bytes
+#[repr(C, packed)]
+pub struct Dirent64 {
+	pub d_ino:    u64,
+	pub d_off:    i64,
+	pub d_reclen: u16,
+	pub d_type:   u8
+}
+
+impl Dirent64 {
+	/// Size of the record needed to store an entry named name
+	pub fn reclen(name: &str) -> usize {
+		crate::utils::math::roundup(
+			core::mem::size_of::<Dirent64>() + name.len() + 1,
+			8
+		)
+	}
+
+	/// Serialize dentry at the start of dst. off is the position of the next entry.
+	/// Return the size of the record or None if dst is too small to contain it
+	pub fn write(dst: &mut [u8], dentry: &Dentry, off: usize) -> Option<usize> {
+		let reclen = Dirent64::reclen(&dentry.name);
+		if reclen > dst.len() {
+			return None;
+		}
+		let header = Dirent64 {
+			d_ino:    dentry.inode as u64,
+			d_off:    off as i64,
+			d_reclen: reclen as u16,
+			d_type:   dtype_of(dentry.r#type)
+		};
+		let size = core::mem::size_of::<Dirent64>();
+		let bytes = unsafe {
+			core::slice::from_raw_parts(&header as *const _ as *const u8, size)
+		};
+		dst[..size].copy_from_slice(bytes);
+		dst[size..size + dentry.name.len()]
+			.copy_from_slice(dentry.name.as_bytes());
+		dst[size + dentry.name.len()..reclen].fill(0);
+		Some(reclen)
+	}
+}
+
+/// Convert an ext2 directory entry type to its DT_* value
+pub fn dtype_of(r#type: u8) -> u8 {
+	match r#type {
+		x if x == Dtype::Regular as u8 => DT_REG,
+		x if x == Dtype::Directory as u8 => DT_DIR,
+		x if x == Dtype::Chardev as u8 => DT_CHR,
+		x if x == Dtype::Blockdev as u8 => DT_BLK,
+		x if x == Dtype::Fifo as u8 => DT_FIFO,
+		x if x == Dtype::Socket as u8 => DT_SOCK,
+		x if x == Dtype::Sym as u8 => DT_LNK,
+		_ => DT_UNKNOWN
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Dirent64, Stat64, DT_DIR, S_IFDIR};
+	use crate::fs::ext2::inode::{Dentry, Dtype, Inode, ITYPE_DIR};
+	use crate::string::String;
+
+	#[sys_macros::test_case]
+	fn test_stat64_layout() {
+		// Size and field offsets of the linux i386 struct stat64
+		assert_eq!(core::mem::size_of::<Stat64>(), 96);
+		assert_eq!(core::mem::size_of::<Dirent64>(), 19);
+		let stat = Stat64 { st_size: 0x42, st_ino: 0x24, ..Default::default() };
+		let bytes: [u8; 96] = unsafe { core::mem::transmute(stat) };
+		assert_eq!(bytes[44], 0x42);
+		assert_eq!(bytes[88], 0x24);
+	}
+
+	#[sys_macros::test_case]
+	fn test_stat64_from_inode() {
+		let mut inode = Inode::new();
+		inode.tperm = ITYPE_DIR | 0o755;
+		inode.count_hl = 3;
+		inode.size_lh = 1024;
+		inode.count_ds = 2;
+		let stat = Stat64::from_inode(12, &inode, 1024);
+		let (mode, nlink, size, ino) =
+			(stat.st_mode, stat.st_nlink, stat.st_size, stat.st_ino);
+		assert_eq!(mode, S_IFDIR | 0o755);
+		assert_eq!(nlink, 3);
+		assert_eq!(size, 1024);
+		assert_eq!(ino, 12);
+	}
+
+	#[sys_macros::test_case]
+	fn test_dirent64_write() {
+		let dentry = Dentry {
+			inode:       42,
+			dentry_size: 12,
+			name_length: 3,
+			r#type:      Dtype::Directory as u8,
+			name:        String::from("dev")
+		};
+		let mut buffer = [0xffu8; 32];
+		assert_eq!(Dirent64::write(&mut buffer[..16], &dentry, 1), None);
+		assert_eq!(Dirent64::write(&mut buffer, &dentry, 1), Some(24));
+		assert_eq!(u64::from_le_bytes(buffer[0..8].try_into().unwrap()), 42);
+		assert_eq!(i64::from_le_bytes(buffer[8..16].try_into().unwrap()), 1);
+		assert_eq!(u16::from_le_bytes(buffer[16..18].try_into().unwrap()), 24);
+		assert_eq!(buffer[18], DT_DIR);
+		assert_eq!(&buffer[19..23], b"dev\0");
+		assert_eq!(buffer[24], 0xff);
+	}
+}
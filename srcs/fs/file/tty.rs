@@ -0,0 +1,109 @@
+//! Keyboard input exposed as a file, the keyboard IRQ buffers the characters
+//! typed while a tty file is open until they are read
+
+use super::{FileInfo, FileOperation, PollTable, POLLIN, POLLOUT};
+use crate::alloc::collections::VecDeque;
+use crate::alloc::sync::Arc;
+use crate::errno::ErrNo;
+use crate::proc::wait_queue::{wait_event, WaitQueue};
+use crate::spin::KMutex;
+use crate::string::String;
+use crate::utils::arcm::Arcm;
+
+/// Path opening the tty file
+pub const TTY_PATH: &str = "/dev/tty";
+
+/// Characters typed and not read yet, shared by every open tty file.
+/// It only exists while a tty file is open so the input is not kept forever
+struct TtyInput {
+	data:  VecDeque<u8>,
+	users: usize,
+	wait:  Arc<WaitQueue>
+}
+
+static INPUT: KMutex<Option<TtyInput>> = KMutex::new(None);
+
+/// Called by the keyboard IRQ for each character typed, readers and pollers
+/// of the tty are woken up
+pub fn push_input(c: char) {
+	if let Some(input) = INPUT.lock().as_mut() {
+		let mut bytes = [0; 4];
+		input.data.extend(c.encode_utf8(&mut bytes).bytes());
+		input.wait.wake_all();
+	}
+}
+
+pub struct Tty;
+
+impl Drop for Tty {
+	fn drop(&mut self) {
+		let mut guard = INPUT.lock();
+		if let Some(input) = guard.as_mut() {
+			input.users -= 1;
+			if input.users == 0 {
+				*guard = None;
+			}
+		}
+	}
+}
+
+pub fn open() -> FileInfo {
+	let mut guard = INPUT.lock();
+	match guard.as_mut() {
+		Some(input) => input.users += 1,
+		None => {
+			*guard = Some(TtyInput {
+				data:  VecDeque::new(),
+				users: 1,
+				wait:  Arc::new(WaitQueue::new())
+			})
+		},
+	}
+	FileInfo::new(String::from(TTY_PATH), Arcm::new(Tty))
+}
+
+impl FileOperation for Tty {
+	/// Block until some input is buffered and read up to length bytes of it
+	fn read(&self, dst: &mut [u8], length: usize) -> Result<usize, ErrNo> {
+		let reading = core::cmp::min(dst.len(), length);
+		let wait = INPUT.lock().as_ref().ok_or(ErrNo::EBADF)?.wait.clone();
+		let mut readed = 0;
+		wait_event(&[wait], None, || {
+			let mut guard = INPUT.lock();
+			let Some(input) = guard.as_mut() else {
+				return true;
+			};
+			while readed < reading {
+				match input.data.pop_front() {
+					Some(byte) => dst[readed] = byte,
+					None => break
+				}
+				readed += 1;
+			}
+			readed != 0 || reading == 0
+		});
+		Ok(readed)
+	}
+
+	/// Print src on the screen
+	fn write(&mut self, src: &[u8], length: usize) -> Result<usize, ErrNo> {
+		let writing = core::cmp::min(src.len(), length);
+		crate::kprint!("{}", String::from_utf8_lossy(&src[0..writing]));
+		Ok(writing)
+	}
+
+	/// Always writable, readable once input is buffered
+	fn poll(&self, table: Option<&mut PollTable>) -> u16 {
+		let guard = INPUT.lock();
+		let Some(input) = guard.as_ref() else {
+			return POLLOUT;
+		};
+		if let Some(table) = table {
+			table.push(input.wait.clone());
+		}
+		match input.data.is_empty() {
+			true => POLLOUT,
+			false => POLLIN | POLLOUT
+		}
+	}
+}
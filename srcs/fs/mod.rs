@@ -2,6 +2,7 @@ use crate::alloc::sync::Arc;
 use crate::errno::ErrNo;
 use crate::fs::FileOperation;
 use crate::proc::process::MAX_FD;
+use crate::proc::wait_queue::wait_event;
 use crate::spin::KMutex;
 use crate::string::String;
 use crate::utils::arcm::Arcm;
@@ -15,6 +16,7 @@ mod test;
 
 pub mod ext2;
 mod file;
+use file::stat::Stat64;
 pub use file::*;
 
 // Contain all file system. This will be probably converted to a BST or something like that
@@ -67,42 +69,303 @@ pub fn delete(name: &str) {
 	}
 }
 
+/// Directory where the shared memory objects of shm_open are found, like the tmpfs of linux
+const SHM_DIR: &str = "/dev/shm";
+
+/// Name of the shared memory object at path if it is inside SHM_DIR
+fn shm_name(path: &str) -> Option<&str> {
+	path.strip_prefix(SHM_DIR)
+		.filter(|name| name.starts_with('/'))
+}
+
 /// Look for a file given its name in SYSFILES and open it.
+/// If the file is not found there, name is looked up as a path on the mounted ext2 filesystem
+/// and opened for reading.
+/// Paths inside /dev/shm open shared memory objects for reading, /dev/tty opens the keyboard
+/// input.
 /// Open files list is common between processses, this will change in later version
 pub fn open(name: &str) -> Result<usize, ErrNo> {
-	let guard = SYSFILES.lock();
+	if let Some(name) = shm_name(name) {
+		return shm_open(name, file::O_RDONLY, 0);
+	}
+	open_file(name, file::O_RDONLY)
+}
 
-	// Error if file does not exist
-	let found_file = guard
+/// Open name from /dev/tty, SYSFILES or the mounted ext2 filesystem, flags are only used by the
+/// files of the filesystem
+fn open_file(name: &str, flags: u32) -> Result<usize, ErrNo> {
+	if name == tty::TTY_PATH {
+		return install(tty::open());
+	}
+	let found_file = SYSFILES
+		.lock()
 		.iter()
 		.find(|elem| elem.name == name)
-		.ok_or(ErrNo::ENOENT)?;
-	let binding = Process::get_running_process();
-	let mut curr_process = binding.lock();
+		.cloned();
+	match found_file {
+		Some(file) => install_shared(file),
+		None => install(file::ext2file::open(name, flags)?)
+	}
+}
 
-	// Error if file table already full
-	let index = curr_process
-		.fds
+/// This function mimic the linux open syscall.
+/// Paths inside /dev/shm are shared memory objects opened with flags and mode, see shm_open.
+/// Files of the ext2 filesystem are opened with the access mode of flags, see
+/// ext2file::open. O_CLOEXEC marks the descriptor close-on-exec
+pub fn open_flags(name: &str, flags: u32, mode: u16) -> Result<usize, ErrNo> {
+	let fd = match shm_name(name) {
+		Some(name) => shm_open(name, flags, mode)?,
+		None => open_file(name, flags)?
+	};
+	if flags & file::O_CLOEXEC != 0 {
+		fcntl(fd, F_SETFD, FD_CLOEXEC as usize)?;
+	}
+	Ok(fd)
+}
+
+/// This function mimic the shm_open function of linux librt.
+/// Open the shared memory object name ("/name"), O_CREAT creates it empty with mode as
+/// permissions and O_EXCL fails if it exists. O_TRUNC empties it. Its pages are shared by the
+/// MAP_SHARED mappings of the file
+pub fn shm_open(name: &str, flags: u32, mode: u16) -> Result<usize, ErrNo> {
+	install(file::shm::open(name, flags, mode)?)
+}
+
+/// This function mimic the shm_unlink function of linux librt.
+/// Remove the name of a shared memory object, it is destroyed once no process uses it
+pub fn shm_unlink(name: &str) -> Result<usize, ErrNo> {
+	file::shm::unlink(name)?;
+	Ok(0)
+}
+
+/// Look for name in SYSFILES first, then on the mounted ext2 filesystem
+fn stat_from(name: &str, follow: bool) -> Result<Stat64, ErrNo> {
+	let found_file = SYSFILES
+		.lock()
 		.iter()
-		.position(|elem| elem.is_none())
-		.ok_or(ErrNo::EMFILE)?;
-	curr_process.fds[index] = Some(Arc::clone(&found_file));
-	return Ok(index);
+		.find(|elem| elem.name == name)
+		.cloned();
+	match found_file {
+		Some(file) => file.op.lock().stat(),
+		None => file::ext2file::stat(name, follow)
+	}
+}
+
+/// This function mimic the linux stat syscall.
+/// Files of SYSFILES are reported first, then name is looked up on the mounted ext2 filesystem
+pub fn stat(name: &str) -> Result<Stat64, ErrNo> {
+	stat_from(name, true)
+}
+
+/// This function mimic the linux lstat syscall.
+/// Same as stat but a symbolic link is reported instead of its target
+pub fn lstat(name: &str) -> Result<Stat64, ErrNo> {
+	stat_from(name, false)
+}
+
+/// This function mimic the linux fstat syscall
+pub fn fstat(fd: usize) -> Result<Stat64, ErrNo> {
+	get_fileop(fd)?.lock().stat()
+}
+
+/// This function mimic the linux ftruncate syscall.
+/// Set the size of the file opened for writing on fd to length bytes
+pub fn ftruncate(fd: usize, length: usize) -> Result<usize, ErrNo> {
+	let flags = fcntl(fd, F_GETFL, 0)? as u32;
+	if !matches!(flags & O_ACCMODE, O_WRONLY | O_RDWR) {
+		return Err(ErrNo::EINVAL);
+	}
+	get_fileop(fd)?.lock().truncate(length)?;
+	Ok(0)
+}
+
+/// This function mimic the linux getdents64 syscall.
+/// dst is filled with linux_dirent64 records of the directory opened on fd
+pub fn getdents(fd: usize, dst: &mut [u8]) -> Result<usize, ErrNo> {
+	get_fileop(fd)?.lock().getdents(dst)
+}
+
+/// This function mimic the linux link syscall.
+/// Create a hard link newpath to oldpath on the mounted ext2 filesystem
+pub fn link(oldpath: &str, newpath: &str) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.link(oldpath, newpath, ctx)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux unlink syscall.
+/// Remove path from the mounted ext2 filesystem, paths inside /dev/shm remove the name of a
+/// shared memory object
+pub fn unlink(path: &str) -> Result<usize, ErrNo> {
+	if let Some(name) = shm_name(path) {
+		return shm_unlink(name);
+	}
+	file::ext2file::with_ext2_mut(|ext2, ctx| ext2.unlink(path, ctx))?;
+	Ok(0)
+}
+
+/// This function mimic the linux rmdir syscall.
+/// Remove the empty directory path from the mounted ext2 filesystem
+pub fn rmdir(path: &str) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| ext2.rmdir(path, ctx))?;
+	Ok(0)
+}
+
+/// This function mimic the linux rename syscall.
+/// Move oldpath to newpath on the mounted ext2 filesystem
+pub fn rename(oldpath: &str, newpath: &str) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.rename(oldpath, newpath, ctx)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux symlink syscall.
+/// Create a symbolic link linkpath pointing to target on the mounted ext2 filesystem
+pub fn symlink(target: &str, linkpath: &str) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.symlink(target, linkpath, ctx)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux chmod syscall.
+/// Change the permission bits of path on the mounted ext2 filesystem
+pub fn chmod(path: &str, mode: u16) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| ext2.chmod(path, ctx, mode))?;
+	Ok(0)
+}
+
+/// This function mimic the linux chown and lchown syscalls.
+/// None keep the current owner or group, symbolic links are only followed if follow is set
+pub fn chown(
+	path: &str,
+	uid: Option<u16>,
+	gid: Option<u16>,
+	follow: bool
+) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.chown(path, ctx, uid, gid, follow)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux utime syscall.
+/// times are the access and modification times, None set both to the current time
+pub fn utime(path: &str, times: Option<(u32, u32)>) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| ext2.utime(path, ctx, times))?;
+	Ok(0)
+}
+
+/// This function mimic the linux readlink syscall.
+/// Return the target of the symbolic link path
+pub fn readlink(path: &str) -> Result<String, ErrNo> {
+	file::ext2file::readlink(path)
+}
+
+/// This function mimic the linux getxattr and lgetxattr syscalls.
+/// Return the value of the extended attribute name of path
+pub fn getxattr(
+	path: &str,
+	name: &str,
+	follow: bool
+) -> Result<Vec<u8>, ErrNo> {
+	file::ext2file::with_ext2(|ext2, ctx| {
+		ext2.getxattr(path, ctx, name, follow)
+	})
+}
+
+/// This function mimic the linux listxattr and llistxattr syscalls.
+/// Return the names of the extended attributes of path the caller can see
+pub fn listxattr(path: &str, follow: bool) -> Result<Vec<String>, ErrNo> {
+	file::ext2file::with_ext2(|ext2, ctx| ext2.listxattr(path, ctx, follow))
+}
+
+/// This function mimic the linux setxattr and lsetxattr syscalls.
+/// flags are XATTR_CREATE or XATTR_REPLACE, 0 creates or replaces the attribute
+pub fn setxattr(
+	path: &str,
+	name: &str,
+	value: &[u8],
+	flags: u32,
+	follow: bool
+) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.setxattr(path, ctx, name, Some(value), flags, follow)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux removexattr and lremovexattr syscalls.
+pub fn removexattr(
+	path: &str,
+	name: &str,
+	follow: bool
+) -> Result<usize, ErrNo> {
+	file::ext2file::with_ext2_mut(|ext2, ctx| {
+		ext2.removexattr(path, ctx, name, follow)
+	})?;
+	Ok(0)
+}
+
+/// This function mimic the linux chdir syscall.
+/// Change the working directory of the running process to path
+pub fn chdir(path: &str) -> Result<usize, ErrNo> {
+	let inodeno =
+		file::ext2file::with_ext2(|ext2, ctx| ext2.lookup_dir(path, ctx))?;
+	Process::get_running_process().lock().cwd = inodeno;
+	Ok(0)
+}
+
+/// This function mimic the linux fchdir syscall.
+/// Change the working directory of the running process to the directory opened on fd
+pub fn fchdir(fd: usize) -> Result<usize, ErrNo> {
+	let inodeno = get_fileop(fd)?.lock().inodeno().ok_or(ErrNo::ENOTDIR)?;
+	file::ext2file::with_ext2(|ext2, ctx| ext2.search_dir(inodeno, ctx))?;
+	Process::get_running_process().lock().cwd = inodeno;
+	Ok(0)
+}
+
+/// This function mimic the linux getcwd syscall.
+/// Return the absolute path of the working directory of the running process
+pub fn getcwd() -> Result<String, ErrNo> {
+	file::ext2file::with_ext2(|ext2, ctx| ext2.getcwd(ctx))
+}
+
+/// This function mimic the linux chroot syscall.
+/// Absolute paths of the running process start from path, only root can change it.
+/// The working directory is left unchanged
+pub fn chroot(path: &str) -> Result<usize, ErrNo> {
+	let inodeno = file::ext2file::with_ext2(|ext2, ctx| {
+		if !ctx.is_root() {
+			return Err(ErrNo::EPERM);
+		}
+		ext2.lookup_dir(path, ctx)
+	})?;
+	Process::get_running_process().lock().root = inodeno;
+	Ok(0)
 }
 
 /// Close a file given its file descriptor. This does not delete the file from the system
+/// The file is released once its last descriptor is closed, outside of the process lock since
+/// dropping it may wake up other processes.
 pub fn close(fd: usize) {
-	// TODO drop_in_place?
 	if fd < MAX_FD {
 		let binding = Process::get_running_process();
-		let mut curr_process = binding.lock();
-		curr_process.fds[fd] = None;
+		let file = {
+			let mut curr_process = binding.lock();
+			curr_process.fd_flags[fd] = 0;
+			curr_process.fds[fd].take()
+		};
+		drop(file);
 	}
 }
 
 /// This function mimic the linux read syscall. Look for a file in file lists and call it's
 /// FileOperation implementation. Mutex on PROC_FILES is acquire during all the read processus
 /// which imply you can't r/w another file at the same time.
+/// Descriptors opened O_WRONLY return EBADF
 pub fn read(fd: usize, dst: &mut [u8], length: usize) -> Result<usize, ErrNo> {
 	if fd >= MAX_FD {
 		return Err(ErrNo::EBADF);
@@ -112,7 +375,10 @@ pub fn read(fd: usize, dst: &mut [u8], length: usize) -> Result<usize, ErrNo> {
 	let guard2 = binding.execute(|guard| {
 		let mut curr_process = guard.lock();
 		let file = curr_process.fds[fd].as_mut().ok_or(ErrNo::EBADF)?;
-		Ok(file.op.clone())
+		match file.readable() {
+			true => Ok(file.op.clone()),
+			false => Err(ErrNo::EBADF)
+		}
 	})?;
 	let fileop = guard2.lock();
 	fileop.read(dst, length)
@@ -122,23 +388,288 @@ use crate::proc::process::Process;
 /// This function mimic the linux write syscall. Look for a file in file lists and call it's
 /// FileOperation implementation. Mutex on PROC_FILES is acquire during all the read processus
 /// which imply you can't r/w another file at the same time.
+/// With O_APPEND the write starts at the end of the file, descriptors opened O_RDONLY return
+/// EBADF
 pub fn write(fd: usize, src: &[u8], length: usize) -> Result<usize, ErrNo> {
 	if fd >= MAX_FD {
 		return Err(ErrNo::EBADF);
 	}
 
 	let binding = Process::get_running_process();
-	let guard2 = binding.execute(|guard| {
+	let (guard2, flags) = binding.execute(|guard| {
 		let mut curr_process = guard.lock();
 		let file = curr_process.fds[fd].as_mut().ok_or(ErrNo::EBADF)?;
-		Ok(file.op.clone())
+		match file.writable() {
+			true => Ok((file.op.clone(), file.get_flags())),
+			false => Err(ErrNo::EBADF)
+		}
 	})?;
 	let mut fileop = guard2.lock();
+	if flags & file::O_APPEND != 0 {
+		fileop.seek_end()?;
+	}
 	fileop.write(src, length)
 }
 
+/// Return the FileOperation behind a file descriptor of the running process
+fn get_fileop(fd: usize) -> Result<Arcm<dyn FileOperation>, ErrNo> {
+	if fd >= MAX_FD {
+		return Err(ErrNo::EBADF);
+	}
+	let binding = Process::get_running_process();
+	let curr_process = binding.lock();
+	let file = curr_process.fds[fd].as_ref().ok_or(ErrNo::EBADF)?;
+	Ok(file.op.clone())
+}
+
+/// Add a file to the first free file descriptor of the running process
+fn install(file: FileInfo) -> Result<usize, ErrNo> {
+	install_shared(Arc::new(file))
+}
+
+/// Same as install for a file that other descriptors may already refer to
+fn install_shared(file: Arc<FileInfo>) -> Result<usize, ErrNo> {
+	let binding = Process::get_running_process();
+	let mut curr_process = binding.lock();
+
+	let index = curr_process
+		.fds
+		.iter()
+		.position(|elem| elem.is_none())
+		.ok_or(ErrNo::EMFILE)?;
+	curr_process.fds[index] = Some(file);
+	curr_process.fd_flags[index] = 0;
+	Ok(index)
+}
+
+// fcntl commands and descriptor flags, values match linux fcntl.h
+pub const F_DUPFD: u32 = 0;
+pub const F_GETFD: u32 = 1;
+pub const F_SETFD: u32 = 2;
+pub const F_GETFL: u32 = 3;
+pub const F_SETFL: u32 = 4;
+pub const F_DUPFD_CLOEXEC: u32 = 1030;
+pub const FD_CLOEXEC: u32 = 1;
+
+/// Duplicate fd on the lowest free descriptor greater or equal to min.
+/// The new descriptor share the file and its status flags but not FD_CLOEXEC
+fn dup_from(fd: usize, min: usize, fd_flags: u32) -> Result<usize, ErrNo> {
+	if fd >= MAX_FD {
+		return Err(ErrNo::EBADF);
+	}
+	if min >= MAX_FD {
+		return Err(ErrNo::EINVAL);
+	}
+	let binding = Process::get_running_process();
+	let mut curr_process = binding.lock();
+
+	let file = curr_process.fds[fd].clone().ok_or(ErrNo::EBADF)?;
+	let index = (min..MAX_FD)
+		.find(|index| curr_process.fds[*index].is_none())
+		.ok_or(ErrNo::EMFILE)?;
+	curr_process.fds[index] = Some(file);
+	curr_process.fd_flags[index] = fd_flags;
+	Ok(index)
+}
+
+/// This function mimic the linux dup syscall.
+/// Return a new descriptor refering to the same file as fd
+pub fn dup(fd: usize) -> Result<usize, ErrNo> {
+	dup_from(fd, 0, 0)
+}
+
+/// This function mimic the linux dup2 syscall.
+/// Make newfd refer to the same file as oldfd, closing newfd first if needed
+pub fn dup2(oldfd: usize, newfd: usize) -> Result<usize, ErrNo> {
+	if oldfd >= MAX_FD || newfd >= MAX_FD {
+		return Err(ErrNo::EBADF);
+	}
+	let binding = Process::get_running_process();
+	let replaced = {
+		let mut curr_process = binding.lock();
+		let file = curr_process.fds[oldfd].clone().ok_or(ErrNo::EBADF)?;
+		if oldfd == newfd {
+			return Ok(newfd);
+		}
+		curr_process.fd_flags[newfd] = 0;
+		curr_process.fds[newfd].replace(file)
+	};
+	// Same as close, release the previous file without holding the process
+	drop(replaced);
+	Ok(newfd)
+}
+
+/// This function mimic the linux fcntl syscall.
+/// Supported commands are F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_SETFD, F_GETFL and F_SETFL
+pub fn fcntl(fd: usize, cmd: u32, arg: usize) -> Result<usize, ErrNo> {
+	match cmd {
+		F_DUPFD => return dup_from(fd, arg, 0),
+		F_DUPFD_CLOEXEC => return dup_from(fd, arg, FD_CLOEXEC),
+		_ => {}
+	}
+	if fd >= MAX_FD {
+		return Err(ErrNo::EBADF);
+	}
+	let binding = Process::get_running_process();
+	let file = {
+		let mut curr_process = binding.lock();
+		let file = curr_process.fds[fd].clone().ok_or(ErrNo::EBADF)?;
+		match cmd {
+			F_GETFD => return Ok(curr_process.fd_flags[fd] as usize),
+			F_SETFD => {
+				curr_process.fd_flags[fd] = arg as u32 & FD_CLOEXEC;
+				return Ok(0);
+			},
+			_ => file
+		}
+	};
+	match cmd {
+		F_GETFL => Ok(file.get_flags() as usize),
+		F_SETFL => {
+			file.set_flags(arg as u32);
+			Ok(0)
+		},
+		_ => Err(ErrNo::EINVAL)
+	}
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct PollFd {
+	pub fd:      i32,
+	pub events:  u16,
+	pub revents: u16
+}
+
+/// Fill revents of each PollFd with the events ready on its file.
+/// Negative fds are ignored, invalid ones get POLLNVAL.
+/// Return the number of PollFd with a non zero revents
+fn poll_once(
+	fds: &mut [PollFd],
+	ops: &[Option<Arcm<dyn FileOperation>>],
+	mut table: Option<&mut PollTable>
+) -> usize {
+	let mut count = 0;
+	for (pollfd, op) in fds.iter_mut().zip(ops) {
+		pollfd.revents = match op {
+			_ if pollfd.fd < 0 => 0,
+			None => POLLNVAL,
+			Some(op) => {
+				let events = op.lock().poll(table.as_deref_mut());
+				events & (pollfd.events | POLLERR | POLLHUP)
+			}
+		};
+		if pollfd.revents != 0 {
+			count += 1;
+		}
+	}
+	count
+}
+
+/// This function mimic the linux poll syscall. Wait until one of the files is ready
+/// for the requested events or the timeout (in ms) expire, None wait forever.
+pub fn poll(
+	fds: &mut [PollFd],
+	timeout: Option<usize>
+) -> Result<usize, ErrNo> {
+	let ops: Vec<Option<Arcm<dyn FileOperation>>> = fds
+		.iter()
+		.map(|pollfd| match pollfd.fd {
+			fd if fd < 0 => None,
+			fd => get_fileop(fd as usize).ok()
+		})
+		.collect();
+	let mut table: PollTable = Vec::new();
+	let mut count = poll_once(fds, &ops, Some(&mut table));
+	if count == 0 && timeout != Some(0) {
+		wait_event(&table, timeout, || {
+			count = poll_once(fds, &ops, None);
+			count != 0
+		});
+	}
+	Ok(count)
+}
+
 // SOCKET HELPERS
-use file::socket::{SocketDomain, SocketProtocol, SocketType};
+use file::socket::{
+	Shutdown,
+	Socket,
+	SocketDomain,
+	SocketProtocol,
+	SocketType
+};
+
+/// Execute f on the socket behind fd, ENOTSOCK is returned if fd is not a socket
+fn with_socket<T>(
+	fd: usize,
+	f: impl FnOnce(&mut Socket) -> Result<T, ErrNo>
+) -> Result<T, ErrNo> {
+	let fileop = get_fileop(fd)?;
+	let mut guard = fileop.lock();
+	let socket = guard.as_socket().ok_or(ErrNo::ENOTSOCK)?;
+	f(socket)
+}
+
+/// Create and open a socket given it's domain, type and protocol.
+/// Prototype is made to match linux syscall
+pub fn socket(
+	domain: SocketDomain,
+	stype: SocketType,
+	protocol: SocketProtocol
+) -> Result<usize, ErrNo> {
+	if domain != SocketDomain::AF_UNIX {
+		return Err(ErrNo::EAFNOSUPPORT);
+	}
+	let socket = Socket::new(domain, stype, protocol);
+	install(FileInfo::new(String::from("socketfs"), Arcm::new(socket)))
+}
+
+/// Address of a socket at path, relative paths start from the working directory of the
+/// running process like the paths of the filesystem
+fn socket_address(path: &str) -> Result<String, ErrNo> {
+	match path.starts_with('/') {
+		true => Ok(String::from(path)),
+		false => Ok(getcwd()? + "/" + path)
+	}
+}
+
+/// Bind the socket fd to a path
+pub fn bind(fd: usize, path: &str) -> Result<usize, ErrNo> {
+	let address = socket_address(path)?;
+	with_socket(fd, |socket| socket.bind(&address))?;
+	Ok(0)
+}
+
+/// Mark the socket fd as accepting connections
+pub fn listen(fd: usize, backlog: usize) -> Result<usize, ErrNo> {
+	with_socket(fd, |socket| socket.listen(backlog))?;
+	Ok(0)
+}
+
+/// Wait for a connection on socket fd and open the new connected socket.
+/// The socket itself is not locked during the wait so other processes sharing
+/// it can still use it
+pub fn accept(fd: usize) -> Result<usize, ErrNo> {
+	let (listener, nonblock) = with_socket(fd, |socket| {
+		Ok((socket.listener()?, socket.is_nonblocking()))
+	})?;
+	let socket = file::socket::accept(&listener, nonblock)?;
+	install(FileInfo::new(String::from("socketfs"), Arcm::new(socket)))
+}
+
+/// Connect the socket fd to the socket listening on path
+pub fn connect(fd: usize, path: &str) -> Result<usize, ErrNo> {
+	let address = socket_address(path)?;
+	with_socket(fd, |socket| socket.connect(&address))?;
+	Ok(0)
+}
+
+/// Shutdown part of a socket connection
+pub fn shutdown(fd: usize, how: Shutdown) -> Result<usize, ErrNo> {
+	with_socket(fd, |socket| socket.shutdown(how))?;
+	Ok(0)
+}
+
 /// Create and open a pair of socket given it's domain, type and protocol.
 /// Fd are written to sockets array. Prototype is made to match linux syscall
 pub fn socket_pair(
@@ -33,6 +33,13 @@ impl fs::FileOperation for Buffer {
 		}
 		Ok(length)
 	}
+
+	fn read_at(&self, dst: &mut [u8], offset: usize) -> Result<usize, ErrNo> {
+		let src = self.buffer.get(offset..).unwrap_or(&[]);
+		let length = core::cmp::min(src.len(), dst.len());
+		dst[..length].copy_from_slice(&src[..length]);
+		Ok(length)
+	}
 }
 
 #[sys_macros::test_case]
@@ -246,3 +253,780 @@ fn threaded_socket(sockets: usize, parent_sockets: usize) {
 	// should close fd 0
 	fs::close(sockets);
 }
+
+const SOCKET_PATH: &str = "/tmp/test_socket";
+#[sys_macros::test_case]
+fn test_socket_stream_thread() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	let server = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_STREAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+	fs::bind(server, SOCKET_PATH).expect("Failed to bind socket");
+	fs::listen(server, 1).expect("Failed to listen on socket");
+
+	let pid = unsafe { crate::exec_fn!(threaded_stream_client, server) };
+
+	let mut dst: [u8; 50] = [0; 50];
+	let client = fs::accept(server).expect("Failed to accept connection");
+	assert_eq!(
+		fs::read(client, &mut dst, CHILD_STRING.len())
+			.expect("Reading client failed"),
+		CHILD_STRING.len()
+	);
+	assert_eq!(CHILD_STRING.as_bytes(), &dst[0..CHILD_STRING.len()]);
+	assert_eq!(
+		fs::write(client, PARENT_STRING.as_bytes(), PARENT_STRING.len())
+			.expect("Writing client failed"),
+		PARENT_STRING.len()
+	);
+
+	let mut status = 0;
+	use crate::syscalls::exit::sys_waitpid;
+	sys_waitpid(pid, &mut status, 0);
+
+	// Client closed its end, we should read EOF
+	assert_eq!(fs::read(client, &mut dst, 50), Ok(0));
+	fs::close(client);
+	fs::close(server);
+}
+
+fn threaded_stream_client(server: usize) {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	let mut dst: [u8; 50] = [0; 50];
+
+	fs::close(server);
+	let socket = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_STREAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+	fs::connect(socket, SOCKET_PATH).expect("Failed to connect");
+	assert_eq!(
+		fs::write(socket, CHILD_STRING.as_bytes(), CHILD_STRING.len())
+			.expect("Writing socket failed"),
+		CHILD_STRING.len()
+	);
+	assert_eq!(
+		fs::read(socket, &mut dst, PARENT_STRING.len())
+			.expect("Reading socket failed"),
+		PARENT_STRING.len()
+	);
+	assert_eq!(PARENT_STRING.as_bytes(), &dst[0..PARENT_STRING.len()]);
+	fs::close(socket);
+}
+
+#[sys_macros::test_case]
+fn test_poll_socket_pair() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::{socket_pair, PollFd, POLLIN, POLLNVAL, POLLOUT};
+	let mut sockets: [usize; 2] = [0; 2];
+	socket_pair(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT,
+		&mut sockets
+	)
+	.expect("Failed to create socket pair");
+
+	let mut fds: [PollFd; 3] = [
+		PollFd { fd: sockets[0] as i32, events: POLLIN, revents: 0 },
+		PollFd { fd: sockets[1] as i32, events: POLLOUT, revents: 0 },
+		PollFd { fd: 20, events: POLLIN, revents: 0 }
+	];
+	// Nothing to read yet, only the write and the invalid fd are reported
+	assert_eq!(fs::poll(&mut fds, Some(0)), Ok(2));
+	assert_eq!(fds[0].revents, 0);
+	assert_eq!(fds[1].revents, POLLOUT);
+	assert_eq!(fds[2].revents, POLLNVAL);
+
+	fs::write(sockets[1], b"ready", 5).expect("Writing failed");
+	fds[2].fd = -1;
+	assert_eq!(fs::poll(&mut fds, None), Ok(2));
+	assert_eq!(fds[0].revents, POLLIN);
+	assert_eq!(fds[2].revents, 0);
+
+	fs::close(sockets[0]);
+	fs::close(sockets[1]);
+}
+
+#[sys_macros::test_case]
+fn test_poll_timeout() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::{socket_pair, PollFd, POLLIN};
+	let mut sockets: [usize; 2] = [0; 2];
+	socket_pair(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT,
+		&mut sockets
+	)
+	.expect("Failed to create socket pair");
+
+	let mut fds: [PollFd; 1] =
+		[PollFd { fd: sockets[0] as i32, events: POLLIN, revents: 0 }];
+	assert_eq!(fs::poll(&mut fds, Some(20)), Ok(0));
+	assert_eq!(fds[0].revents, 0);
+
+	fs::close(sockets[0]);
+	fs::close(sockets[1]);
+}
+
+#[sys_macros::test_case]
+fn test_poll_thread() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::{socket_pair, PollFd, POLLIN};
+	let mut sockets: [usize; 2] = [0; 2];
+	socket_pair(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT,
+		&mut sockets
+	)
+	.expect("Failed to create socket pair");
+
+	let pid = unsafe {
+		crate::exec_fn!(threaded_poll_writer, sockets[1], sockets[0])
+	};
+	fs::close(sockets[1]);
+
+	// Sleep until the child write to the socket
+	let mut fds: [PollFd; 1] =
+		[PollFd { fd: sockets[0] as i32, events: POLLIN, revents: 0 }];
+	assert_eq!(fs::poll(&mut fds, None), Ok(1));
+	assert_eq!(fds[0].revents, POLLIN);
+
+	let mut status = 0;
+	use crate::syscalls::exit::sys_waitpid;
+	sys_waitpid(pid, &mut status, 0);
+	fs::close(sockets[0]);
+}
+
+fn threaded_poll_writer(socket: usize, parent_socket: usize) {
+	fs::close(parent_socket);
+	crate::time::sleep(10);
+	fs::write(socket, CHILD_STRING.as_bytes(), CHILD_STRING.len())
+		.expect("Writing socket failed");
+	fs::close(socket);
+}
+
+#[sys_macros::test_case]
+fn test_poll_tty() {
+	use super::tty::{push_input, TTY_PATH};
+	use super::{PollFd, POLLIN, POLLOUT};
+	let fd = fs::open(TTY_PATH).expect("Failed to open tty");
+	let mut fds: [PollFd; 1] =
+		[
+			PollFd {
+				fd:      fd as i32,
+				events:  POLLIN | POLLOUT,
+				revents: 0
+			}
+		];
+	assert_eq!(fs::poll(&mut fds, Some(0)), Ok(1));
+	assert_eq!(fds[0].revents, POLLOUT);
+
+	// Characters typed are buffered until read
+	push_input('o');
+	push_input('k');
+	assert_eq!(fs::poll(&mut fds, Some(0)), Ok(1));
+	assert_eq!(fds[0].revents, POLLIN | POLLOUT);
+	let mut buffer: [u8; 4] = [0; 4];
+	assert_eq!(fs::read(fd, &mut buffer, 4), Ok(2));
+	assert_eq!(&buffer[0..2], b"ok");
+	assert_eq!(fs::poll(&mut fds, Some(0)), Ok(1));
+	assert_eq!(fds[0].revents, POLLOUT);
+
+	fs::close(fd);
+	// Input is dropped once no tty file is open
+	push_input('x');
+	let fd = fs::open(TTY_PATH).expect("Failed to open tty");
+	fds[0].fd = fd as i32;
+	assert_eq!(fs::poll(&mut fds, Some(0)), Ok(1));
+	assert_eq!(fds[0].revents, POLLOUT);
+	fs::close(fd);
+}
+
+#[sys_macros::test_case]
+fn test_select_socket_pair() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::socket_pair;
+	use crate::syscalls::poll::{fd_set, sys_select, timeval};
+	let mut sockets: [usize; 2] = [0; 2];
+	socket_pair(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT,
+		&mut sockets
+	)
+	.expect("Failed to create socket pair");
+
+	let mut readfds = fd_set::new();
+	let mut writefds = fd_set::new();
+	let mut timeout = timeval { tv_sec: 0, tv_usec: 0 };
+	readfds.set(sockets[0]);
+	writefds.set(sockets[1]);
+	let nfds = sockets[1] as i32 + 1;
+	// Bits past nfds are ignored and cleared
+	writefds.set(nfds as usize);
+	writefds.set(1023);
+	assert_eq!(
+		sys_select(
+			nfds,
+			&mut readfds,
+			&mut writefds,
+			core::ptr::null_mut(),
+			&mut timeout
+		),
+		1
+	);
+	assert!(!readfds.is_set(sockets[0]));
+	assert!(writefds.is_set(sockets[1]));
+	assert!(!writefds.is_set(nfds as usize) && !writefds.is_set(1023));
+
+	fs::write(sockets[1], b"ready", 5).expect("Writing failed");
+	readfds.set(sockets[0]);
+	assert_eq!(
+		sys_select(
+			nfds,
+			&mut readfds,
+			core::ptr::null_mut(),
+			core::ptr::null_mut(),
+			core::ptr::null_mut()
+		),
+		1
+	);
+	assert!(readfds.is_set(sockets[0]));
+
+	// Negative or out of range timeouts are refused, huge ones saturate
+	for (tv_sec, tv_usec) in [(-1, 0), (0, -1), (0, 1_000_000)] {
+		let mut timeout = timeval { tv_sec, tv_usec };
+		assert_eq!(
+			sys_select(
+				nfds,
+				&mut readfds,
+				core::ptr::null_mut(),
+				core::ptr::null_mut(),
+				&mut timeout
+			),
+			-(ErrNo::EINVAL as i32)
+		);
+	}
+	let mut timeout = timeval { tv_sec: i32::MAX, tv_usec: 999_999 };
+	assert_eq!(
+		sys_select(
+			nfds,
+			&mut readfds,
+			core::ptr::null_mut(),
+			core::ptr::null_mut(),
+			&mut timeout
+		),
+		1
+	);
+
+	fs::close(sockets[0]);
+	fs::close(sockets[1]);
+	let mut timeout = timeval { tv_sec: 0, tv_usec: 0 };
+	// Closed fds are reported as bad file descriptors
+	assert_eq!(
+		sys_select(
+			nfds,
+			&mut readfds,
+			core::ptr::null_mut(),
+			core::ptr::null_mut(),
+			&mut timeout
+		),
+		-(ErrNo::EBADF as i32)
+	);
+}
+
+#[sys_macros::test_case]
+fn test_dup() {
+	let buffer: Buffer = Buffer::new();
+	fs::create_from_raw("test_file", buffer).expect("Failed to create file");
+	let fd = fs::open("test_file").expect("Failed to open file");
+	let fd2 = fs::dup(fd).expect("Failed to dup file");
+	let mut dst: [u8; 11] = [0; 11];
+
+	assert_ne!(fd, fd2);
+	fs::write(fd, b"hello world", 11).expect("Writing failed");
+	fs::close(fd);
+	// Duplicated descriptor still refer to the file
+	assert_eq!(fs::read(fd2, &mut dst, 11), Ok(11));
+	assert_eq!(&dst, b"hello world");
+	assert_eq!(fs::dup(fd), Err(ErrNo::EBADF));
+
+	// dup2 reuse the given descriptor, closing it first
+	assert_eq!(fs::dup2(fd2, 10), Ok(10));
+	assert_eq!(fs::dup2(fd2, 10), Ok(10));
+	assert_eq!(fs::dup2(10, 10), Ok(10));
+	assert_eq!(fs::dup2(fd, 10), Err(ErrNo::EBADF));
+	assert_eq!(fs::read(10, &mut dst, 5), Ok(5));
+	fs::close(fd2);
+	fs::close(10);
+	fs::delete("test_file");
+}
+
+#[sys_macros::test_case]
+fn test_fcntl() {
+	use super::{
+		FD_CLOEXEC,
+		F_DUPFD,
+		F_GETFD,
+		F_GETFL,
+		F_SETFD,
+		F_SETFL,
+		O_NONBLOCK,
+		O_RDWR
+	};
+	let buffer: Buffer = Buffer::new();
+	fs::create_from_raw("test_file", buffer).expect("Failed to create file");
+	let fd = fs::open("test_file").expect("Failed to open file");
+
+	assert_eq!(fs::fcntl(fd, F_DUPFD, 5), Ok(5));
+	assert_eq!(fs::fcntl(fd, F_DUPFD, 5), Ok(6));
+	assert_eq!(fs::fcntl(fd, F_GETFD, 0), Ok(0));
+	assert_eq!(fs::fcntl(fd, F_SETFD, FD_CLOEXEC as usize), Ok(0));
+	assert_eq!(fs::fcntl(fd, F_GETFD, 0), Ok(FD_CLOEXEC as usize));
+	// Descriptor flags are not shared with duplicates
+	assert_eq!(fs::fcntl(5, F_GETFD, 0), Ok(0));
+
+	// Status flags are shared, access mode can't be changed
+	assert_eq!(fs::fcntl(fd, F_GETFL, 0), Ok(O_RDWR as usize));
+	assert_eq!(fs::fcntl(5, F_SETFL, (O_NONBLOCK | 1) as usize), Ok(0));
+	assert_eq!(fs::fcntl(6, F_GETFL, 0), Ok((O_RDWR | O_NONBLOCK) as usize));
+	assert_eq!(fs::fcntl(fd, 42, 0), Err(ErrNo::EINVAL));
+	assert_eq!(fs::fcntl(20, F_GETFD, 0), Err(ErrNo::EBADF));
+
+	fs::close(fd);
+	fs::close(5);
+	fs::close(6);
+	fs::delete("test_file");
+}
+
+#[sys_macros::test_case]
+fn test_fcntl_nonblock_socket() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::{socket_pair, F_SETFL, O_NONBLOCK};
+	let mut sockets: [usize; 2] = [0; 2];
+	let mut dst: [u8; 8] = [0; 8];
+	socket_pair(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT,
+		&mut sockets
+	)
+	.expect("Failed to create socket pair");
+
+	fs::fcntl(sockets[0], F_SETFL, O_NONBLOCK as usize)
+		.expect("Failed to set O_NONBLOCK");
+	assert_eq!(fs::read(sockets[0], &mut dst, 8), Err(ErrNo::EAGAIN));
+	fs::close(sockets[0]);
+	fs::close(sockets[1]);
+}
+
+fn check_cloexec(fd: usize, cloexec_fd: usize) -> usize {
+	let mut dst: [u8; 1] = [0; 1];
+	let mut res = 0;
+	if fs::read(fd, &mut dst, 1).is_ok() {
+		res += 1;
+	}
+	if fs::read(cloexec_fd, &mut dst, 1) == Err(ErrNo::EBADF) {
+		res += 1;
+	}
+	res
+}
+
+#[sys_macros::test_case]
+fn test_cloexec() {
+	use super::{FD_CLOEXEC, F_SETFD};
+	let buffer: Buffer = Buffer::new();
+	fs::create_from_raw("test_file", buffer).expect("Failed to create file");
+	let fd = fs::open("test_file").expect("Failed to open file");
+	let cloexec_fd = fs::dup(fd).expect("Failed to dup file");
+	fs::fcntl(cloexec_fd, F_SETFD, FD_CLOEXEC as usize)
+		.expect("Failed to set FD_CLOEXEC");
+
+	let pid = unsafe { crate::exec_fn!(check_cloexec, fd, cloexec_fd) };
+	let mut status = 0;
+	use crate::syscalls::exit::sys_waitpid;
+	sys_waitpid(pid, &mut status, 0);
+	assert_eq!(__WEXITSTATUS!(status), 2);
+
+	fs::close(fd);
+	fs::close(cloexec_fd);
+	fs::delete("test_file");
+}
+
+#[sys_macros::test_case]
+fn test_close_last_reference() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	let mut dst: [u8; 8] = [0; 8];
+	let server = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_STREAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+	let client = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_STREAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+	fs::bind(server, SOCKET_PATH).expect("Failed to bind socket");
+	fs::listen(server, 1).expect("Failed to listen on socket");
+	fs::connect(client, SOCKET_PATH).expect("Failed to connect");
+	let accepted = fs::accept(server).expect("Failed to accept");
+
+	// Connection is only closed once every descriptor is closed
+	let dup = fs::dup(client).expect("Failed to dup");
+	fs::close(client);
+	fs::write(dup, b"alive", 5).expect("Writing failed");
+	assert_eq!(fs::read(accepted, &mut dst, 8), Ok(5));
+	fs::close(dup);
+	assert_eq!(fs::read(accepted, &mut dst, 8), Ok(0));
+
+	fs::close(accepted);
+	fs::close(server);
+	// Address is released with the last descriptor of the socket
+	let server = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_STREAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+	fs::bind(server, SOCKET_PATH).expect("Failed to bind socket");
+	fs::close(server);
+}
+
+#[sys_macros::test_case]
+fn test_socketcall() {
+	use crate::syscalls::socket::{
+		sockaddr_un,
+		sys_socketcall,
+		SYS_BIND,
+		SYS_CONNECT,
+		SYS_LISTEN,
+		SYS_SOCKET,
+		SYS_SOCKETPAIR,
+		UNIX_PATH_MAX
+	};
+
+	let mut sv: [i32; 2] = [-1; 2];
+	let mut args: [u32; 6] = [1, 1, 0, 0, 0, 0];
+	assert_eq!(
+		sys_socketcall(SYS_SOCKETPAIR, args.as_ptr()),
+		-(ErrNo::EFAULT as i32)
+	);
+	args[3] = sv.as_mut_ptr() as u32;
+	assert_eq!(sys_socketcall(SYS_SOCKETPAIR, args.as_ptr()), 0);
+	assert!(sv[0] >= 0 && sv[1] >= 0 && sv[0] != sv[1]);
+	// Stream pairs are connected both ways
+	let (first, second) = (sv[0] as usize, sv[1] as usize);
+	let mut dst: [u8; 4] = [0; 4];
+	assert_eq!(fs::write(first, b"ping", 4), Ok(4));
+	assert_eq!(fs::read(second, &mut dst, 4), Ok(4));
+	assert_eq!(&dst, b"ping");
+	assert_eq!(fs::write(second, b"pong", 4), Ok(4));
+	assert_eq!(fs::read(first, &mut dst, 4), Ok(4));
+	assert_eq!(&dst, b"pong");
+	fs::close(first);
+	fs::close(second);
+	assert_eq!(sys_socketcall(42, args.as_ptr()), -(ErrNo::EINVAL as i32));
+
+	let server = sys_socketcall(SYS_SOCKET, [1, 1, 0, 0, 0, 0].as_ptr());
+	let client = sys_socketcall(SYS_SOCKET, [1, 1, 0, 0, 0, 0].as_ptr());
+	assert!(server >= 0 && client >= 0);
+	let mut addr =
+		sockaddr_un { sun_family: 1, sun_path: [0; UNIX_PATH_MAX] };
+	addr.sun_path[..SOCKET_PATH.len()].copy_from_slice(SOCKET_PATH.as_bytes());
+	let len = core::mem::size_of::<sockaddr_un>() as u32;
+	let args = [server as u32, &addr as *const _ as u32, len, 0, 0, 0];
+	assert_eq!(sys_socketcall(SYS_BIND, args.as_ptr()), 0);
+	assert_eq!(
+		sys_socketcall(SYS_LISTEN, [server as u32, 1, 0, 0, 0, 0].as_ptr()),
+		0
+	);
+	let args = [client as u32, &addr as *const _ as u32, len, 0, 0, 0];
+	assert_eq!(sys_socketcall(SYS_CONNECT, args.as_ptr()), 0);
+	fs::close(server as usize);
+	fs::close(client as usize);
+}
+
+#[sys_macros::test_case]
+fn test_socket_relative_path() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use crate::cli::DISKNO;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::file::ext2file::with_ext2_mut;
+
+	let disk = DISKNO.lock().replace(ramdisk_fs(&MkfsOptions::default()));
+	with_ext2_mut(|ext2, ctx| ext2.mkdir("/sockets", ctx, 0o755))
+		.expect("Failed to create directory");
+	fs::chdir("/sockets").expect("Failed to change directory");
+	let socket = || {
+		fs::socket(
+			SocketDomain::AF_UNIX,
+			SocketType::SOCK_STREAM,
+			SocketProtocol::DEFAULT
+		)
+		.expect("Failed to create socket")
+	};
+	let (server, client, other) = (socket(), socket(), socket());
+	fs::bind(server, "test_socket").expect("Failed to bind socket");
+	fs::listen(server, 2).expect("Failed to listen on socket");
+	assert_eq!(
+		fs::bind(other, "../sockets/test_socket"),
+		Err(ErrNo::EADDRINUSE)
+	);
+	fs::connect(client, "/sockets/test_socket").expect("Failed to connect");
+	fs::chdir("/").expect("Failed to change directory");
+	assert_eq!(fs::connect(other, "test_socket"), Err(ErrNo::ECONNREFUSED));
+	fs::connect(other, "sockets/test_socket").expect("Failed to connect");
+	fs::close(server);
+	fs::close(client);
+	fs::close(other);
+	*DISKNO.lock() = disk;
+}
+
+#[sys_macros::test_case]
+fn test_ext2_open_flags() {
+	use crate::cli::DISKNO;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::fs::file::ext2file::with_ext2_mut;
+	use crate::fs::{
+		F_GETFL,
+		O_APPEND,
+		O_CREAT,
+		O_RDONLY,
+		O_RDWR,
+		O_TRUNC,
+		O_WRONLY
+	};
+
+	let disk = DISKNO.lock().replace(ramdisk_fs(&MkfsOptions::default()));
+	with_ext2_mut(|ext2, ctx| ext2.create("/file", ctx, 0o644))
+		.expect("Failed to create file");
+	let fd = fs::open_flags("/file", O_RDWR | O_APPEND, 0)
+		.expect("Failed to open file");
+	assert_eq!(fs::fcntl(fd, F_GETFL, 0), Ok((O_RDWR | O_APPEND) as usize));
+	fs::close(fd);
+	let fd = fs::open("/file").expect("Failed to open file");
+	assert_eq!(fs::fcntl(fd, F_GETFL, 0), Ok(O_RDONLY as usize));
+	assert_eq!(fs::write(fd, b"yak", 3), Err(ErrNo::EBADF));
+	fs::close(fd);
+	let fd = fs::open_flags("/file", O_WRONLY, 0).expect("Failed to open file");
+	let mut dst = [0; 4];
+	assert_eq!(fs::read(fd, &mut dst, 4), Err(ErrNo::EBADF));
+	fs::close(fd);
+
+	assert_eq!(fs::open_flags("/", O_RDWR, 0), Err(ErrNo::EISDIR));
+	assert_eq!(
+		fs::open_flags("/file", O_RDWR | O_TRUNC, 0),
+		Err(ErrNo::EINVAL)
+	);
+	assert_eq!(
+		fs::open_flags("/new", O_RDWR | O_CREAT, 0o644),
+		Err(ErrNo::EINVAL)
+	);
+	*DISKNO.lock() = disk;
+}
+
+#[sys_macros::test_case]
+fn test_stat() {
+	use super::socket::{SocketDomain, SocketProtocol, SocketType};
+	use super::stat::{S_IFMT, S_IFSOCK};
+
+	let buffer: Buffer = Buffer::new();
+	fs::create_from_raw("test_file", buffer).expect("Failed to create file");
+	let fd = fs::open("test_file").expect("Failed to open file");
+	let socket = fs::socket(
+		SocketDomain::AF_UNIX,
+		SocketType::SOCK_DGRAM,
+		SocketProtocol::DEFAULT
+	)
+	.expect("Failed to create socket");
+
+	assert!(fs::stat("test_file").is_ok());
+	assert!(fs::fstat(fd).is_ok());
+	let mode = fs::fstat(socket).expect("Failed to stat socket").st_mode;
+	assert_eq!(mode & S_IFMT, S_IFSOCK);
+	assert_eq!(fs::fstat(socket + 1).err(), Some(ErrNo::EBADF));
+	// Only directories can be listed
+	let mut dirp: [u8; 64] = [0; 64];
+	assert_eq!(fs::getdents(fd, &mut dirp), Err(ErrNo::ENOTDIR));
+	assert_eq!(fs::getdents(socket, &mut dirp), Err(ErrNo::ENOTDIR));
+	fs::close(socket);
+	fs::close(fd);
+	fs::delete("test_file");
+}
+
+#[sys_macros::test_case]
+fn test_mmap_file() {
+	use crate::memory::{MAP_PRIVATE, MAP_SHARED, PROT_READ, PROT_WRITE};
+	use crate::syscalls::mmap::{sys_mmap, sys_mremap, sys_munmap};
+
+	let mut buffer: Buffer = Buffer::new();
+	buffer.buffer[..11].copy_from_slice(b"hello world");
+	fs::create_from_raw("test_file", buffer).expect("Failed to create file");
+	let fd = fs::open("test_file").expect("Failed to open file");
+
+	let addr = sys_mmap(0, 0x1000, PROT_READ, MAP_PRIVATE, fd as i32, 0)
+		.expect("Failed to map file");
+	let page =
+		unsafe { core::slice::from_raw_parts(addr as *const u8, 0x1000) };
+	assert_eq!(&page[..11], b"hello world");
+	assert!(page[1024..].iter().all(|x| *x == 0));
+	assert_eq!(
+		sys_munmap(addr as *const usize, usize::MAX),
+		-(ErrNo::EINVAL as i32)
+	);
+	assert_eq!(
+		sys_munmap(addr as *const usize, usize::MAX - addr as usize),
+		-(ErrNo::EINVAL as i32)
+	);
+	assert_eq!(
+		sys_mremap(addr, 0x1000, usize::MAX, 0, 0),
+		-(ErrNo::EINVAL as i32)
+	);
+	assert_eq!(
+		sys_mremap(addr, usize::MAX - 0x800, 0x1000, 0, 0),
+		-(ErrNo::EINVAL as i32)
+	);
+	assert_eq!(sys_munmap(addr as *const usize, 0x1000), 0);
+	// Pages of shared mappings can't be written back
+	assert_eq!(
+		sys_mmap(0, 0x1000, PROT_READ | PROT_WRITE, MAP_SHARED, fd as i32, 0),
+		Err(ErrNo::ENODEV)
+	);
+	assert_eq!(
+		sys_mmap(0, 0x1000, PROT_READ, MAP_PRIVATE | MAP_SHARED, fd as i32, 0),
+		Err(ErrNo::EINVAL)
+	);
+	assert_eq!(
+		sys_mmap(0, usize::MAX, PROT_READ, MAP_PRIVATE, fd as i32, 0),
+		Err(ErrNo::ENOMEM)
+	);
+	fs::close(fd);
+	assert_eq!(
+		sys_mmap(0, 0x1000, PROT_READ, MAP_PRIVATE, fd as i32, 0),
+		Err(ErrNo::EBADF)
+	);
+	fs::delete("test_file");
+}
+
+#[sys_macros::test_case]
+fn test_shm_open() {
+	use crate::fs::{O_CREAT, O_EXCL, O_RDONLY, O_RDWR};
+	use crate::memory::{MAP_SHARED, PROT_READ, PROT_WRITE};
+	use crate::syscalls::mmap::{sys_mmap, sys_munmap};
+
+	assert_eq!(fs::shm_open("test_shm", O_RDWR, 0), Err(ErrNo::EINVAL));
+	assert_eq!(fs::shm_open("/test_shm", O_RDWR, 0), Err(ErrNo::ENOENT));
+	let fd = fs::shm_open("/test_shm", O_RDWR | O_CREAT, 0o600)
+		.expect("Failed to create shared memory object");
+	assert_eq!(
+		fs::shm_open("/test_shm", O_RDWR | O_CREAT | O_EXCL, 0o600),
+		Err(ErrNo::EEXIST)
+	);
+	assert_eq!(fs::fstat(fd).map(|stat| stat.st_size), Ok(0));
+	assert_eq!(fs::ftruncate(fd, 0x2000), Ok(0));
+	assert_eq!(fs::fstat(fd).map(|stat| stat.st_size), Ok(0x2000));
+
+	let addr =
+		sys_mmap(0, 0x2000, PROT_READ | PROT_WRITE, MAP_SHARED, fd as i32, 0)
+			.expect("Failed to map shared memory object");
+	let fd2 = fs::shm_open("/test_shm", O_RDONLY, 0)
+		.expect("Failed to open shared memory object");
+	unsafe { *((addr + 0x1000) as *mut u8) = 42 };
+	let mut dst = [0; 0x1001];
+	assert_eq!(fs::read(fd2, &mut dst, 0x1001), Ok(0x1001));
+	assert_eq!(dst[0x1000], 42);
+	assert_eq!(fs::ftruncate(fd2, 0), Err(ErrNo::EINVAL));
+
+	// The object lives while it is mapped
+	assert_eq!(fs::shm_unlink("/test_shm"), Ok(0));
+	assert_eq!(fs::shm_open("/test_shm", O_RDWR, 0), Err(ErrNo::ENOENT));
+	fs::close(fd);
+	fs::close(fd2);
+	assert_eq!(unsafe { *((addr + 0x1000) as *const u8) }, 42);
+	assert_eq!(sys_munmap(addr as *const usize, 0x2000), 0);
+}
+
+#[sys_macros::test_case]
+fn test_write_append() {
+	use crate::fs::{F_SETFL, O_APPEND, O_CREAT, O_RDONLY, O_RDWR};
+
+	let fd = fs::shm_open("/test_append", O_RDWR | O_CREAT | O_APPEND, 0o600)
+		.expect("Failed to create shared memory object");
+	let fd2 = fs::shm_open("/test_append", O_RDWR, 0)
+		.expect("Failed to open shared memory object");
+	assert_eq!(fs::write(fd2, b"hello", 5), Ok(5));
+	assert_eq!(fs::write(fd, b" world", 6), Ok(6));
+	// Other descriptors keep their own position
+	assert_eq!(fs::write(fd2, b"HELLO", 5), Ok(5));
+	assert_eq!(fs::write(fd, b"!", 1), Ok(1));
+	assert_eq!(fs::fcntl(fd2, F_SETFL, O_APPEND as usize), Ok(0));
+	assert_eq!(fs::write(fd2, b"?", 1), Ok(1));
+
+	let reader = fs::shm_open("/test_append", O_RDONLY, 0)
+		.expect("Failed to open shared memory object");
+	let mut dst = [0; 16];
+	assert_eq!(fs::read(reader, &mut dst, 16), Ok(13));
+	assert_eq!(&dst[..13], b"helloHELLOd!?");
+	fs::close(reader);
+	fs::close(fd);
+	fs::close(fd2);
+	assert_eq!(fs::shm_unlink("/test_append"), Ok(0));
+}
+
+#[sys_macros::test_case]
+fn test_shm_access_mode() {
+	use crate::fs::{O_CREAT, O_RDONLY, O_RDWR, O_WRONLY};
+
+	let fd = fs::shm_open("/test_access", O_WRONLY | O_CREAT, 0o600)
+		.expect("Failed to create shared memory object");
+	let mut dst = [0; 4];
+	assert_eq!(fs::write(fd, b"yak", 3), Ok(3));
+	assert_eq!(fs::read(fd, &mut dst, 4), Err(ErrNo::EBADF));
+	assert_eq!(fs::ftruncate(fd, 2), Ok(0));
+
+	let reader = fs::shm_open("/test_access", O_RDONLY, 0)
+		.expect("Failed to open shared memory object");
+	assert_eq!(fs::write(reader, b"yak", 3), Err(ErrNo::EBADF));
+	assert_eq!(fs::ftruncate(reader, 0), Err(ErrNo::EINVAL));
+	assert_eq!(fs::read(reader, &mut dst, 4), Ok(2));
+	assert_eq!(&dst[..2], b"ya");
+
+	let both = fs::shm_open("/test_access", O_RDWR, 0)
+		.expect("Failed to open shared memory object");
+	assert_eq!(fs::write(both, b"Y", 1), Ok(1));
+	assert_eq!(fs::read(both, &mut dst, 4), Ok(1));
+	fs::close(fd);
+	fs::close(reader);
+	fs::close(both);
+	assert_eq!(fs::shm_unlink("/test_access"), Ok(0));
+}
+
+#[sys_macros::test_case]
+fn test_shm_dev_path() {
+	use crate::fs::{FD_CLOEXEC, F_GETFD, O_CLOEXEC, O_CREAT, O_RDWR};
+
+	let path = "/dev/shm/test_dev_shm";
+	let fd = fs::open_flags(path, O_RDWR | O_CREAT | O_CLOEXEC, 0o600)
+		.expect("Failed to create shared memory object");
+	assert_eq!(fs::fcntl(fd, F_GETFD, 0), Ok(FD_CLOEXEC as usize));
+	assert_eq!(fs::write(fd, b"yak", 3), Ok(3));
+	let fd2 = fs::open(path).expect("Failed to open shared memory object");
+	let mut dst = [0; 3];
+	assert_eq!(fs::read(fd2, &mut dst, 3), Ok(3));
+	assert_eq!(&dst, b"yak");
+	assert_eq!(fs::unlink(path), Ok(0));
+	assert_eq!(fs::open(path), Err(ErrNo::ENOENT));
+	fs::close(fd);
+	fs::close(fd2);
+}
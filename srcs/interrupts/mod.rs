@@ -1,5 +1,10 @@
 //! Setup interrupts and exception handler
 
+use crate::memory::paging::{get_paddr, page_directory};
+use crate::memory::VirtAddr;
+use crate::proc::kill_running;
+use crate::proc::process::Process;
+use crate::proc::signal::{Signal, SignalType};
 use crate::proc::task::Task;
 use crate::syscalls::syscall_handler;
 
@@ -111,12 +116,57 @@ impl Registers {
 
 use crate::pic::{PIC1_IRQ_OFFSET, PIC2_IRQ_OFFSET};
 
-fn page_fault_handler(reg: &Registers) {
+/// Page fault error code bit set when the page was present, the access violated its protection
+const PF_PRESENT: u32 = 0b1;
+/// Page fault error code bit set when the access was a write
+const PF_WRITE: u32 = 0b10;
+
+/// Handle a page fault of the running process.
+/// Pages of its memory zones get a frame on first access and their own copy of a frame shared
+/// copy-on-write on first write, other faults deliver SIGSEGV: the
+/// process handler runs if it has one, otherwise the process is terminated.
+/// Return false if the fault happened in the kernel itself and can't be handled
+fn page_fault_handler(reg: &Registers) -> bool {
+	let cr2: usize;
+	unsafe { core::arch::asm!("mov {}, cr2", out(reg) cr2) };
+	{
+		let binding = Process::get_running_process();
+		let (pid, own_space) = {
+			let process = binding.lock();
+			(process.pid, !process.pd.is_null())
+		};
+		// A process with its own page directory faulting in the kernel one was
+		// inside an interrupt handler
+		let kernel_space =
+			reg.cr3 == unsafe { get_paddr!((&page_directory) as *const _) };
+		if pid == 0 || kernel_space == own_space {
+			return false;
+		}
+		let write = reg.err_code & PF_WRITE != 0;
+		if (reg.err_code & PF_PRESENT == 0 || write)
+			&& Process::fault_in(&binding, cr2 as VirtAddr, write).is_ok()
+		{
+			return true;
+		}
+		let mut process = binding.lock();
+		if process
+			.signal_handlers
+			.iter()
+			.any(|handler| handler.signal == SignalType::SIGSEGV as i32)
+		{
+			Signal::send_to_process(&mut process, pid, SignalType::SIGSEGV, 0);
+			return true;
+		}
+	}
+	unsafe { kill_running(SignalType::SIGSEGV) }
+}
+
+fn log_page_fault(reg: &Registers) {
 	unsafe {
 		let cr2: usize;
 		core::arch::asm!("mov {}, cr2", out(reg) cr2);
 		crate::kprintln!("at addr {:#x}", cr2);
-		let binding = crate::proc::process::Process::get_running_process();
+		let binding = Process::get_running_process();
 		crate::kprintln!("Current pid {}", binding.lock().pid);
 	}
 	crate::kprintln!("{:#x?}", reg);
@@ -132,7 +182,9 @@ pub unsafe extern "C" fn exception_handler(regs: &mut Registers) {
 	let task = Task::get_running_task();
 	task.regs = *regs; // dump regs for syscall (e.g: fork)
 	let int_no: usize = regs.int_no as usize;
-	if int_no < EXCEPTION_SIZE && STR_EXCEPTION[int_no] != "Reserved" {
+	if int_no == 14 && page_fault_handler(regs) {
+		// Resolved or signaled, the process resumes
+	} else if int_no < EXCEPTION_SIZE && STR_EXCEPTION[int_no] != "Reserved" {
 		crate::kprintln!(
 			"\n{} exception (code: {}):",
 			STR_EXCEPTION[int_no],
@@ -140,7 +192,7 @@ pub unsafe extern "C" fn exception_handler(regs: &mut Registers) {
 		);
 		match int_no {
 			// TODO: enum exceptions
-			14 => page_fault_handler(regs),
+			14 => log_page_fault(regs),
 			_ => {
 				crate::kprintln!("{:#x?}", regs);
 			}
@@ -107,13 +107,13 @@ mod test;
 // Modules used function and variable
 
 use cli::Command;
-use memory::allocator::linked_list::LinkedListAllocator;
+use memory::allocator::slab::SlabAllocator;
 use memory::paging::{init_paging, page_directory};
 use pci::ide::IDE;
 use pic::setup_pic8259;
 
 #[global_allocator]
-static mut KALLOCATOR: LinkedListAllocator = LinkedListAllocator::new();
+static mut KALLOCATOR: SlabAllocator = SlabAllocator::new();
 
 #[alloc_error_handler]
 pub fn rust_oom(layout: core::alloc::Layout) -> ! {
@@ -40,9 +40,10 @@ use crate::fs::ext2;
 pub extern "C" fn kmain() -> ! {
 	// Mounting first ext2 disk found to DISKNO
 	let disks = disk::discover();
-	for i in disks {
-		if let Ok(ext) = ext2::Ext2::new(i) {
+	for (diskno, disk) in disks.into_iter().enumerate() {
+		if let Ok(mut ext) = ext2::Ext2::new(disk) {
 			kprintln!("Found an ext2 fs");
+			ext.diskno = Some(diskno);
 			*DISKNO.lock() = Some(ext);
 		}
 	}
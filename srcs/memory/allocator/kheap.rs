@@ -0,0 +1,95 @@
+use crate::memory::paging::{
+	fault_page,
+	release_page,
+	reserve_pages_at_addr,
+	PAGE_WRITABLE
+};
+use crate::memory::VirtAddr;
+
+/// Start of the kernel address space reserved for the kernel heap
+pub const KHEAP_ADDR: VirtAddr = 0xd0000000;
+/// Size of the kernel heap at init, it is never shrunk below it
+pub const KHEAP_SIZE: usize = 100 * 0x1000;
+/// Largest size the kernel heap can grow to
+pub const KHEAP_LIMIT: usize = 0x4000000;
+/// Pages added at once when the heap grows, to avoid growing on every allocation
+const KHEAP_GROWTH: usize = 16 * 0x1000;
+
+/// Pages of the kernel heap, they are all reserved at init so they stay contiguous and get a
+/// frame when the heap grows over them
+#[derive(Debug)]
+pub struct KernelHeap {
+	end:   VirtAddr,
+	min:   VirtAddr,
+	limit: VirtAddr
+}
+
+impl KernelHeap {
+	pub const fn new() -> Self {
+		Self { end: 0, min: 0, limit: 0 }
+	}
+
+	/// Use the size bytes of pages mapped at start as the heap, the pages after them are
+	/// reserved so it can grow up to KHEAP_LIMIT bytes
+	pub fn init(&mut self, start: VirtAddr, size: usize) -> Result<(), ()> {
+		let end = start + size as VirtAddr;
+		if KHEAP_LIMIT > size {
+			reserve_pages_at_addr(
+				end,
+				(KHEAP_LIMIT - size) / 0x1000,
+				PAGE_WRITABLE
+			)?;
+		}
+		self.end = end;
+		self.min = end;
+		self.limit = start + KHEAP_LIMIT as VirtAddr;
+		Ok(())
+	}
+
+	/// Add at least size bytes of pages at the end of the heap, it fails without growing if
+	/// they would go past KHEAP_LIMIT.
+	/// Return the address and the size of the new area
+	pub fn grow(&mut self, size: usize) -> Result<(VirtAddr, usize), ()> {
+		let (size, left) =
+			((size + 0xfff) & !0xfff, (self.limit - self.end) as usize);
+		if size == 0 || size > left {
+			return Err(());
+		}
+		let size = core::cmp::min(core::cmp::max(size, KHEAP_GROWTH), left);
+		let start = self.end;
+		for page in (start..start + size as VirtAddr).step_by(0x1000) {
+			if fault_page(page).is_err() {
+				self.release(start, page);
+				return Err(());
+			}
+		}
+		self.end += size as VirtAddr;
+		Ok((start, size))
+	}
+
+	/// Give back the frames of the pages of the heap from the page aligned addr, the heap keeps
+	/// its initial size. Return the new end of the heap
+	pub fn shrink(&mut self, addr: VirtAddr) -> VirtAddr {
+		let addr = core::cmp::max(addr, self.min);
+		if addr & 0xfff == 0 && addr < self.end {
+			self.release(addr, self.end);
+			self.end = addr;
+		}
+		self.end
+	}
+
+	pub fn end(&self) -> VirtAddr {
+		self.end
+	}
+
+	/// Lowest end of the heap, see shrink
+	pub fn min(&self) -> VirtAddr {
+		self.min
+	}
+
+	fn release(&self, start: VirtAddr, end: VirtAddr) {
+		for page in (start..end).step_by(0x1000) {
+			let _ = release_page(page);
+		}
+	}
+}
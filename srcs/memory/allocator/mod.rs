@@ -1,5 +1,6 @@
 pub mod bump;
-pub mod linked_list;
+pub mod kheap;
+pub mod slab;
 
 pub mod kglobal;
 pub use kglobal::KGlobal;
@@ -0,0 +1,321 @@
+use crate::memory::allocator::kheap::KernelHeap;
+use crate::memory::allocator::AllocatorInit;
+use crate::memory::VirtAddr;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+
+/// Object sizes of the caches, larger allocations get pages of their own
+const SIZES: [usize; 10] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+const EMPTY_CACHE: Option<&'static mut FreeObject> = None;
+
+impl AllocatorInit for SlabAllocator {
+	unsafe fn init(&mut self, heap_start: VirtAddr, heap_size: usize) {
+		self.heap
+			.init(heap_start, heap_size)
+			.expect("unable to reserve the kernel heap");
+		self.free_pages(heap_start, heap_size / 0x1000);
+	}
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let vaddr: u32 = self as *const Self as u32;
+		let mut_self: &mut Self = &mut *(vaddr as *mut _);
+
+		if layout.align() > 0x1000 {
+			return null_mut();
+		}
+		let size = SlabAllocator::block_size(layout);
+		let ptr = match SlabAllocator::cache_index(size) {
+			Some(index) => mut_self.alloc_object(index),
+			None => mut_self.alloc_pages(size / 0x1000)
+		};
+		if ptr.is_null() {
+			return ptr;
+		}
+		// Update allocation tracker
+		crate::KTRACKER.allocation += 1;
+		crate::KTRACKER.allocated_bytes += size;
+		#[cfg(feature = "alloc_debug")]
+		crate::dprintln!("Alloc: {:#x} bytes at {:p}", size, ptr);
+		ptr
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		let vaddr: u32 = self as *const Self as u32;
+		let mut_self: &mut Self = &mut *(vaddr as *mut _);
+
+		let size = SlabAllocator::block_size(layout);
+		// Update allocation tracker
+		crate::KTRACKER.freed += 1;
+		crate::KTRACKER.freed_bytes += size;
+		#[cfg(feature = "alloc_debug")]
+		crate::dprintln!("Freed: {:#x} bytes at {:#p}", size, ptr);
+		match SlabAllocator::cache_index(size) {
+			Some(index) => mut_self.free_object(index, ptr as VirtAddr),
+			None => mut_self.free_pages(ptr as VirtAddr, size / 0x1000)
+		}
+	}
+}
+
+/// Free object of a cache, stored in the object itself
+struct FreeObject {
+	next: Option<&'static mut FreeObject>
+}
+
+/// Run of free pages of the heap, stored in its first page
+struct FreeRun {
+	pages: usize,
+	next:  *mut FreeRun
+}
+
+impl FreeRun {
+	fn end_addr(&self) -> VirtAddr {
+		self as *const Self as VirtAddr + (self.pages * 0x1000) as VirtAddr
+	}
+}
+
+/// Objects up to a page are taken from the cache of their size class, caches are filled a
+/// page at a time and keep their pages. Larger allocations take a run of pages from the free
+/// runs, which are merged when freed and given back to the heap when they end it
+pub struct SlabAllocator {
+	caches: [Option<&'static mut FreeObject>; SIZES.len()],
+	runs:   *mut FreeRun,
+	heap:   KernelHeap
+}
+
+impl SlabAllocator {
+	pub const fn new() -> Self {
+		Self {
+			caches: [EMPTY_CACHE; SIZES.len()],
+			runs:   null_mut(),
+			heap:   KernelHeap::new()
+		}
+	}
+
+	// Size of the block given for layout, the size class of its cache or a
+	// number of pages
+	fn block_size(layout: Layout) -> usize {
+		let size = layout.size().max(layout.align()).max(SIZES[0]);
+		match size <= SIZES[SIZES.len() - 1] {
+			true => size.next_power_of_two(),
+			false => (size + 0xfff) & !0xfff
+		}
+	}
+
+	// Cache of the blocks of size if they aren't allocated in pages
+	fn cache_index(size: usize) -> Option<usize> {
+		SIZES.iter().position(|class| *class == size)
+	}
+
+	unsafe fn alloc_object(&mut self, index: usize) -> *mut u8 {
+		if self.caches[index].is_none() {
+			self.fill_cache(index);
+		}
+		match self.caches[index].take() {
+			Some(object) => {
+				self.caches[index] = object.next.take();
+				object as *mut FreeObject as *mut u8
+			},
+			None => null_mut()
+		}
+	}
+
+	unsafe fn free_object(&mut self, index: usize, addr: VirtAddr) {
+		let object = addr as *mut FreeObject;
+		object.write(FreeObject { next: self.caches[index].take() });
+		self.caches[index] = Some(&mut *object);
+	}
+
+	// Cut a page in objects of the cache
+	unsafe fn fill_cache(&mut self, index: usize) {
+		let page = self.alloc_pages(1) as VirtAddr;
+		if page == 0 {
+			return;
+		}
+		for offset in (0..0x1000).step_by(SIZES[index]).rev() {
+			self.free_object(index, page + offset as VirtAddr);
+		}
+	}
+
+	// Take nb pages from the first run large enough, the heap grows if there
+	// is none
+	unsafe fn alloc_pages(&mut self, nb: usize) -> *mut u8 {
+		let mut ptr = self.take_pages(nb);
+		if ptr.is_null() {
+			if let Ok((start, size)) = self.heap.grow(nb * 0x1000) {
+				self.free_pages(start, size / 0x1000);
+				ptr = self.take_pages(nb);
+			}
+		}
+		ptr
+	}
+
+	unsafe fn take_pages(&mut self, nb: usize) -> *mut u8 {
+		let mut prev: *mut *mut FreeRun = &mut self.runs;
+		while !(*prev).is_null() {
+			let run = *prev;
+			if (*run).pages >= nb {
+				*prev = match (*run).pages == nb {
+					true => (*run).next,
+					false => {
+						let rest = (run as VirtAddr + (nb * 0x1000) as VirtAddr)
+							as *mut FreeRun;
+						rest.write(FreeRun {
+							pages: (*run).pages - nb,
+							next:  (*run).next
+						});
+						rest
+					}
+				};
+				return run as *mut u8;
+			}
+			prev = &mut (*run).next;
+		}
+		null_mut()
+	}
+
+	// Add nb pages at addr to the runs sorted by address, merging them with
+	// their neighbours. The pages of a run ending the heap are given back
+	unsafe fn free_pages(&mut self, addr: VirtAddr, nb: usize) {
+		let mut prev: *mut FreeRun = null_mut();
+		let mut next: *mut FreeRun = self.runs;
+		while !next.is_null() && (next as VirtAddr) < addr {
+			prev = next;
+			next = (*next).next;
+		}
+		let mut run = addr as *mut FreeRun;
+		run.write(FreeRun { pages: nb, next });
+		if !next.is_null() && (*run).end_addr() == next as VirtAddr {
+			(*run).pages += (*next).pages;
+			(*run).next = (*next).next;
+		}
+		if prev.is_null() {
+			self.runs = run;
+		} else if (*prev).end_addr() == addr {
+			(*prev).pages += (*run).pages;
+			(*prev).next = (*run).next;
+			run = prev;
+		} else {
+			(*prev).next = run;
+		}
+		if (*run).end_addr() != self.heap.end() {
+			return;
+		}
+		// The run can't be written once its first page is given back
+		let end = self.heap.shrink(run as VirtAddr);
+		if end != run as VirtAddr {
+			(*run).pages = ((end - run as VirtAddr) / 0x1000) as usize;
+			return;
+		}
+		let mut prev: *mut *mut FreeRun = &mut self.runs;
+		while *prev != run {
+			prev = &mut (**prev).next;
+		}
+		*prev = null_mut();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SlabAllocator;
+	use crate::memory::paging::buddy::physmap_as_mut;
+	use crate::memory::{MemoryZone, TypeZone, VirtAddr, WRITABLE};
+	use core::alloc::{GlobalAlloc, Layout};
+
+	fn layout(size: usize, align: usize) -> Layout {
+		Layout::from_size_align(size, align).unwrap()
+	}
+
+	/// Allocator whose free runs are the pages of a zone, its heap can't grow
+	fn zone_allocator(zone: &mut MemoryZone) -> SlabAllocator {
+		zone.populate().unwrap();
+		let mut slab = SlabAllocator::new();
+		unsafe { slab.free_pages(zone.offset, zone.size / 0x1000) };
+		slab
+	}
+
+	fn heap_end() -> VirtAddr {
+		unsafe { (*core::ptr::addr_of!(crate::KALLOCATOR)).heap.end() }
+	}
+
+	#[sys_macros::test_case]
+	fn slab_size_classes() {
+		assert_eq!(SlabAllocator::block_size(layout(1, 1)), 8);
+		assert_eq!(SlabAllocator::block_size(layout(24, 8)), 32);
+		assert_eq!(SlabAllocator::block_size(layout(0x1000, 8)), 0x1000);
+		assert_eq!(SlabAllocator::block_size(layout(0x1001, 8)), 0x2000);
+		assert_eq!(SlabAllocator::cache_index(32), Some(2));
+		assert_eq!(SlabAllocator::cache_index(0x2000), None);
+
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x2000, WRITABLE);
+		let slab = zone_allocator(&mut zone);
+		unsafe {
+			let first = slab.alloc(layout(24, 8));
+			assert_eq!(first as VirtAddr, zone.offset);
+			// Objects of a class are cut from the same page
+			let second = slab.alloc(layout(20, 4));
+			assert_eq!(second as VirtAddr, zone.offset + 32);
+			// The last freed object is given first
+			slab.dealloc(first, layout(24, 8));
+			assert_eq!(slab.alloc(layout(32, 8)), first);
+			let other = slab.alloc(layout(64, 8));
+			assert_eq!(other as VirtAddr, zone.offset + 0x1000);
+			// Both pages are used by the caches
+			assert!(slab.alloc(layout(128, 8)).is_null());
+		}
+	}
+
+	#[sys_macros::test_case]
+	fn slab_alignment() {
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x3000, WRITABLE);
+		let slab = zone_allocator(&mut zone);
+		unsafe {
+			assert!(!slab.alloc(layout(8, 8)).is_null());
+			let aligned = slab.alloc(layout(8, 64));
+			assert_eq!(aligned as VirtAddr % 64, 0);
+			let page = slab.alloc(layout(16, 0x1000));
+			assert_eq!(page as VirtAddr % 0x1000, 0);
+			assert!(slab.alloc(layout(16, 0x2000)).is_null());
+		}
+	}
+
+	#[sys_macros::test_case]
+	fn slab_merge_runs() {
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x4000, WRITABLE);
+		zone.populate().unwrap();
+		let page = |index: usize| zone.offset + (index * 0x1000) as VirtAddr;
+		let mut slab = SlabAllocator::new();
+		unsafe {
+			slab.free_pages(page(0), 1);
+			slab.free_pages(page(2), 2);
+			assert!(slab.take_pages(3).is_null());
+			// The page between them joins both runs
+			slab.free_pages(page(1), 1);
+			assert_eq!((*slab.runs).pages, 4);
+			assert!((*slab.runs).next.is_null());
+			let large = slab.alloc(layout(0x3000, 8));
+			assert_eq!(large as VirtAddr, page(0));
+			assert_eq!(slab.runs as VirtAddr, page(3));
+			slab.dealloc(large, layout(0x3000, 8));
+			assert_eq!(slab.runs as VirtAddr, page(0));
+			assert_eq!((*slab.runs).pages, 4);
+		}
+	}
+
+	#[sys_macros::test_case]
+	fn slab_release_heap_tail() {
+		let end = heap_end();
+		let used_pages = physmap_as_mut().used;
+		// Larger than the initial heap so it has to grow
+		let layout = layout(256 * 0x1000, 8);
+		unsafe {
+			let ptr = crate::alloc::alloc::alloc(layout);
+			assert!(!ptr.is_null());
+			assert!(heap_end() > end);
+			crate::alloc::alloc::dealloc(ptr, layout);
+		}
+		assert_eq!(heap_end(), end);
+		assert_eq!(physmap_as_mut().used, used_pages);
+	}
+}
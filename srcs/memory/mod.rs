@@ -3,13 +3,27 @@
 pub mod allocator;
 #[macro_use]
 pub mod paging;
+pub mod shm;
+pub mod swap;
 
+use crate::alloc::sync::Arc;
+use crate::fs::FileInfo;
 use crate::memory::paging::{
 	alloc_pages,
 	alloc_pages_at_addr,
+	fault_page,
 	free_pages,
 	kalloc_pages,
-	kalloc_pages_at_addr
+	kalloc_pages_at_addr,
+	move_page,
+	page_frame,
+	page_is_cow,
+	page_is_swapped,
+	reserve_pages,
+	reserve_pages_at_addr,
+	share_page,
+	share_swapped_page,
+	unshare_page
 };
 
 pub type VirtAddr = u32;
@@ -18,31 +32,48 @@ pub type PhysAddr = u32;
 /// Permission aren't yet use, and will probably be seperated from Paging permission
 /// Since paging flags are already stored in page directory it's probably not needed to store them
 /// here as well
-use crate::memory::paging::PAGE_WRITABLE;
+use crate::memory::paging::{PAGE_USER, PAGE_WRITABLE};
 /// Prots
 pub const WRITABLE: u32 = PAGE_WRITABLE;
 pub const READABLE: u32 = WRITABLE << 1;
 pub const EXECUTABLE: u32 = WRITABLE << 2;
 
-/// Flags
+/// Protections of mappings, values match linux mman.h
+pub const PROT_NONE: u32 = 0x0;
+pub const PROT_READ: u32 = 0x1;
+pub const PROT_WRITE: u32 = 0x2;
+pub const PROT_EXEC: u32 = 0x4;
+
+/// Flags of mappings, values match linux mman.h
 /// Flags starting with an underscore re ignored by linux kernel and so are useless
-pub const MAP_SHARED: u32 = 1 << 0;
-pub const MAP_PRIVATE: u32 = 1 << 1;
+pub const MAP_SHARED: u32 = 0x01;
+pub const MAP_PRIVATE: u32 = 0x02;
+pub const MAP_FIXED: u32 = 0x10;
+pub const MAP_ANONYMOUS: u32 = 0x20;
+pub const MAP_ANON: u32 = MAP_ANONYMOUS;
 /// Valid only for 64 bits system
-pub const MAP_32BIT: u32 = 1 << 2;
-pub const MAP_ANON: u32 = 1 << 3;
-pub const MAP_ANONYMOUS: u32 = 1 << 4;
-pub const _MAP_DENYWRITE: u32 = 1 << 5;
-pub const _MAP_EXECUTABLE: u32 = 1 << 6;
-pub const _MAP_FILE: u32 = 1 << 7;
-pub const MAP_FIXED: u32 = 1 << 8;
-pub const MAP_GROWSDOWN: u32 = 1 << 9;
-pub const MAP_LOCKED: u32 = 1 << 10;
-pub const MAP_NONBLOCK: u32 = 1 << 11;
-pub const MAP_NORESERVE: u32 = 1 << 12;
-pub const MAP_POPULATE: u32 = 1 << 13;
-
-pub const REMAP_FIXED: u32 = 1 << 0;
+pub const MAP_32BIT: u32 = 0x40;
+pub const MAP_GROWSDOWN: u32 = 0x100;
+pub const _MAP_DENYWRITE: u32 = 0x800;
+pub const _MAP_EXECUTABLE: u32 = 0x1000;
+pub const _MAP_FILE: u32 = 0;
+pub const MAP_LOCKED: u32 = 0x2000;
+pub const MAP_NORESERVE: u32 = 0x4000;
+pub const MAP_POPULATE: u32 = 0x8000;
+pub const MAP_NONBLOCK: u32 = 0x10000;
+
+pub const REMAP_MAYMOVE: u32 = 1 << 0;
+pub const REMAP_FIXED: u32 = 1 << 1;
+
+/// Paging flags of user pages with protection prot. Paging can't prevent reading a page that
+/// is present, so only PROT_NONE and PROT_WRITE are enforced
+pub fn prot_flags(prot: u32) -> u32 {
+	match prot & (PROT_READ | PROT_WRITE | PROT_EXEC) {
+		PROT_NONE => 0,
+		prot if prot & PROT_WRITE != 0 => PAGE_USER | PAGE_WRITABLE,
+		_ => PAGE_USER
+	}
+}
 
 pub fn init_memory_addr(
 	addr: VirtAddr,
@@ -75,23 +106,60 @@ pub fn init_memory(
 	}
 }
 
+/// Reserve the address space of a zone, frames are only allocated when its pages are accessed
+pub fn reserve_memory(size: usize, flags: u32) -> Result<VirtAddr, ()> {
+	assert!(size % 4096 == 0, "size must be a multiple of 4096");
+	reserve_pages(size / 4096, flags)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum TypeZone {
 	Unassigned,
 	Stack,
 	Heap,
 	Anon,
+	/// Pages of a shared memory object, see shm::SharedMemory
+	Shm,
 	File(&'static str)
 }
 
+impl TypeZone {
+	/// Name given to the zones of this type
+	fn name(self) -> &'static str {
+		match self {
+			TypeZone::Heap => "heap",
+			TypeZone::Stack => "stack",
+			TypeZone::Unassigned => "unassigned",
+			TypeZone::Shm => "shm",
+			TypeZone::File(name) => name,
+			_ => "Not yet named"
+		}
+	}
+}
+
+/// File the pages of a TypeZone::File zone are read from, page i starts at offset + i * 4096
+#[derive(Clone)]
+pub struct ZoneFile {
+	pub file:   Arc<FileInfo>,
+	pub offset: usize
+}
+
+/// offset is the address of the zone in the kernel page directory and vaddr the one its
+/// process sees, they are the same for processes without their own page directory.
+/// Pages of lazy zones only get a frame when accessed, see MemoryZone::reserve.
+/// Shared zones are kept by the children of their process instead of being copied
 #[derive(Clone)]
 pub struct MemoryZone {
 	pub name:      &'static str,
 	pub offset:    VirtAddr,
+	pub vaddr:     VirtAddr,
 	pub type_zone: TypeZone,
 	pub size:      usize,
 	pub flags:     u32,
-	pub kphys:     bool
+	pub kphys:     bool,
+	pub lazy:      bool,
+	pub shared:    bool,
+	pub file:      Option<ZoneFile>
 }
 
 impl MemoryZone {
@@ -99,10 +167,14 @@ impl MemoryZone {
 		Self {
 			name:      "",
 			offset:    0,
+			vaddr:     0,
 			type_zone: TypeZone::Unassigned,
 			size:      0,
 			flags:     0,
-			kphys:     false
+			kphys:     false,
+			lazy:      false,
+			shared:    false,
+			file:      None
 		}
 	}
 
@@ -114,19 +186,16 @@ impl MemoryZone {
 		kphys: bool
 	) -> MemoryZone {
 		let mut mz: MemoryZone = MemoryZone {
-			name: "",
+			name: ztype.name(),
 			offset,
+			vaddr: offset,
 			type_zone: ztype,
 			size,
 			flags,
-			kphys
-		};
-		mz.name = match ztype {
-			TypeZone::Heap => "heap",
-			TypeZone::Stack => "stack",
-			TypeZone::Unassigned => "unassigned",
-			TypeZone::File(name) => name,
-			_ => "Not yet named"
+			kphys,
+			lazy: false,
+			shared: false,
+			file: None
 		};
 		mz.offset = init_memory_addr(offset, size, flags, kphys)
 			.expect("unable to allocate pages for stack");
@@ -140,22 +209,42 @@ impl MemoryZone {
 		kphys: bool
 	) -> MemoryZone {
 		let mut mz: MemoryZone = MemoryZone {
-			name: "",
+			name: ztype.name(),
 			offset: 0,
+			vaddr: 0,
 			type_zone: ztype,
 			size,
 			flags,
-			kphys
-		};
-		mz.name = match ztype {
-			TypeZone::Heap => "heap",
-			TypeZone::Stack => "stack",
-			TypeZone::Unassigned => "unassigned",
-			TypeZone::File(name) => name,
-			_ => "Not yet named"
+			kphys,
+			lazy: false,
+			shared: false,
+			file: None
 		};
 		mz.offset = init_memory(size, flags, kphys)
 			.expect("unable to allocate pages for stack");
+		mz.vaddr = mz.offset;
+		mz
+	}
+
+	/// Same as init but pages only get a frame on first access, see fault.
+	/// The kernel must not access these pages on its own stack or inside an interrupt before
+	/// faulting them in, so this is meant for zones used by user processes
+	pub fn reserve(ztype: TypeZone, size: usize, flags: u32) -> MemoryZone {
+		let mut mz: MemoryZone = MemoryZone {
+			name: ztype.name(),
+			offset: 0,
+			vaddr: 0,
+			type_zone: ztype,
+			size,
+			flags,
+			kphys: false,
+			lazy: true,
+			shared: false,
+			file: None
+		};
+		mz.offset = reserve_memory(size, flags)
+			.expect("unable to reserve pages for memory zone");
+		mz.vaddr = mz.offset;
 		mz
 	}
 }
@@ -177,26 +266,279 @@ impl MemoryZone {
 		(self.offset, self.size)
 	}
 
-	/// Add pages to the memory zone, growing upward for most zones. And downward for stacks type
-	/// Should return an error if failed
-	pub fn grow(&mut self) -> Result<(), ()> {
-		todo!()
+	/// Number of pages of the zone
+	pub fn pages(&self) -> usize {
+		(self.size + 0xfff) / 4096
+	}
+
+	/// Check if addr of the process address space is inside the zone
+	pub fn contains(&self, addr: VirtAddr) -> bool {
+		addr >= self.vaddr && ((addr - self.vaddr) as usize) < self.size
+	}
+
+	/// Kernel address of addr from the process address space
+	pub fn to_kernel(&self, addr: VirtAddr) -> VirtAddr {
+		self.offset + (addr - self.vaddr)
+	}
+
+	/// Physical address of page index if it has a frame
+	pub fn frame(&self, index: usize) -> Option<PhysAddr> {
+		page_frame(self.offset + (index * 4096) as VirtAddr)
+	}
+
+	/// Give a frame to page index if it has none yet. Swapped out pages are read back from swap,
+	/// the other pages of file zones are read from the file and the others are zero-filled.
+	/// Return the physical address of the page
+	pub fn fault_index(&mut self, index: usize) -> Result<PhysAddr, ()> {
+		if index >= self.pages() {
+			return Err(());
+		}
+		let page = self.offset + (index * 4096) as VirtAddr;
+		if let Some(paddr) = page_frame(page) {
+			return Ok(paddr);
+		}
+		let swapped = page_is_swapped(page);
+		let paddr = fault_page(page)?;
+		if let Some(zone_file) = self.file.as_ref().filter(|_| !swapped) {
+			let dst = unsafe {
+				core::slice::from_raw_parts_mut(page as *mut u8, 4096)
+			};
+			zone_file
+				.file
+				.op
+				.lock()
+				.read_at(dst, zone_file.offset + index * 4096)
+				.map_err(|_| ())?;
+		}
+		Ok(paddr)
+	}
+
+	/// Same as fault_index for the page containing addr of the process address space. A write
+	/// gives the page its own copy of a frame shared copy-on-write, and fails on read-only zones
+	pub fn fault(
+		&mut self,
+		addr: VirtAddr,
+		write: bool
+	) -> Result<PhysAddr, ()> {
+		if !self.contains(addr) {
+			return Err(());
+		}
+		let index = (addr - self.vaddr) as usize / 4096;
+		let paddr = self.fault_index(index)?;
+		match write {
+			false => Ok(paddr),
+			true if self.flags & WRITABLE == 0 => Err(()),
+			true => unshare_page(self.offset + (index * 4096) as VirtAddr)
+		}
+	}
+
+	/// Paging flags of the page containing addr of the process address space, pages sharing
+	/// their frame copy-on-write are read-only
+	pub fn page_flags(&self, addr: VirtAddr) -> u32 {
+		match page_is_cow(self.to_kernel(addr & !0xfff)) {
+			true => self.flags & !WRITABLE,
+			false => self.flags
+		}
+	}
+
+	/// Give a frame to every page, for reserved zones the kernel accesses directly
+	pub fn populate(&mut self) -> Result<(), ()> {
+		for index in 0..self.pages() {
+			self.fault_index(index)?;
+		}
+		Ok(())
+	}
+
+	/// Copy the pages of src that have a frame, the others are left to be faulted in
+	pub fn copy_from(&mut self, src: &MemoryZone) -> Result<(), ()> {
+		for index in 0..core::cmp::min(self.pages(), src.pages()) {
+			if src.frame(index).is_none() {
+				continue;
+			}
+			self.fault_index(index)?;
+			unsafe {
+				core::ptr::copy_nonoverlapping(
+					(src.offset + (index * 4096) as VirtAddr) as *const u8,
+					(self.offset + (index * 4096) as VirtAddr) as *mut u8,
+					4096
+				);
+			}
+		}
+		Ok(())
+	}
+
+	/// Map the frames of src in this reserved zone instead of copying them, both zones get their
+	/// own copy of a page on its first write. Swapped out pages of src share their swap slot
+	/// and the other pages without a frame are left to be faulted in
+	pub fn share_from(&mut self, src: &MemoryZone) -> Result<(), ()> {
+		for index in 0..core::cmp::min(self.pages(), src.pages()) {
+			let src_page = src.offset + (index * 4096) as VirtAddr;
+			let page = self.offset + (index * 4096) as VirtAddr;
+			if page_is_swapped(src_page) {
+				share_swapped_page(src_page, page)?;
+			} else if src.frame(index).is_some() {
+				share_page(src_page, page)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Reserved zone at the same address sharing the frames of the zone copy-on-write, for the
+	/// private zones of a forked process
+	pub fn copy_on_write(&self) -> Result<MemoryZone, ()> {
+		let mut zone =
+			MemoryZone::reserve(self.type_zone, self.size, self.flags);
+		zone.vaddr = self.vaddr;
+		zone.file = self.file.clone();
+		zone.share_from(self)?;
+		Ok(zone)
+	}
+
+	/// Add size bytes of pages to the zone, after its end or before its start for stacks.
+	/// Pages of lazy zones are faulted in, the others get a frame immediately. When the pages
+	/// next to the zone are used it is moved in the kernel address space, unless it is seen
+	/// there by its process
+	pub fn grow(&mut self, size: usize) -> Result<(), ()> {
+		let nb = (size + 0xfff) / 4096;
+		if self.kphys || nb == 0 {
+			return Err(());
+		}
+		let (old, visible) = (self.pages(), self.offset == self.vaddr);
+		let down = matches!(self.type_zone, TypeZone::Stack);
+		let start = match down {
+			true => self.offset.checked_sub((nb * 4096) as VirtAddr),
+			false => Some(self.offset + (old * 4096) as VirtAddr)
+		};
+		match start.map(|start| reserve_pages_at_addr(start, nb, self.flags)) {
+			Some(Ok(start)) if down => self.offset = start,
+			Some(Ok(_)) => {},
+			_ if visible => return Err(()),
+			_ => self.relocate(None, old + nb, if down { nb } else { 0 })?
+		}
+		self.size = (old + nb) * 4096;
+		if down {
+			self.vaddr -= (nb * 4096) as VirtAddr;
+		}
+		if visible {
+			self.vaddr = self.offset;
+		}
+		if !self.lazy {
+			self.populate()?;
+		}
+		Ok(())
 	}
 
-	/// Remap the memory zone in the virtual address space using new_addr as a hint or strict addr
-	/// if flags REMAP_FIXED is set.
+	/// Resize the zone to new_size, pages are added or removed at its end. The zone moves to
+	/// new_addr in the process address space if flags has REMAP_FIXED, or REMAP_MAYMOVE and
+	/// new_addr is not 0. Pages keep their frames. Return the address of the zone for its
+	/// process
 	pub fn remap(
 		&mut self,
-		_new_addr: VirtAddr,
-		_new_size: usize,
-		_flags: u32
+		new_addr: VirtAddr,
+		new_size: usize,
+		flags: u32
 	) -> Result<VirtAddr, ()> {
-		todo!()
+		let (old, pages) = (self.pages(), (new_size + 0xfff) / 4096);
+		let moves = flags & REMAP_FIXED != 0
+			|| (flags & REMAP_MAYMOVE != 0 && new_addr != 0);
+		if self.kphys || pages == 0 || (moves && new_addr & 0xfff != 0) {
+			return Err(());
+		}
+		let (visible, offset, size) =
+			(self.offset == self.vaddr, self.offset, self.size);
+		let in_place = pages <= old
+			|| reserve_pages_at_addr(
+				self.offset + (old * 4096) as VirtAddr,
+				pages - old,
+				self.flags
+			)
+			.is_ok();
+		if in_place {
+			self.size = new_size;
+		}
+		let res = match (visible, moves, in_place) {
+			(true, true, _) => self.relocate(Some(new_addr), pages, 0),
+			(true, false, false) => Err(()),
+			(false, _, false) => self.relocate(None, pages, 0),
+			_ => Ok(())
+		};
+		// Pages are only freed once the zone is in its new place, it is left as it was on
+		// failure
+		if res.is_err() {
+			if in_place && pages > old {
+				free_pages(offset + (old * 4096) as VirtAddr, pages - old);
+			}
+			self.size = size;
+			return Err(());
+		}
+		if pages < old {
+			free_pages(offset + (pages * 4096) as VirtAddr, old - pages);
+		}
+		self.size = new_size;
+		if !self.lazy {
+			self.populate()?;
+		}
+		if moves {
+			self.vaddr = new_addr;
+		}
+		if visible {
+			self.vaddr = self.offset;
+		}
+		Ok(self.vaddr)
+	}
+
+	/// Move the pages of the zone to a new range of 'pages' pages of the kernel address space,
+	/// at addr if given, starting at its page 'at'
+	fn relocate(
+		&mut self,
+		addr: Option<VirtAddr>,
+		pages: usize,
+		at: usize
+	) -> Result<(), ()> {
+		let offset = match addr {
+			Some(addr) => reserve_pages_at_addr(addr, pages, self.flags)?,
+			None => reserve_pages(pages, self.flags)?
+		};
+		for index in 0..self.pages() {
+			move_page(
+				self.offset + (index * 4096) as VirtAddr,
+				offset + ((at + index) * 4096) as VirtAddr
+			)?;
+		}
+		self.offset = offset;
+		Ok(())
 	}
 
-	/// Change protection of a memory zone
-	pub fn protect(&mut self, _prot: u32) {
-		todo!()
+	/// Change the protection of the zone to prot (PROT_*), see prot_flags. It is enforced by the
+	/// page directory of the process, the kernel can still write to the pages that don't share
+	/// their frame copy-on-write
+	pub fn protect(&mut self, prot: u32) {
+		self.flags = (self.flags & !(PAGE_USER | WRITABLE)) | prot_flags(prot);
+	}
+
+	/// Split the zone at the page aligned byte at, the zone keeps the pages before it and the
+	/// others are returned in a new zone
+	pub fn split(&mut self, at: usize) -> Result<MemoryZone, ()> {
+		if at == 0 || at & 0xfff != 0 || at >= self.size {
+			return Err(());
+		}
+		let upper = MemoryZone {
+			name:      self.name,
+			offset:    self.offset + at as VirtAddr,
+			vaddr:     self.vaddr + at as VirtAddr,
+			type_zone: self.type_zone,
+			size:      self.size - at,
+			flags:     self.flags,
+			kphys:     self.kphys,
+			lazy:      self.lazy,
+			shared:    self.shared,
+			file:      self.file.clone().map(|zone_file| ZoneFile {
+				offset: zone_file.offset + at,
+				..zone_file
+			})
+		};
+		self.size = at;
+		Ok(upper)
 	}
 }
 
@@ -233,11 +575,7 @@ impl fmt::Display for MemoryZone {
 		} else {
 			'-'
 		};
-		let shared: char = if self.flags & MAP_SHARED == MAP_SHARED {
-			'S'
-		} else {
-			'-'
-		};
+		let shared: char = if self.shared { 'S' } else { '-' };
 		write!(
 			f,
 			"{:#10x} {:#10x} {}{}{}{} [ {} ]",
@@ -271,8 +609,8 @@ impl Drop for MemoryZone {
 
 #[cfg(test)]
 mod tests {
-	use super::{MemoryZone, TypeZone};
-	use crate::memory::paging::bitmap::physmap_as_mut;
+	use super::{MemoryZone, TypeZone, PROT_READ, PROT_WRITE, REMAP_FIXED};
+	use crate::memory::paging::buddy::physmap_as_mut;
 	#[sys_macros::test_case]
 	fn base_memory_zone() {
 		let used_pages = physmap_as_mut().used;
@@ -294,6 +632,136 @@ mod tests {
 		}
 	}
 
+	#[sys_macros::test_case]
+	fn reserved_memory_zone() {
+		let mut mz =
+			MemoryZone::reserve(TypeZone::Anon, 0x3000, super::WRITABLE);
+		let used_pages = physmap_as_mut().used;
+		assert!(mz.frame(1).is_none());
+		assert!(mz.fault(mz.vaddr + 0x1800, false).is_ok());
+		assert_eq!(used_pages + 1, physmap_as_mut().used);
+		assert!(mz.frame(0).is_none() && mz.frame(1).is_some());
+		assert!(mz[0x1000..0x2000].iter().all(|x| *x == 0));
+		assert!(mz.fault(mz.vaddr + 0x3000, false).is_err());
+		assert!(mz.populate().is_ok());
+		assert_eq!(used_pages + 3, physmap_as_mut().used);
+	}
+
+	#[sys_macros::test_case]
+	fn shared_memory_zone() {
+		let mut parent =
+			MemoryZone::reserve(TypeZone::Anon, 0x2000, super::WRITABLE);
+		parent.populate().unwrap();
+		parent[0] = 42;
+		let mut child =
+			MemoryZone::reserve(TypeZone::Anon, 0x2000, super::WRITABLE);
+		let used_pages = physmap_as_mut().used;
+		assert!(child.share_from(&parent).is_ok());
+		assert_eq!(used_pages, physmap_as_mut().used);
+		assert_eq!(child.frame(0), parent.frame(0));
+		assert_eq!(child.page_flags(child.vaddr), 0);
+		assert_eq!(child[0], 42);
+		assert!(child.fault(child.vaddr, true).is_ok());
+		assert_eq!(used_pages + 1, physmap_as_mut().used);
+		assert_ne!(child.frame(0), parent.frame(0));
+		child[0] = 21;
+		assert_eq!(parent[0], 42);
+		// The last page mapping the frame takes it back
+		assert!(parent.fault(parent.vaddr, true).is_ok());
+		assert_eq!(used_pages + 1, physmap_as_mut().used);
+		assert_eq!(parent.page_flags(parent.vaddr), super::WRITABLE);
+		parent[0] = 0;
+		drop(child);
+		drop(parent);
+	}
+
+	#[sys_macros::test_case]
+	fn grow_memory_zone() {
+		let mut heap =
+			MemoryZone::init(TypeZone::Heap, 0x1000, super::WRITABLE, false);
+		heap[0] = 42;
+		let used_pages = physmap_as_mut().used;
+		assert!(heap.grow(0x2000).is_ok());
+		assert_eq!(heap.size, 0x3000);
+		assert!(physmap_as_mut().used >= used_pages + 2);
+		assert_eq!(heap[0], 42);
+		heap[0x2fff] = 42;
+		// Stacks grow down from where their process sees them
+		let mut stack =
+			MemoryZone::reserve(TypeZone::Stack, 0x1000, super::WRITABLE);
+		stack.vaddr = 0x40000000;
+		stack.fault(stack.vaddr, true).unwrap();
+		stack[0] = 21;
+		assert!(stack.grow(0x1000).is_ok());
+		assert_eq!(stack.vaddr, 0x40000000 - 0x1000);
+		assert_eq!(stack.size, 0x2000);
+		assert!(stack.frame(0).is_none());
+		assert_eq!(stack[0x1000], 21);
+	}
+
+	#[sys_macros::test_case]
+	fn remap_memory_zone() {
+		let mut mz =
+			MemoryZone::reserve(TypeZone::Anon, 0x2000, super::WRITABLE);
+		mz.vaddr = 0x40000000;
+		mz.populate().unwrap();
+		mz[0] = 42;
+		let used_pages = physmap_as_mut().used;
+		assert_eq!(mz.remap(0, 0x1000, 0), Ok(0x40000000));
+		assert_eq!(used_pages - 1, physmap_as_mut().used);
+		assert_eq!(mz.remap(0x50000000, 0x3000, REMAP_FIXED), Ok(0x50000000));
+		assert_eq!(mz.size, 0x3000);
+		assert!(mz.frame(0).is_some() && mz.frame(1).is_none());
+		assert_eq!(mz[0], 42);
+		assert!(mz.remap(0x50000800, 0x1000, REMAP_FIXED).is_err());
+	}
+
+	#[sys_macros::test_case]
+	fn remap_failure_keeps_pages() {
+		let mut mz =
+			MemoryZone::reserve(TypeZone::Anon, 0x2000, super::WRITABLE);
+		let used = MemoryZone::reserve(TypeZone::Anon, 0x1000, super::WRITABLE);
+		mz.populate().unwrap();
+		mz[0x1000] = 42;
+		let used_pages = physmap_as_mut().used;
+		assert!(mz.remap(used.offset, 0x1000, REMAP_FIXED).is_err());
+		assert_eq!(mz.size, 0x2000);
+		assert_eq!(used_pages, physmap_as_mut().used);
+		assert_eq!(mz[0x1000], 42);
+	}
+
+	#[sys_macros::test_case]
+	fn protect_memory_zone() {
+		let mut mz =
+			MemoryZone::reserve(TypeZone::Anon, 0x2000, super::WRITABLE);
+		mz.fault(mz.vaddr, true).unwrap();
+		mz.protect(PROT_READ);
+		assert_eq!(mz.flags & super::WRITABLE, 0);
+		assert!(mz.fault(mz.vaddr, true).is_err());
+		assert!(mz.fault(mz.vaddr + 0x1000, false).is_ok());
+		mz.protect(PROT_READ | PROT_WRITE);
+		assert!(mz.fault(mz.vaddr + 0x1000, true).is_ok());
+		mz[0x1000] = 42;
+		assert_ne!(mz.page_flags(mz.vaddr) & super::WRITABLE, 0);
+	}
+
+	#[sys_macros::test_case]
+	fn split_memory_zone() {
+		let mut mz =
+			MemoryZone::reserve(TypeZone::Anon, 0x3000, super::WRITABLE);
+		mz.populate().unwrap();
+		assert!(mz.split(0x800).is_err());
+		assert!(mz.split(0x3000).is_err());
+		let upper = mz.split(0x1000).unwrap();
+		assert_eq!(mz.size, 0x1000);
+		assert_eq!(upper.vaddr, mz.vaddr + 0x1000);
+		assert_eq!(upper.size, 0x2000);
+		let used_pages = physmap_as_mut().used;
+		drop(upper);
+		assert_eq!(used_pages - 2, physmap_as_mut().used);
+		assert!(mz.frame(0).is_some());
+	}
+
 	#[sys_macros::test_case]
 	fn memory_zone_for_files() {
 		let mz = MemoryZone::init(
@@ -0,0 +1,555 @@
+//! Buddy allocator of the physical pages. Free memory is split in blocks of 2^order pages,
+//! freed blocks are merged back with their buddy, the other half of the block they come from.
+//! Only the RAM given by the multiboot memory map is handed out
+
+use crate::alloc::collections::btree_map::BTreeMap;
+use crate::memory::PhysAddr;
+
+pub const PAGE_SIZE: usize = 4096;
+/// Blocks have up to 2^MAX_ORDER pages
+pub const MAX_ORDER: usize = 10;
+/// End of the memory reachable by ISA DMA
+pub const DMA_END: PhysAddr = 0x1000000;
+
+/// Pages of the 32 bits physical address space
+const FRAMES: usize = 1 << 20;
+const ORDERS: usize = MAX_ORDER + 1;
+/// Levels of the bit tree of an order, a bit of the first level is set when its block is free
+/// and a bit of the others when their word below is not empty
+const LEVELS: usize = 4;
+/// Ranges of RAM given by the memory map that are kept
+const MAX_RANGES: usize = 32;
+
+/// Offset and number of words of each level of the trees of every order, and the total
+/// number of words
+const fn layout() -> ([[(usize, usize); LEVELS]; ORDERS], usize) {
+	let mut layout = [[(0, 0); LEVELS]; ORDERS];
+	let mut offset = 0;
+	let mut order = 0;
+	while order < ORDERS {
+		let mut bits = FRAMES >> order;
+		let mut level = 0;
+		while level < LEVELS {
+			let words = (bits + 31) / 32;
+			layout[order][level] = (offset, words);
+			offset += words;
+			bits = words;
+			level += 1;
+		}
+		order += 1;
+	}
+	(layout, offset)
+}
+
+const LAYOUT: [[(usize, usize); LEVELS]; ORDERS] = layout().0;
+const WORDS: usize = layout().1;
+
+pub static mut PHYSMAP: BuddyAllocator = BuddyAllocator::new();
+
+/// Physical memory zones, allocations that don't need a zone are served from Normal first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+	/// Below DMA_END
+	Dma,
+	Normal
+}
+
+impl Zone {
+	/// First and end page of the zone
+	fn frames(&self) -> (usize, usize) {
+		match self {
+			Zone::Dma => (0, DMA_END as usize / PAGE_SIZE),
+			Zone::Normal => (DMA_END as usize / PAGE_SIZE, FRAMES)
+		}
+	}
+
+	fn of(frame: usize) -> Zone {
+		match frame < DMA_END as usize / PAGE_SIZE {
+			true => Zone::Dma,
+			false => Zone::Normal
+		}
+	}
+}
+
+pub struct BuddyAllocator {
+	trees:    [u32; WORDS],
+	/// First and end page of the ranges of RAM
+	ram:      [(usize, usize); MAX_RANGES],
+	nb_ram:   usize,
+	/// Pages of RAM and used pages of RAM of each zone
+	zones:    [(usize, usize); 2],
+	/// Extra references of frames mapped by several pages (copy-on-write),
+	/// frames referenced once have no entry
+	shared:   BTreeMap<PhysAddr, usize>,
+	pub used: usize
+}
+
+impl BuddyAllocator {
+	pub const fn new() -> BuddyAllocator {
+		BuddyAllocator {
+			trees:  [0; WORDS],
+			ram:    [(0, 0); MAX_RANGES],
+			nb_ram: 0,
+			zones:  [(0, 0); 2],
+			shared: BTreeMap::new(),
+			used:   0
+		}
+	}
+
+	/// Give the pages of RAM from addr to the allocator, they are free until claimed
+	pub fn add_range(&mut self, addr: PhysAddr, pages: usize) {
+		let start = addr as usize / PAGE_SIZE;
+		let end = core::cmp::min(start + pages, FRAMES);
+		if start >= end || self.nb_ram == MAX_RANGES {
+			return;
+		}
+		self.ram[self.nb_ram] = (start, end);
+		self.nb_ram += 1;
+		for frame in start..end {
+			if self.free_order(frame).is_none() {
+				self.zones[Zone::of(frame) as usize].0 += 1;
+				self.free_block(frame, 0);
+			}
+		}
+	}
+
+	/// Claim a page at a specific physical address
+	///
+	/// if the page is not free return an error result containing its page
+	/// number
+	pub fn claim(&mut self, addr: PhysAddr) -> Result<PhysAddr, usize> {
+		let frame: usize = addr as usize / PAGE_SIZE;
+		let mut order = self.free_order(frame).ok_or(frame)?;
+		self.clear(order, frame >> order);
+		// Split the block, keeping free the halves without the page
+		while order > 0 {
+			order -= 1;
+			self.set(order, (frame >> order) ^ 1);
+		}
+		self.account(frame, 1, true);
+		Ok((frame * PAGE_SIZE) as PhysAddr)
+	}
+
+	/// Claim multiple pages from specific physical address
+	///
+	/// if a page is not free return an error result containing its page
+	/// number and don't claim the memory
+	pub fn claim_range(
+		&mut self,
+		addr: PhysAddr,
+		range: usize
+	) -> Result<PhysAddr, usize> {
+		let start: usize = addr as usize / PAGE_SIZE;
+		if let Some(frame) = (start..start + range)
+			.find(|frame| self.free_order(*frame).is_none())
+		{
+			return Err(frame);
+		}
+		for i in 0..range {
+			self.claim(addr + (i * PAGE_SIZE) as u32)?;
+		}
+		Ok(addr)
+	}
+
+	/// Force a claim of multipage pages from specific physical address
+	///
+	/// This function aim to claim starting memory and thus ignore if memory is
+	/// already claimed
+	pub fn force_claim_range(&mut self, addr: PhysAddr, range: usize) {
+		for i in 0..range {
+			let _ = self.claim(addr + (i * PAGE_SIZE) as u32);
+		}
+	}
+
+	/// Get multiple pages that are physically next to each other, from the
+	/// Normal zone or from the DMA zone when it is full
+	///
+	/// Return the first physical address
+	pub fn get_pages(&mut self, nb: usize) -> Result<PhysAddr, ()> {
+		self.get_zone_pages(Zone::Normal, nb)
+			.or_else(|_| self.get_zone_pages(Zone::Dma, nb))
+	}
+
+	/// Get nb pages physically next to each other from zone, aligned on the
+	/// power of two above nb. At most 2^MAX_ORDER pages can be taken at once
+	///
+	/// Return the first physical address
+	pub fn get_zone_pages(
+		&mut self,
+		zone: Zone,
+		nb: usize
+	) -> Result<PhysAddr, ()> {
+		if nb == 0 || nb > 1 << MAX_ORDER {
+			return Err(());
+		}
+		let order = nb.next_power_of_two().trailing_zeros() as usize;
+		let frame = self.take_block(zone, order).ok_or(())?;
+		// Pages of the block past nb are given back
+		for extra in frame + nb..frame + (1 << order) {
+			self.free_block(extra, 0);
+		}
+		self.account(frame, nb, true);
+		Ok((frame * PAGE_SIZE) as PhysAddr)
+	}
+
+	/// Get the next claimable page
+	///
+	/// Return its physical address
+	pub fn get_page(&mut self) -> Result<PhysAddr, ()> {
+		self.get_pages(1)
+	}
+
+	/// Add a reference to a claimed page, it is only freed once every
+	/// reference is dropped with free_page
+	pub fn share_page(&mut self, addr: PhysAddr) {
+		*self.shared.entry(addr & !0xfff).or_insert(0) += 1;
+	}
+
+	/// Number of references to a page, 0 if the page is free
+	pub fn page_refs(&self, addr: PhysAddr) -> usize {
+		if self.free_order(addr as usize / PAGE_SIZE).is_some() {
+			return 0;
+		}
+		1 + self.shared.get(&(addr & !0xfff)).unwrap_or(&0)
+	}
+
+	/// Indicates to the allocator that a page is not used anymore, a shared
+	/// page only loses a reference. Pages that are not RAM are left as is
+	pub fn free_page(&mut self, addr: PhysAddr) {
+		if let Some(refs) = self.shared.get_mut(&(addr & !0xfff)) {
+			*refs -= 1;
+			if *refs == 0 {
+				self.shared.remove(&(addr & !0xfff));
+			}
+			return;
+		}
+		let frame: usize = addr as usize / PAGE_SIZE;
+		// If pages is in fact used, free it
+		if self.is_ram(frame) && self.free_order(frame).is_none() {
+			self.free_block(frame, 0);
+			self.account(frame, 1, false);
+		}
+	}
+
+	/// Number of free pages of RAM
+	pub fn free(&self) -> usize {
+		self.total() - self.used
+	}
+
+	/// Number of pages of RAM
+	pub fn total(&self) -> usize {
+		self.zones.iter().map(|zone| zone.0).sum()
+	}
+
+	/// Pages of RAM and used pages of RAM of zone
+	pub fn zone_stats(&self, zone: Zone) -> (usize, usize) {
+		self.zones[zone as usize]
+	}
+
+	fn is_ram(&self, frame: usize) -> bool {
+		self.ram[..self.nb_ram]
+			.iter()
+			.any(|(start, end)| (*start..*end).contains(&frame))
+	}
+
+	fn account(&mut self, frame: usize, nb: usize, used: bool) {
+		let zone = &mut self.zones[Zone::of(frame) as usize];
+		match used {
+			true => {
+				zone.1 += nb;
+				self.used += nb;
+			},
+			false => {
+				zone.1 -= nb;
+				self.used -= nb;
+			}
+		}
+	}
+
+	/// Order of the free block holding frame, if it is free
+	fn free_order(&self, frame: usize) -> Option<usize> {
+		if frame >= FRAMES {
+			return None;
+		}
+		(0..ORDERS).find(|order| self.test(*order, frame >> order))
+	}
+
+	/// Free the block of 2^order pages at frame, merged with its buddy while
+	/// it is free too
+	fn free_block(&mut self, frame: usize, mut order: usize) {
+		let mut index = frame >> order;
+		while order < MAX_ORDER && self.test(order, index ^ 1) {
+			self.clear(order, index ^ 1);
+			index >>= 1;
+			order += 1;
+		}
+		self.set(order, index);
+	}
+
+	/// Take the lowest free block of 2^order pages of zone, larger blocks are
+	/// split if there is none. Return its first page
+	fn take_block(&mut self, zone: Zone, order: usize) -> Option<usize> {
+		let (start, end) = zone.frames();
+		for mut current in order..ORDERS {
+			let mut index = match self.find(current, 0, start >> current) {
+				Some(index) if (index + 1) << current <= end => index,
+				_ => continue
+			};
+			self.clear(current, index);
+			while current > order {
+				current -= 1;
+				index <<= 1;
+				self.set(current, index + 1);
+			}
+			return Some(index << order);
+		}
+		None
+	}
+
+	fn test(&self, order: usize, index: usize) -> bool {
+		let (offset, _) = LAYOUT[order][0];
+		self.trees[offset + index / 32] & (1 << (index % 32)) != 0
+	}
+
+	fn set(&mut self, order: usize, mut index: usize) {
+		for level in 0..LEVELS {
+			let (offset, _) = LAYOUT[order][level];
+			let word = &mut self.trees[offset + index / 32];
+			let empty = *word == 0;
+			*word |= 1 << (index % 32);
+			if !empty {
+				break;
+			}
+			index /= 32;
+		}
+	}
+
+	fn clear(&mut self, order: usize, mut index: usize) {
+		for level in 0..LEVELS {
+			let (offset, _) = LAYOUT[order][level];
+			let word = &mut self.trees[offset + index / 32];
+			*word &= !(1 << (index % 32));
+			if *word != 0 {
+				break;
+			}
+			index /= 32;
+		}
+	}
+
+	/// Lowest set bit of the level of the tree of order from index
+	fn find(&self, order: usize, level: usize, index: usize) -> Option<usize> {
+		let (offset, words) = LAYOUT[order][level];
+		let word = index / 32;
+		if word >= words {
+			return None;
+		}
+		let bits = self.trees[offset + word] & (!0u32 << (index % 32));
+		if bits != 0 {
+			return Some(word * 32 + bits.trailing_zeros() as usize);
+		}
+		// The next word that is not empty is found with the level above
+		if level + 1 == LEVELS {
+			return None;
+		}
+		let word = self.find(order, level + 1, word + 1)?;
+		Some(word * 32 + self.trees[offset + word].trailing_zeros() as usize)
+	}
+}
+
+pub fn physmap_as_mut() -> &'static mut BuddyAllocator {
+	unsafe {
+		return &mut PHYSMAP;
+	}
+}
+
+use core::fmt;
+impl fmt::Debug for BuddyAllocator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("BuddyAllocator")
+			.field("Page size (bytes)", &PAGE_SIZE)
+			.field("Max order", &MAX_ORDER)
+			.field("RAM pages", &self.total())
+			.field("Used pages", &self.used)
+			.field("DMA zone", &self.zone_stats(Zone::Dma))
+			.field("Normal zone", &self.zone_stats(Zone::Normal))
+			.field("Shared pages", &self.shared.len())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::memory::paging::buddy::{
+		physmap_as_mut,
+		Zone,
+		DMA_END,
+		PAGE_SIZE
+	};
+	use crate::memory::PhysAddr;
+
+	#[test_case]
+	fn buddy_claim() {
+		use crate::page_directory;
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let mut x: usize = 0x100000;
+		let used = physmap.used;
+
+		unsafe {
+			let pd_addr = page_directory.get_vaddr() & 0x3ff000 as PhysAddr;
+			let nmb_claim_pages = ((pd_addr / 0x1000) + 1024) as u32;
+
+			// At start the kernel claim kernel code and memory pages to initialize the allocator
+			// claim occur at adress 0x0 to 1MiB then from it to pd_addr / 0x1000 + 1024
+			assert_eq!(physmap.claim(0x0), Err(0));
+			assert_eq!(used, physmap.used);
+			loop {
+				match physmap.claim(x as u32) {
+					Err(frame) => {
+						assert_eq!(frame, x / PAGE_SIZE);
+						assert_eq!(used, physmap.used);
+					},
+					Ok(addr) => {
+						assert!(
+							addr >= 0x100000
+								+ nmb_claim_pages * PAGE_SIZE as u32
+						);
+						break;
+					}
+				}
+				x += PAGE_SIZE;
+			}
+			assert_eq!(used + 1, physmap.used);
+			physmap.free_page(x as u32);
+			assert_eq!(used, physmap.used);
+		}
+	}
+
+	#[test_case]
+	fn buddy_claim_range() {
+		use crate::page_directory;
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let mut x: usize = 0x100000;
+		let mut used = physmap.used;
+
+		unsafe {
+			let pd_addr = page_directory.get_vaddr() & 0x3ff000 as PhysAddr;
+			let nmb_claim_pages = ((pd_addr / 0x1000) + 1024) as usize;
+
+			let res = physmap.claim_range(x as u32, nmb_claim_pages);
+			assert_eq!(res, Err(x / PAGE_SIZE));
+			assert_eq!(used, physmap.used);
+
+			x = physmap.get_pages(10).unwrap() as usize;
+			for i in 0..10 {
+				physmap.free_page((x + i * PAGE_SIZE) as u32);
+			}
+
+			let res = physmap.claim_range(x as u32, 10);
+			assert_eq!(res, Ok(x as u32));
+			assert_eq!(used + 10, physmap.used);
+		}
+
+		for i in 0..10 {
+			used = physmap.used;
+			physmap.free_page((x + i * PAGE_SIZE) as u32);
+			assert_eq!(used - 1, physmap.used);
+		}
+	}
+
+	#[test_case]
+	fn buddy_get_page() {
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let mut addresses: [u32; 50] = [0; 50];
+		let mut used;
+
+		for i in 0..50 {
+			used = physmap.used;
+			match physmap.get_page() {
+				Err(index) => {
+					panic!("Failed to get pages at index: {:?}", index)
+				},
+				Ok(addr) => addresses[i] = addr
+			}
+			assert_eq!(used + 1, physmap.used);
+		}
+		for i in addresses {
+			used = physmap.used;
+			physmap.free_page(i);
+			assert_eq!(used - 1, physmap.used);
+		}
+	}
+
+	#[test_case]
+	fn buddy_get_pages() {
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let mut used = physmap.used;
+
+		let addr = match physmap.get_pages(50) {
+			Err(index) => panic!("Failed to get pages at index: {:?}", index),
+			Ok(addr) => addr
+		};
+		// Blocks are aligned on their size
+		assert_eq!(addr as usize % (64 * PAGE_SIZE), 0);
+		assert_eq!(used + 50, physmap.used);
+		for i in 0..50 {
+			used = physmap.used;
+			physmap.free_page((addr + i * PAGE_SIZE as u32) as u32);
+			assert_eq!(used - 1, physmap.used);
+		}
+		// Here page is already free so the counter shouldn't be decremented
+		used = physmap.used;
+		physmap.free_page(addr);
+		assert_eq!(used, physmap.used);
+		assert_eq!(physmap.page_refs(addr), 0);
+	}
+
+	#[test_case]
+	fn buddy_zones() {
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let (total, used) = physmap.zone_stats(Zone::Dma);
+		let free = physmap.free();
+
+		let addr = physmap.get_zone_pages(Zone::Dma, 4).unwrap();
+		assert!(addr + 4 * PAGE_SIZE as u32 <= DMA_END);
+		assert_eq!(physmap.zone_stats(Zone::Dma), (total, used + 4));
+		assert_eq!(physmap.free(), free - 4);
+		for i in 0..4 {
+			physmap.free_page(addr + (i * PAGE_SIZE) as u32);
+		}
+		assert_eq!(physmap.zone_stats(Zone::Dma), (total, used));
+		// Pages of the Normal zone are given first
+		let addr = physmap.get_page().unwrap();
+		assert!(addr >= DMA_END);
+		physmap.free_page(addr);
+		// Pages that are not RAM are never given
+		assert_eq!(physmap.get_zone_pages(Zone::Normal, 0), Err(()));
+		physmap.free_page(0xb8000);
+		assert_eq!(physmap.page_refs(0xb8000), 1);
+	}
+
+	#[test_case]
+	fn buddy_share_page() {
+		crate::print_fn!();
+		let physmap = physmap_as_mut();
+		let used = physmap.used;
+
+		let addr = physmap.get_page().unwrap();
+		assert_eq!(physmap.page_refs(addr), 1);
+		physmap.share_page(addr);
+		physmap.share_page(addr);
+		assert_eq!(physmap.page_refs(addr), 3);
+		assert_eq!(used + 1, physmap.used);
+		physmap.free_page(addr);
+		physmap.free_page(addr);
+		assert_eq!(physmap.page_refs(addr), 1);
+		assert_eq!(used + 1, physmap.used);
+		physmap.free_page(addr);
+		assert_eq!(physmap.page_refs(addr), 0);
+		assert_eq!(used, physmap.used);
+	}
+}
@@ -1,9 +1,10 @@
-pub mod bitmap;
+pub mod buddy;
 pub mod page_directory;
 pub mod page_table;
 
 use crate::boot::KERNEL_BASE;
 
+use crate::alloc::vec::Vec;
 use crate::memory::{PhysAddr, VirtAddr};
 use crate::multiboot;
 
@@ -19,31 +20,40 @@ pub const PAGE_GLOBAL: u32 = 0b100000000;
 pub const PAGE_USER: u32 = 0b100;
 pub const PAGE_WRITABLE: u32 = 0b10;
 pub const PAGE_PRESENT: u32 = 0b1;
+/// Software bit of a page reserved by a memory zone that has no frame yet, the frame is
+/// allocated on first access
+pub const PAGE_LAZY: u32 = 0b1000000000;
+/// Software bit of a writable page whose frame is shared read-only with other pages, it gets its
+/// own copy of the frame on the first write
+pub const PAGE_COW: u32 = 0b10000000000;
+/// Software bit of a reserved page whose content was written to a swap area, it is read back on
+/// first access, see page_table::swap_entry
+pub const PAGE_SWAP: u32 = 0b100000000000;
+/// Set by the CPU when a page is accessed
+pub const PAGE_ACCESSED: u32 = 0b100000;
 
 /// Initialize the paging:
 /// + setup a page_table at the index 768 containing kernel code paddrs and
 /// page_directoy paddr
 /// + reset the initial page_table at index 0 and setup the page_directory
 /// to index every page_tables in memory
-/// + initialize the bitmap of page tables
+/// + give the RAM of the multiboot memory map to the page allocator
 /// + refresh tlb to clear the cache of the CPU
 pub fn init_paging() {
 	unsafe {
 		let pd_paddr: PhysAddr =
 			(page_directory.get_vaddr() & 0x3ff000) as PhysAddr;
-		// Claim 1st MiB used by BIOS Real Mode
-		bitmap::physmap_as_mut()
-			.claim_range(0x0, (1024 * 1024) / 0x1000)
-			.expect("Failed to claim BIOS Real Mode 1st MiB");
-		// Claim all memory reserved by grub multiboot
-		multiboot::claim_multiboot();
+		// Only the RAM of the memory map can be allocated
+		multiboot::add_available_memory();
+		// Claim 1st MiB used by BIOS Real Mode, part of it is not RAM
+		buddy::physmap_as_mut().force_claim_range(0x0, (1024 * 1024) / 0x1000);
 		// Claim page_directory
-		bitmap::physmap_as_mut()
+		buddy::physmap_as_mut()
 			.claim_range(0x100000, ((pd_paddr / 0x1000) + 1024) as usize)
 			.expect("Failed to claim code pages");
 
 		// Init paging map
-		let kernel_pt_paddr: PhysAddr = bitmap::physmap_as_mut()
+		let kernel_pt_paddr: PhysAddr = buddy::physmap_as_mut()
 			.get_page()
 			.expect("Failed to get kernel page table");
 		// Use identity mapping to setup kernel page
@@ -96,6 +106,82 @@ pub fn alloc_pages(nb: usize, flags: u32) -> Result<VirtAddr, ()> {
 	unsafe { Ok(page_directory.get_page_frames(nb, flags)?) }
 }
 
+// Reserve 'nb' virtually adjacent pages without frames, see PAGE_LAZY
+pub fn reserve_pages(nb: usize, flags: u32) -> Result<VirtAddr, ()> {
+	unsafe { Ok(page_directory.reserve_page_frames(nb, flags)?) }
+}
+
+// Reserve 'nb' pages without frames from vaddr, fails if one of them is used
+pub fn reserve_pages_at_addr(
+	vaddr: VirtAddr,
+	nb: usize,
+	flags: u32
+) -> Result<VirtAddr, ()> {
+	unsafe { Ok(page_directory.reserve_page_frames_at_addr(vaddr, nb, flags)?) }
+}
+
+// Move the page at src with its frame to the reserved page dst
+pub fn move_page(src: VirtAddr, dst: VirtAddr) -> Result<(), ()> {
+	unsafe { page_directory.move_frame(src, dst) }
+}
+
+// Give a zeroed frame to the reserved page at vaddr if it has none yet
+pub fn fault_page(vaddr: VirtAddr) -> Result<PhysAddr, ()> {
+	unsafe { page_directory.claim_lazy_frame(vaddr) }
+}
+
+// Free the frame of the page at vaddr, keeping the page reserved
+pub fn release_page(vaddr: VirtAddr) -> Result<(), ()> {
+	unsafe { page_directory.release_frame(vaddr) }
+}
+
+// Free the frame of the page at vaddr written to slot of swap area, the
+// page is read back on its next access
+pub fn swap_page(vaddr: VirtAddr, area: usize, slot: usize) -> Result<(), ()> {
+	unsafe { page_directory.swap_frame(vaddr, area, slot) }
+}
+
+// Check if the page at vaddr was swapped out
+pub fn page_is_swapped(vaddr: VirtAddr) -> bool {
+	unsafe { page_directory.is_swapped(vaddr) }
+}
+
+// Give the reserved page dst the swap slot of the swapped out page at src
+pub fn share_swapped_page(src: VirtAddr, dst: VirtAddr) -> Result<(), ()> {
+	unsafe { page_directory.share_swap(src, dst) }
+}
+
+// Pages swapped out to swap area
+pub fn swapped_pages(area: usize) -> Vec<VirtAddr> {
+	unsafe { page_directory.swapped_pages(area) }
+}
+
+// Physical address of the page at vaddr if it has a frame
+pub fn page_frame(vaddr: VirtAddr) -> Option<PhysAddr> {
+	unsafe { page_directory.get_frame(vaddr) }
+}
+
+// Map the frame of the page at src at the reserved page dst, copy-on-write
+pub fn share_page(src: VirtAddr, dst: VirtAddr) -> Result<PhysAddr, ()> {
+	unsafe { page_directory.share_frame(src, dst) }
+}
+
+// Map the frame of the page at src at the reserved page dst, both pages write
+// to the same frame
+pub fn link_page(src: VirtAddr, dst: VirtAddr) -> Result<PhysAddr, ()> {
+	unsafe { page_directory.link_frame(src, dst) }
+}
+
+// Give its own writable frame to the copy-on-write page at vaddr
+pub fn unshare_page(vaddr: VirtAddr) -> Result<PhysAddr, ()> {
+	unsafe { page_directory.unshare_frame(vaddr) }
+}
+
+// Check if the page at vaddr shares its frame copy-on-write
+pub fn page_is_cow(vaddr: VirtAddr) -> bool {
+	unsafe { page_directory.is_cow(vaddr) }
+}
+
 // Allocate a page frame
 pub fn alloc_page(flags: u32) -> Result<VirtAddr, ()> {
 	unsafe { Ok(page_directory.get_page_frame(flags)?) }
@@ -2,10 +2,26 @@ use core::fmt;
 
 use crate::boot::KERNEL_BASE;
 
-use crate::memory::paging::{bitmap, page_directory, PageTable};
+use crate::memory::paging::{buddy, page_directory, PageTable};
 use crate::memory::{PhysAddr, VirtAddr};
 
-use crate::memory::paging::{get_paddr, get_vaddr, refresh_tlb, PAGE_PRESENT};
+use crate::alloc::vec::Vec;
+use crate::memory::paging::page_table::{
+	lazy_entry,
+	swap_entry,
+	PageTableEntry
+};
+use crate::memory::paging::{
+	get_paddr,
+	get_vaddr,
+	refresh_tlb,
+	PAGE_COW,
+	PAGE_LAZY,
+	PAGE_PRESENT,
+	PAGE_USER,
+	PAGE_WRITABLE
+};
+use crate::memory::swap;
 
 #[repr(transparent)]
 pub struct PageDirectory {
@@ -90,7 +106,7 @@ impl PageDirectory {
 			if self.get_entry(i).get_present() == 1 {
 				j = 0;
 				while j < 1024 && available != nb {
-					if self.get_page_table(i).entries[j].get_present() == 0 {
+					if self.get_page_table(i).entries[j].is_unused() {
 						if available == 0 {
 							i_saved = i;
 						}
@@ -121,6 +137,358 @@ impl PageDirectory {
 		nb: usize,
 		flags: u32
 	) -> Result<VirtAddr, ()> {
+		let (pd_index, pt_index) = self.find_page_frames(nb, flags)?;
+		self.claim_index_page_frames(pd_index, pt_index, nb, flags)?;
+		Ok(get_vaddr!(pd_index, pt_index))
+	}
+
+	// Reserve 'nb' virtually adjacent pages without frames, they get one on
+	// first access with claim_lazy_frame
+	pub fn reserve_page_frames(
+		&mut self,
+		nb: usize,
+		flags: u32
+	) -> Result<VirtAddr, ()> {
+		let (mut pd_index, mut pt_index) = self.find_page_frames(nb, flags)?;
+		let vaddr: VirtAddr = get_vaddr!(pd_index, pt_index);
+		for _ in 0..nb {
+			if pt_index == 1024 {
+				pt_index = 0;
+				pd_index += 1;
+			}
+			self.get_page_table(pd_index)
+				.set_entry(pt_index, lazy_entry(flags));
+			pt_index += 1;
+		}
+		Ok(vaddr)
+	}
+
+	// Reserve 'nb' pages without frames from vaddr, fails without reserving
+	// anything if one of them is already used
+	pub fn reserve_page_frames_at_addr(
+		&mut self,
+		vaddr: VirtAddr,
+		nb: usize,
+		flags: u32
+	) -> Result<VirtAddr, ()> {
+		let end = (vaddr as usize).checked_add(nb * 4096).ok_or(())?;
+		if vaddr & 0xfff != 0 || nb == 0 || end > get_vaddr!(1023, 0) as usize {
+			return Err(());
+		}
+		for i in 0..nb {
+			let page: usize = vaddr as usize + i * 4096;
+			if self.get_entry(page >> 22).get_present() == 1
+				&& !self.get_page_table(page >> 22).entries
+					[(page & 0x3ff000) >> 12]
+					.is_unused()
+			{
+				return Err(());
+			}
+		}
+		for i in 0..nb {
+			let page: usize = vaddr as usize + i * 4096;
+			if self.get_entry(page >> 22).get_present() == 0 {
+				self.claim_index_page_table(page >> 22, flags)?;
+			}
+			self.get_page_table(page >> 22)
+				.set_entry((page & 0x3ff000) >> 12, lazy_entry(flags));
+		}
+		Ok(vaddr)
+	}
+
+	// Move the page at src with its frame, or its reservation, to the
+	// reserved page dst
+	pub fn move_frame(
+		&mut self,
+		src: VirtAddr,
+		dst: VirtAddr
+	) -> Result<(), ()> {
+		let src_pd_index: usize = (src >> 22) as usize;
+		let src_pt_index: usize = ((src & 0x3ff000) >> 12) as usize;
+		let dst_pd_index: usize = (dst >> 22) as usize;
+		let dst_pt_index: usize = ((dst & 0x3ff000) >> 12) as usize;
+		if self.get_entry(src_pd_index).get_present() == 0
+			|| self.get_entry(dst_pd_index).get_present() == 0
+			|| !self.get_page_table(dst_pd_index).entries[dst_pt_index]
+				.is_lazy()
+		{
+			return Err(());
+		}
+		let entry = self.get_page_table(src_pd_index).entries[src_pt_index];
+		if entry.is_unused() {
+			return Err(());
+		}
+		self.get_page_table(dst_pd_index)
+			.set_entry(dst_pt_index, entry.value);
+		self.unmap_page_frame(src);
+		unsafe { refresh_tlb!() };
+		Ok(())
+	}
+
+	// Give a zeroed frame to the reserved page at vaddr, a page that already
+	// has one is left as is and a swapped out page is read back in it. The
+	// kernel fills the page so it is writable whatever its flags. Return the
+	// physical address of the page
+	pub fn claim_lazy_frame(
+		&mut self,
+		vaddr: VirtAddr
+	) -> Result<PhysAddr, ()> {
+		if let Some(paddr) = self.get_frame(vaddr) {
+			return Ok(paddr);
+		}
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		if self.get_entry(pd_index).get_present() == 0 {
+			return Err(());
+		}
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		if !entry.is_lazy() {
+			return Err(());
+		}
+		let flags: u32 = match entry.get_swap() {
+			Some(_) => entry.value & (PAGE_WRITABLE | PAGE_USER),
+			None => entry.value & 0xfff & !PAGE_LAZY
+		};
+		let paddr: PhysAddr = buddy::physmap_as_mut().get_page()?;
+		self.get_page_table(pd_index).new_index_frame(
+			pt_index,
+			paddr,
+			flags | PAGE_WRITABLE
+		);
+		unsafe { refresh_tlb!() };
+		let page: VirtAddr = vaddr & !0xfff;
+		match entry.get_swap() {
+			Some((area, slot)) => {
+				if swap::swap_in(area, slot, page).is_err() {
+					self.get_page_table(pd_index)
+						.set_entry(pt_index, entry.value);
+					buddy::physmap_as_mut().free_page(paddr);
+					unsafe { refresh_tlb!() };
+					return Err(());
+				}
+			},
+			None => unsafe {
+				core::ptr::write_bytes(page as *mut u8, 0, 4096);
+			}
+		}
+		Ok(paddr)
+	}
+
+	// Free the frame of the page at vaddr, the page stays reserved and gets
+	// a new frame on its next access with claim_lazy_frame
+	pub fn release_frame(&mut self, vaddr: VirtAddr) -> Result<(), ()> {
+		let paddr: PhysAddr = self.get_frame(vaddr).ok_or(())?;
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		self.get_page_table(pd_index).set_entry(
+			pt_index,
+			lazy_entry(entry.value & (PAGE_WRITABLE | PAGE_USER))
+		);
+		buddy::physmap_as_mut().free_page(paddr);
+		unsafe { refresh_tlb!() };
+		Ok(())
+	}
+
+	// Free the frame of the page at vaddr once its content is written to slot
+	// of swap area, the page reads it back on its next access with
+	// claim_lazy_frame
+	pub fn swap_frame(
+		&mut self,
+		vaddr: VirtAddr,
+		area: usize,
+		slot: usize
+	) -> Result<(), ()> {
+		let paddr: PhysAddr = self.get_frame(vaddr).ok_or(())?;
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		self.get_page_table(pd_index)
+			.set_entry(pt_index, swap_entry(entry.value, area, slot));
+		buddy::physmap_as_mut().free_page(paddr);
+		unsafe { refresh_tlb!() };
+		Ok(())
+	}
+
+	// Check if the page at vaddr was swapped out
+	pub fn is_swapped(&self, vaddr: VirtAddr) -> bool {
+		self.get_page_entry(vaddr)
+			.is_some_and(|entry| entry.get_swap().is_some())
+	}
+
+	// Give the swap slot of the swapped out page at src to the reserved page
+	// dst, each of them reads its own copy of the page back
+	pub fn share_swap(
+		&mut self,
+		src: VirtAddr,
+		dst: VirtAddr
+	) -> Result<(), ()> {
+		let entry = self.get_page_entry(src).ok_or(())?;
+		let (area, slot) = entry.get_swap().ok_or(())?;
+		if !self
+			.get_page_entry(dst)
+			.is_some_and(|entry| entry.is_lazy())
+		{
+			return Err(());
+		}
+		swap::dup_slot(area, slot)?;
+		self.get_page_table((dst >> 22) as usize)
+			.set_entry(((dst & 0x3ff000) >> 12) as usize, entry.value);
+		Ok(())
+	}
+
+	// Pages whose content is in swap area
+	pub fn swapped_pages(&self, area: usize) -> Vec<VirtAddr> {
+		let mut pages: Vec<VirtAddr> = Vec::new();
+		for i in 0..1023 {
+			if self.get_entry(i).get_present() == 0 {
+				continue;
+			}
+			for (j, entry) in self.get_page_table(i).entries.iter().enumerate()
+			{
+				if entry.get_swap().is_some_and(|(index, _)| index == area) {
+					pages.push(get_vaddr!(i, j));
+				}
+			}
+		}
+		pages
+	}
+
+	// Entry of the page at vaddr if its page table is present
+	fn get_page_entry(&self, vaddr: VirtAddr) -> Option<PageTableEntry> {
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		match self.get_entry(pd_index).get_present() {
+			1 => Some(self.get_page_table(pd_index).entries[pt_index]),
+			_ => None
+		}
+	}
+
+	// Physical address of the page at vaddr if it has a frame
+	pub fn get_frame(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		if self.get_entry(pd_index).get_present() == 0 {
+			return None;
+		}
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		match entry.get_present() {
+			1 => Some(entry.get_paddr()),
+			_ => None
+		}
+	}
+
+	// Map the frame of the page at src at the reserved page dst. A writable
+	// frame becomes read-only and copy-on-write in both pages, see
+	// unshare_frame. Return the physical address of the frame
+	pub fn share_frame(
+		&mut self,
+		src: VirtAddr,
+		dst: VirtAddr
+	) -> Result<PhysAddr, ()> {
+		let paddr: PhysAddr = self.get_frame(src).ok_or(())?;
+		let dst_pd_index: usize = (dst >> 22) as usize;
+		let dst_pt_index: usize = ((dst & 0x3ff000) >> 12) as usize;
+		if self.get_entry(dst_pd_index).get_present() == 0
+			|| !self.get_page_table(dst_pd_index).entries[dst_pt_index]
+				.is_lazy()
+		{
+			return Err(());
+		}
+		let src_pd_index: usize = (src >> 22) as usize;
+		let src_pt_index: usize = ((src & 0x3ff000) >> 12) as usize;
+		let mut flags: u32 = self.get_page_table(src_pd_index).entries
+			[src_pt_index]
+			.value & 0xfff;
+		if flags & PAGE_WRITABLE != 0 {
+			flags = (flags & !PAGE_WRITABLE) | PAGE_COW;
+		}
+		self.get_page_table(src_pd_index)
+			.set_entry(src_pt_index, paddr | flags);
+		self.get_page_table(dst_pd_index)
+			.set_entry(dst_pt_index, paddr | flags);
+		buddy::physmap_as_mut().share_page(paddr);
+		unsafe { refresh_tlb!() };
+		Ok(paddr)
+	}
+
+	// Map the frame of the page at src at the reserved page dst, writable if
+	// dst is. Unlike share_frame both pages keep writing to the same frame,
+	// it is freed with the last page mapping it. Return its physical address
+	pub fn link_frame(
+		&mut self,
+		src: VirtAddr,
+		dst: VirtAddr
+	) -> Result<PhysAddr, ()> {
+		let paddr: PhysAddr = self.get_frame(src).ok_or(())?;
+		let pd_index: usize = (dst >> 22) as usize;
+		let pt_index: usize = ((dst & 0x3ff000) >> 12) as usize;
+		if self.get_entry(pd_index).get_present() == 0 {
+			return Err(());
+		}
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		if !entry.is_lazy() || entry.get_swap().is_some() {
+			return Err(());
+		}
+		let flags: u32 = entry.value & (PAGE_WRITABLE | PAGE_USER);
+		self.get_page_table(pd_index)
+			.set_entry(pt_index, paddr | flags | PAGE_PRESENT);
+		buddy::physmap_as_mut().share_page(paddr);
+		unsafe { refresh_tlb!() };
+		Ok(paddr)
+	}
+
+	// Make the copy-on-write page at vaddr writable again. The frame is
+	// copied in a new one while other pages still map it, the last one keeps
+	// it. Return the physical address of the page
+	pub fn unshare_frame(&mut self, vaddr: VirtAddr) -> Result<PhysAddr, ()> {
+		let paddr: PhysAddr = self.get_frame(vaddr).ok_or(())?;
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		let entry = self.get_page_table(pd_index).entries[pt_index];
+		if entry.value & PAGE_COW == 0 {
+			return Ok(paddr);
+		}
+		let flags: u32 = (entry.value & 0xfff & !PAGE_COW) | PAGE_WRITABLE;
+		if buddy::physmap_as_mut().page_refs(paddr) == 1 {
+			self.get_page_table(pd_index)
+				.set_entry(pt_index, paddr | flags);
+			unsafe { refresh_tlb!() };
+			return Ok(paddr);
+		}
+		let copy: VirtAddr = self.get_page_frame(PAGE_WRITABLE)?;
+		unsafe {
+			core::ptr::copy_nonoverlapping(
+				(vaddr & !0xfff) as *const u8,
+				copy as *mut u8,
+				4096
+			);
+		}
+		let new_paddr: PhysAddr = self.unmap_page_frame(copy).ok_or(())?;
+		self.get_page_table(pd_index)
+			.set_entry(pt_index, new_paddr | flags);
+		buddy::physmap_as_mut().free_page(paddr);
+		unsafe { refresh_tlb!() };
+		Ok(new_paddr)
+	}
+
+	// Check if the page at vaddr shares its frame copy-on-write
+	pub fn is_cow(&self, vaddr: VirtAddr) -> bool {
+		let pd_index: usize = (vaddr >> 22) as usize;
+		let pt_index: usize = ((vaddr & 0x3ff000) >> 12) as usize;
+		self.get_entry(pd_index).get_present() == 1
+			&& self.get_page_table(pd_index).entries[pt_index].value
+				& (PAGE_COW | PAGE_PRESENT)
+				== PAGE_COW | PAGE_PRESENT
+	}
+
+	// Find 'nb' free virtually adjacent pages (by lowest index), claiming new
+	// page tables if needed. Return their page directory and page table index
+	fn find_page_frames(
+		&mut self,
+		nb: usize,
+		flags: u32
+	) -> Result<(usize, usize), ()> {
 		let mut available: usize = 0;
 		let mut i: usize = 0;
 		let mut i_saved: usize = 1;
@@ -133,7 +501,7 @@ impl PageDirectory {
 			if self.get_entry(i).get_present() == 1 {
 				j = 0;
 				while j < 1024 && available != nb {
-					if self.get_page_table(i).entries[j].get_present() == 0 {
+					if self.get_page_table(i).entries[j].is_unused() {
 						if available == 0 {
 							i_saved = i;
 						}
@@ -152,14 +520,12 @@ impl PageDirectory {
 		} else {
 			j -= nb;
 		}
-		let vaddr: VirtAddr = get_vaddr!(i_saved, j);
-		self.claim_index_page_frames(i_saved, j, nb, flags)?;
-		Ok(vaddr)
+		Ok((i_saved, j))
 	}
 
 	// Claim a page frame (by lowest index)
 	pub fn get_page_frame(&mut self, flags: u32) -> Result<VirtAddr, ()> {
-		let paddr = bitmap::physmap_as_mut().get_page()?;
+		let paddr = buddy::physmap_as_mut().get_page()?;
 		let mut i: usize = 0;
 
 		while i < 1023 {
@@ -184,7 +550,7 @@ impl PageDirectory {
 		nb: usize,
 		flags: u32
 	) -> Result<(), ()> {
-		let mut paddr: PhysAddr = bitmap::physmap_as_mut().get_pages(nb)?;
+		let mut paddr: PhysAddr = buddy::physmap_as_mut().get_pages(nb)?;
 
 		let mut i: usize = 0;
 		while i < nb {
@@ -216,7 +582,7 @@ impl PageDirectory {
 				pt_index = 0;
 				pd_index += 1;
 			}
-			let paddr: PhysAddr = bitmap::physmap_as_mut().get_page()?;
+			let paddr: PhysAddr = buddy::physmap_as_mut().get_page()?;
 			self.get_page_table(pd_index)
 				.new_index_frame(pt_index, paddr, flags);
 			pt_index += 1;
@@ -231,7 +597,7 @@ impl PageDirectory {
 		flags: u32
 	) -> Result<usize, ()> {
 		unsafe {
-			let paddr: PhysAddr = bitmap::physmap_as_mut().get_page()?;
+			let paddr: PhysAddr = buddy::physmap_as_mut().get_page()?;
 			self.set_entry(index, paddr | flags | PAGE_PRESENT);
 			refresh_tlb!();
 			self.get_page_table(index).clear();
@@ -326,15 +692,30 @@ impl PageDirectory {
 
 	// Remove a page frame at a specified virtual address
 	pub fn remove_page_frame(&mut self, vaddr: VirtAddr) {
+		let swapped = match vaddr & 0xfff {
+			0 => self.get_page_entry(vaddr).and_then(|x| x.get_swap()),
+			_ => None
+		};
+		if let Some(paddr) = self.unmap_page_frame(vaddr) {
+			buddy::physmap_as_mut().free_page(paddr);
+		} else if let Some((area, slot)) = swapped {
+			swap::free_slot(area, slot);
+		}
+	}
+
+	// Remove the page at a specified virtual address without freeing its
+	// frame. Return the physical address of the frame if it had one
+	fn unmap_page_frame(&mut self, vaddr: VirtAddr) -> Option<PhysAddr> {
 		unsafe {
 			if vaddr & 0xfff != 0 {
-				return; // Not aligned
+				return None; // Not aligned
 			}
-			let paddr: PhysAddr = get_paddr!(vaddr);
 			let pd_index: usize = (vaddr >> 22) as usize;
 			let i: usize = ((vaddr & 0x3ff000) >> 12) as usize;
 			let page_table: &mut PageTable =
 				page_directory.get_page_table(pd_index);
+			// Reserved pages that were never accessed have no frame to free
+			let frame = page_table.entries[i];
 			page_table.set_entry(i, 0);
 			// if last page_frame, free the page_table
 			let mut i = 0;
@@ -352,7 +733,10 @@ impl PageDirectory {
 					pd_index
 				);
 			}
-			bitmap::physmap_as_mut().free_page(paddr);
+			match frame.get_present() {
+				1 => Some(frame.get_paddr()),
+				_ => None
+			}
 		}
 	}
 
@@ -362,7 +746,7 @@ impl PageDirectory {
 			if self.get_entry(index).get_present() == 1 {
 				let page_table: &mut PageTable = self.get_page_table(index);
 				page_table.clear();
-				bitmap::physmap_as_mut()
+				buddy::physmap_as_mut()
 					.free_page(self.get_entry(index).get_paddr());
 				self.set_entry(index, 0);
 				refresh_tlb!();
@@ -1,6 +1,12 @@
 use core::fmt;
 
-use crate::memory::paging::{PAGE_PRESENT, PAGE_WRITABLE};
+use crate::memory::paging::{
+	PAGE_LAZY,
+	PAGE_PRESENT,
+	PAGE_SWAP,
+	PAGE_USER,
+	PAGE_WRITABLE
+};
 use crate::memory::{PhysAddr, VirtAddr};
 use crate::page_directory;
 
@@ -13,7 +19,7 @@ pub struct PageTable {
 	pub entries: [PageTableEntry; 1024]
 }
 
-use crate::memory::paging::bitmap;
+use crate::memory::paging::buddy;
 use core::ops;
 impl ops::Drop for PageTable {
 	fn drop(&mut self) {
@@ -21,7 +27,7 @@ impl ops::Drop for PageTable {
 		for i in 0..1024 {
 			if self.entries[i].get_present() != 0 {
 				crate::kprintln!("free pages {}", self.entries[i].get_paddr());
-				bitmap::physmap_as_mut().free_page(self.entries[i].get_paddr());
+				buddy::physmap_as_mut().free_page(self.entries[i].get_paddr());
 			}
 		}
 	}
@@ -34,7 +40,10 @@ impl PageTable {
 			if !res.is_ok() {
 				todo!();
 			}
-			&mut *(res.unwrap() as *mut _)
+			// Unused entries must be zero, reserved ones are not present
+			let page_table: &'static mut Self = &mut *(res.unwrap() as *mut _);
+			page_table.clear();
+			page_table
 		}
 	}
 
@@ -90,7 +99,7 @@ i <= (page_directory_entry & 0x3ff000) >> 12) || i == (0xb8000 >> 12)
 		let mut i: usize = 0;
 
 		while i < 1024 {
-			if self.entries[i].get_present() != 1 {
+			if self.entries[i].is_unused() {
 				self.entries[i] = (paddr | flags | PAGE_PRESENT).into();
 				return Ok(i as u16);
 			}
@@ -114,10 +123,19 @@ i <= (page_directory_entry & 0x3ff000) >> 12) || i == (0xb8000 >> 12)
 		Ok(index as u16)
 	}
 
+	/// Reserve 'no' adjacent entries without frames, see PAGE_LAZY
+	pub fn reserve_frames(&mut self, no: u32, flags: u32) -> Result<u16, ()> {
+		let index = self.find_space(no)?;
+		for i in 0..no as usize {
+			self.entries[index as usize + i] = lazy_entry(flags).into();
+		}
+		Ok(index)
+	}
+
 	pub fn find_space(&self, no: u32) -> Result<u16, ()> {
 		for i in 0..(1024 - no as usize) {
 			for j in 0..=no as usize {
-				if self.entries[i + j].get_present() == 1 {
+				if !self.entries[i + j].is_unused() {
 					break;
 				} else if j == no as usize {
 					// Found enough space
@@ -133,6 +151,20 @@ i <= (page_directory_entry & 0x3ff000) >> 12) || i == (0xb8000 >> 12)
 	}
 }
 
+/// Entry of a reserved page without frame, flags are kept for when it gets one
+pub fn lazy_entry(flags: u32) -> u32 {
+	(flags & 0xfff & !PAGE_PRESENT) | PAGE_LAZY
+}
+
+/// Entry of a reserved page whose content is in slot of swap area. The area takes the place of
+/// the other flags, only WRITABLE and USER are kept
+pub fn swap_entry(flags: u32, area: usize, slot: usize) -> u32 {
+	lazy_entry(flags & (PAGE_WRITABLE | PAGE_USER))
+		| PAGE_SWAP
+		| ((area as u32) << 3)
+		| ((slot as u32) << 12)
+}
+
 #[repr(transparent)]
 #[derive(Copy, Clone)]
 pub struct PageTableEntry {
@@ -154,6 +186,26 @@ self.get_global(), self.get_avl(), self.get_paddr())
 }
 
 impl PageTableEntry {
+	/// Entries reserved for a lazy page are not present but still used
+	pub fn is_unused(&self) -> bool {
+		self.value == 0
+	}
+
+	pub fn is_lazy(&self) -> bool {
+		self.get_present() == 0 && self.value & PAGE_LAZY != 0
+	}
+
+	/// Swap area and slot holding the content of a swapped out page, see swap_entry
+	pub fn get_swap(&self) -> Option<(usize, usize)> {
+		match self.is_lazy() && self.value & PAGE_SWAP != 0 {
+			true => Some((
+				((self.value >> 3) & 0b111) as usize,
+				(self.value >> 12) as usize
+			)),
+			false => None
+		}
+	}
+
 	pub fn get_present(&self) -> u8 {
 		(self.value & 0b00000001) as u8
 	}
@@ -0,0 +1,573 @@
+//! Shared memory, System V segments of shmget and the objects of shm_open are made of frames
+//! mapped in the zones of every process attaching them
+
+use crate::alloc::collections::btree_map::BTreeMap;
+use crate::alloc::sync::{Arc, Weak};
+use crate::alloc::vec::Vec;
+use crate::errno::ErrNo;
+use crate::fs::ext2::perm::{MAY_READ, MAY_WRITE};
+use crate::memory::paging::link_page;
+use crate::memory::{
+	prot_flags,
+	MemoryZone,
+	TypeZone,
+	VirtAddr,
+	PROT_READ,
+	PROT_WRITE,
+	WRITABLE
+};
+use crate::proc::process::{Pid, Process};
+use crate::proc::Id;
+use crate::spin::{Mutex, RawMutex};
+use crate::utils::arcm::Arcm;
+
+// shmget flags and shmctl commands, values match linux ipc.h and shm.h
+pub const IPC_PRIVATE: i32 = 0;
+pub const IPC_CREAT: u32 = 0o1000;
+pub const IPC_EXCL: u32 = 0o2000;
+pub const IPC_RMID: u32 = 0;
+pub const IPC_SET: u32 = 1;
+pub const IPC_STAT: u32 = 2;
+/// Set by the C library on shmctl commands using shmid64_ds, the only layout supported
+pub const IPC_64: u32 = 0x100;
+pub const SHM_RDONLY: u32 = 0o10000;
+pub const SHM_RND: u32 = 0o20000;
+/// Reported in the mode of segments marked for destruction
+pub const SHM_DEST: u16 = 0o1000;
+/// Attach addresses are rounded down to a multiple of SHMLBA with SHM_RND
+pub const SHMLBA: VirtAddr = 0x1000;
+/// Largest segment or object, its pages get a frame when it is created or grows
+pub const SHMMAX: usize = 0x2000000;
+/// Segments existing at once
+pub const SHMMNI: usize = 4096;
+
+/// Pages of a shared memory object. They get a frame when the object is created and are mapped
+/// in the zones attaching it, frames are reference counted so they are freed once the object
+/// and every zone mapping them are dropped
+pub struct SharedMemory {
+	zone: MemoryZone,
+	size: usize
+}
+
+impl SharedMemory {
+	/// Object of size bytes of zeroed pages
+	pub fn new(size: usize) -> Result<Self, ErrNo> {
+		if size > SHMMAX {
+			return Err(ErrNo::EFBIG);
+		}
+		let pages = core::cmp::max((size + 0xfff) / 4096, 1);
+		let mut zone =
+			MemoryZone::reserve(TypeZone::Shm, pages * 4096, WRITABLE);
+		// No process sees the zone, it can move when resized
+		zone.vaddr = 0;
+		zone.populate().map_err(|_| ErrNo::ENOMEM)?;
+		Ok(Self { zone, size })
+	}
+
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Resize the object to size bytes, added pages are zeroed. Pages past its end are freed
+	/// once the zones attaching them are dropped. It can't grow past SHMMAX
+	pub fn resize(&mut self, size: usize) -> Result<(), ErrNo> {
+		if size > SHMMAX {
+			return Err(ErrNo::EFBIG);
+		}
+		let pages = core::cmp::max((size + 0xfff) / 4096, 1);
+		self.zone
+			.remap(0, pages * 4096, 0)
+			.map_err(|_| ErrNo::ENOMEM)?;
+		self.zone.populate().map_err(|_| ErrNo::ENOMEM)?;
+		if size < self.size {
+			self.zone[size..].fill(0);
+		}
+		self.size = size;
+		Ok(())
+	}
+
+	/// Zone of size bytes mapping the pages of the object from the page aligned offset, with
+	/// paging flags. The zone is shared so the children of its process keep it.
+	/// The pages must be inside the object
+	pub fn attach(
+		&self,
+		offset: usize,
+		size: usize,
+		flags: u32
+	) -> Result<MemoryZone, ErrNo> {
+		let pages = (size + 0xfff) / 4096;
+		if pages == 0 || offset & 0xfff != 0 {
+			return Err(ErrNo::EINVAL);
+		}
+		match offset.checked_add(pages * 4096) {
+			Some(end) if end <= (self.size + 0xfff) & !0xfff => {},
+			_ => return Err(ErrNo::ENXIO)
+		}
+		let mut zone = MemoryZone::reserve(TypeZone::Shm, pages * 4096, flags);
+		zone.shared = true;
+		for index in 0..pages {
+			link_page(
+				self.zone.offset + (offset + index * 4096) as VirtAddr,
+				zone.offset + (index * 4096) as VirtAddr
+			)
+			.map_err(|_| ErrNo::ENOMEM)?;
+		}
+		Ok(zone)
+	}
+
+	/// Copy the bytes of the object from offset to dst, return the number of bytes read
+	pub fn read_at(&self, dst: &mut [u8], offset: usize) -> usize {
+		let src = self.zone.get(offset..self.size).unwrap_or(&[]);
+		let length = core::cmp::min(src.len(), dst.len());
+		dst[..length].copy_from_slice(&src[..length]);
+		length
+	}
+
+	/// Copy src to the object at offset, it grows if needed but not past SHMMAX
+	pub fn write_at(
+		&mut self,
+		src: &[u8],
+		offset: usize
+	) -> Result<usize, ErrNo> {
+		let end = offset.checked_add(src.len()).ok_or(ErrNo::EFBIG)?;
+		if end > self.size {
+			self.resize(end)?;
+		}
+		self.zone[offset..end].copy_from_slice(src);
+		Ok(src.len())
+	}
+}
+
+/// ipc64_perm of linux, permissions of a segment
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct IpcPerm {
+	pub key:  i32,
+	pub uid:  u32,
+	pub gid:  u32,
+	pub cuid: u32,
+	pub cgid: u32,
+	pub mode: u16,
+	__pad1:   u16,
+	pub seq:  u16,
+	__pad2:   u16,
+	__unused: [u32; 2]
+}
+
+/// shmid64_ds of linux, status of a segment given by shmctl. Times are in seconds since epoch
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct ShmidDs {
+	pub shm_perm:   IpcPerm,
+	pub shm_segsz:  usize,
+	pub shm_atime:  u32,
+	__atime_high:   u32,
+	pub shm_dtime:  u32,
+	__dtime_high:   u32,
+	pub shm_ctime:  u32,
+	__ctime_high:   u32,
+	pub shm_cpid:   Pid,
+	pub shm_lpid:   Pid,
+	pub shm_nattch: u32,
+	__unused:       [u32; 2]
+}
+
+/// System V segment, it lives until it is removed and no zone attaches it anymore
+struct Segment {
+	key:      i32,
+	memory:   SharedMemory,
+	uid:      Id,
+	gid:      Id,
+	cuid:     Id,
+	cgid:     Id,
+	mode:     u16,
+	cpid:     Pid,
+	lpid:     Pid,
+	atime:    u32,
+	dtime:    u32,
+	ctime:    u32,
+	/// Zones of shmat, they are dropped with the last process mapping them
+	attaches: Vec<Weak<RawMutex<MemoryZone, false>>>,
+	removed:  bool
+}
+
+impl Segment {
+	/// Number of processes attaching the segment, a zone kept by forked processes counts once
+	/// for each of them
+	fn nattch(&mut self) -> usize {
+		self.attaches.retain(|zone| zone.strong_count() != 0);
+		self.attaches.iter().map(|zone| zone.strong_count()).sum()
+	}
+
+	/// Check that the mask (MAY_*) rights are granted to uid and gid. Only the owner, group or
+	/// other class matching the caller is used, root is granted everything
+	fn permits(&self, uid: Id, gid: Id, mask: u16) -> bool {
+		let perm = match uid {
+			0 => return true,
+			uid if uid == self.uid || uid == self.cuid => self.mode >> 6,
+			_ if gid == self.gid || gid == self.cgid => self.mode >> 3,
+			_ => self.mode
+		};
+		perm & mask == mask
+	}
+
+	fn is_owner(&self, uid: Id) -> bool {
+		uid == 0 || uid == self.uid || uid == self.cuid
+	}
+
+	fn attached(&self, zone: &Arcm<MemoryZone>) -> bool {
+		self.attaches
+			.iter()
+			.any(|attach| core::ptr::eq(attach.as_ptr(), Arc::as_ptr(zone)))
+	}
+
+	fn stat(&mut self) -> ShmidDs {
+		let mut mode = self.mode;
+		if self.removed {
+			mode |= SHM_DEST;
+		}
+		ShmidDs {
+			shm_perm: IpcPerm {
+				key: if self.removed { IPC_PRIVATE } else { self.key },
+				uid: self.uid as u32,
+				gid: self.gid as u32,
+				cuid: self.cuid as u32,
+				cgid: self.cgid as u32,
+				mode,
+				..Default::default()
+			},
+			shm_segsz: self.memory.size(),
+			shm_atime: self.atime,
+			shm_dtime: self.dtime,
+			shm_ctime: self.ctime,
+			shm_cpid: self.cpid,
+			shm_lpid: self.lpid,
+			shm_nattch: self.nattch() as u32,
+			..Default::default()
+		}
+	}
+}
+
+static SEGMENTS: Mutex<BTreeMap<usize, Segment>> = Mutex::new(BTreeMap::new());
+static mut NEXT_SHMID: usize = 0;
+
+/// Drop the removed segments no zone attaches anymore
+fn purge(segments: &mut BTreeMap<usize, Segment>) {
+	segments.retain(|_, segment| !segment.removed || segment.nattch() != 0);
+}
+
+/// Free the removed segments whose last attach was dropped outside of shmdt, when a process
+/// exits or unmaps it. Segments in use are left to the next shm call, it purges them
+pub fn release_removed() {
+	if let Some(mut segments) = SEGMENTS.try_lock() {
+		purge(&mut segments);
+	}
+}
+
+/// Owner, group and pid of the running process
+fn caller() -> (Id, Id, Pid) {
+	let binding = Process::get_running_process();
+	let process = binding.lock();
+	(process.owner, process.group, process.pid)
+}
+
+fn now() -> u32 {
+	crate::cmos::get_time().timestamp()
+}
+
+/// This function mimic the linux shmget syscall.
+/// Return the identifier of the segment of key, IPC_PRIVATE always creates a new one.
+/// With IPC_CREAT a segment of size bytes is created if none exists, IPC_EXCL fails if one
+/// does. The lower 9 bits of flags are the permissions of a new segment, and the rights asked
+/// on an existing one
+pub fn shmget(key: i32, size: usize, flags: u32) -> Result<usize, ErrNo> {
+	let (uid, gid, pid) = caller();
+	let mut segments = SEGMENTS.lock();
+	purge(&mut segments);
+	let found = segments
+		.iter()
+		.find(|(_, segment)| segment.key == key && !segment.removed)
+		.filter(|_| key != IPC_PRIVATE);
+	match found {
+		Some(_) if flags & IPC_EXCL != 0 && flags & IPC_CREAT != 0 => {
+			Err(ErrNo::EEXIST)
+		},
+		Some((_, segment)) if size > segment.memory.size() => {
+			Err(ErrNo::EINVAL)
+		},
+		Some((shmid, segment)) => {
+			let mask = ((flags >> 6) | (flags >> 3) | flags) as u16 & 0o7;
+			match segment.permits(uid, gid, mask) {
+				true => Ok(*shmid),
+				false => Err(ErrNo::EACCES)
+			}
+		},
+		None if key != IPC_PRIVATE && flags & IPC_CREAT == 0 => {
+			Err(ErrNo::ENOENT)
+		},
+		None if size == 0 || size > SHMMAX => Err(ErrNo::EINVAL),
+		None if segments.len() >= SHMMNI => Err(ErrNo::ENOSPC),
+		None => {
+			let segment = Segment {
+				key,
+				memory: SharedMemory::new(size)?,
+				uid,
+				gid,
+				cuid: uid,
+				cgid: gid,
+				mode: flags as u16 & 0o777,
+				cpid: pid,
+				lpid: 0,
+				atime: 0,
+				dtime: 0,
+				ctime: now(),
+				attaches: Vec::new(),
+				removed: false
+			};
+			let shmid = unsafe {
+				NEXT_SHMID += 1;
+				NEXT_SHMID
+			};
+			segments.insert(shmid, segment);
+			Ok(shmid)
+		}
+	}
+}
+
+/// This function mimic the linux shmat syscall.
+/// Map the segment shmid in the address space of the running process, at addr if it is not 0.
+/// SHM_RND rounds addr down to SHMLBA and SHM_RDONLY maps it read-only.
+/// Return the address of the segment, processes without their own page directory ignore addr
+pub fn shmat(
+	shmid: usize,
+	addr: VirtAddr,
+	flags: u32
+) -> Result<VirtAddr, ErrNo> {
+	let addr = match flags & SHM_RND {
+		0 => addr,
+		_ => addr & !(SHMLBA - 1)
+	};
+	if addr & 0xfff != 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	let (uid, gid, pid) = caller();
+	let (mask, prot) = match flags & SHM_RDONLY {
+		0 => (MAY_READ | MAY_WRITE, PROT_READ | PROT_WRITE),
+		_ => (MAY_READ, PROT_READ)
+	};
+	let mut segments = SEGMENTS.lock();
+	let segment = segments.get_mut(&shmid).ok_or(ErrNo::EINVAL)?;
+	if segment.removed {
+		return Err(ErrNo::EIDRM);
+	}
+	if !segment.permits(uid, gid, mask) {
+		return Err(ErrNo::EACCES);
+	}
+	let size = segment.memory.size();
+	let mut zone = segment.memory.attach(0, size, prot_flags(prot))?;
+	let binding = Process::get_running_process();
+	let mut process = binding.lock();
+	let kernel = process.pd.is_null();
+	zone.vaddr = match addr {
+		_ if kernel && addr != 0 => return Err(ErrNo::EINVAL),
+		_ if kernel => zone.offset,
+		0 => process
+			.get_unmapped_area(0, zone.size)
+			.ok_or(ErrNo::ENOMEM)?,
+		_ if process.range_free(addr, zone.size) => addr,
+		_ => return Err(ErrNo::EINVAL)
+	};
+	let vaddr = zone.vaddr;
+	process.map_zone_pages(&zone).map_err(|_| ErrNo::ENOMEM)?;
+	let zone = Arcm::new(zone);
+	segment.attaches.push(Arc::downgrade(&zone));
+	process.add_memory_zone(zone);
+	segment.lpid = pid;
+	segment.atime = now();
+	Ok(vaddr)
+}
+
+/// This function mimic the linux shmdt syscall.
+/// Unmap the segment attached at addr from the running process, a removed segment is
+/// destroyed with its last attach
+pub fn shmdt(addr: VirtAddr) -> Result<usize, ErrNo> {
+	let mut segments = SEGMENTS.lock();
+	let binding = Process::get_running_process();
+	let (zone, pid) = {
+		let mut process = binding.lock();
+		let zone = process
+			.mem_map
+			.iter()
+			.find(|zone| {
+				zone.lock().vaddr == addr
+					&& segments.values().any(|segment| segment.attached(zone))
+			})
+			.cloned()
+			.ok_or(ErrNo::EINVAL)?;
+		process.mem_map = core::mem::take(&mut process.mem_map)
+			.into_iter()
+			.filter(|other| !Arc::ptr_eq(other, &zone))
+			.collect();
+		let pages = zone.lock().pages();
+		process.unmap_pages(addr, pages);
+		(zone, process.pid)
+	};
+	if let Some(segment) = segments
+		.values_mut()
+		.find(|segment| segment.attached(&zone))
+	{
+		segment.lpid = pid;
+		segment.dtime = now();
+	}
+	drop(zone);
+	purge(&mut segments);
+	Ok(0)
+}
+
+/// This function mimic the linux shmctl syscall.
+/// IPC_STAT fills buf with the status of the segment, IPC_SET changes its owner, group and
+/// permissions to the ones of buf and IPC_RMID marks it to be destroyed after its last detach.
+/// Only the owner or creator of the segment can change or remove it
+pub fn shmctl(
+	shmid: usize,
+	cmd: u32,
+	buf: &mut ShmidDs
+) -> Result<usize, ErrNo> {
+	let (uid, gid, _) = caller();
+	let mut segments = SEGMENTS.lock();
+	let segment = segments.get_mut(&shmid).ok_or(ErrNo::EINVAL)?;
+	match cmd & !IPC_64 {
+		IPC_STAT if !segment.permits(uid, gid, MAY_READ) => {
+			return Err(ErrNo::EACCES)
+		},
+		IPC_STAT => *buf = segment.stat(),
+		IPC_SET | IPC_RMID if !segment.is_owner(uid) => {
+			return Err(ErrNo::EPERM)
+		},
+		IPC_SET => {
+			segment.uid = buf.shm_perm.uid as Id;
+			segment.gid = buf.shm_perm.gid as Id;
+			segment.mode = buf.shm_perm.mode & 0o777;
+			segment.ctime = now();
+		},
+		IPC_RMID => {
+			segment.removed = true;
+			segment.ctime = now();
+		},
+		_ => return Err(ErrNo::EINVAL)
+	}
+	purge(&mut segments);
+	Ok(0)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		release_removed,
+		shmat,
+		shmctl,
+		shmdt,
+		shmget,
+		SharedMemory,
+		ShmidDs,
+		IPC_CREAT,
+		IPC_EXCL,
+		IPC_PRIVATE,
+		IPC_RMID,
+		IPC_STAT,
+		SHMMAX,
+		SHM_DEST
+	};
+	use crate::errno::ErrNo;
+	use crate::memory::paging::buddy::physmap_as_mut;
+	use crate::memory::WRITABLE;
+	use crate::proc::process::Process;
+
+	#[sys_macros::test_case]
+	fn shared_memory_attach() {
+		let mut memory = SharedMemory::new(0x2000).unwrap();
+		let mut first = memory.attach(0, 0x2000, WRITABLE).unwrap();
+		let second = memory.attach(0x1000, 0x1000, WRITABLE).unwrap();
+		assert_eq!(first.frame(1), second.frame(0));
+		first[0x1000] = 42;
+		assert_eq!(second[0], 42);
+		assert_eq!(
+			memory.attach(0x1000, 0x2000, WRITABLE).err(),
+			Some(ErrNo::ENXIO)
+		);
+		let used_pages = physmap_as_mut().used;
+		memory.resize(0x1000).unwrap();
+		// The page is still mapped by the zones
+		assert_eq!(used_pages, physmap_as_mut().used);
+		drop(first);
+		drop(second);
+		assert_eq!(used_pages - 1, physmap_as_mut().used);
+	}
+
+	#[sys_macros::test_case]
+	fn shared_memory_limits() {
+		assert_eq!(SharedMemory::new(SHMMAX + 1).err(), Some(ErrNo::EFBIG));
+		let mut memory = SharedMemory::new(0x1000).unwrap();
+		assert_eq!(memory.resize(SHMMAX + 1), Err(ErrNo::EFBIG));
+		assert_eq!(memory.write_at(&[42], usize::MAX), Err(ErrNo::EFBIG));
+		assert_eq!(memory.write_at(&[42], SHMMAX), Err(ErrNo::EFBIG));
+		assert_eq!(memory.size(), 0x1000);
+		assert_eq!(
+			memory.attach(usize::MAX & !0xfff, 0x2000, WRITABLE).err(),
+			Some(ErrNo::ENXIO)
+		);
+	}
+
+	#[sys_macros::test_case]
+	fn shm_segment_freed_on_unmap() {
+		let used_pages = physmap_as_mut().used;
+		let shmid = shmget(IPC_PRIVATE, 0x1000, IPC_CREAT | 0o600).unwrap();
+		let addr = shmat(shmid, 0, 0).unwrap();
+		shmctl(shmid, IPC_RMID, &mut ShmidDs::default()).unwrap();
+		let binding = Process::get_running_process();
+		binding.lock().unmap_zones(addr, 0x1000).unwrap();
+		release_removed();
+		assert_eq!(used_pages, physmap_as_mut().used);
+	}
+
+	#[sys_macros::test_case]
+	fn shm_segment_lifetime() {
+		let used_pages = physmap_as_mut().used;
+		let shmid = shmget(IPC_PRIVATE, 0x1800, IPC_CREAT | 0o600).unwrap();
+		let first = shmat(shmid, 0, 0).unwrap();
+		let second = shmat(shmid, 0, 0).unwrap();
+		assert_ne!(first, second);
+		unsafe {
+			*(first as *mut u8) = 42;
+			assert_eq!(*(second as *const u8), 42);
+		}
+		let mut ds = ShmidDs::default();
+		shmctl(shmid, IPC_STAT, &mut ds).unwrap();
+		assert_eq!((ds.shm_segsz, ds.shm_nattch), (0x1800, 2));
+		shmctl(shmid, IPC_RMID, &mut ds).unwrap();
+		assert_eq!(shmat(shmid, 0, 0).err(), Some(ErrNo::EIDRM));
+		shmdt(first).unwrap();
+		shmctl(shmid, IPC_STAT, &mut ds).unwrap();
+		assert_eq!(ds.shm_nattch, 1);
+		assert_ne!(ds.shm_perm.mode & SHM_DEST, 0);
+		unsafe { assert_eq!(*(second as *const u8), 42) };
+		shmdt(second).unwrap();
+		assert_eq!(shmctl(shmid, IPC_STAT, &mut ds).err(), Some(ErrNo::EINVAL));
+		assert_eq!(shmdt(second).err(), Some(ErrNo::EINVAL));
+		assert_eq!(used_pages, physmap_as_mut().used);
+	}
+
+	#[sys_macros::test_case]
+	fn shm_segment_keys() {
+		let shmid = shmget(0x42, 0x1000, IPC_CREAT | 0o600).unwrap();
+		assert_eq!(shmget(0x42, 0, 0), Ok(shmid));
+		assert_eq!(shmget(0x42, 0x2000, 0), Err(ErrNo::EINVAL));
+		assert_eq!(
+			shmget(0x42, 0x1000, IPC_CREAT | IPC_EXCL),
+			Err(ErrNo::EEXIST)
+		);
+		assert_eq!(shmget(0x43, 0x1000, 0), Err(ErrNo::ENOENT));
+		shmctl(shmid, IPC_RMID, &mut ShmidDs::default()).unwrap();
+		assert_eq!(shmget(0x42, 0, 0), Err(ErrNo::ENOENT));
+	}
+}
@@ -0,0 +1,483 @@
+//! Swap areas, pages of user processes are written to them when physical memory runs low and
+//! read back on their next access
+
+use crate::alloc::boxed::Box;
+use crate::alloc::vec::Vec;
+use crate::cli::DISKNO;
+use crate::disk::DiskIO;
+use crate::errno::ErrNo;
+use crate::fs::ext2file::{with_ext2, with_ext2_mut};
+use crate::memory::paging::{buddy, fault_page, swap_page, swapped_pages};
+use crate::memory::VirtAddr;
+use crate::proc::process::PROCESS_TREE;
+use crate::spin::Mutex;
+
+/// Areas used at once, the area of a swapped out page is stored in 3 bits of its entry
+pub const MAX_SWAPFILES: usize = 8;
+/// Slots of an area, the slot of a swapped out page is stored in the 20 upper bits of its entry
+const MAX_SLOTS: usize = 1 << 20;
+/// Frames kept free by swapping out pages, for the pages faulted in and the kernel heap
+const FREE_PAGES_LOW: usize = 128;
+
+const NO_AREA: Option<SwapArea> = None;
+static SWAP: Mutex<[Option<SwapArea>; MAX_SWAPFILES]> =
+	Mutex::new([NO_AREA; MAX_SWAPFILES]);
+
+/// Storage of a swap area, its previous content is lost
+pub enum SwapBacking {
+	/// Whole disk, diskno is its index in disk::discover
+	Disk {
+		diskno: usize,
+		disk:   Box<dyn DiskIO + Send>
+	},
+	/// Regular file of the mounted filesystem. The first sector of each of its blocks is found
+	/// at swapon, pages are then accessed on the disk without going through the filesystem
+	File {
+		inodeno: usize,
+		sectors: Vec<u32>,
+		bsize:   usize
+	}
+}
+
+/// Pages of an area are stored in slots. A swapped out page keeps its slot in its entry, the
+/// pages sharing it after a fork each hold a reference
+struct SwapArea {
+	backing:     SwapBacking,
+	sector_size: usize,
+	/// References to each slot, 0 if it is free
+	refs:        Vec<u16>,
+	used:        usize,
+	/// Set by swapoff, no page is swapped out to the area anymore
+	closing:     bool
+}
+
+impl SwapArea {
+	fn is_full(&self) -> bool {
+		self.closing || self.used == self.refs.len()
+	}
+
+	fn alloc_slot(&mut self) -> Option<usize> {
+		let slot = self.refs.iter().position(|refs| *refs == 0)?;
+		self.refs[slot] = 1;
+		self.used += 1;
+		Some(slot)
+	}
+
+	fn release(&mut self, slot: usize) {
+		if let Some(refs) = self.refs.get_mut(slot).filter(|refs| **refs != 0) {
+			*refs -= 1;
+			if *refs == 0 {
+				self.used -= 1;
+			}
+		}
+	}
+
+	// Disk sectors of the part of slot from offset that is contiguous on the
+	// disk, up to the end of the page or of a block of a swap file.
+	// Return the first sector and the number of sectors
+	fn sectors(&self, slot: usize, offset: usize) -> (u32, usize) {
+		let pos = slot * 0x1000 + offset;
+		match &self.backing {
+			SwapBacking::Disk { .. } => (
+				(pos / self.sector_size) as u32,
+				(0x1000 - offset) / self.sector_size
+			),
+			SwapBacking::File { sectors, bsize, .. } => {
+				let len = core::cmp::min(bsize - pos % bsize, 0x1000 - offset);
+				(
+					sectors[pos / bsize]
+						+ ((pos % bsize) / self.sector_size) as u32,
+					len / self.sector_size
+				)
+			}
+		}
+	}
+
+	// Write the page at vaddr to slot, or read slot in it.
+	// Writes happen when memory runs low, maybe while the filesystem is in use
+	// so they fail instead of waiting for it
+	fn transfer(
+		&mut self,
+		slot: usize,
+		vaddr: VirtAddr,
+		write: bool
+	) -> Result<(), ()> {
+		let mut offset = 0;
+		while offset < 0x1000 {
+			let (lba, count) = self.sectors(slot, offset);
+			let buffer = vaddr + offset as VirtAddr;
+			match &mut self.backing {
+				SwapBacking::Disk { disk, .. } => {
+					transfer_sectors(disk.as_mut(), lba, count, buffer, write)
+				},
+				SwapBacking::File { .. } => {
+					let mut guard = match write {
+						true => DISKNO.try_lock().ok_or(())?,
+						false => DISKNO.lock()
+					};
+					let ext2 = guard.as_mut().ok_or(())?;
+					transfer_sectors(ext2.disk(), lba, count, buffer, write)
+				}
+			}?;
+			offset += count * self.sector_size;
+		}
+		Ok(())
+	}
+}
+
+fn transfer_sectors(
+	disk: &mut (dyn DiskIO + Send),
+	lba: u32,
+	count: usize,
+	buffer: VirtAddr,
+	write: bool
+) -> Result<(), ()> {
+	match write {
+		true => disk.write_sectors(count as u8, lba, buffer),
+		false => disk.read_sectors(count as u8, lba, buffer)
+	}
+	.map_err(|_| ())
+}
+
+/// Swap pages out to pages pages of backing, it can't be larger than the disk or the file.
+/// Return the index of the area
+pub fn swapon(backing: SwapBacking, pages: usize) -> Result<usize, ErrNo> {
+	let sector_size = match &backing {
+		SwapBacking::Disk { disk, .. } => disk.sector_size(),
+		SwapBacking::File { .. } => {
+			DISKNO.lock().as_ref().ok_or(ErrNo::ENOENT)?.sector_size()
+		},
+	};
+	if pages == 0 || sector_size == 0 || 0x1000 % sector_size != 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	// Allocated before locking the areas since the heap may grow
+	let area = SwapArea {
+		backing,
+		sector_size,
+		refs: crate::vec![0; core::cmp::min(pages, MAX_SLOTS)],
+		used: 0,
+		closing: false
+	};
+	let mut areas = SWAP.lock();
+	let in_use = areas.iter().flatten().any(|other| {
+		match (&other.backing, &area.backing) {
+			(
+				SwapBacking::Disk { diskno: a, .. },
+				SwapBacking::Disk { diskno: b, .. }
+			) => a == b,
+			(
+				SwapBacking::File { inodeno: a, .. },
+				SwapBacking::File { inodeno: b, .. }
+			) => a == b,
+			_ => false
+		}
+	});
+	if in_use {
+		return Err(ErrNo::EBUSY);
+	}
+	let index = areas.iter().position(Option::is_none).ok_or(ErrNo::EPERM)?;
+	areas[index] = Some(area);
+	Ok(index)
+}
+
+/// Swap pages out to the disk at index diskno of disk::discover, everything stored on it is
+/// lost. The disk of the mounted filesystem returns EBUSY
+pub fn swapon_disk(diskno: usize) -> Result<usize, ErrNo> {
+	if DISKNO
+		.lock()
+		.as_ref()
+		.is_some_and(|ext2| ext2.diskno == Some(diskno))
+	{
+		return Err(ErrNo::EBUSY);
+	}
+	let mut disks = crate::disk::discover();
+	if diskno >= disks.len() {
+		return Err(ErrNo::ENXIO);
+	}
+	let disk = disks.swap_remove(diskno);
+	let pages = disk.sector_count() * disk.sector_size() / 0x1000;
+	swapon(SwapBacking::Disk { diskno, disk }, pages)
+}
+
+/// Swap pages out to the regular file at path of the mounted filesystem, its content is lost.
+/// Only root can use it and the file must have no holes. The file can't be removed until
+/// swapoff since its blocks are accessed without the filesystem
+pub fn swapon_file(path: &str) -> Result<usize, ErrNo> {
+	let (inodeno, sectors, bsize) = with_ext2_mut(|ext2, ctx| {
+		if !ctx.is_root() {
+			return Err(ErrNo::EPERM);
+		}
+		let (inodeno, inode) = ext2.lookup(path, ctx, true)?;
+		if !inode.is_regular() {
+			return Err(ErrNo::EINVAL);
+		}
+		if ext2.is_swapfile(inodeno) {
+			return Err(ErrNo::EBUSY);
+		}
+		let sectors = ext2.file_sectors(&inode)?;
+		ext2.set_swapfile(inodeno, true);
+		Ok((inodeno, sectors, ext2.sblock.bsize()))
+	})?;
+	let pages = sectors.len() * bsize / 0x1000;
+	let res = swapon(SwapBacking::File { inodeno, sectors, bsize }, pages);
+	if res.is_err() {
+		release_swapfile(inodeno);
+	}
+	res
+}
+
+/// Allow the swap file inodeno to be removed again
+fn release_swapfile(inodeno: usize) {
+	if let Some(ext2) = DISKNO.lock().as_mut() {
+		ext2.set_swapfile(inodeno, false);
+	}
+}
+
+/// Stop swapping to area, the pages swapped out to it are read back first. Fails with ENOMEM
+/// if they don't fit in memory, the area is then still used
+pub fn swapoff(area: usize) -> Result<(), ErrNo> {
+	match SWAP.lock().get_mut(area).and_then(Option::as_mut) {
+		Some(area) => area.closing = true,
+		None => return Err(ErrNo::EINVAL)
+	}
+	for page in swapped_pages(area) {
+		balance();
+		if fault_page(page).is_err() {
+			if let Some(area) = SWAP.lock()[area].as_mut() {
+				area.closing = false;
+			}
+			return Err(ErrNo::ENOMEM);
+		}
+	}
+	// Dropped once unlocked
+	let area = SWAP.lock()[area].take();
+	if let Some(SwapBacking::File { inodeno, .. }) = area.map(|x| x.backing) {
+		release_swapfile(inodeno);
+	}
+	Ok(())
+}
+
+/// Stop swapping to the disk at index diskno of disk::discover, see swapoff
+pub fn swapoff_disk(diskno: usize) -> Result<(), ErrNo> {
+	swapoff(find_area(
+		|backing| matches!(backing, SwapBacking::Disk { diskno: x, .. } if *x == diskno)
+	)?)
+}
+
+/// Stop swapping to the file at path, only root can use it. See swapoff
+pub fn swapoff_file(path: &str) -> Result<(), ErrNo> {
+	let inodeno = with_ext2(|ext2, ctx| {
+		if !ctx.is_root() {
+			return Err(ErrNo::EPERM);
+		}
+		Ok(ext2.lookup(path, ctx, true)?.0)
+	})?;
+	swapoff(find_area(
+		|backing| matches!(backing, SwapBacking::File { inodeno: x, .. } if *x == inodeno)
+	)?)
+}
+
+fn find_area(f: impl Fn(&SwapBacking) -> bool) -> Result<usize, ErrNo> {
+	SWAP.lock()
+		.iter()
+		.position(|area| area.as_ref().is_some_and(|area| f(&area.backing)))
+		.ok_or(ErrNo::EINVAL)
+}
+
+/// Size and used pages of each area in use
+pub fn areas() -> Vec<(usize, usize, usize)> {
+	SWAP.lock()
+		.iter()
+		.enumerate()
+		.filter_map(|(index, area)| {
+			area.as_ref()
+				.map(|area| (index, area.refs.len(), area.used))
+		})
+		.collect()
+}
+
+/// Write the page at vaddr of the kernel address space to a free slot and free its frame, it
+/// is read back on its next access. No other page must share the frame.
+/// Fails without waiting if the areas are in use
+pub fn swap_out(vaddr: VirtAddr) -> Result<(), ()> {
+	let mut areas = SWAP.try_lock().ok_or(())?;
+	for (index, area) in areas.iter_mut().enumerate() {
+		let Some(area) = area.as_mut().filter(|area| !area.is_full()) else {
+			continue;
+		};
+		let Some(slot) = area.alloc_slot() else {
+			continue;
+		};
+		if area.transfer(slot, vaddr, true).is_ok()
+			&& swap_page(vaddr, index, slot).is_ok()
+		{
+			return Ok(());
+		}
+		area.release(slot);
+	}
+	Err(())
+}
+
+/// Read slot of area in the page at vaddr and drop the reference of the page to it
+pub fn swap_in(area: usize, slot: usize, vaddr: VirtAddr) -> Result<(), ()> {
+	let mut areas = SWAP.lock();
+	let area = areas.get_mut(area).and_then(Option::as_mut).ok_or(())?;
+	area.transfer(slot, vaddr, false)?;
+	area.release(slot);
+	Ok(())
+}
+
+/// Drop a reference to slot of area, for a swapped out page that is freed
+pub fn free_slot(area: usize, slot: usize) {
+	if let Some(area) = SWAP.lock().get_mut(area).and_then(Option::as_mut) {
+		area.release(slot);
+	}
+}
+
+/// Add a reference to slot of area, for a swapped out page shared by a fork
+pub fn dup_slot(area: usize, slot: usize) -> Result<(), ()> {
+	let mut areas = SWAP.lock();
+	let area = areas.get_mut(area).and_then(Option::as_mut).ok_or(())?;
+	match area.refs.get_mut(slot) {
+		Some(refs) if *refs != 0 && *refs != u16::MAX => *refs += 1,
+		_ => return Err(())
+	}
+	Ok(())
+}
+
+/// Swap out pages of user processes until FREE_PAGES_LOW frames are free, see
+/// Process::swap_out_page. Nothing is done without swap areas
+pub fn balance() {
+	while buddy::physmap_as_mut().free() < FREE_PAGES_LOW && reclaim_page() {}
+}
+
+// Swap out a page of a user process, the pages that were not accessed since
+// the last scan are taken first. Processes and zones in use are skipped
+fn reclaim_page() -> bool {
+	if SWAP.lock().iter().flatten().all(SwapArea::is_full) {
+		return false;
+	}
+	for all in [false, true] {
+		for process in unsafe { PROCESS_TREE.values() } {
+			let Some(mut process) = process.try_lock() else {
+				continue;
+			};
+			if process.swap_out_page(all) {
+				return true;
+			}
+		}
+	}
+	false
+}
+
+#[cfg(test)]
+mod test {
+	use super::{areas, swap_out, swapoff, swapon, swapon_disk, SwapBacking};
+	use crate::alloc::boxed::Box;
+	use crate::cli::DISKNO;
+	use crate::disk::ramdisk::RamDisk;
+	use crate::errno::ErrNo;
+	use crate::fs::ext2::mkfs::{ramdisk_fs, MkfsOptions};
+	use crate::memory::paging::{page_frame, page_is_swapped};
+	use crate::memory::{MemoryZone, TypeZone, WRITABLE};
+
+	fn ramdisk_area(pages: usize) -> usize {
+		let disk = Box::new(RamDisk::new(pages * 0x1000));
+		// Past the indexes of discovered disks
+		swapon(SwapBacking::Disk { diskno: 42, disk }, pages).unwrap()
+	}
+
+	fn used(area: usize) -> usize {
+		areas().iter().find(|x| x.0 == area).unwrap().2
+	}
+
+	#[sys_macros::test_case]
+	fn swap_out_and_in() {
+		let area = ramdisk_area(4);
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x2000, WRITABLE);
+		zone.populate().unwrap();
+		zone[..0x2000].fill(0x42);
+		assert!(swap_out(zone.offset).is_ok());
+		assert!(page_is_swapped(zone.offset));
+		assert!(page_frame(zone.offset).is_none());
+		assert_eq!(used(area), 1);
+		assert!(zone.fault_index(0).is_ok());
+		assert!(!page_is_swapped(zone.offset));
+		assert!(zone.iter().all(|byte| *byte == 0x42));
+		assert_eq!(used(area), 0);
+		drop(zone);
+		assert!(swapoff(area).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn swap_slot_freed_with_zone() {
+		let area = ramdisk_area(4);
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x2000, WRITABLE);
+		zone.populate().unwrap();
+		assert!(swap_out(zone.offset).is_ok());
+		assert!(swap_out(zone.offset + 0x1000).is_ok());
+		assert_eq!(used(area), 2);
+		drop(zone);
+		assert_eq!(used(area), 0);
+		assert!(swapoff(area).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn swap_area_full() {
+		let area = ramdisk_area(1);
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x2000, WRITABLE);
+		zone.populate().unwrap();
+		assert!(swap_out(zone.offset).is_ok());
+		assert!(swap_out(zone.offset + 0x1000).is_err());
+		assert!(page_frame(zone.offset + 0x1000).is_some());
+		drop(zone);
+		assert!(swapoff(area).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn swap_out_next_area() {
+		let first = ramdisk_area(1);
+		let disk = Box::new(RamDisk::new(0x1000));
+		let second = swapon(SwapBacking::Disk { diskno: 43, disk }, 1).unwrap();
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x3000, WRITABLE);
+		zone.populate().unwrap();
+		assert!(swap_out(zone.offset).is_ok());
+		assert!(swap_out(zone.offset + 0x1000).is_ok());
+		assert_eq!((used(first), used(second)), (1, 1));
+		assert!(swap_out(zone.offset + 0x2000).is_err());
+		drop(zone);
+		assert!(swapoff(first).is_ok());
+		assert!(swapoff(second).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn swapoff_reads_pages_back() {
+		let area = ramdisk_area(4);
+		let mut zone = MemoryZone::reserve(TypeZone::Anon, 0x1000, WRITABLE);
+		zone.populate().unwrap();
+		zone[..0x1000].fill(0x24);
+		assert!(swap_out(zone.offset).is_ok());
+		assert!(swapoff(area).is_ok());
+		assert!(page_frame(zone.offset).is_some());
+		assert!(zone.iter().all(|byte| *byte == 0x24));
+		assert!(swapoff(area).is_err());
+	}
+
+	#[sys_macros::test_case]
+	fn swapon_same_disk() {
+		let area = ramdisk_area(1);
+		let disk = Box::new(RamDisk::new(0x1000));
+		assert!(swapon(SwapBacking::Disk { diskno: 42, disk }, 1).is_err());
+		assert!(swapoff(area).is_ok());
+	}
+
+	#[sys_macros::test_case]
+	fn swapon_mounted_disk() {
+		let mut ext2 = ramdisk_fs(&MkfsOptions::default());
+		ext2.diskno = Some(42);
+		let disk = DISKNO.lock().replace(ext2);
+		assert_eq!(swapon_disk(42), Err(ErrNo::EBUSY));
+		*DISKNO.lock() = disk;
+	}
+}
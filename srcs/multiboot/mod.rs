@@ -1,6 +1,6 @@
 //!  This module aim to parse mutliboot specification
 
-use crate::memory::paging::bitmap;
+use crate::memory::paging::buddy;
 use crate::memory::PhysAddr;
 use crate::{kprint, kprintln};
 
@@ -117,7 +117,9 @@ struct LoadBasePhys {
 	load_base_addr: u32
 }
 
-pub unsafe fn claim_multiboot() {
+/// Give the available RAM of the memory map to the physical page allocator, the memory above
+/// 4GiB can't be addressed
+pub unsafe fn add_available_memory() {
 	let mut ptr: *const u8 = (multiboot_ptr as *const u8).offset(8);
 	let mut tag_ptr: *const TagHeader = ptr as *const TagHeader;
 
@@ -132,16 +134,15 @@ pub unsafe fn claim_multiboot() {
 				let mut i: u32 = 0;
 
 				while i < entry_number {
-					if (*mmap_entry).mtype == 2 {
-						crate::dprintln!(
-							"{} {}",
-							(*mmap_entry).baseaddr as PhysAddr / 4096,
-							(*mmap_entry).length as usize / 4096
-						);
-						// Force because it could be already mapped by BIOS
-						bitmap::physmap_as_mut().force_claim_range(
-							(*mmap_entry).baseaddr as PhysAddr,
-							(*mmap_entry).length as usize / 4096
+					let start: u64 = ((*mmap_entry).baseaddr + 0xfff) & !0xfff;
+					let end: u64 = core::cmp::min(
+						(*mmap_entry).baseaddr + (*mmap_entry).length,
+						1 << 32
+					) & !0xfff;
+					if (*mmap_entry).mtype == 1 && start < end {
+						buddy::physmap_as_mut().add_range(
+							start as PhysAddr,
+							((end - start) / 4096) as usize
 						);
 					}
 					mmap_entry = mmap_entry.add(1);
@@ -5,6 +5,11 @@ use crate::pic::PIC1_IRQ_OFFSET;
 pub fn handler(reg: &Registers, int_no: usize) {
 	if crate::keyboard::keyboard_event() {
 		if let Some(event) = crate::keyboard::handle_event() {
+			if let (crate::cli::Input::Ascii(c), _) = event {
+				if c != '\0' {
+					crate::fs::tty::push_input(c);
+				}
+			}
 			match &mut *crate::cli::INPUT_BUFFER.lock() {
 				Some(buffer) => buffer.push(event),
 				None => {}
@@ -5,7 +5,6 @@ use crate::utils::arcm::KArcm;
 use crate::vec::Vec;
 use crate::wrappers::{_cli, _rst};
 use crate::{VirtAddr, KSTACK_ADDR};
-use alloc::sync::Arc;
 use core::ffi::CStr;
 
 use crate::memory::paging::PAGE_WRITABLE;
@@ -15,14 +14,16 @@ use crate::memory::paging::page_directory;
 pub mod process;
 pub mod signal;
 pub mod task;
+pub mod wait_queue;
 
 #[cfg(test)]
 pub mod test;
 
 use process::{Pid, Process, PROCESS_TREE};
+use signal::SignalType;
 use task::{schedule_task, Task, TASKLIST};
 
-use crate::syscalls::exit::__W_EXITCODE;
+use crate::syscalls::exit::{__W_EXITCODE, __W_STOPCODE};
 
 pub type Id = i32;
 
@@ -39,6 +40,20 @@ pub unsafe extern "C" fn _exit(status: i32) -> ! {
 	// Never goes there
 }
 
+/// Terminate the running process as if it was killed by sigtype, for faults it can't recover
+/// from
+pub unsafe fn kill_running(sigtype: SignalType) -> ! {
+	_cli();
+	{
+		let task: Task = TASKLIST.pop_front().unwrap();
+		let pid = task.process.lock().pid;
+		Process::zombify(pid, __W_STOPCODE!(sigtype as i32));
+	}
+	_rst();
+	schedule_task()
+	// Never goes there
+}
+
 #[naked]
 #[no_mangle]
 pub unsafe extern "C" fn wrapper_fn(fn_addr: VirtAddr) {
@@ -81,12 +96,7 @@ pub unsafe extern "C" fn exec_fn(
 		parent.stack.kphys
 	);
 	// Copying all open fd from parent. Should not copy 0 and 1 but create new one instead
-	for i in 0..process::MAX_FD {
-		process.fds[i] = match &parent.fds[i] {
-			Some(fd) => Some(Arc::clone(&fd)),
-			None => None
-		};
-	}
+	process.inherit_fds(&parent, true);
 
 	// init_fn_task - Can't move to another function ??
 	let sum: usize = args_size.iter().sum();
@@ -20,20 +20,31 @@ use crate::proc::Id;
 use crate::errno::ErrNo;
 
 use crate::memory::paging::page_directory::PageDirectory;
-use crate::memory::paging::page_table::PageTable;
+use crate::memory::paging::page_table::{lazy_entry, PageTable};
 use crate::memory::paging::{
+	buddy,
+	fault_page,
 	page_directory,
+	page_is_swapped,
+	PAGE_ACCESSED,
 	PAGE_PRESENT,
 	PAGE_USER,
 	PAGE_WRITABLE
 };
-use crate::memory::PhysAddr;
+use crate::memory::{swap, PhysAddr, VirtAddr};
 use crate::utils::arcm::Arcm;
 
-use crate::user::{USER_HEAP_ADDR, USER_STACK_ADDR};
+use crate::user::{
+	USER_HEAP_ADDR,
+	USER_HEAP_LIMIT,
+	USER_MMAP_ADDR,
+	USER_MMAP_END,
+	USER_STACK_ADDR
+};
 use crate::KSTACK_ADDR;
 
-use crate::fs::FileInfo;
+use crate::fs::ext2::namei::ROOT_INODE;
+use crate::fs::{FileInfo, FD_CLOEXEC};
 use alloc::sync::Arc;
 
 pub type Pid = Id;
@@ -61,14 +72,24 @@ pub struct Process {
 	pub childs:          Vec<KArcm<Process>>,
 	pub stack:           MemoryZone,
 	pub heap:            MemoryZone,
+	/// Size of the heap zone up to the program break
+	pub brk:             usize,
+	/// Lowest brk, the program is loaded below it
+	pub brk_min:         usize,
 	pub kernel_stack:    MemoryZone,
 	pub mem_map:         LinkedList<Arcm<MemoryZone>>,
 	pub fds:             [Option<Arc<FileInfo>>; MAX_FD],
+	pub fd_flags:        [u32; MAX_FD],
 	pub signals:         Vec<Signal>,
 	pub signal_handlers: Vec<SignalHandler>,
 	pub page_tables:     Vec<&'static mut PageTable>,
 	pub pd:              *mut PageDirectory,
-	pub owner:           Id
+	pub owner:           Id,
+	pub group:           Id,
+	/// Inode of the working directory, relative paths start from it
+	pub cwd:             usize,
+	/// Inode of the root directory set by chroot
+	pub root:            usize
 }
 
 const DEFAULT_FILE: Option<Arc<FileInfo>> = None;
@@ -82,14 +103,20 @@ impl Process {
 			childs:          Vec::new(),
 			stack:           MemoryZone::new(),
 			heap:            MemoryZone::new(),
+			brk:             0,
+			brk_min:         0,
 			kernel_stack:    MemoryZone::new(),
 			mem_map:         LinkedList::new(),
 			fds:             [DEFAULT_FILE; MAX_FD],
+			fd_flags:        [0; MAX_FD],
 			signals:         Vec::new(),
 			signal_handlers: Vec::new(),
 			page_tables:     Vec::new(),
 			pd:              0x0 as *mut PageDirectory,
-			owner:           0
+			owner:           0,
+			group:           0,
+			cwd:             ROOT_INODE,
+			root:            ROOT_INODE
 		}
 	}
 
@@ -126,15 +153,82 @@ impl Process {
 		self.state = Status::Run;
 		self.parent = Some(parent.clone());
 		self.owner = parent.lock().owner;
+		self.group = parent.lock().group;
+		self.cwd = parent.lock().cwd;
+		self.root = parent.lock().root;
 		NEXT_PID += 1;
 	}
 
+	/// Copy open file descriptors of parent along with their flags.
+	/// When exec is true, descriptors marked FD_CLOEXEC are not inherited
+	pub fn inherit_fds(&mut self, parent: &Process, exec: bool) {
+		for i in 0..MAX_FD {
+			if exec && parent.fd_flags[i] & FD_CLOEXEC != 0 {
+				continue;
+			}
+			self.fds[i] = parent.fds[i].clone();
+			self.fd_flags[i] = parent.fd_flags[i];
+		}
+	}
+
+	/// Zones of user processes are faulted in on access, kernel tasks would fault on their own
+	/// stack so their zones are backed immediately
+	fn new_zone(
+		ztype: TypeZone,
+		size: usize,
+		flags: u32,
+		kphys: bool
+	) -> MemoryZone {
+		match flags & PAGE_USER != 0 && !kphys {
+			true => MemoryZone::reserve(ztype, size, flags),
+			false => MemoryZone::init(ztype, size, flags, kphys)
+		}
+	}
+
 	pub fn setup_stack(&mut self, size: usize, flags: u32, kphys: bool) {
-		self.stack = MemoryZone::init(TypeZone::Stack, size, flags, kphys);
+		self.stack = Self::new_zone(TypeZone::Stack, size, flags, kphys);
 	}
 
 	pub fn setup_heap(&mut self, size: usize, flags: u32, kphys: bool) {
-		self.heap = MemoryZone::init(TypeZone::Heap, size, flags, kphys);
+		self.heap = Self::new_zone(TypeZone::Heap, size, flags, kphys);
+		self.brk = size;
+		self.brk_min = size;
+	}
+
+	/// Move the program break to addr, the heap zone grows or shrinks to the page holding it.
+	/// The break can't go below brk_min nor make the heap larger than USER_HEAP_LIMIT or reach
+	/// another zone. Return the new program break, or the current one if it can't be moved
+	pub fn set_brk(&mut self, addr: VirtAddr) -> VirtAddr {
+		let start = self.heap.vaddr;
+		let current = start + self.brk as VirtAddr;
+		if self.heap.size == 0 {
+			return current;
+		}
+		let brk = match (addr as usize).checked_sub(start as usize) {
+			Some(brk) if brk >= self.brk_min && brk <= USER_HEAP_LIMIT => brk,
+			_ => return current
+		};
+		let (old, size) = (self.heap.size, (brk + 0xfff) & !0xfff);
+		if size > old {
+			let end = start + old as VirtAddr;
+			if !self.range_free(end, size - old)
+				|| self.heap.grow(size - old).is_err()
+			{
+				return current;
+			}
+			if self.map_heap().is_err() {
+				let _ = self.heap.remap(0, old, 0);
+				return current;
+			}
+		} else if size < old {
+			self.unmap_pages(start + size as VirtAddr, (old - size) / 0x1000);
+			if self.heap.remap(0, size, 0).is_err() {
+				let _ = self.map_heap();
+				return current;
+			}
+		}
+		self.brk = brk;
+		start + brk as VirtAddr
 	}
 
 	pub fn setup_kernel_stack(&mut self, flags: u32) {
@@ -142,17 +236,33 @@ impl Process {
 			MemoryZone::init(TypeZone::Stack, 0x1000, flags, false);
 	}
 
+	/// Frames of user zones are shared copy-on-write with the parent, whose pages become
+	/// read-only until written to. Zones of kernel tasks are copied since the kernel can't fault
+	/// on them. Shared zones of mem_map are kept as is
 	pub unsafe fn copy_mem(&mut self, parent: &mut Process) {
-		copy_nonoverlapping(
-			parent.stack.offset as *const u8,
-			self.stack.offset as *mut u8,
-			self.stack.size
-		);
-		copy_nonoverlapping(
-			parent.heap.offset as *const u8,
-			self.heap.offset as *mut u8,
-			self.heap.size
-		);
+		for (zone, src) in [
+			(&mut self.stack, &parent.stack),
+			(&mut self.heap, &parent.heap)
+		] {
+			match src.lazy {
+				true => zone.share_from(src),
+				false => zone.copy_from(src)
+			}
+			.expect("unable to allocate pages for memory zone");
+		}
+		for zone in parent.mem_map.iter() {
+			let guard = zone.lock();
+			let zone = match guard.shared {
+				true => zone.clone(),
+				false => Arcm::new(
+					guard
+						.copy_on_write()
+						.expect("unable to allocate pages for memory zone")
+				)
+			};
+			self.mem_map.push_back(zone);
+		}
+		parent.protect_shared();
 		copy_nonoverlapping(
 			parent.kernel_stack.offset as *const u8,
 			self.kernel_stack.offset as *mut u8,
@@ -160,6 +270,34 @@ impl Process {
 		);
 	}
 
+	/// Map again the pages of the zones that have a frame, so pages shared copy-on-write become
+	/// read-only in the page directory of the process
+	fn protect_shared(&mut self) {
+		let pages: Vec<(VirtAddr, PhysAddr, u32)> = [&self.heap, &self.stack]
+			.into_iter()
+			.flat_map(|zone| {
+				(0..zone.pages()).filter_map(move |index| {
+					let page = zone.vaddr + (index * 0x1000) as VirtAddr;
+					zone.frame(index)
+						.map(|paddr| (page, paddr, zone.page_flags(page)))
+				})
+			})
+			.collect();
+		for (page, paddr, flags) in pages {
+			let _ = self.map_page(page, paddr, flags);
+		}
+		self.map_mem_map();
+	}
+
+	/// Map the zones of mem_map in the page directory of the process
+	pub fn map_mem_map(&mut self) {
+		let zones: Vec<Arcm<MemoryZone>> =
+			self.mem_map.iter().cloned().collect();
+		for zone in zones {
+			let _ = self.map_zone_pages(&zone.lock());
+		}
+	}
+
 	pub unsafe fn zombify(pid: Pid, wstatus: i32) {
 		let binding = Process::search_from_pid(pid).unwrap();
 		let binding_parent = {
@@ -222,17 +360,16 @@ impl Process {
 		}
 		let mut process = binding.lock();
 		if process.owner != 0 {
-			use crate::memory::paging::bitmap;
 			let pd = &mut *process.pd;
 			for i in &process.page_tables {
 				let vaddr = i.get_vaddr() as usize;
-				bitmap::physmap_as_mut().free_page(get_paddr!(vaddr));
+				buddy::physmap_as_mut().free_page(get_paddr!(vaddr));
 				page_directory
 					.get_page_table(vaddr >> 22)
 					.set_entry((vaddr & 0x3ff000) >> 12, 0);
 			}
 			let vaddr = pd.get_vaddr() as usize;
-			bitmap::physmap_as_mut().free_page(get_paddr!(vaddr));
+			buddy::physmap_as_mut().free_page(get_paddr!(vaddr));
 			page_directory
 				.get_page_table(vaddr >> 22)
 				.set_entry((vaddr & 0x3ff000) >> 12, 0);
@@ -334,17 +471,10 @@ impl Process {
 				| parent.kernel_stack.flags
 				| PAGE_USER | PAGE_PRESENT
 		);
-		// Setup stack and heap
-		process_heap.new_index_frame(
-			(USER_HEAP_ADDR as usize & 0x3ff000) >> 12,
-			get_paddr!(self.heap.offset),
-			PAGE_WRITABLE | PAGE_USER
-		);
-		process_stack.new_index_frame(
-			(USER_STACK_ADDR as usize & 0x3ff000) >> 12,
-			get_paddr!(self.stack.offset),
-			PAGE_WRITABLE | PAGE_USER
-		);
+		// Setup stack and heap, the stack ends at USER_STACK_ADDR
+		self.heap.vaddr = USER_HEAP_ADDR;
+		self.stack.vaddr = USER_STACK_ADDR + 1 - self.stack.size as VirtAddr;
+		Self::map_zone(process_stack, &self.stack);
 		process_kernel_stack.new_index_frame(
 			(TASK_STACK.offset as usize & 0x3ff000) >> 12,
 			get_paddr!(TASK_STACK.offset),
@@ -359,10 +489,415 @@ impl Process {
 		self.page_tables.push(process_heap);
 		self.page_tables.push(process_stack);
 		self.page_tables.push(process_kernel_stack);
+		drop(parent);
+		// The heap may have grown past its page table
+		self.map_heap().expect("unable to map the heap");
 		refresh_tlb!();
 		page_dir
 	}
 
+	/// Map the pages of zone that have a frame in page_table, read-only if they are shared
+	/// copy-on-write, the others are reserved to be faulted in. The zone must fit in page_table
+	fn map_zone(page_table: &mut PageTable, zone: &MemoryZone) {
+		for i in 0..zone.pages() {
+			let page = zone.vaddr + (i * 0x1000) as VirtAddr;
+			let index = (page as usize & 0x3ff000) >> 12;
+			match zone.frame(i) {
+				Some(paddr) => page_table.new_index_frame(
+					index,
+					paddr,
+					(zone.page_flags(page) & PAGE_WRITABLE) | PAGE_USER
+				),
+				None => page_table.set_entry(
+					index,
+					lazy_entry((zone.flags & PAGE_WRITABLE) | PAGE_USER)
+				)
+			}
+		}
+	}
+
+	/// Page table of the process mapping page, it is created if needed when create is set.
+	/// Tables the process shares with the kernel are not given
+	fn page_table(
+		&mut self,
+		page: VirtAddr,
+		create: bool
+	) -> Result<&mut PageTable, ()> {
+		if self.pd.is_null() {
+			return Err(());
+		}
+		let pd_index = page as usize >> 22;
+		let entry = unsafe { (*self.pd).get_entry(pd_index) };
+		if entry.get_present() == 0 {
+			if !create {
+				return Err(());
+			}
+			let page_table: &'static mut PageTable = PageTable::new();
+			unsafe {
+				(*self.pd).set_entry(
+					pd_index,
+					get_paddr!(page_table as *const _)
+						| PAGE_WRITABLE | PAGE_USER
+						| PAGE_PRESENT
+				);
+			}
+			self.page_tables.push(page_table);
+			return self.page_tables.last_mut().map(|pt| &mut **pt).ok_or(());
+		}
+		self.page_tables
+			.iter_mut()
+			.find(
+				|pt| unsafe { get_paddr!(pt.get_vaddr()) } == entry.get_paddr()
+			)
+			.map(|pt| &mut **pt)
+			.ok_or(())
+	}
+
+	/// Map the frame paddr at page in the page directory of the process, pages without
+	/// PAGE_USER are only reachable by the kernel and pages with PAGE_ACCESSED are seen as
+	/// accessed by swap_out_page.
+	/// Processes without their own page directory use the kernel one, where the frame is
+	/// already mapped by its zone
+	fn map_page(
+		&mut self,
+		page: VirtAddr,
+		paddr: PhysAddr,
+		flags: u32
+	) -> Result<(), ()> {
+		if self.pd.is_null() {
+			return Ok(());
+		}
+		self.page_table(page, true)?.new_index_frame(
+			(page as usize & 0x3ff000) >> 12,
+			paddr,
+			flags & (PAGE_WRITABLE | PAGE_USER | PAGE_ACCESSED)
+		);
+		Ok(())
+	}
+
+	/// Map every page of zone in the page directory of the process, pages without a frame are
+	/// reserved to be faulted in
+	pub fn map_zone_pages(&mut self, zone: &MemoryZone) -> Result<(), ()> {
+		if self.pd.is_null() {
+			return Ok(());
+		}
+		for index in 0..zone.pages() {
+			let page = zone.vaddr + (index * 0x1000) as VirtAddr;
+			let flags = zone.page_flags(page) & (PAGE_WRITABLE | PAGE_USER);
+			let entry = match zone.frame(index) {
+				Some(paddr) => paddr | flags | PAGE_PRESENT,
+				None => lazy_entry(flags)
+			};
+			self.page_table(page, true)?
+				.set_entry((page as usize & 0x3ff000) >> 12, entry);
+		}
+		Ok(())
+	}
+
+	/// Map the pages of the heap zone in the page directory of the process
+	fn map_heap(&mut self) -> Result<(), ()> {
+		let heap = core::mem::replace(&mut self.heap, MemoryZone::new());
+		let res = self.map_zone_pages(&heap);
+		self.heap = heap;
+		res
+	}
+
+	/// Remove pages of the process address space from its page directory, their frames belong
+	/// to memory zones
+	pub fn unmap_pages(&mut self, vaddr: VirtAddr, pages: usize) {
+		for index in 0..pages {
+			let page = vaddr + (index * 0x1000) as VirtAddr;
+			if let Ok(page_table) = self.page_table(page, false) {
+				page_table.set_entry((page as usize & 0x3ff000) >> 12, 0);
+			}
+		}
+	}
+
+	/// Check that no zone of the process nor page its page directory maps for the kernel is in
+	/// [vaddr, vaddr + size)
+	pub fn range_free(&self, vaddr: VirtAddr, size: usize) -> bool {
+		let end = match (vaddr as usize).checked_add(size) {
+			Some(end) => end,
+			None => return false
+		};
+		let overlaps = |zone: &MemoryZone| {
+			(zone.vaddr as usize) < end
+				&& (vaddr as usize) < zone.vaddr as usize + zone.size
+		};
+		if overlaps(&self.heap)
+			|| overlaps(&self.stack)
+			|| self.mem_map.iter().any(|zone| overlaps(&zone.lock()))
+		{
+			return false;
+		}
+		if self.pd.is_null() {
+			return true;
+		}
+		(vaddr as usize..end).step_by(0x1000).all(|page| {
+			let entry = unsafe { (*self.pd).get_entry(page >> 22) };
+			entry.get_present() == 0
+				|| self.page_tables.iter().any(|pt| {
+					let pt_paddr = unsafe { get_paddr!(pt.get_vaddr()) };
+					pt_paddr == entry.get_paddr()
+						&& pt.entries[(page & 0x3ff000) >> 12].is_unused()
+				})
+		})
+	}
+
+	/// End of [vaddr, vaddr + size) if the range is inside the address space of the process,
+	/// below USER_MMAP_END for processes with their own page directory
+	pub fn range_end(&self, vaddr: VirtAddr, size: usize) -> Option<usize> {
+		let end = (vaddr as usize).checked_add(size)?;
+		match self.pd.is_null() || end <= USER_MMAP_END as usize {
+			true => Some(end),
+			false => None
+		}
+	}
+
+	/// Find size free bytes in the mapping area of the process address space, at hint if it is
+	/// free
+	pub fn get_unmapped_area(
+		&self,
+		hint: VirtAddr,
+		size: usize
+	) -> Option<VirtAddr> {
+		let size = (size + 0xfff) & !0xfff;
+		if hint != 0 && hint & 0xfff == 0 && self.range_free(hint, size) {
+			return Some(hint);
+		}
+		(USER_MMAP_ADDR as usize..USER_MMAP_END as usize)
+			.step_by(0x1000)
+			.take_while(|addr| addr + size <= USER_MMAP_END as usize)
+			.find(|addr| self.range_free(*addr as VirtAddr, size))
+			.map(|addr| addr as VirtAddr)
+	}
+
+	/// Zone of mem_map holding exactly [vaddr, vaddr + size), the parts of the zone containing
+	/// vaddr around that range are split in their own zones
+	pub fn split_zone(
+		&mut self,
+		vaddr: VirtAddr,
+		size: usize
+	) -> Result<Arcm<MemoryZone>, ErrNo> {
+		let zone = self
+			.mem_map
+			.iter()
+			.find(|zone| zone.lock().contains(vaddr))
+			.cloned()
+			.ok_or(ErrNo::EFAULT)?;
+		let mut guard = zone.lock();
+		let start = (vaddr - guard.vaddr) as usize;
+		if start.checked_add(size).map_or(true, |end| end > guard.size) {
+			return Err(ErrNo::EFAULT);
+		}
+		let zone = match guard.split(start) {
+			Ok(upper) => Arcm::new(upper),
+			Err(_) => zone.clone()
+		};
+		drop(guard);
+		if let Ok(upper) = zone.lock().split(size) {
+			self.mem_map.push_back(Arcm::new(upper));
+		}
+		if start != 0 {
+			self.mem_map.push_back(zone.clone());
+		}
+		Ok(zone)
+	}
+
+	/// Remove the pages of [vaddr, vaddr + size) from the zones of mem_map and from the page
+	/// directory, zones left empty are dropped. Fails with EINVAL if the range leaves the address
+	/// space of the process, see range_end
+	pub fn unmap_zones(
+		&mut self,
+		vaddr: VirtAddr,
+		size: usize
+	) -> Result<(), ErrNo> {
+		let end = self.range_end(vaddr, size).ok_or(ErrNo::EINVAL)?;
+		let mut kept = LinkedList::new();
+		while let Some(zone) = self.mem_map.pop_front() {
+			let mut guard = zone.lock();
+			let (start, zone_end) =
+				(guard.vaddr as usize, guard.vaddr as usize + guard.size);
+			if zone_end <= vaddr as usize || start >= end {
+				drop(guard);
+				kept.push_back(zone);
+				continue;
+			}
+			if zone_end > end {
+				if let Ok(upper) = guard.split(end - start) {
+					kept.push_back(Arcm::new(upper));
+				}
+			}
+			if start < vaddr as usize {
+				let _ = guard.split(vaddr as usize - start);
+				drop(guard);
+				kept.push_back(zone);
+			}
+		}
+		self.mem_map = kept;
+		self.unmap_pages(vaddr, size / 0x1000);
+		Ok(())
+	}
+
+	/// Give a frame to the page containing addr of the process address space if it is inside one
+	/// of its zones, and map it. On a write the page gets its own copy of a frame shared
+	/// copy-on-write. Return the kernel address of the page.
+	/// Pages of user processes are swapped out first when memory runs low. The process lock is
+	/// released while a zone of mem_map is filled and while a page is read back from swap since
+	/// they are read from the disk
+	pub fn fault_in(
+		binding: &KArcm<Process>,
+		addr: VirtAddr,
+		write: bool
+	) -> Result<VirtAddr, ()> {
+		let page = addr & !0xfff;
+		swap::balance();
+		let swapped = {
+			let process = binding.lock();
+			let kpage = [&process.heap, &process.stack]
+				.into_iter()
+				.find(|zone| zone.contains(page))
+				.map(|zone| zone.to_kernel(page));
+			kpage.filter(|kpage| page_is_swapped(*kpage))
+		};
+		if let Some(kpage) = swapped {
+			fault_page(kpage)?;
+		}
+		let resolved = {
+			let mut guard = binding.lock();
+			let process = &mut *guard;
+			let zone = [&mut process.heap, &mut process.stack]
+				.into_iter()
+				.find(|zone| zone.contains(page));
+			match zone {
+				Some(zone) => Some((
+					zone.fault(page, write)?,
+					zone.to_kernel(page),
+					zone.page_flags(page)
+				)),
+				None => None
+			}
+		};
+		let (paddr, kaddr, flags) = match resolved {
+			Some(resolved) => resolved,
+			None => {
+				let zone = binding
+					.lock()
+					.mem_map
+					.iter()
+					.find(|zone| zone.lock().contains(page))
+					.cloned()
+					.ok_or(())?;
+				let mut zone = zone.lock();
+				// Mappings without protection can't be accessed
+				if zone.flags & PAGE_USER == 0 {
+					return Err(());
+				}
+				(
+					zone.fault(page, write)?,
+					zone.to_kernel(page),
+					zone.page_flags(page)
+				)
+			}
+		};
+		// The page is in use, it isn't swapped out right away
+		binding
+			.lock()
+			.map_page(page, paddr, flags | PAGE_ACCESSED)?;
+		Ok(kaddr)
+	}
+
+	/// Swap out a page of the zones of the process. Unless all is set, pages accessed since the
+	/// last call are only marked as not accessed so the others go first. Pages sharing their
+	/// frame, pages of shared zones and zones in use stay in memory.
+	/// Return true if a page was swapped out
+	pub fn swap_out_page(&mut self, all: bool) -> bool {
+		if self.pd.is_null() {
+			return false;
+		}
+		for stack in [false, true] {
+			let pages = match stack {
+				false => self.heap.pages(),
+				true => self.stack.pages()
+			};
+			for index in 0..pages {
+				let zone = match stack {
+					false => &self.heap,
+					true => &self.stack
+				};
+				if let Some((page, kpage, flags)) = Self::swappable(zone, index)
+				{
+					if self.try_swap_out(page, kpage, flags, all) {
+						return true;
+					}
+				}
+			}
+		}
+		let zones: Vec<Arcm<MemoryZone>> =
+			self.mem_map.iter().cloned().collect();
+		for zone in zones {
+			let Some(zone) = zone.try_lock() else {
+				continue;
+			};
+			for index in 0..zone.pages() {
+				if let Some((page, kpage, flags)) =
+					Self::swappable(&zone, index)
+				{
+					if self.try_swap_out(page, kpage, flags, all) {
+						return true;
+					}
+				}
+			}
+		}
+		false
+	}
+
+	/// Page index of zone if it can be swapped out, with its kernel address and paging flags
+	fn swappable(
+		zone: &MemoryZone,
+		index: usize
+	) -> Option<(VirtAddr, VirtAddr, u32)> {
+		if !zone.lazy || zone.shared {
+			return None;
+		}
+		let paddr = zone.frame(index)?;
+		let page = zone.vaddr + (index * 0x1000) as VirtAddr;
+		match buddy::physmap_as_mut().page_refs(paddr) {
+			1 => Some((
+				page,
+				zone.to_kernel(page),
+				zone.flags & (PAGE_WRITABLE | PAGE_USER)
+			)),
+			_ => None
+		}
+	}
+
+	/// Swap out page held at kpage in the kernel address space, the process faults it back in
+	/// on its next access. Unless all is set an accessed page is only marked as not accessed
+	fn try_swap_out(
+		&mut self,
+		page: VirtAddr,
+		kpage: VirtAddr,
+		flags: u32,
+		all: bool
+	) -> bool {
+		let index = (page as usize & 0x3ff000) >> 12;
+		if let Ok(page_table) = self.page_table(page, false) {
+			let entry = page_table.entries[index];
+			if !all && entry.get_accessed() == 1 {
+				page_table.set_entry(index, entry.value & !PAGE_ACCESSED);
+				return false;
+			}
+		}
+		if swap::swap_out(kpage).is_err() {
+			return false;
+		}
+		if let Ok(page_table) = self.page_table(page, false) {
+			page_table.set_entry(index, lazy_entry(flags));
+		}
+		true
+	}
+
 	pub fn get_nb_process() -> usize {
 		unsafe { PROCESS_TREE.len() }
 	}
@@ -1,5 +1,6 @@
 use crate::alloc::string::String;
 use crate::interrupts::Registers;
+use crate::memory::allocator::kheap::{KHEAP_ADDR, KHEAP_SIZE};
 use crate::memory::allocator::AllocatorInit;
 use crate::memory::paging::page_directory;
 use crate::memory::{MemoryZone, TypeZone, VirtAddr};
@@ -28,7 +29,9 @@ pub enum TaskStatus {
 pub struct Task {
 	pub regs:    Registers,
 	pub state:   TaskStatus,
-	pub process: KArcm<Process>
+	pub process: KArcm<Process>,
+	pub woken:   bool,          // Set by WaitQueue::wake_all
+	pub timeout: Option<usize> /* Jiffies at which an Interruptible task is woken up */
 }
 
 impl Task {
@@ -36,16 +39,19 @@ impl Task {
 		Self {
 			regs:    Registers::new(),
 			state:   TaskStatus::Running,
-			process: KArcm::new(Process::new())
+			process: KArcm::new(Process::new()),
+			woken:   false,
+			timeout: None
 		}
 	}
 
 	pub fn init_multitasking(stack_addr: VirtAddr) {
 		unsafe {
-			// Init kernel heap
-			let heap = MemoryZone::init(
+			// Init kernel heap, the allocator adds pages after it on demand
+			let heap = MemoryZone::init_addr(
+				KHEAP_ADDR,
 				TypeZone::Heap,
-				100 * 0x1000,
+				KHEAP_SIZE,
 				PAGE_WRITABLE,
 				true
 			);
@@ -77,6 +83,7 @@ impl Task {
 			process.childs = Vec::with_capacity(8);
 			process.signals = Vec::with_capacity(8);
 			process.owner = 0;
+			process.group = 0;
 
 			task.process = KArcm::new(process);
 			PROCESS_TREE.insert(NEXT_PID, task.process.clone());
@@ -268,11 +275,13 @@ pub unsafe extern "C" fn schedule_task() -> ! {
 }
 
 use crate::pci::ide::IDEController;
+use crate::time::jiffies;
 
 #[no_mangle]
 unsafe extern "C" fn find_task() -> ! {
 	_cli();
 	IDEController::irq();
+	let mut skipped: usize = 0;
 	loop {
 		let new_task: &mut Task = Task::get_running_task();
 		// TODO: IF SIGNAL JUMP ?
@@ -280,7 +289,17 @@ unsafe extern "C" fn find_task() -> ! {
 			new_task.do_signal();
 			// Potentially never return
 		}
-		if new_task.state != TaskStatus::Interruptible {
+		if new_task.state == TaskStatus::Interruptible
+			&& new_task.timeout.is_some_and(|timeout| jiffies() >= timeout)
+		{
+			new_task.state = TaskStatus::Running;
+			new_task.timeout = None;
+		}
+		// If every task is sleeping, resume one anyway so it can hlt until the
+		// next interrupt instead of looping here with interrupts disabled
+		if new_task.state != TaskStatus::Interruptible
+			|| skipped >= TASKLIST.len()
+		{
 			// Copy registers to shared memory
 			let new_regs: Registers = new_task.regs;
 			new_task.process.execute(|mutex| {
@@ -290,6 +309,7 @@ unsafe extern "C" fn find_task() -> ! {
 			switch_task(&new_regs);
 			// never goes there
 		}
+		skipped += 1;
 		TASKLIST.push_back(TASKLIST.pop_front().unwrap());
 	}
 }
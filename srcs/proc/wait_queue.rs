@@ -0,0 +1,122 @@
+//! Wait queues, allow processes to sleep until an event happen on a resource
+//!
+//! A resource owns a WaitQueue and call `wake_all` each time its state change.
+//! Processes waiting on it register to the queue and are put Interruptible, the
+//! scheduler then skip them until they are woken up or their timeout expire.
+
+use crate::alloc::sync::Arc;
+use crate::proc::process::Process;
+use crate::proc::task::{Task, TaskStatus, TASKLIST};
+use crate::proc::Pid;
+use crate::spin::KMutex;
+use crate::time::{jiffies, ms_to_jiffies};
+use crate::vec::Vec;
+use crate::wrappers::{_cli, _rst, _sti, cli, cli_count, hlt, sti};
+
+#[derive(Default)]
+pub struct WaitQueue {
+	sleepers: KMutex<Vec<Pid>>
+}
+
+impl WaitQueue {
+	pub const fn new() -> Self {
+		Self { sleepers: KMutex::new(Vec::new()) }
+	}
+
+	/// Add the running process to the queue
+	pub fn register(&self) {
+		let pid = Process::get_running_process().lock().pid;
+		let mut guard = self.sleepers.lock();
+		if !guard.contains(&pid) {
+			guard.push(pid);
+		}
+	}
+
+	/// Remove the running process from the queue
+	pub fn unregister(&self) {
+		let pid = Process::get_running_process().lock().pid;
+		let mut guard = self.sleepers.lock();
+		guard.retain(|elem| *elem != pid);
+		// Same as SYSFILES, avoid keeping memory allocated once empty
+		if guard.is_empty() {
+			guard.shrink_to_fit();
+		}
+	}
+
+	/// Wake up every process registered to the queue
+	pub fn wake_all(&self) {
+		let guard = self.sleepers.lock();
+		if guard.is_empty() {
+			return;
+		}
+		unsafe {
+			_cli();
+			for task in TASKLIST.iter_mut() {
+				if guard.contains(&task.process.lock().pid) {
+					task.woken = true;
+					if task.state == TaskStatus::Interruptible {
+						task.state = TaskStatus::Running;
+						task.timeout = None;
+					}
+				}
+			}
+			_sti();
+		}
+	}
+}
+
+/// Put the running process to sleep on queues until `ready` return true.
+/// `ready` is called each time one of the queues is woken up.
+/// timeout: maximum time to wait in millisecond, None wait forever
+///
+/// Return false if the timeout expired before `ready` returned true
+pub fn wait_event(
+	queues: &[Arc<WaitQueue>],
+	timeout: Option<usize>,
+	mut ready: impl FnMut() -> bool
+) -> bool {
+	if ready() {
+		return true;
+	}
+	let deadline = timeout.map(|ms| jiffies() + ms_to_jiffies(ms));
+	for queue in queues {
+		queue.register();
+	}
+	let res = loop {
+		unsafe {
+			_cli();
+			Task::get_running_task().woken = false;
+			_sti();
+		}
+		// Called with interrupts enabled since it may lock resources
+		if ready() {
+			break true;
+		}
+		if deadline.is_some_and(|deadline| jiffies() >= deadline) {
+			break false;
+		}
+		unsafe {
+			_cli();
+			let task: &mut Task = Task::get_running_task();
+			// A wake_all between our check and here must not be lost
+			if !task.woken {
+				task.state = TaskStatus::Interruptible;
+				task.timeout = deadline;
+			}
+			let save = cli_count;
+			_rst();
+			sti!();
+			hlt!(); // wait for scheduler
+			cli!(); // woken up here
+			cli_count = save;
+			let task: &mut Task = Task::get_running_task();
+			task.state = TaskStatus::Running;
+			task.timeout = None;
+			_sti();
+		}
+	};
+	for queue in queues {
+		queue.unregister();
+	}
+	res
+}
@@ -52,7 +52,6 @@ pub fn sys_wait4(
 }
 
 // TODO: EINTR
-// + Make a func to get wstatus adress in userspace to kernel addr
 pub fn sys_waitpid(pid: Pid, wstatus: *mut i32, options: u32) -> Pid {
 	unsafe {
 		_cli();
@@ -64,11 +63,15 @@ pub fn sys_waitpid(pid: Pid, wstatus: *mut i32, options: u32) -> Pid {
 				let res = Process::search_from_pid(signal.sender);
 				if res.is_ok() {
 					Process::remove(signal.sender);
+					// Its zones were the last attaches of removed segments
+					crate::memory::shm::release_removed();
 				}
 				if !wstatus.is_null() {
-					let ptr = super::mmap::translate_vaddr(wstatus as u32)
-						as *mut i32;
-					*ptr = signal.wstatus; // TODO
+					let res = super::mmap::put_user(wstatus, signal.wstatus);
+					if let Err(errno) = res {
+						_sti();
+						return -(errno as i32);
+					}
 				}
 				_sti();
 				return signal.sender;
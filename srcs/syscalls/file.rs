@@ -0,0 +1,314 @@
+//! File descriptors syscalls
+
+use crate::errno::ErrNo;
+use crate::fs;
+use crate::fs::stat::Stat64;
+use crate::string::String;
+use crate::vec::Vec;
+
+use super::mmap::{get_user, put_user, read_user, write_user};
+use super::to_ret;
+
+/// Open path with the O_* flags, mode is used by the objects created in /dev/shm
+pub fn sys_open(path: *const u8, flags: u32, mode: u32) -> i32 {
+	to_ret(
+		user_path(path)
+			.and_then(|path| fs::open_flags(&path, flags, mode as u16))
+	)
+}
+
+/// Close fd, the file is released with its last descriptor
+pub fn sys_close(fd: i32) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	// fcntl is used to report EBADF since fs::close silently ignore unused fds
+	to_ret(fs::fcntl(fd as usize, fs::F_GETFD, 0).map(|_| {
+		fs::close(fd as usize);
+		0
+	}))
+}
+
+pub fn sys_dup(oldfd: i32) -> i32 {
+	if oldfd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	to_ret(fs::dup(oldfd as usize))
+}
+
+pub fn sys_dup2(oldfd: i32, newfd: i32) -> i32 {
+	if oldfd < 0 || newfd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	to_ret(fs::dup2(oldfd as usize, newfd as usize))
+}
+
+pub fn sys_fcntl(fd: i32, cmd: u32, arg: usize) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	to_ret(fs::fcntl(fd as usize, cmd, arg))
+}
+
+/// Maximum length of a path, including the null byte
+pub const PATH_MAX: usize = 4096;
+
+/// Copy a null terminated path from user space.
+/// Each byte is read on its own since the string may cross a page boundary
+pub fn user_path(path: *const u8) -> Result<String, ErrNo> {
+	if path.is_null() {
+		return Err(ErrNo::EFAULT);
+	}
+	let mut bytes: Vec<u8> = Vec::new();
+	for i in 0..PATH_MAX {
+		let c = get_user(path.wrapping_add(i))?;
+		if c == 0 {
+			return String::from_utf8(bytes).map_err(|_| ErrNo::EINVAL);
+		}
+		bytes.push(c);
+	}
+	Err(ErrNo::ENAMETOOLONG)
+}
+
+/// Write stat to the user buffer, which may cross a page boundary
+fn put_stat(buf: *mut Stat64, stat: Stat64) -> Result<usize, ErrNo> {
+	put_user(buf, stat)?;
+	Ok(0)
+}
+
+pub fn sys_stat64(path: *const u8, buf: *mut Stat64) -> i32 {
+	to_ret(user_path(path).and_then(|path| put_stat(buf, fs::stat(&path)?)))
+}
+
+pub fn sys_lstat64(path: *const u8, buf: *mut Stat64) -> i32 {
+	to_ret(user_path(path).and_then(|path| put_stat(buf, fs::lstat(&path)?)))
+}
+
+pub fn sys_fstat64(fd: i32, buf: *mut Stat64) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	to_ret(fs::fstat(fd as usize).and_then(|stat| put_stat(buf, stat)))
+}
+
+pub fn sys_ftruncate(fd: i32, length: i32) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	if length < 0 {
+		return -(ErrNo::EINVAL as i32);
+	}
+	to_ret(fs::ftruncate(fd as usize, length as usize))
+}
+
+/// Records are built in a kernel buffer then copied to user space. The buffer is capped at one
+/// page, only the records fitting in it are returned and the next call continues from there
+pub fn sys_getdents64(fd: i32, dirp: *mut u8, count: usize) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	if dirp.is_null() {
+		return -(ErrNo::EFAULT as i32);
+	}
+	let mut buffer: Vec<u8> = crate::vec![0; core::cmp::min(count, 0x1000)];
+	to_ret(fs::getdents(fd as usize, &mut buffer).and_then(|written| {
+		write_user(dirp, &buffer[..written])?;
+		Ok(written)
+	}))
+}
+
+pub fn sys_link(oldpath: *const u8, newpath: *const u8) -> i32 {
+	to_ret(
+		user_path(oldpath)
+			.and_then(|oldpath| fs::link(&oldpath, &user_path(newpath)?))
+	)
+}
+
+pub fn sys_unlink(path: *const u8) -> i32 {
+	to_ret(user_path(path).and_then(|path| fs::unlink(&path)))
+}
+
+pub fn sys_rmdir(path: *const u8) -> i32 {
+	to_ret(user_path(path).and_then(|path| fs::rmdir(&path)))
+}
+
+pub fn sys_rename(oldpath: *const u8, newpath: *const u8) -> i32 {
+	to_ret(
+		user_path(oldpath)
+			.and_then(|oldpath| fs::rename(&oldpath, &user_path(newpath)?))
+	)
+}
+
+pub fn sys_symlink(target: *const u8, linkpath: *const u8) -> i32 {
+	to_ret(
+		user_path(target)
+			.and_then(|target| fs::symlink(&target, &user_path(linkpath)?))
+	)
+}
+
+pub fn sys_chmod(path: *const u8, mode: u32) -> i32 {
+	to_ret(user_path(path).and_then(|path| fs::chmod(&path, mode as u16)))
+}
+
+/// Convert a chown id argument, -1 keep the current value.
+/// Ext2 only store 16 bits ids
+fn chown_id(id: u32) -> Result<Option<u16>, ErrNo> {
+	match id {
+		u32::MAX => Ok(None),
+		id if id > u16::MAX as u32 => Err(ErrNo::EINVAL),
+		id => Ok(Some(id as u16))
+	}
+}
+
+/// Change the owner of path, symbolic links are only followed if follow is set
+pub fn sys_chown(path: *const u8, uid: u32, gid: u32, follow: bool) -> i32 {
+	to_ret(user_path(path).and_then(|path| {
+		fs::chown(&path, chown_id(uid)?, chown_id(gid)?, follow)
+	}))
+}
+
+/// Layout of the linux struct utimbuf
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UtimBuf {
+	pub actime:  i32,
+	pub modtime: i32
+}
+
+/// A null times set access and modification times to the current time
+pub fn sys_utime(path: *const u8, times: *const UtimBuf) -> i32 {
+	let times = match times.is_null() {
+		true => None,
+		false => match get_user(times) {
+			Ok(times) => Some((times.actime as u32, times.modtime as u32)),
+			Err(errno) => return -(errno as i32)
+		}
+	};
+	to_ret(user_path(path).and_then(|path| fs::utime(&path, times)))
+}
+
+/// The target is truncated to bufsiz and is not null terminated
+pub fn sys_readlink(path: *const u8, buf: *mut u8, bufsiz: usize) -> i32 {
+	if buf.is_null() {
+		return -(ErrNo::EFAULT as i32);
+	}
+	if bufsiz == 0 {
+		return -(ErrNo::EINVAL as i32);
+	}
+	to_ret(user_path(path).and_then(|path| {
+		let target = fs::readlink(&path)?;
+		let len = core::cmp::min(target.len(), bufsiz);
+		write_user(buf, &target.as_bytes()[..len])?;
+		Ok(len)
+	}))
+}
+
+pub fn sys_chdir(path: *const u8) -> i32 {
+	to_ret(user_path(path).and_then(|path| fs::chdir(&path)))
+}
+
+pub fn sys_fchdir(fd: i32) -> i32 {
+	if fd < 0 {
+		return -(ErrNo::EBADF as i32);
+	}
+	to_ret(fs::fchdir(fd as usize))
+}
+
+/// The path is null terminated, its length including the terminator is returned
+pub fn sys_getcwd(buf: *mut u8, size: usize) -> i32 {
+	if buf.is_null() {
+		return -(ErrNo::EFAULT as i32);
+	}
+	to_ret(fs::getcwd().and_then(|cwd| {
+		if cwd.len() + 1 > size {
+			return Err(ErrNo::ERANGE);
+		}
+		write_user(buf, cwd.as_bytes())?;
+		put_user(buf.wrapping_add(cwd.len()), 0)?;
+		Ok(cwd.len() + 1)
+	}))
+}
+
+pub fn sys_chroot(path: *const u8) -> i32 {
+	to_ret(user_path(path).and_then(|path| fs::chroot(&path)))
+}
+
+/// Largest attribute value accepted, like linux XATTR_SIZE_MAX
+const XATTR_SIZE_MAX: usize = 65536;
+
+/// Copy src to the user buffer of size bytes. A size of 0 only query the length needed
+fn copy_to_user(src: &[u8], buf: *mut u8, size: usize) -> Result<usize, ErrNo> {
+	if size == 0 {
+		return Ok(src.len());
+	}
+	if src.len() > size {
+		return Err(ErrNo::ERANGE);
+	}
+	if buf.is_null() {
+		return Err(ErrNo::EFAULT);
+	}
+	write_user(buf, src)?;
+	Ok(src.len())
+}
+
+/// Set the attribute name of path to the size bytes of value
+pub fn sys_setxattr(
+	path: *const u8,
+	name: *const u8,
+	value: *const u8,
+	size: usize,
+	flags: u32,
+	follow: bool
+) -> i32 {
+	if size > XATTR_SIZE_MAX {
+		return -(ErrNo::E2BIG as i32);
+	}
+	if size != 0 && value.is_null() {
+		return -(ErrNo::EFAULT as i32);
+	}
+	let mut bytes: Vec<u8> = crate::vec![0; size];
+	if let Err(errno) = read_user(&mut bytes, value) {
+		return -(errno as i32);
+	}
+	to_ret(user_path(path).and_then(|path| {
+		fs::setxattr(&path, &user_path(name)?, &bytes, flags, follow)
+	}))
+}
+
+/// Copy the value of the attribute name of path to value, see copy_to_user
+pub fn sys_getxattr(
+	path: *const u8,
+	name: *const u8,
+	value: *mut u8,
+	size: usize,
+	follow: bool
+) -> i32 {
+	to_ret(user_path(path).and_then(|path| {
+		let attr = fs::getxattr(&path, &user_path(name)?, follow)?;
+		copy_to_user(&attr, value, size)
+	}))
+}
+
+/// Copy the null terminated names of the attributes of path to list, see copy_to_user
+pub fn sys_listxattr(
+	path: *const u8,
+	list: *mut u8,
+	size: usize,
+	follow: bool
+) -> i32 {
+	to_ret(user_path(path).and_then(|path| {
+		let mut names: Vec<u8> = Vec::new();
+		for name in fs::listxattr(&path, follow)? {
+			names.extend_from_slice(name.as_bytes());
+			names.push(0);
+		}
+		copy_to_user(&names, list, size)
+	}))
+}
+
+pub fn sys_removexattr(path: *const u8, name: *const u8, follow: bool) -> i32 {
+	to_ret(
+		user_path(path)
+			.and_then(|path| fs::removexattr(&path, &user_path(name)?, follow))
+	)
+}
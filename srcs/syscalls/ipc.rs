@@ -0,0 +1,81 @@
+//! System V IPC syscalls
+
+use crate::errno::ErrNo;
+use crate::memory::shm::{self, ShmidDs, IPC_SET, IPC_STAT};
+use crate::memory::VirtAddr;
+
+use super::mmap::{get_user, put_user};
+use super::to_ret;
+
+// Calls of the ipc multiplexer, values match linux ipc.h
+pub const SHMAT: u32 = 21;
+pub const SHMDT: u32 = 22;
+pub const SHMGET: u32 = 23;
+pub const SHMCTL: u32 = 24;
+
+pub fn sys_shmget(key: i32, size: usize, flags: u32) -> i32 {
+	to_ret(shm::shmget(key, size, flags))
+}
+
+pub fn sys_shmat(shmid: i32, addr: VirtAddr, flags: u32) -> i32 {
+	if shmid < 0 {
+		return -(ErrNo::EINVAL as i32);
+	}
+	to_ret(shm::shmat(shmid as usize, addr, flags).map(|vaddr| vaddr as usize))
+}
+
+pub fn sys_shmdt(addr: VirtAddr) -> i32 {
+	to_ret(shm::shmdt(addr))
+}
+
+/// The shmid64_ds of IPC_STAT and IPC_SET is copied from and to user space
+pub fn sys_shmctl(shmid: i32, cmd: u32, buf: *mut ShmidDs) -> i32 {
+	if shmid < 0 {
+		return -(ErrNo::EINVAL as i32);
+	}
+	let user = matches!(cmd & !shm::IPC_64, IPC_STAT | IPC_SET);
+	if user && buf.is_null() {
+		return -(ErrNo::EFAULT as i32);
+	}
+	let mut ds = match cmd & !shm::IPC_64 {
+		IPC_SET => match get_user(buf) {
+			Ok(ds) => ds,
+			Err(errno) => return -(errno as i32)
+		},
+		_ => ShmidDs::default()
+	};
+	let res = shm::shmctl(shmid as usize, cmd, &mut ds);
+	if res.is_ok() && cmd & !shm::IPC_64 == IPC_STAT {
+		if let Err(errno) = put_user(buf, ds) {
+			return -(errno as i32);
+		}
+	}
+	to_ret(res)
+}
+
+/// Old multiplexer of System V IPC calls, the upper 16 bits of call are a version.
+/// shmat stores the address of the segment at third and returns 0
+pub fn sys_ipc(
+	call: u32,
+	first: i32,
+	second: usize,
+	third: usize,
+	ptr: usize,
+	_fifth: usize
+) -> i32 {
+	match call & 0xffff {
+		SHMAT => {
+			let vaddr = sys_shmat(first, ptr as VirtAddr, second as u32);
+			if vaddr < 0 && vaddr > -4096 {
+				return vaddr;
+			}
+			to_ret(
+				put_user(third as *mut VirtAddr, vaddr as VirtAddr).map(|_| 0)
+			)
+		},
+		SHMDT => sys_shmdt(ptr as VirtAddr),
+		SHMGET => sys_shmget(first, second, third as u32),
+		SHMCTL => sys_shmctl(first, second as u32, ptr as *mut ShmidDs),
+		_ => -(ErrNo::ENOSYS as i32)
+	}
+}
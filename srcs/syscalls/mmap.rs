@@ -1,9 +1,7 @@
-use crate::memory::paging;
-use crate::memory::paging::page_table::PageTable;
-use crate::memory::paging::{page_directory, PAGE_PRESENT, PAGE_WRITABLE};
+use crate::memory::paging::page_directory;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct mmap_arg {
 	addr:   usize,
 	length: usize,
@@ -13,23 +11,47 @@ pub struct mmap_arg {
 	offset: usize
 }
 
-/// Translate VirtAddr from userspace to kernelspace
+/// Translate VirtAddr from userspace to kernelspace, write tells if the kernel is going to
+/// write to the page. EFAULT is returned if the process can't access the page that way
 /// This can be optimize a lot.
-pub fn translate_vaddr(addr: VirtAddr) -> VirtAddr {
+pub fn translate_vaddr(addr: VirtAddr, write: bool) -> Result<VirtAddr, ErrNo> {
 	unsafe {
 		let binding = Process::get_running_process();
 		let curr_process = binding.lock();
 
 		if curr_process.owner == 0 {
-			return addr;
+			return Ok(addr);
 		}
+		let page = addr & !0xfff;
+		let in_zone = curr_process.heap.contains(page)
+			|| curr_process.stack.contains(page)
+			|| curr_process
+				.mem_map
+				.iter()
+				.any(|zone| zone.lock().contains(page));
+		drop(curr_process);
+		// Pages of the zones are faulted in here since the kernel can't take a
+		// page fault while handling a syscall, a write unshares the page and
+		// fails on read-only zones
+		if in_zone {
+			return Process::fault_in(&binding, addr, write)
+				.map_err(|_| ErrNo::EFAULT);
+		}
+		let curr_process = binding.lock();
 
 		let pd_index = (addr as usize >> 22) as usize;
 		let pt_index = ((addr as usize & 0x3ff000) >> 12) as usize;
 		let pt_paddr = (*curr_process.pd).get_entry(pd_index).get_paddr();
 		for i in &curr_process.page_tables {
 			if get_paddr!(i.get_vaddr()) == pt_paddr {
-				let page_paddr = i.entries[pt_index].get_paddr();
+				let entry = &i.entries[pt_index];
+				if entry.get_present() == 0
+					|| entry.get_user_supervisor() == 0
+					|| (write && entry.get_writable() == 0)
+				{
+					return Err(ErrNo::EFAULT);
+				}
+				let page_paddr = entry.get_paddr();
 				for j in 0..1024 {
 					if page_directory.get_entry(j).get_present() == 1 {
 						for k in 0..1024 {
@@ -41,159 +63,407 @@ pub fn translate_vaddr(addr: VirtAddr) -> VirtAddr {
 									j,
 									k
 								);
-								return get_vaddr!(j, k);
+								return Ok(get_vaddr!(j, k));
 							}
 						}
 					}
 				}
-				return 0x0;
+				return Err(ErrNo::EFAULT);
 			}
 		}
-		return 0x0;
+		Err(ErrNo::EFAULT)
 	}
 }
 
-pub fn mmap(addr: *const mmap_arg) -> *const u8 {
-	//! WARNING: Currently flags sended to MemoryZone are ignored and used to give PageDirectory
-	//! the page flags. The flag PAGE_USER may overlap with one of mmap flags
-
-	let translated_addr = translate_vaddr(addr as VirtAddr);
-	let translated_addr = (translated_addr as usize) + (addr as usize & 0xfff);
-
-	let ptr = unsafe { &*(translated_addr as *const mmap_arg) };
-	let mz = sys_mmap(
-		ptr.addr as VirtAddr,
-		ptr.length,
-		ptr.prot,
-		ptr.flags,
-		ptr.fd,
-		ptr.offset
-	);
-	let size = ptr.length + (4096 - (ptr.length % 4096));
-	let binding = Process::get_running_process();
-	let mut curr_process = binding.lock();
-
-	let user_pd = unsafe { &mut *curr_process.pd };
+/// Translate a pointer from userspace to kernelspace, keeping its offset in the page.
+/// See translate_vaddr
+pub fn translate_ptr<T>(addr: *const T, write: bool) -> Result<*mut T, ErrNo> {
+	let page = translate_vaddr(addr as VirtAddr & !0xfff, write)?;
+	Ok((page as usize + (addr as usize & 0xfff)) as *mut T)
+}
 
-	if mz.is_err() {
-		return 0xff as *const u8;
+/// Call f with the kernel pointer, the offset and the length of each part of
+/// the user buffer [addr, addr + len) contained in a single page, consecutive
+/// user pages are not consecutive in kernel space. write is the access
+/// checked on each page
+fn for_each_user_page(
+	addr: *const u8,
+	len: usize,
+	write: bool,
+	mut f: impl FnMut(*mut u8, usize, usize)
+) -> Result<(), ErrNo> {
+	if len != 0 && addr.is_null() {
+		return Err(ErrNo::EFAULT);
 	}
-	let mz = mz.unwrap();
-	let offset = mz.lock().offset;
-	if user_pd.get_entry(991).get_present() == 1 {
-		let pt_paddr = user_pd.get_entry(991).get_paddr();
-		for i in &mut curr_process.page_tables {
-			if pt_paddr == unsafe { get_paddr!(i.get_vaddr()) } {
-				let pt_index = i.new_frames(
-					unsafe { get_paddr!(offset) },
-					(size / 4096) as u32,
-					ptr.flags | paging::PAGE_USER
-				);
-				return match pt_index {
-					Ok(index) => get_vaddr!(991, index as usize) as *const u8,
-					Err(_) => 0xff as *const u8
-				};
-			}
+	let mut done = 0;
+	while done < len {
+		let ptr = addr.wrapping_add(done);
+		let size = core::cmp::min(len - done, 0x1000 - (ptr as usize & 0xfff));
+		f(translate_ptr(ptr, write)?, done, size);
+		done += size;
+	}
+	Ok(())
+}
+
+/// Copy src to the user buffer dst page by page
+pub fn write_user<T: Copy>(dst: *mut T, src: &[T]) -> Result<(), ErrNo> {
+	let bytes = src.as_ptr() as *const u8;
+	for_each_user_page(
+		dst as *const u8,
+		core::mem::size_of_val(src),
+		true,
+		|ptr, off, len| unsafe {
+			core::ptr::copy_nonoverlapping(bytes.add(off), ptr, len)
 		}
-		// try map in pt
-		// if fail try next
-		todo!()
-	} else {
-		let pt: &'static mut PageTable = PageTable::new();
-		unsafe {
-			let pt_index = pt
-				.new_frames(
-					get_paddr!(offset),
-					(size / 4096) as u32,
-					ptr.flags | paging::PAGE_USER
-				)
-				.expect("Mmap failed to create first PageTable");
-			user_pd.set_entry(
-				991,
-				get_paddr!(pt as *const _)
-					| PAGE_WRITABLE | PAGE_PRESENT
-					| paging::PAGE_USER
-			);
-			curr_process.page_tables.push(pt);
-			return get_vaddr!(991, pt_index) as *const u8;
+	)
+}
+
+/// Copy the user buffer src to dst page by page
+pub fn read_user<T: Copy>(dst: &mut [T], src: *const T) -> Result<(), ErrNo> {
+	let bytes = dst.as_mut_ptr() as *mut u8;
+	for_each_user_page(
+		src as *const u8,
+		core::mem::size_of_val(dst),
+		false,
+		|ptr, off, len| unsafe {
+			core::ptr::copy_nonoverlapping(ptr, bytes.add(off), len)
 		}
+	)
+}
+
+/// Read a C struct from user space, T must be valid when zeroed
+pub fn get_user<T: Copy>(src: *const T) -> Result<T, ErrNo> {
+	let mut value: T = unsafe { core::mem::zeroed() };
+	read_user(core::slice::from_mut(&mut value), src)?;
+	Ok(value)
+}
+
+/// Write a C struct to user space
+pub fn put_user<T: Copy>(dst: *mut T, value: T) -> Result<(), ErrNo> {
+	write_user(dst, core::slice::from_ref(&value))
+}
+
+/// Old mmap syscall, its arguments are given in a mmap_arg struct
+pub fn mmap(addr: *const mmap_arg) -> i32 {
+	let arg = match get_user(addr) {
+		Ok(arg) => arg,
+		Err(errno) => return -(errno as i32)
+	};
+	let (hint, length, prot, flags, fd, offset) =
+		(arg.addr, arg.length, arg.prot, arg.flags, arg.fd, arg.offset);
+	map_ret(sys_mmap(hint as VirtAddr, length, prot, flags, fd, offset))
+}
+
+/// Same as mmap with arguments in registers and an offset in pages
+pub fn mmap2(
+	addr: VirtAddr,
+	length: usize,
+	prot: u32,
+	flags: u32,
+	fd: i32,
+	pgoffset: usize
+) -> i32 {
+	let Some(offset) = pgoffset.checked_mul(4096) else {
+		return -(ErrNo::EINVAL as i32);
+	};
+	map_ret(sys_mmap(addr, length, prot, flags, fd, offset))
+}
+
+/// Return value of the mapping syscalls. Mappings are page aligned, so as an i32 their address
+/// is a multiple of 4096 and never falls in -4095..-1 where callers look for an errno, even
+/// above 0x80000000
+fn map_ret(res: Result<VirtAddr, ErrNo>) -> i32 {
+	match res {
+		Ok(vaddr) => {
+			debug_assert!(vaddr & 0xfff == 0);
+			vaddr as i32
+		},
+		Err(errno) => -(errno as i32)
 	}
 }
 
-use crate::memory::{MemoryZone, TypeZone, VirtAddr};
+use super::file::user_path;
+use super::to_ret;
+use crate::errno::ErrNo;
+use crate::fs::{O_ACCMODE, O_RDONLY, O_WRONLY};
+use crate::memory::{
+	prot_flags,
+	shm,
+	swap,
+	MemoryZone,
+	TypeZone,
+	VirtAddr,
+	ZoneFile,
+	MAP_ANONYMOUS,
+	MAP_FIXED,
+	MAP_POPULATE,
+	MAP_PRIVATE,
+	MAP_SHARED,
+	PROT_WRITE,
+	REMAP_FIXED,
+	REMAP_MAYMOVE
+};
 use crate::proc::process::Process;
 use crate::utils::arcm::Arcm;
 
-/// hint: Adress at which the kernel will look for free space.
-/// size; Size of the mapping, will be rounded up to a multiplpe of PAGE_SIZE
-/// prot: Indicate protection settings to setup the LDT for the current process
-/// flags: Flags of the memory zone that will be allocated
-/// fd: FileDescriptor to the file that needs to be mapped
-/// offset: offset on the file from which the mapping will start
+/// Map size bytes of the file open at fd from offset, or zeroed memory with MAP_ANONYMOUS,
+/// in the address space of the running process.
+/// hint: Address of the mapping if it is free. With MAP_FIXED the mapping is placed there,
+/// replacing the mappings it overlaps
+/// prot: PROT_* protection of the pages, see memory::prot_flags
+/// flags: MAP_SHARED mappings are kept by the children of the process, MAP_PRIVATE ones are
+/// copied on write. Shared mappings of files that share their pages, like shared memory
+/// objects, map them instead of reading the file. Pages are faulted in on access unless
+/// MAP_POPULATE is set
+/// Return the address of the mapping, processes without their own page directory ignore hint
 pub fn sys_mmap(
-	_hint: VirtAddr,
+	hint: VirtAddr,
 	size: usize,
-	_prot: u32,
+	prot: u32,
 	flags: u32,
-	_fd: i32,
-	_offset: usize
-) -> Result<Arcm<MemoryZone>, ()> {
-	// Parameters given to init will vary depending on flags given
-	let mz = Arcm::new(MemoryZone::init(TypeZone::Anon, size, flags, false));
+	fd: i32,
+	offset: usize
+) -> Result<VirtAddr, ErrNo> {
+	let size = size.checked_next_multiple_of(0x1000).ok_or(ErrNo::ENOMEM)?;
+	if size == 0
+		|| offset & 0xfff != 0
+		|| (flags & (MAP_SHARED | MAP_PRIVATE)).count_ones() != 1
+		|| (flags & MAP_FIXED != 0 && hint & 0xfff != 0)
+	{
+		return Err(ErrNo::EINVAL);
+	}
 	let binding = Process::get_running_process();
-	binding.lock().add_memory_zone(mz.clone());
-	Ok(mz)
+	let file = match flags & MAP_ANONYMOUS {
+		0 => {
+			let file = binding
+				.lock()
+				.fds
+				.get(fd as usize)
+				.cloned()
+				.flatten()
+				.ok_or(ErrNo::EBADF)?;
+			let mode = file.get_flags() & O_ACCMODE;
+			if mode == O_WRONLY
+				|| (flags & MAP_SHARED != 0
+					&& prot & PROT_WRITE != 0
+					&& mode == O_RDONLY)
+			{
+				return Err(ErrNo::EACCES);
+			}
+			Some(ZoneFile { file, offset })
+		},
+		_ => None
+	};
+	// Processes without their own page directory see the zone at its kernel
+	// address, and can't take a page fault in it
+	let kernel = binding.lock().pd.is_null();
+	if kernel && flags & MAP_FIXED != 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	// Files sharing their pages give the zone, the others are read in it
+	let shared = match file.as_ref().filter(|_| flags & MAP_SHARED != 0) {
+		Some(zone_file) => {
+			match zone_file.file.op.lock().map_shared(
+				offset,
+				size,
+				prot_flags(prot)
+			) {
+				Ok(zone) => Some(zone),
+				// Pages can't be written back to the other files
+				Err(ErrNo::ENODEV) if prot & PROT_WRITE == 0 => None,
+				Err(errno) => return Err(errno)
+			}
+		},
+		None => None
+	};
+	let mut zone = match shared {
+		Some(zone) => zone,
+		None => {
+			let ztype = match file {
+				Some(_) => TypeZone::File("file"),
+				None => TypeZone::Anon
+			};
+			let mut zone = MemoryZone::reserve(ztype, size, prot_flags(prot));
+			zone.file = file;
+			zone.shared = flags & MAP_SHARED != 0;
+			zone
+		}
+	};
+	// Files are read with the process unlocked
+	if flags & MAP_POPULATE != 0 || kernel {
+		zone.populate().map_err(|_| ErrNo::ENOMEM)?;
+	}
+	let mut process = binding.lock();
+	zone.vaddr = match flags & MAP_FIXED {
+		_ if kernel => zone.offset,
+		0 => process.get_unmapped_area(hint, size).ok_or(ErrNo::ENOMEM)?,
+		_ => {
+			process.unmap_zones(hint, size)?;
+			if !process.range_free(hint, size) {
+				return Err(ErrNo::EINVAL);
+			}
+			hint
+		}
+	};
+	let vaddr = zone.vaddr;
+	process.map_zone_pages(&zone).map_err(|_| ErrNo::ENOMEM)?;
+	process.add_memory_zone(Arcm::new(zone));
+	Ok(vaddr)
 }
 
-/// Current implementation have a lot of unverified cases.
-///
-/// Current optimal use is to give munmap the real addr and length of a MemoryZone
-/// else this is undefined behaviour.
+/// Set the program break of the running process to addr, the heap grows or shrinks with it.
+/// Return the new program break, or the current one when addr is 0 or the break can't be
+/// moved there
+pub fn sys_brk(addr: VirtAddr) -> VirtAddr {
+	let binding = Process::get_running_process();
+	let mut process = binding.lock();
+	process.set_brk(addr)
+}
+
+/// Swap pages out to the regular file at path when memory runs low, see swap::swapon_file.
+/// Flags are ignored
+pub fn sys_swapon(path: *const u8, _flags: u32) -> i32 {
+	to_ret(
+		user_path(path)
+			.and_then(|path| swap::swapon_file(&path))
+			.map(|_| 0)
+	)
+}
+
+/// Stop swapping to the file at path, its pages are read back first
+pub fn sys_swapoff(path: *const u8) -> i32 {
+	to_ret(
+		user_path(path)
+			.and_then(|path| swap::swapoff_file(&path))
+			.map(|_| 0)
+	)
+}
+
+/// Unmap the pages of [addr, addr + length) from the mappings of the process, mappings only
+/// partly covered are shrunk or split
 pub fn sys_munmap(addr: *const usize, length: usize) -> i32 {
-	let size = length + (4096 - (length % 4096));
+	let addr = addr as VirtAddr;
+	let size = match length.checked_next_multiple_of(0x1000) {
+		Some(size) if addr & 0xfff == 0 && size != 0 => size,
+		_ => return -(ErrNo::EINVAL as i32)
+	};
+	let binding = Process::get_running_process();
+	if let Err(errno) = binding.lock().unmap_zones(addr, size) {
+		return -(errno as i32);
+	}
+	// The unmapped zone may be the last attach of a removed segment
+	shm::release_removed();
+	0
+}
 
-	let translated_addr = translate_vaddr(addr as VirtAddr);
-	let translated_addr = (translated_addr as usize) + (addr as usize & 0xfff);
+/// Resize the mapping of [old_addr, old_addr + old_size) to new_size bytes. It grows in place
+/// if the pages after it are free and moves otherwise if REMAP_MAYMOVE is set. REMAP_FIXED
+/// moves it to new_addr, replacing the mappings there.
+/// Return the new address of the mapping
+pub fn sys_mremap(
+	old_addr: VirtAddr,
+	old_size: usize,
+	new_size: usize,
+	flags: u32,
+	new_addr: VirtAddr
+) -> i32 {
+	let (Some(old_size), Some(new_size)) = (
+		old_size.checked_next_multiple_of(0x1000),
+		new_size.checked_next_multiple_of(0x1000)
+	) else {
+		return -(ErrNo::EINVAL as i32);
+	};
+	if old_addr & 0xfff != 0
+		|| old_size == 0
+		|| new_size == 0
+		|| flags & !(REMAP_MAYMOVE | REMAP_FIXED) != 0
+		|| (flags & REMAP_FIXED != 0
+			&& (flags & REMAP_MAYMOVE == 0 || new_addr & 0xfff != 0))
+	{
+		return -(ErrNo::EINVAL as i32);
+	}
+	let binding = Process::get_running_process();
+	let mut process = binding.lock();
+	let zone = match process.split_zone(old_addr, old_size) {
+		Ok(zone) => zone,
+		Err(errno) => return -(errno as i32)
+	};
+	let target = if flags & REMAP_FIXED != 0 {
+		match process.range_end(new_addr, new_size) {
+			Some(end)
+				if (new_addr as usize) >= old_addr as usize + old_size
+					|| (old_addr as usize) >= end => {},
+			_ => return -(ErrNo::EINVAL as i32)
+		}
+		if let Err(errno) = process.unmap_zones(new_addr, new_size) {
+			return -(errno as i32);
+		}
+		Some(new_addr)
+	} else if new_size <= old_size
+		|| process
+			.range_free(old_addr + old_size as VirtAddr, new_size - old_size)
+	{
+		None
+	} else if flags & REMAP_MAYMOVE != 0 {
+		match process.get_unmapped_area(0, new_size) {
+			Some(addr) => Some(addr),
+			None => return -(ErrNo::ENOMEM as i32)
+		}
+	} else {
+		return -(ErrNo::ENOMEM as i32);
+	};
+	process.unmap_pages(old_addr, old_size / 0x1000);
+	let mut guard = zone.lock();
+	let res = match target {
+		Some(addr) => guard.remap(addr, new_size, REMAP_FIXED),
+		None => guard.remap(0, new_size, 0)
+	};
+	// The zone is mapped back where it is on failure
+	if process.map_zone_pages(&guard).is_err() {
+		return -(ErrNo::ENOMEM as i32);
+	}
+	map_ret(res.map_err(|_| ErrNo::ENOMEM))
+}
 
-	// Bind and lock the current process
+/// Change the protection of the pages of [addr, addr + len) to prot, only WRITABLE is
+/// enforced. Every page of the range must be mapped
+pub fn sys_mprotect(addr: VirtAddr, len: usize, prot: u32) -> i32 {
+	if addr & 0xfff != 0 {
+		return -(ErrNo::EINVAL as i32);
+	}
 	let binding = Process::get_running_process();
-	let mut curr_process = binding.lock();
-
-	// Look if addr given is in range of one MemoryZone
-	// Current lookup does not handle if the given zone doesn't exist
-	let mut index = 0;
-	for i in &curr_process.mem_map {
-		let (offset, size) = i.lock().area();
-		if offset >= translated_addr as VirtAddr
-			&& (translated_addr as usize) < offset as usize + size
+	let mut process = binding.lock();
+	let end = match len
+		.checked_next_multiple_of(0x1000)
+		.and_then(|size| process.range_end(addr, size))
+	{
+		Some(end) => end,
+		None => return -(ErrNo::EINVAL as i32)
+	};
+	let mut vaddr = addr as usize;
+	while vaddr < end {
+		let zone_end = match process
+			.mem_map
+			.iter()
+			.find(|zone| zone.lock().contains(vaddr as VirtAddr))
 		{
-			break;
+			Some(zone) => {
+				let zone = zone.lock();
+				zone.vaddr as usize + zone.size
+			},
+			None => return -(ErrNo::ENOMEM as i32)
+		};
+		let size = core::cmp::min(zone_end, end) - vaddr;
+		let zone = match process.split_zone(vaddr as VirtAddr, size) {
+			Ok(zone) => zone,
+			Err(errno) => return -(errno as i32)
+		};
+		let mut guard = zone.lock();
+		guard.protect(prot);
+		if process.map_zone_pages(&guard).is_err() {
+			return -(ErrNo::ENOMEM as i32);
 		}
-		index += 1;
-	}
-	// Get back the concerned MemoryZone
-	let mut split_list = curr_process.mem_map.split_off(index);
-	let mz = split_list.pop_front();
-
-	// Bind and lock the concerned MemoryZone
-	// This cause panic if we try to unmap non mapped MemoryZone
-	let mzbinding = mz.unwrap();
-	let mut guard = mzbinding.lock();
-
-	// If given size is lower than the total size, we need to shrink the MemoryZone
-	if size < guard.size {
-		let (offset, size) = guard.area();
-		// Expect may need to be replaced with proper error handling later
-		// The remap function isn't yet implemented, this will result in a panic
-		guard
-			.remap(offset, size - length, crate::memory::MAP_FIXED)
-			.expect("Remap failed");
-		// Drop guard in order to get back the ownership over mzbinding
-		drop(guard);
-		// Add back the new MemoryZone to our list
-		split_list.push_front(mzbinding);
+		vaddr += size;
 	}
-
-	curr_process.mem_map.append(&mut split_list);
 	0
 }
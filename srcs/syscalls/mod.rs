@@ -1,19 +1,73 @@
 //! Syscall handler and all implemented syscalls
 
+use crate::errno::ErrNo;
 use crate::interrupts::Registers;
 
 #[macro_use]
 pub mod exit;
+pub mod file;
+pub mod ipc;
 pub mod mmap;
+pub mod poll;
 pub mod process;
 pub mod signal;
+pub mod socket;
 pub mod timer;
 
 use exit::{sys_exit, sys_wait4, sys_waitpid};
-use mmap::{mmap, sys_munmap};
+use file::{
+	sys_chdir,
+	sys_chmod,
+	sys_chown,
+	sys_chroot,
+	sys_close,
+	sys_dup,
+	sys_dup2,
+	sys_fchdir,
+	sys_fcntl,
+	sys_fstat64,
+	sys_ftruncate,
+	sys_getcwd,
+	sys_getdents64,
+	sys_getxattr,
+	sys_link,
+	sys_listxattr,
+	sys_lstat64,
+	sys_open,
+	sys_readlink,
+	sys_removexattr,
+	sys_rename,
+	sys_rmdir,
+	sys_setxattr,
+	sys_stat64,
+	sys_symlink,
+	sys_unlink,
+	sys_utime
+};
+use ipc::{sys_ipc, sys_shmat, sys_shmctl, sys_shmdt, sys_shmget};
+use mmap::{
+	mmap,
+	mmap2,
+	sys_brk,
+	sys_mprotect,
+	sys_mremap,
+	sys_munmap,
+	sys_swapoff,
+	sys_swapon
+};
+use poll::{sys_old_select, sys_poll, sys_select};
 use process::sys_fork;
 use signal::{sys_kill, sys_signal};
-use timer::{sys_getpid, sys_getppid, sys_getuid};
+use socket::sys_socketcall;
+use timer::{sys_getgid, sys_getpid, sys_getppid, sys_getuid};
+
+/// Convert a syscall result to the value expected in eax
+pub fn to_ret(res: Result<usize, ErrNo>) -> i32 {
+	match res {
+		Ok(value) => value as i32,
+		Err(errno) => -(errno as i32)
+	}
+}
 
 /// Parameters order: ebx, ecx, edx, esi, edi, ebp
 pub fn syscall_handler(reg: &mut Registers) {
@@ -30,6 +84,7 @@ pub fn syscall_handler(reg: &mut Registers) {
 		},
 		_ if reg.eax == Syscall::getpid as u32 => reg.eax = sys_getpid() as u32,
 		_ if reg.eax == Syscall::getuid as u32 => reg.eax = sys_getuid() as u32,
+		_ if reg.eax == Syscall::getgid as u32 => reg.eax = sys_getgid() as u32,
 		_ if reg.eax == Syscall::kill as u32 => {
 			reg.eax = sys_kill(reg.ebx as _, reg.ecx as _) as u32
 		},
@@ -44,9 +99,209 @@ pub fn syscall_handler(reg: &mut Registers) {
 		_ if reg.eax == Syscall::mmap as u32 => {
 			reg.eax = mmap(reg.ebx as *const mmap::mmap_arg) as u32
 		},
+		_ if reg.eax == Syscall::mmap2 as u32 => {
+			reg.eax = mmap2(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				reg.edi as _,
+				reg.ebp as _
+			) as u32
+		},
+		_ if reg.eax == Syscall::brk as u32 => {
+			reg.eax = sys_brk(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::swapon as u32 => {
+			reg.eax = sys_swapon(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::swapoff as u32 => {
+			reg.eax = sys_swapoff(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::ipc as u32 => {
+			reg.eax = sys_ipc(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				reg.edi as _,
+				reg.ebp as _
+			) as u32
+		},
+		_ if reg.eax == Syscall::shmget as u32 => {
+			reg.eax =
+				sys_shmget(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::shmat as u32 => {
+			reg.eax = sys_shmat(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::shmdt as u32 => {
+			reg.eax = sys_shmdt(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::shmctl as u32 => {
+			reg.eax =
+				sys_shmctl(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
 		_ if reg.eax == Syscall::munmap as u32 => {
 			reg.eax = sys_munmap(reg.ebx as _, reg.ecx as _) as u32
 		},
+		_ if reg.eax == Syscall::mremap as u32 => {
+			reg.eax = sys_mremap(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				reg.edi as _
+			) as u32
+		},
+		_ if reg.eax == Syscall::mprotect as u32 => {
+			reg.eax =
+				sys_mprotect(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::open as u32 => {
+			reg.eax = sys_open(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::close as u32 => {
+			reg.eax = sys_close(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::dup as u32 => {
+			reg.eax = sys_dup(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::dup2 as u32 => {
+			reg.eax = sys_dup2(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::fcntl as u32
+			|| reg.eax == Syscall::fcntl64 as u32 =>
+		{
+			reg.eax = sys_fcntl(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::link as u32 => {
+			reg.eax = sys_link(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::unlink as u32 => {
+			reg.eax = sys_unlink(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::rename as u32 => {
+			reg.eax = sys_rename(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::rmdir as u32 => {
+			reg.eax = sys_rmdir(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::symlink as u32 => {
+			reg.eax = sys_symlink(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::readlink as u32 => {
+			reg.eax =
+				sys_readlink(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::chdir as u32 => {
+			reg.eax = sys_chdir(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::fchdir as u32 => {
+			reg.eax = sys_fchdir(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::getcwd as u32 => {
+			reg.eax = sys_getcwd(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::chroot as u32 => {
+			reg.eax = sys_chroot(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::chmod as u32 => {
+			reg.eax = sys_chmod(reg.ebx as _, reg.ecx as _) as u32
+		},
+		// 16 bits ids versions, -1 is sign extended
+		_ if reg.eax == Syscall::chown as u32
+			|| reg.eax == Syscall::lchown as u32 =>
+		{
+			let follow = reg.eax == Syscall::chown as u32;
+			let uid = reg.ecx as u16 as i16 as u32;
+			let gid = reg.edx as u16 as i16 as u32;
+			reg.eax = sys_chown(reg.ebx as _, uid, gid, follow) as u32
+		},
+		_ if reg.eax == Syscall::chown32 as u32
+			|| reg.eax == Syscall::lchown32 as u32 =>
+		{
+			let follow = reg.eax == Syscall::chown32 as u32;
+			reg.eax =
+				sys_chown(reg.ebx as _, reg.ecx as _, reg.edx as _, follow)
+					as u32
+		},
+		_ if reg.eax == Syscall::utime as u32 => {
+			reg.eax = sys_utime(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::setxattr as u32
+			|| reg.eax == Syscall::lsetxattr as u32 =>
+		{
+			let follow = reg.eax == Syscall::setxattr as u32;
+			reg.eax = sys_setxattr(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				reg.edi as _,
+				follow
+			) as u32
+		},
+		_ if reg.eax == Syscall::getxattr as u32
+			|| reg.eax == Syscall::lgetxattr as u32 =>
+		{
+			let follow = reg.eax == Syscall::getxattr as u32;
+			reg.eax = sys_getxattr(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				follow
+			) as u32
+		},
+		_ if reg.eax == Syscall::listxattr as u32
+			|| reg.eax == Syscall::llistxattr as u32 =>
+		{
+			let follow = reg.eax == Syscall::listxattr as u32;
+			reg.eax =
+				sys_listxattr(reg.ebx as _, reg.ecx as _, reg.edx as _, follow)
+					as u32
+		},
+		_ if reg.eax == Syscall::removexattr as u32
+			|| reg.eax == Syscall::lremovexattr as u32 =>
+		{
+			let follow = reg.eax == Syscall::removexattr as u32;
+			reg.eax = sys_removexattr(reg.ebx as _, reg.ecx as _, follow) as u32
+		},
+		_ if reg.eax == Syscall::stat64 as u32 => {
+			reg.eax = sys_stat64(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::lstat64 as u32 => {
+			reg.eax = sys_lstat64(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::fstat64 as u32 => {
+			reg.eax = sys_fstat64(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::ftruncate as u32 => {
+			reg.eax = sys_ftruncate(reg.ebx as _, reg.ecx as _) as u32
+		},
+		_ if reg.eax == Syscall::getdents64 as u32 => {
+			reg.eax =
+				sys_getdents64(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::select as u32 => {
+			reg.eax = sys_old_select(reg.ebx as _) as u32
+		},
+		_ if reg.eax == Syscall::_newselect as u32 => {
+			reg.eax = sys_select(
+				reg.ebx as _,
+				reg.ecx as _,
+				reg.edx as _,
+				reg.esi as _,
+				reg.edi as _
+			) as u32
+		},
+		_ if reg.eax == Syscall::poll as u32 => {
+			reg.eax = sys_poll(reg.ebx as _, reg.ecx as _, reg.edx as _) as u32
+		},
+		_ if reg.eax == Syscall::socketcall as u32 => {
+			reg.eax = sys_socketcall(reg.ebx as _, reg.ecx as _) as u32
+		},
 		_ if reg.eax == Syscall::wait4 as u32 => {
 			reg.eax = sys_wait4(
 				reg.ebx as _,
@@ -0,0 +1,182 @@
+//! poll and select syscalls, select is implemented on top of fs::poll
+
+use crate::errno::ErrNo;
+use crate::fs::{
+	self,
+	PollFd,
+	POLLERR,
+	POLLHUP,
+	POLLIN,
+	POLLNVAL,
+	POLLOUT,
+	POLLPRI
+};
+use crate::proc::process::MAX_FD;
+use crate::vec::Vec;
+
+use super::mmap::{get_user, put_user, read_user, write_user};
+use super::to_ret;
+
+// Events mapped to each fd_set of select
+const POLLIN_SET: u16 = POLLIN | POLLHUP | POLLERR;
+const POLLOUT_SET: u16 = POLLOUT | POLLERR;
+const POLLEX_SET: u16 = POLLPRI;
+
+/// Bitmap of file descriptors, only MAX_FD bits are used by the kernel
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct fd_set {
+	pub fds_bits: [u32; 32]
+}
+
+impl fd_set {
+	pub const fn new() -> Self {
+		Self { fds_bits: [0; 32] }
+	}
+
+	pub fn is_set(&self, fd: usize) -> bool {
+		self.fds_bits[fd / 32] & (1 << (fd % 32)) != 0
+	}
+
+	pub fn set(&mut self, fd: usize) {
+		self.fds_bits[fd / 32] |= 1 << (fd % 32);
+	}
+
+	pub fn clear(&mut self, fd: usize) {
+		self.fds_bits[fd / 32] &= !(1 << (fd % 32));
+	}
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct timeval {
+	pub tv_sec:  i32,
+	pub tv_usec: i32
+}
+
+/// Arguments of the old select syscall, packed in a struct on i386
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct sel_arg_struct {
+	n:    i32,
+	inp:  *mut fd_set,
+	outp: *mut fd_set,
+	exp:  *mut fd_set,
+	tvp:  *mut timeval
+}
+
+/// Convert tv to ms rounded up, huge timeouts saturate
+fn timeval_ms(tv: timeval) -> Result<usize, ErrNo> {
+	if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+		return Err(ErrNo::EINVAL);
+	}
+	Ok((tv.tv_sec as usize)
+		.saturating_mul(1000)
+		.saturating_add((tv.tv_usec as usize).div_ceil(1000)))
+}
+
+/// Read a nullable user pointer
+fn get_nullable<T: Copy>(ptr: *const T) -> Result<Option<T>, ErrNo> {
+	match ptr.is_null() {
+		true => Ok(None),
+		false => get_user(ptr).map(Some)
+	}
+}
+
+/// fds: array of nfds PollFd
+/// timeout: time to wait in ms, negative wait forever
+pub fn sys_poll(fds: *mut PollFd, nfds: u32, timeout: i32) -> i32 {
+	to_ret(poll(fds, nfds, timeout))
+}
+
+fn poll(fds: *mut PollFd, nfds: u32, timeout: i32) -> Result<usize, ErrNo> {
+	if nfds as usize > MAX_FD {
+		return Err(ErrNo::EINVAL);
+	}
+	let mut pollfds = crate::vec![PollFd::default(); nfds as usize];
+	read_user(&mut pollfds, fds)?;
+	let timeout = match timeout {
+		timeout if timeout < 0 => None,
+		timeout => Some(timeout as usize)
+	};
+	let count = fs::poll(&mut pollfds, timeout)?;
+	write_user(fds, &pollfds)?;
+	Ok(count)
+}
+
+/// Wait for some fds in the sets to be ready, sets are updated to keep only
+/// the ready ones, every other bit is cleared.
+/// timeout: NULL wait forever
+pub fn sys_select(
+	nfds: i32,
+	readfds: *mut fd_set,
+	writefds: *mut fd_set,
+	exceptfds: *mut fd_set,
+	timeout: *mut timeval
+) -> i32 {
+	to_ret(select(nfds, [readfds, writefds, exceptfds], timeout))
+}
+
+fn select(
+	nfds: i32,
+	ptrs: [*mut fd_set; 3],
+	timeout: *mut timeval
+) -> Result<usize, ErrNo> {
+	if nfds < 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	let nfds = core::cmp::min(nfds as usize, MAX_FD);
+	let sets = [
+		get_nullable(ptrs[0])?,
+		get_nullable(ptrs[1])?,
+		get_nullable(ptrs[2])?
+	];
+	let masks = [POLLIN_SET, POLLOUT_SET, POLLEX_SET];
+	let timeout = match get_nullable(timeout)? {
+		Some(tv) => Some(timeval_ms(tv)?),
+		None => None
+	};
+
+	let mut pollfds: Vec<PollFd> = Vec::new();
+	for fd in 0..nfds {
+		let mut events = 0;
+		for (set, mask) in sets.iter().zip(masks) {
+			if set.as_ref().is_some_and(|set| set.is_set(fd)) {
+				events |= mask;
+			}
+		}
+		if events != 0 {
+			pollfds.push(PollFd { fd: fd as i32, events, revents: 0 });
+		}
+	}
+	fs::poll(&mut pollfds, timeout)?;
+	if pollfds.iter().any(|pollfd| pollfd.revents & POLLNVAL != 0) {
+		return Err(ErrNo::EBADF);
+	}
+
+	// Sets are rebuilt from the ready fds, bits past nfds or MAX_FD are cleared as well
+	let mut count = 0;
+	for ((set, ptr), mask) in sets.iter().zip(ptrs).zip(masks) {
+		if let Some(set) = set {
+			let mut ready = fd_set::new();
+			for pollfd in pollfds.iter().filter(|pollfd| {
+				pollfd.revents & mask != 0 && set.is_set(pollfd.fd as usize)
+			}) {
+				ready.set(pollfd.fd as usize);
+				count += 1;
+			}
+			put_user(ptr, ready)?;
+		}
+	}
+	Ok(count)
+}
+
+/// Old select syscall taking its arguments from a struct
+pub fn sys_old_select(args: *mut sel_arg_struct) -> i32 {
+	to_ret(get_user(args).and_then(|args| {
+		select(args.n, [args.inp, args.outp, args.exp], args.tvp)
+	}))
+}
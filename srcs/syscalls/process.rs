@@ -15,7 +15,7 @@ use crate::memory::paging::page_directory::PageDirectory;
 // must dump registers
 
 /// Create a new process from the calling process,
-/// copy stack, heap and registers
+/// share its stack and heap copy-on-write and copy its registers
 ///
 /// Heap contains the prg and the heap allocated
 pub fn sys_fork() -> Pid {
@@ -42,10 +42,14 @@ pub fn sys_fork() -> Pid {
 				parent.heap.flags,
 				parent.heap.kphys
 			);
+			process.brk = parent.brk;
+			process.brk_min = parent.brk_min;
 			process.copy_mem(&mut parent);
+			process.inherit_fds(&parent, false);
 		}
 
 		let page_dir: &mut PageDirectory = process.setup_pagination();
+		process.map_mem_map();
 
 		new_task.regs = running_task.regs;
 		new_task.regs.int_no = u32::MAX; // trigger for switch_task
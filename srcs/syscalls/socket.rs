@@ -0,0 +1,137 @@
+//! Socket syscalls, multiplexed by socketcall like on linux i386
+
+use crate::errno::ErrNo;
+use crate::fs;
+use crate::fs::socket::{Shutdown, SocketDomain, SocketProtocol, SocketType};
+use crate::string::String;
+
+use super::mmap::{get_user, read_user, write_user};
+use super::to_ret;
+
+pub const SYS_SOCKET: u32 = 1;
+pub const SYS_BIND: u32 = 2;
+pub const SYS_CONNECT: u32 = 3;
+pub const SYS_LISTEN: u32 = 4;
+pub const SYS_ACCEPT: u32 = 5;
+pub const SYS_SOCKETPAIR: u32 = 8;
+pub const SYS_SHUTDOWN: u32 = 13;
+
+pub const UNIX_PATH_MAX: usize = 108;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct sockaddr_un {
+	pub sun_family: u16,
+	pub sun_path:   [u8; UNIX_PATH_MAX]
+}
+
+/// Copy the path out of a user sockaddr_un, only the len bytes given are read.
+/// Only AF_UNIX addresses are supported
+fn sockaddr_path(
+	addr: *const sockaddr_un,
+	len: usize
+) -> Result<String, ErrNo> {
+	if addr.is_null() || len < core::mem::size_of::<u16>() {
+		return Err(ErrNo::EINVAL);
+	}
+	if get_user(addr as *const u16)? != 1 {
+		return Err(ErrNo::EAFNOSUPPORT);
+	}
+	let max = core::cmp::min(len - core::mem::size_of::<u16>(), UNIX_PATH_MAX);
+	let mut sun_path: [u8; UNIX_PATH_MAX] = [0; UNIX_PATH_MAX];
+	let src = (addr as *const u8).wrapping_add(core::mem::size_of::<u16>());
+	read_user(&mut sun_path[0..max], src)?;
+	let path = &sun_path[0..max];
+	let end = path.iter().position(|c| *c == 0).unwrap_or(max);
+	if end == 0 {
+		return Err(ErrNo::EINVAL);
+	}
+	core::str::from_utf8(&path[0..end])
+		.map(String::from)
+		.map_err(|_| ErrNo::EINVAL)
+}
+
+/// Flags that can be or'ed to the socket type
+pub const SOCK_NONBLOCK: u32 = 0o4000;
+pub const SOCK_CLOEXEC: u32 = 0o2000000;
+
+fn sys_socket(domain: u32, stype: u32, protocol: u32) -> Result<usize, ErrNo> {
+	let fd = fs::socket(
+		SocketDomain::try_from(domain)?,
+		SocketType::try_from(stype & !(SOCK_NONBLOCK | SOCK_CLOEXEC))?,
+		SocketProtocol::try_from(protocol)?
+	)?;
+	if stype & SOCK_NONBLOCK != 0 {
+		fs::fcntl(fd, fs::F_SETFL, fs::O_NONBLOCK as usize)?;
+	}
+	if stype & SOCK_CLOEXEC != 0 {
+		fs::fcntl(fd, fs::F_SETFD, fs::FD_CLOEXEC as usize)?;
+	}
+	Ok(fd)
+}
+
+fn sys_socketpair(
+	domain: u32,
+	stype: u32,
+	protocol: u32,
+	sv: *mut i32
+) -> Result<usize, ErrNo> {
+	if sv.is_null() {
+		return Err(ErrNo::EFAULT);
+	}
+	let mut sockets: [usize; 2] = [0; 2];
+	fs::socket_pair(
+		SocketDomain::try_from(domain)?,
+		SocketType::try_from(stype)?,
+		SocketProtocol::try_from(protocol)?,
+		&mut sockets
+	)?;
+	// Like linux the sockets are closed if their fds can't be stored
+	if let Err(errno) = write_user(sv, &[sockets[0] as i32, sockets[1] as i32])
+	{
+		fs::close(sockets[0]);
+		fs::close(sockets[1]);
+		return Err(errno);
+	}
+	Ok(0)
+}
+
+/// Copy the arguments of call from user space, only the ones it takes are read
+fn socketcall_args(call: u32, args: *const u32) -> Result<[u32; 6], ErrNo> {
+	let nargs = match call {
+		SYS_SOCKET | SYS_BIND | SYS_CONNECT | SYS_ACCEPT => 3,
+		SYS_LISTEN | SYS_SHUTDOWN => 2,
+		SYS_SOCKETPAIR => 4,
+		_ => return Err(ErrNo::EINVAL)
+	};
+	let mut values: [u32; 6] = [0; 6];
+	read_user(&mut values[0..nargs], args)?;
+	Ok(values)
+}
+
+/// Entry point of all socket syscalls.
+/// call: which socket syscall to execute (SYS_SOCKET, SYS_BIND...)
+/// args: pointer to the arguments of the syscall
+pub fn sys_socketcall(call: u32, args: *const u32) -> i32 {
+	let args = match socketcall_args(call, args) {
+		Ok(args) => args,
+		Err(errno) => return -(errno as i32)
+	};
+	let res = match call {
+		SYS_SOCKET => sys_socket(args[0], args[1], args[2]),
+		SYS_BIND => sockaddr_path(args[1] as _, args[2] as usize)
+			.and_then(|path| fs::bind(args[0] as usize, &path)),
+		SYS_CONNECT => sockaddr_path(args[1] as _, args[2] as usize)
+			.and_then(|path| fs::connect(args[0] as usize, &path)),
+		SYS_LISTEN => fs::listen(args[0] as usize, args[1] as usize),
+		// Peer address is not filled since connecting sockets are never bound
+		SYS_ACCEPT => fs::accept(args[0] as usize),
+		SYS_SOCKETPAIR => {
+			sys_socketpair(args[0], args[1], args[2], args[3] as *mut i32)
+		},
+		SYS_SHUTDOWN => Shutdown::try_from(args[1])
+			.and_then(|how| fs::shutdown(args[0] as usize, how)),
+		_ => Err(ErrNo::EINVAL)
+	};
+	to_ret(res)
+}
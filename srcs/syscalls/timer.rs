@@ -9,6 +9,10 @@ pub fn sys_getuid() -> Id {
 	Process::get_running_process().lock().owner
 }
 
+pub fn sys_getgid() -> Id {
+	Process::get_running_process().lock().group
+}
+
 pub fn sys_getppid() -> Id {
 	let binding = Process::get_running_process();
 	let process = binding.lock();
@@ -33,7 +33,7 @@ pub fn leaks() -> bool {
 
 #[cfg(test)]
 pub fn test_runner(tests: &[&dyn Fn()]) {
-	use crate::memory::paging::bitmap::physmap_as_mut;
+	use crate::memory::paging::buddy::physmap_as_mut;
 	crate::kprintln!("Running {} tests", tests.len());
 	let used_pages = physmap_as_mut().used;
 	crate::kprintln!("Kernel as mapped {} pages", used_pages);
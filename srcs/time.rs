@@ -46,6 +46,12 @@ pub fn jiffies() -> usize {
 	JIFFIES.load(Ordering::Relaxed)
 }
 
+/// Convert a duration in millisecond to a number of jiffies, rounded up
+pub fn ms_to_jiffies(ms: usize) -> usize {
+	// SYSTEM_FRACTION shouldn't be change after boot and should so be safe
+	unsafe { (ms as f64 / SYSTEM_FRACTION) as usize + 1 }
+}
+
 /// Sleep until x millisecond have passed
 pub fn sleep(ms: usize) {
 	if ms > 1000 {
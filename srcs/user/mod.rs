@@ -23,7 +23,13 @@ extern "C" {
 }
 
 pub const USER_HEAP_ADDR: VirtAddr = 0x0800000;
+/// Largest size of the heap zone, the program break can't be moved past it
+pub const USER_HEAP_LIMIT: usize = 0x10000000;
 pub const USER_STACK_ADDR: VirtAddr = 0xbfffffff;
+/// Area of the process address space where mappings are placed, it ends at the page table of
+/// the kernel stacks
+pub const USER_MMAP_ADDR: VirtAddr = 0xf7c00000;
+pub const USER_MMAP_END: VirtAddr = 0xff800000;
 
 #[naked]
 unsafe extern "C" fn jump_usermode(func: VirtAddr, cr3: u32, esp: u32) -> ! {
@@ -61,6 +67,7 @@ pub unsafe fn exec_fn_userspace(
 	process.init(&binding);
 	process.exe = name.clone();
 	process.owner = 1; // user
+	process.group = 1;
 
 	let pid = process.pid;
 	process.setup_kernel_stack(PAGE_WRITABLE | PAGE_USER);
@@ -70,6 +77,11 @@ pub unsafe fn exec_fn_userspace(
 		PAGE_WRITABLE | PAGE_USER,
 		false
 	);
+	// The program is copied by the kernel, the stack is faulted in on use
+	process
+		.heap
+		.populate()
+		.expect("unable to allocate pages for heap");
 
 	// TODO: free those when process ends ?
 	let page_dir: &mut PageDirectory = process.setup_pagination();
@@ -210,8 +210,8 @@ userfunc_6:
 
     mov DWORD ptr [esp + 0x00], 0            // hint
     mov DWORD ptr [esp + 0x04], {tmmap_size} // page size
-    mov DWORD ptr [esp + 0x08], 0            // prot
-    mov DWORD ptr [esp + 0x0c], 2            // flags
+    mov DWORD ptr [esp + 0x08], 3            // prot: read | write
+    mov DWORD ptr [esp + 0x0c], 0x22         // flags: private | anonymous
     mov DWORD ptr [esp + 0x10], -1           // fd
     mov DWORD ptr [esp + 0x14], 0            // offset
 
@@ -221,8 +221,8 @@ userfunc_6:
         lea ebx, [esp]
         mov eax, 90   // mmap syscall
         int 0x80
-        cmp eax, 0xff  // Check if mmap failed
-        je .error_6
+        cmp eax, -4095  // Check if mmap failed
+        jae .error_6
         mov [esp + 0x4 * 6 + 0x4 * edx], eax // Save ptr
         mov ecx, 0x00 
 
@@ -282,3 +282,538 @@ fn test_mmap_userspace() {
 		assert_eq!(__WEXITSTATUS!(status), 0x0);
 	}
 }
+
+global_asm!(
+	r#"
+.globl userfunc_7
+.globl end_userfunc_7
+userfunc_7:
+	// The stack page is faulted in, the write outside of any zone is not
+	push 42
+	pop ebx
+	mov DWORD ptr [0x40000000], ebx
+	mov eax, 1
+	int 0x80
+end_userfunc_7:
+"#
+);
+
+extern "C" {
+	fn userfunc_7();
+	fn end_userfunc_7();
+}
+
+#[crate::sys_macros::test_case]
+fn test_segfault_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_7 as u32,
+			end_userfunc_7 as usize - userfunc_7 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFSIGNALED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 11);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_8
+.globl end_userfunc_8
+userfunc_8:
+	// The stack page is shared with the child which writes its own copy
+	push 21
+	mov eax, 2 // fork
+	int 0x80
+	cmp eax, 0
+	jne .wait_child_8
+
+	mov DWORD ptr [esp], 42
+	mov ebx, [esp]
+	mov eax, 1
+	int 0x80
+
+	.wait_child_8:
+	mov edx, 0
+	mov ecx, 0
+	mov ebx, eax
+	mov eax, 7 // waitpid
+	int 0x80
+	pop ebx // exit
+	mov eax, 1
+	int 0x80
+end_userfunc_8:
+"#
+);
+
+extern "C" {
+	fn userfunc_8();
+	fn end_userfunc_8();
+}
+
+#[crate::sys_macros::test_case]
+fn test_fork_copy_on_write_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_8 as u32,
+			end_userfunc_8 as usize - userfunc_8 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFEXITED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 21);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_9
+.globl end_userfunc_9
+userfunc_9:
+	// Save space for mmap arg struct
+	sub esp, 0x4 * 6
+	mov DWORD ptr [esp + 0x00], 0    // hint
+	mov DWORD ptr [esp + 0x04], 4096 // page size
+	mov DWORD ptr [esp + 0x08], 3    // prot: read | write
+	mov DWORD ptr [esp + 0x0c], 0x22 // flags: private | anonymous
+	mov DWORD ptr [esp + 0x10], -1   // fd
+	mov DWORD ptr [esp + 0x14], 0    // offset
+	lea ebx, [esp]
+	mov eax, 90 // mmap
+	int 0x80
+	mov esi, eax
+
+	mov ebx, esi
+	mov ecx, 4096
+	mov edx, 1 // prot: read
+	mov eax, 125 // mprotect
+	int 0x80
+	cmp eax, 0
+	jne .error_9
+	// The mapping is read-only now
+	mov BYTE ptr [esi], 42
+
+	.error_9:
+	mov ebx, 1
+	mov eax, 1
+	int 0x80
+end_userfunc_9:
+"#
+);
+
+extern "C" {
+	fn userfunc_9();
+	fn end_userfunc_9();
+}
+
+#[crate::sys_macros::test_case]
+fn test_mprotect_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_9 as u32,
+			end_userfunc_9 as usize - userfunc_9 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFSIGNALED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 11);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_10
+.globl end_userfunc_10
+userfunc_10:
+	mov ebx, 0
+	mov eax, 45 // brk
+	int 0x80
+	mov esi, eax
+
+	// Grow the heap by two pages
+	lea ebx, [esi + 0x2000]
+	mov eax, 45 // brk
+	int 0x80
+	lea ecx, [esi + 0x2000]
+	cmp eax, ecx
+	jne .error_10
+	// The new pages are zeroed and writable
+	mov ecx, DWORD ptr [esi + 0x1000]
+	cmp ecx, 0
+	jne .error_10
+	mov BYTE ptr [esi + 0x1000], 42
+
+	// The break can't go past the heap limit
+	mov ebx, 0xb0000000
+	mov eax, 45 // brk
+	int 0x80
+	lea ecx, [esi + 0x2000]
+	cmp eax, ecx
+	jne .error_10
+
+	// Shrink it back, the pages past the break are unmapped
+	mov ebx, esi
+	mov eax, 45 // brk
+	int 0x80
+	cmp eax, esi
+	jne .error_10
+	mov BYTE ptr [esi + 0x1000], 42
+
+	.error_10:
+	mov ebx, 1
+	mov eax, 1
+	int 0x80
+end_userfunc_10:
+"#
+);
+
+extern "C" {
+	fn userfunc_10();
+	fn end_userfunc_10();
+}
+
+#[crate::sys_macros::test_case]
+fn test_brk_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_10 as u32,
+			end_userfunc_10 as usize - userfunc_10 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFSIGNALED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 11);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_11
+.globl end_userfunc_11
+userfunc_11:
+	mov ebx, 0     // IPC_PRIVATE
+	mov ecx, 4096
+	mov edx, 0x380 // IPC_CREAT | 0600
+	mov eax, 395   // shmget
+	int 0x80
+	mov edi, eax
+
+	mov ebx, edi
+	mov ecx, 0
+	mov edx, 0
+	mov eax, 397 // shmat
+	int 0x80
+	mov esi, eax
+
+	// The child writes to the segment it inherits
+	mov eax, 2 // fork
+	int 0x80
+	cmp eax, 0
+	jne .wait_child_11
+	mov DWORD ptr [esi], 42
+	mov ebx, 0
+	mov eax, 1
+	int 0x80
+
+	.wait_child_11:
+	mov edx, 0
+	mov ecx, 0
+	mov ebx, eax
+	mov eax, 7 // waitpid
+	int 0x80
+
+	// The removed segment stays until detached
+	mov ebx, edi
+	mov ecx, 0 // IPC_RMID
+	mov edx, 0
+	mov eax, 396 // shmctl
+	int 0x80
+	push DWORD ptr [esi]
+	mov edi, esi
+	mov ebx, 22 // SHMDT
+	mov eax, 117 // ipc
+	int 0x80
+	pop ebx
+	cmp eax, 0
+	je .exit_11
+	mov ebx, 1
+
+	.exit_11:
+	mov eax, 1
+	int 0x80
+end_userfunc_11:
+"#
+);
+
+extern "C" {
+	fn userfunc_11();
+	fn end_userfunc_11();
+}
+
+#[crate::sys_macros::test_case]
+fn test_shm_fork_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_11 as u32,
+			end_userfunc_11 as usize - userfunc_11 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFEXITED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 42);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_12
+.globl end_userfunc_12
+userfunc_12:
+	// "/dev/shm/yak" built on the stack
+	push 0
+	push 0x6b61792f
+	push 0x6d68732f
+	push 0x7665642f
+	mov ebx, esp
+	mov ecx, 0x42 // O_RDWR | O_CREAT
+	mov edx, 0x180 // 0600
+	mov eax, 5 // open
+	int 0x80
+	cmp eax, 0
+	jl .error_12
+	mov edi, eax
+
+	mov ebx, edi
+	mov ecx, 4096
+	mov eax, 93 // ftruncate
+	int 0x80
+	cmp eax, 0
+	jne .error_12
+
+	push ebp
+	mov ebx, 0
+	mov ecx, 4096
+	mov edx, 3 // PROT_READ | PROT_WRITE
+	mov esi, 1 // MAP_SHARED
+	mov ebp, 0
+	mov eax, 192 // mmap2
+	int 0x80
+	pop ebp
+	cmp eax, 0xfffff000
+	ja .error_12
+	mov esi, eax
+
+	// The child writes to the mapping it inherits
+	mov eax, 2 // fork
+	int 0x80
+	cmp eax, 0
+	jne .wait_child_12
+	mov DWORD ptr [esi], 42
+	mov ebx, 0
+	mov eax, 1
+	int 0x80
+
+	.wait_child_12:
+	mov edx, 0
+	mov ecx, 0
+	mov ebx, eax
+	mov eax, 7 // waitpid
+	int 0x80
+
+	// Once unlinked the name is gone, the mapping stays
+	mov ebx, esp
+	mov eax, 10 // unlink
+	int 0x80
+	cmp eax, 0
+	jne .error_12
+	mov ebx, esp
+	mov ecx, 2 // O_RDWR
+	mov edx, 0
+	mov eax, 5 // open
+	int 0x80
+	cmp eax, -2 // ENOENT
+	jne .error_12
+	mov ebx, DWORD ptr [esi]
+	mov eax, 1
+	int 0x80
+
+	.error_12:
+	mov ebx, 1
+	mov eax, 1
+	int 0x80
+end_userfunc_12:
+"#
+);
+
+extern "C" {
+	fn userfunc_12();
+	fn end_userfunc_12();
+}
+
+#[crate::sys_macros::test_case]
+fn test_shm_open_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_12 as u32,
+			end_userfunc_12 as usize - userfunc_12 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFEXITED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 42);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_13
+.globl end_userfunc_13
+userfunc_13:
+	// "/dev/shm/yak13" built on the stack
+	push 0x3331
+	push 0x6b61792f
+	push 0x6d68732f
+	push 0x7665642f
+	mov ebx, esp
+	mov ecx, 0x42 // O_RDWR | O_CREAT
+	mov edx, 0x180 // 0600
+	mov eax, 5 // open
+	int 0x80
+	cmp eax, 0
+	jl .error_13
+	push eax
+
+	mov ebx, eax
+	mov ecx, 0x1234
+	mov eax, 93 // ftruncate
+	int 0x80
+	cmp eax, 0
+	jne .error_13
+
+	push ebp
+	mov ebx, 0
+	mov ecx, 0x2000
+	mov edx, 3 // PROT_READ | PROT_WRITE
+	mov esi, 0x22 // MAP_PRIVATE | MAP_ANONYMOUS
+	mov edi, -1
+	mov ebp, 0
+	mov eax, 192 // mmap2
+	int 0x80
+	pop ebp
+	cmp eax, 0xfffff000
+	ja .error_13
+	mov esi, eax
+
+	// st_size is at offset 44 of the stat, in the second page of the mapping
+	pop ebx
+	lea ecx, [esi + 0x1000 - 40]
+	mov eax, 197 // fstat64
+	int 0x80
+	cmp eax, 0
+	jne .error_13
+	cmp DWORD ptr [esi + 0x1000 + 4], 0x1234
+	jne .error_13
+
+	mov ebx, esp
+	mov eax, 10 // unlink
+	int 0x80
+	mov ebx, 42
+	mov eax, 1
+	int 0x80
+
+	.error_13:
+	mov ebx, 1
+	mov eax, 1
+	int 0x80
+end_userfunc_13:
+"#
+);
+
+extern "C" {
+	fn userfunc_13();
+	fn end_userfunc_13();
+}
+
+#[crate::sys_macros::test_case]
+fn test_stat_page_boundary_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_13 as u32,
+			end_userfunc_13 as usize - userfunc_13 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFEXITED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 42);
+	}
+}
+
+global_asm!(
+	r#"
+.globl userfunc_14
+.globl end_userfunc_14
+userfunc_14:
+	push ebp
+	mov ebx, 0
+	mov ecx, 0x1000
+	mov edx, 1 // PROT_READ
+	mov esi, 0x22 // MAP_PRIVATE | MAP_ANONYMOUS
+	mov edi, -1
+	mov ebp, 0
+	mov eax, 192 // mmap2
+	int 0x80
+	pop ebp
+	cmp eax, 0xfffff000
+	ja .error_14
+	mov ecx, eax
+
+	// The read set is read from the read-only page but can't be written back
+	push 0
+	push 0
+	mov edi, esp // timeout of 0
+	mov ebx, 0
+	mov edx, 0
+	mov esi, 0
+	mov eax, 142 // _newselect
+	int 0x80
+	cmp eax, -14 // EFAULT
+	jne .error_14
+	mov ebx, 42
+	mov eax, 1
+	int 0x80
+
+	.error_14:
+	mov ebx, 1
+	mov eax, 1
+	int 0x80
+end_userfunc_14:
+"#
+);
+
+extern "C" {
+	fn userfunc_14();
+	fn end_userfunc_14();
+}
+
+#[crate::sys_macros::test_case]
+fn test_write_read_only_page_userspace() {
+	unsafe {
+		let mut status: i32 = 0;
+		let pid = crate::exec_fn_userspace!(
+			userfunc_14 as u32,
+			end_userfunc_14 as usize - userfunc_14 as usize
+		);
+		let ret = crate::syscalls::exit::sys_waitpid(pid, &mut status, 0);
+		assert_eq!(ret, pid);
+		assert_eq!(__WIFEXITED!(status), true);
+		assert_eq!(__WEXITSTATUS!(status), 42);
+	}
+}
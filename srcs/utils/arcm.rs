@@ -13,7 +13,7 @@ pub struct RawArcm<T: ?Sized, const INT: bool> {
 	arc: Arc<RawMutex<T, INT>>
 }
 
-impl<T, const INT: bool> Clone for RawArcm<T, INT> {
+impl<T: ?Sized, const INT: bool> Clone for RawArcm<T, INT> {
 	fn clone(&self) -> Self {
 		Self { arc: self.arc.clone() }
 	}